@@ -0,0 +1,132 @@
+//! Compares completion providers on a suite of prompts.
+//!
+//! ```sh
+//! cargo run -p ai --example provider_bench -- \
+//!     --iterations 3 \
+//!     openai:gpt-4-turbo-preview \
+//!     vllm:codellama/CodeLlama-13b-Instruct-hf@http://localhost:8000/v1
+//! ```
+//!
+//! Pass `--suite prompts.json` to run your own prompts instead of the built-in
+//! ones. OpenAI targets read their API key from `OPENAI_API_KEY`.
+
+use ai::{
+    bench::{render_reports, run_suite, BenchTarget, PromptSuite},
+    completion::CompletionProvider,
+    providers::{
+        open_ai::{OpenAiCompletionProvider, OPEN_AI_API_URL},
+        vllm::{VllmCompletionProvider, VLLM_API_URL},
+    },
+};
+use anyhow::{anyhow, Context as _, Result};
+use gpui::{AppContext, AsyncAppContext};
+use std::{path::PathBuf, sync::Arc};
+use util::http::HttpClient;
+
+struct Args {
+    suite: Option<PathBuf>,
+    iterations: usize,
+    /// `provider:model[@api_url]` specs.
+    targets: Vec<String>,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        suite: None,
+        iterations: 1,
+        targets: Vec::new(),
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--suite" => args.suite = Some(argv.next().context("--suite needs a path")?.into()),
+            "--iterations" => {
+                args.iterations = argv
+                    .next()
+                    .context("--iterations needs a number")?
+                    .parse()?
+            }
+            _ => args.targets.push(arg),
+        }
+    }
+    if args.targets.is_empty() {
+        return Err(anyhow!(
+            "usage: provider_bench [--suite PATH] [--iterations N] PROVIDER:MODEL[@API_URL]..."
+        ));
+    }
+    Ok(args)
+}
+
+async fn build_target(
+    spec: &str,
+    client: Arc<dyn HttpClient>,
+    cx: &mut AsyncAppContext,
+) -> Result<BenchTarget> {
+    let (provider_name, rest) = spec
+        .split_once(':')
+        .with_context(|| format!("invalid target {spec:?}"))?;
+    let (model, api_url) = match rest.rsplit_once('@') {
+        Some((model, api_url)) => (model, Some(api_url.to_string())),
+        None => (rest, None),
+    };
+    let executor = cx.background_executor().clone();
+    let provider: Box<dyn CompletionProvider> = match provider_name {
+        "openai" => Box::new(
+            OpenAiCompletionProvider::new(
+                api_url.unwrap_or_else(|| OPEN_AI_API_URL.into()),
+                model.into(),
+                client,
+                executor,
+            )
+            .await,
+        ),
+        "vllm" => Box::new(VllmCompletionProvider::new(
+            api_url.unwrap_or_else(|| VLLM_API_URL.into()),
+            model.into(),
+            client,
+            executor,
+        )),
+        _ => return Err(anyhow!("unknown provider {provider_name:?}")),
+    };
+    cx.update(|cx| provider.retrieve_credentials(cx))?.await;
+    Ok(BenchTarget {
+        name: spec.into(),
+        model: model.into(),
+        provider,
+    })
+}
+
+fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    };
+
+    gpui::App::new().run(move |cx: &mut AppContext| {
+        cx.spawn(|mut cx| async move {
+            let result = async {
+                let suite = match &args.suite {
+                    Some(path) => PromptSuite::load(path)?,
+                    None => PromptSuite::builtin(),
+                };
+                let client = util::http::client();
+                let mut targets = Vec::new();
+                for spec in &args.targets {
+                    targets.push(build_target(spec, client.clone(), &mut cx).await?);
+                }
+                let reports = run_suite(&targets, &suite, args.iterations).await;
+                println!("{}", render_reports(&reports));
+                anyhow::Ok(())
+            }
+            .await;
+            if let Err(error) = result {
+                eprintln!("{error:?}");
+            }
+            cx.update(|cx| cx.quit()).ok();
+        })
+        .detach();
+    });
+}
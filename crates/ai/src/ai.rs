@@ -1,8 +1,17 @@
 pub mod auth;
+pub mod bench;
+#[cfg(any(test, feature = "test-support"))]
+pub mod cassette;
+pub mod chat;
 pub mod completion;
 pub mod embedding;
+pub mod endpoint_pool;
+pub mod metrics;
 pub mod models;
 pub mod prompts;
+pub mod provider_status;
 pub mod providers;
 #[cfg(any(test, feature = "test-support"))]
 pub mod test;
+pub mod tokenizer;
+pub mod trace;
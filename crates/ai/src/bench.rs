@@ -0,0 +1,303 @@
+//! Runs a suite of prompts against several providers and reports how each one
+//! performed, to compare models empirically. See `examples/provider_bench.rs`.
+
+use anyhow::Result;
+use futures::StreamExt;
+use serde::Deserialize;
+use std::{
+    fmt::Write,
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    chat::{ChatRequest, RequestMessage, Role},
+    completion::{CompletionEvent, CompletionProvider, SamplingParams},
+};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BenchPrompt {
+    pub name: String,
+    pub messages: Vec<RequestMessage>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+}
+
+/// The prompts a benchmark runs, loaded from a JSON array of
+/// `{"name", "messages", "max_tokens"}` objects.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+pub struct PromptSuite {
+    pub prompts: Vec<BenchPrompt>,
+}
+
+impl PromptSuite {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// A few short prompts resembling what the assistant sends.
+    pub fn builtin() -> Self {
+        let prompt = |name: &str, content: &str| BenchPrompt {
+            name: name.into(),
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: content.into(),
+            }],
+            max_tokens: Some(256),
+        };
+        Self {
+            prompts: vec![
+                prompt(
+                    "explain",
+                    "Explain what this Rust code does:\n\nfn gcd(a: u64, b: u64) -> u64 {\n    if b == 0 { a } else { gcd(b, a % b) }\n}",
+                ),
+                prompt(
+                    "generate",
+                    "Write a Rust function that parses a semantic version string like \"1.2.3\" into a tuple of three integers.",
+                ),
+                prompt(
+                    "transform",
+                    "Rewrite this loop using iterators:\n\nlet mut total = 0;\nfor item in &items {\n    if item.enabled {\n        total += item.price;\n    }\n}",
+                ),
+            ],
+        }
+    }
+}
+
+/// A provider to benchmark, and the model to request from it.
+pub struct BenchTarget {
+    pub name: String,
+    pub model: String,
+    pub provider: Box<dyn CompletionProvider>,
+}
+
+/// The outcome of running one prompt once.
+#[derive(Clone, Debug)]
+pub struct BenchSample {
+    pub prompt: String,
+    pub latency: Duration,
+    pub time_to_first_token: Option<Duration>,
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: usize,
+    pub error: Option<String>,
+}
+
+impl BenchSample {
+    /// How quickly the response was generated once it started.
+    pub fn tokens_per_second(&self) -> Option<f32> {
+        let generation_time = self.latency - self.time_to_first_token?;
+        (generation_time > Duration::ZERO && self.completion_tokens > 1)
+            .then(|| self.completion_tokens as f32 / generation_time.as_secs_f32())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BenchReport {
+    pub target: String,
+    pub samples: Vec<BenchSample>,
+}
+
+fn median<T: Copy + PartialOrd>(mut values: Vec<T>) -> Option<T> {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    values.get(values.len() / 2).copied()
+}
+
+impl BenchReport {
+    fn successes(&self) -> impl Iterator<Item = &BenchSample> {
+        self.samples.iter().filter(|sample| sample.error.is_none())
+    }
+
+    pub fn errors(&self) -> usize {
+        self.samples.len() - self.successes().count()
+    }
+
+    pub fn median_latency(&self) -> Option<Duration> {
+        median(self.successes().map(|sample| sample.latency).collect())
+    }
+
+    pub fn median_time_to_first_token(&self) -> Option<Duration> {
+        median(
+            self.successes()
+                .filter_map(|sample| sample.time_to_first_token)
+                .collect(),
+        )
+    }
+
+    pub fn median_tokens_per_second(&self) -> Option<f32> {
+        median(
+            self.successes()
+                .filter_map(|sample| sample.tokens_per_second())
+                .collect(),
+        )
+    }
+
+    pub fn total_completion_tokens(&self) -> usize {
+        self.successes()
+            .map(|sample| sample.completion_tokens)
+            .sum()
+    }
+}
+
+/// Sends `prompt` to `target` and measures the response.
+pub async fn run_prompt(target: &BenchTarget, prompt: &BenchPrompt) -> BenchSample {
+    let request = ChatRequest {
+        model: target.model.clone(),
+        messages: prompt.messages.clone(),
+        stream: true,
+        sampling: SamplingParams {
+            max_tokens: prompt.max_tokens,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let started_at = Instant::now();
+    let mut sample = BenchSample {
+        prompt: prompt.name.clone(),
+        latency: Duration::ZERO,
+        time_to_first_token: None,
+        prompt_tokens: None,
+        completion_tokens: 0,
+        error: None,
+    };
+    let mut text = String::new();
+    let mut reported_completion_tokens = None;
+    match target.provider.complete(request).await {
+        Ok(mut events) => {
+            while let Some(event) = events.next().await {
+                match event {
+                    Ok(CompletionEvent::Delta { text: delta, .. }) => {
+                        sample
+                            .time_to_first_token
+                            .get_or_insert_with(|| started_at.elapsed());
+                        text.push_str(&delta);
+                    }
+                    Ok(CompletionEvent::Usage(usage)) => {
+                        sample.prompt_tokens = Some(usage.prompt_tokens);
+                        reported_completion_tokens = Some(usage.completion_tokens as usize);
+                    }
+                    Ok(_) => {}
+                    Err(error) => {
+                        sample.error = Some(error.to_string());
+                        break;
+                    }
+                }
+            }
+        }
+        Err(error) => sample.error = Some(error.to_string()),
+    }
+    sample.latency = started_at.elapsed();
+    // Providers that don't report usage are measured with the model's tokenizer.
+    sample.completion_tokens = reported_completion_tokens.unwrap_or_else(|| {
+        target
+            .provider
+            .base_model()
+            .count_tokens(&text)
+            .unwrap_or_default()
+    });
+    sample
+}
+
+/// Runs every prompt in `suite` against each target `iterations` times. Targets
+/// are run one after another, so they don't compete for the same hardware.
+pub async fn run_suite(
+    targets: &[BenchTarget],
+    suite: &PromptSuite,
+    iterations: usize,
+) -> Vec<BenchReport> {
+    let mut reports = Vec::new();
+    for target in targets {
+        let mut samples = Vec::new();
+        for _ in 0..iterations {
+            for prompt in &suite.prompts {
+                let sample = run_prompt(target, prompt).await;
+                if let Some(error) = &sample.error {
+                    log::warn!("{} failed on {:?}: {error}", target.name, prompt.name);
+                }
+                samples.push(sample);
+            }
+        }
+        reports.push(BenchReport {
+            target: target.name.clone(),
+            samples,
+        });
+    }
+    reports
+}
+
+/// Formats reports as a Markdown table.
+pub fn render_reports(reports: &[BenchReport]) -> String {
+    let millis = |duration: Option<Duration>| {
+        duration.map_or("-".to_string(), |duration| duration.as_millis().to_string())
+    };
+    let mut table = String::new();
+    table.push_str(
+        "| Provider | Requests | Errors | Latency (ms) | First token (ms) | Tokens/s | Completion tokens |\n",
+    );
+    table.push_str("|---|---|---|---|---|---|---|\n");
+    for report in reports {
+        writeln!(
+            table,
+            "| {} | {} | {} | {} | {} | {} | {} |",
+            report.target,
+            report.samples.len(),
+            report.errors(),
+            millis(report.median_latency()),
+            millis(report.median_time_to_first_token()),
+            report
+                .median_tokens_per_second()
+                .map_or("-".to_string(), |rate| format!("{rate:.1}")),
+            report.total_completion_tokens(),
+        )
+        .unwrap();
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        completion::TokenUsage,
+        test::{FakeCompletionProvider, FakeResponse},
+    };
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_run_suite() {
+        let provider = FakeCompletionProvider::new();
+        for _ in 0..3 {
+            provider.respond_with(FakeResponse::chunks(["Hello", " world"]).event(
+                CompletionEvent::Usage(TokenUsage {
+                    prompt_tokens: 20,
+                    completion_tokens: 2,
+                    total_tokens: 22,
+                }),
+            ));
+        }
+        provider.fail_next(anyhow::anyhow!("connection refused"));
+        provider.respond_with(FakeResponse::chunks(["abc"]));
+        let targets = [BenchTarget {
+            name: "fake".into(),
+            model: "codellama".into(),
+            provider: Box::new(provider.clone()),
+        }];
+        let mut suite = PromptSuite::builtin();
+        suite.prompts.truncate(1);
+
+        let reports = block_on(run_suite(&targets, &suite, 5));
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.samples.len(), 5);
+        assert_eq!(report.errors(), 1);
+        // The last response has no usage, so its tokens are counted locally.
+        assert_eq!(report.total_completion_tokens(), 2 * 3 + 3);
+        assert!(report.median_time_to_first_token().is_some());
+        assert_eq!(provider.requests()[0].model, "codellama");
+
+        let table = render_reports(&reports);
+        assert!(table.contains("| fake | 5 | 1 |"));
+    }
+}
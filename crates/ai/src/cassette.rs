@@ -0,0 +1,295 @@
+//! Record/replay fixtures for provider tests. A cassette is a JSON file of HTTP
+//! interactions: tests replay it in place of a real server, so the wire formats
+//! providers parse are covered without network access. Setting
+//! `ZED_RECORD_CASSETTES=1` sends the requests to the real server instead and
+//! overwrites the cassette with its responses.
+
+use futures::{future::BoxFuture, AsyncReadExt, FutureExt};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use util::http::{AsyncBody, Error, HttpClient, Request, Response};
+
+/// Response headers kept in recordings. Everything else, such as cookies and
+/// request IDs, is dropped.
+const RECORDED_HEADERS: &[&str] = &["content-type", "retry-after"];
+const RECORDED_HEADER_PREFIXES: &[&str] = &["x-ratelimit-"];
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub uri: String,
+    /// The request body, as JSON when it parses as JSON. Requests are only
+    /// matched against it when it's present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+impl Cassette {
+    /// The path of a cassette committed to the `ai` crate's `fixtures` directory.
+    pub fn fixture_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(format!("{name}.json"))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        fs::write(path, json)?;
+        Ok(())
+    }
+}
+
+fn is_recording() -> bool {
+    std::env::var("ZED_RECORD_CASSETTES").map_or(false, |value| !value.is_empty() && value != "0")
+}
+
+enum Mode {
+    Replay(Mutex<VecDeque<Interaction>>),
+    Record {
+        client: Arc<dyn HttpClient>,
+        interactions: Arc<Mutex<Vec<Interaction>>>,
+    },
+}
+
+/// An [`HttpClient`] that replays a [`Cassette`], or records one while sending
+/// requests to a real client.
+pub struct CassetteClient {
+    path: PathBuf,
+    mode: Mode,
+}
+
+impl CassetteClient {
+    /// Replays the fixture `name`, or records it with the client returned by
+    /// `real_client` when `ZED_RECORD_CASSETTES` is set.
+    pub fn fixture(name: &str, real_client: impl FnOnce() -> Arc<dyn HttpClient>) -> Arc<Self> {
+        let path = Cassette::fixture_path(name);
+        if is_recording() {
+            Self::record(path, real_client())
+        } else {
+            Self::replay(path).unwrap_or_else(|error| panic!("failed to load {name}: {error}"))
+        }
+    }
+
+    pub fn replay(path: PathBuf) -> anyhow::Result<Arc<Self>> {
+        let cassette = Cassette::load(&path)?;
+        Ok(Arc::new(Self {
+            path,
+            mode: Mode::Replay(Mutex::new(cassette.interactions.into())),
+        }))
+    }
+
+    pub fn record(path: PathBuf, client: Arc<dyn HttpClient>) -> Arc<Self> {
+        Arc::new(Self {
+            path,
+            mode: Mode::Record {
+                client,
+                interactions: Default::default(),
+            },
+        })
+    }
+
+    /// Saves the cassette when recording, or checks that every recorded
+    /// interaction was replayed.
+    pub fn finish(&self) -> anyhow::Result<()> {
+        match &self.mode {
+            Mode::Replay(interactions) => {
+                let remaining = interactions.lock().len();
+                anyhow::ensure!(
+                    remaining == 0,
+                    "{remaining} interactions in {:?} weren't replayed",
+                    self.path
+                );
+                Ok(())
+            }
+            Mode::Record { interactions, .. } => Cassette {
+                interactions: interactions.lock().clone(),
+            }
+            .save(&self.path),
+        }
+    }
+}
+
+fn parse_body(body: &[u8]) -> Option<serde_json::Value> {
+    if body.is_empty() {
+        return None;
+    }
+    let body = String::from_utf8_lossy(body);
+    Some(serde_json::from_str(&body).unwrap_or_else(|_| serde_json::Value::String(body.into())))
+}
+
+impl HttpClient for CassetteClient {
+    fn send(&self, request: Request<AsyncBody>) -> BoxFuture<Result<Response<AsyncBody>, Error>> {
+        let (parts, mut body) = request.into_parts();
+        match &self.mode {
+            Mode::Replay(interactions) => async move {
+                let mut request_body = Vec::new();
+                body.read_to_end(&mut request_body).await?;
+                let request = RecordedRequest {
+                    method: parts.method.to_string(),
+                    uri: parts.uri.to_string(),
+                    body: parse_body(&request_body),
+                };
+
+                let interaction = interactions.lock().pop_front().unwrap_or_else(|| {
+                    panic!("unexpected request to {}: {:?}", request.uri, self.path)
+                });
+                let expected = &interaction.request;
+                assert_eq!(
+                    (&request.method, &request.uri),
+                    (&expected.method, &expected.uri),
+                    "request doesn't match {:?}",
+                    self.path
+                );
+                if expected.body.is_some() {
+                    assert_eq!(
+                        request.body, expected.body,
+                        "request body doesn't match {:?}",
+                        self.path
+                    );
+                }
+
+                let mut response = Response::builder().status(interaction.response.status);
+                for (name, value) in &interaction.response.headers {
+                    response = response.header(name, value);
+                }
+                Ok(response
+                    .body(AsyncBody::from(interaction.response.body))
+                    .unwrap())
+            }
+            .boxed(),
+            Mode::Record {
+                client,
+                interactions,
+            } => {
+                let client = client.clone();
+                let interactions = interactions.clone();
+                async move {
+                    let mut request_body = Vec::new();
+                    body.read_to_end(&mut request_body).await?;
+                    let request = RecordedRequest {
+                        method: parts.method.to_string(),
+                        uri: parts.uri.to_string(),
+                        body: parse_body(&request_body),
+                    };
+
+                    let response = client
+                        .send(Request::from_parts(parts, AsyncBody::from(request_body)))
+                        .await?;
+                    let (parts, mut body) = response.into_parts();
+                    let mut response_body = String::new();
+                    body.read_to_string(&mut response_body).await?;
+                    let headers = parts
+                        .headers
+                        .iter()
+                        .filter(|(name, _)| {
+                            let name = name.as_str();
+                            RECORDED_HEADERS.contains(&name)
+                                || RECORDED_HEADER_PREFIXES
+                                    .iter()
+                                    .any(|prefix| name.starts_with(prefix))
+                        })
+                        .filter_map(|(name, value)| {
+                            Some((name.to_string(), value.to_str().ok()?.to_string()))
+                        })
+                        .collect();
+                    interactions.lock().push(Interaction {
+                        request,
+                        response: RecordedResponse {
+                            status: parts.status.as_u16(),
+                            headers,
+                            body: response_body.clone(),
+                        },
+                    });
+
+                    Ok(Response::from_parts(parts, AsyncBody::from(response_body)))
+                }
+                .boxed()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use util::http::FakeHttpClient;
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("models.json");
+        let server = FakeHttpClient::create(|_| async move {
+            Ok(Response::builder()
+                .status(200)
+                .header("content-type", "application/json")
+                .header("set-cookie", "session=secret")
+                .body(AsyncBody::from(r#"{"data": []}"#))
+                .unwrap())
+        });
+
+        let send = |client: &CassetteClient| {
+            block_on(async {
+                let request = Request::post("http://localhost:8000/v1/models")
+                    .body(AsyncBody::from(r#"{"limit": 1}"#))
+                    .unwrap();
+                let mut response = client.send(request).await.unwrap();
+                let mut body = String::new();
+                response.body_mut().read_to_string(&mut body).await.unwrap();
+                (response.status().as_u16(), body)
+            })
+        };
+
+        let recorder = CassetteClient::record(path.clone(), server);
+        assert_eq!(send(&recorder), (200, r#"{"data": []}"#.to_string()));
+        recorder.finish().unwrap();
+
+        let cassette = Cassette::load(&path).unwrap();
+        assert_eq!(
+            cassette.interactions[0].request.body,
+            Some(serde_json::json!({"limit": 1}))
+        );
+        assert_eq!(
+            cassette.interactions[0].response.headers,
+            BTreeMap::from_iter([("content-type".into(), "application/json".into())])
+        );
+
+        let player = CassetteClient::replay(path).unwrap();
+        assert!(player.finish().is_err());
+        assert_eq!(send(&player), (200, r#"{"data": []}"#.to_string()));
+        player.finish().unwrap();
+    }
+}
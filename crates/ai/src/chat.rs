@@ -0,0 +1,155 @@
+//! Provider-independent chat requests. Callers describe a completion with a
+//! [`ChatRequest`], layers around a provider can inspect and rewrite it, and each
+//! provider translates it into its own wire format when the request is sent.
+//! Providers that speak the OpenAI chat format serialize the messages directly;
+//! any provider with its own format should convert from these types in its
+//! module rather than define its own `Role`.
+
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+use crate::completion::SamplingParams;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+    System,
+}
+
+impl Role {
+    /// The role's name in the OpenAI chat format.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::System => "system",
+        }
+    }
+
+    pub fn cycle(&mut self) {
+        *self = match self {
+            Role::User => Role::Assistant,
+            Role::Assistant => Role::System,
+            Role::System => Role::User,
+        }
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::User => write!(f, "User"),
+            Role::Assistant => write!(f, "Assistant"),
+            Role::System => write!(f, "System"),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct RequestMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+/// A chat completion request. Providers send the parameters their API supports
+/// and ignore the rest, except Perplexity, which fails instead.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ChatRequest {
+    /// The model to complete with. Wrappers such as the fallback provider replace
+    /// it with the model of the provider they forward the request to.
+    pub model: String,
+    pub messages: Vec<RequestMessage>,
+    /// Whether to stream the response. Providers that support it also report the
+    /// token usage of streamed completions.
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    pub temperature: f32,
+    pub sampling: SamplingParams,
+    /// Makes sampling deterministic on a best-effort basis: requests with the same
+    /// seed and parameters should return the same result.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// How many choices to generate. Events for choices after the first arrive
+    /// wrapped in [`CompletionEvent::Choice`](crate::completion::CompletionEvent::Choice).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    /// Provider-specific parameters, added to the request body as they are. Only
+    /// set these when the request is going to a provider that accepts them.
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Constrains the shape of the generated text.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    /// The output must be a valid JSON object. The prompt itself must also ask for
+    /// JSON, or the API rejects the request.
+    JsonObject,
+    /// The output must be JSON matching the given schema.
+    JsonSchema {
+        json_schema: JsonSchemaFormat,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct JsonSchemaFormat {
+    pub name: String,
+    pub schema: serde_json::Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+/// A tool the model may call instead of, or before, answering.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Tool {
+    Function { function: FunctionDefinition },
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// A JSON schema describing the function's arguments.
+    pub parameters: serde_json::Value,
+}
+
+/// Controls whether the model calls a tool.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ToolChoice {
+    /// The model decides whether to call a tool.
+    Auto,
+    /// The model answers without calling a tool.
+    None,
+    /// The model must call at least one tool.
+    Required,
+    /// The model must call the function with this name.
+    Function(String),
+}
+
+impl Serialize for ToolChoice {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ToolChoice::Auto => serializer.serialize_str("auto"),
+            ToolChoice::None => serializer.serialize_str("none"),
+            ToolChoice::Required => serializer.serialize_str("required"),
+            ToolChoice::Function(name) => serde_json::json!({
+                "type": "function",
+                "function": { "name": name },
+            })
+            .serialize(serializer),
+        }
+    }
+}
@@ -1,23 +1,1135 @@
-use anyhow::Result;
-use futures::{future::BoxFuture, stream::BoxStream};
+use anyhow::{anyhow, Result};
+use futures::{
+    future::{self, AbortHandle, Abortable, BoxFuture, Either},
+    stream::{self, BoxStream},
+    Future, FutureExt, Stream, StreamExt, TryStreamExt,
+};
+use gpui::{BackgroundExecutor, Task};
+use isahc::{
+    config::{CaCertificate, Configurable, SslOption},
+    http::{request, HeaderMap, StatusCode},
+};
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::BTreeMap, ops::AddAssign, path::PathBuf, time::Duration};
+use thiserror::Error;
+use tiktoken_rs::CoreBPE;
 
-use crate::{auth::CredentialProvider, models::LanguageModel};
+use crate::{
+    auth::CredentialProvider,
+    chat::{ChatRequest, Role},
+    models::LanguageModel,
+};
 
-pub trait CompletionRequest: Send + Sync {
-    fn data(&self) -> serde_json::Result<String>;
+mod cache;
+mod continuation;
+mod fallback;
+mod middleware;
+mod scheduler;
+
+pub use cache::*;
+pub use continuation::*;
+pub use fallback::*;
+pub use middleware::*;
+pub use scheduler::*;
+
+/// The ways a completion request can fail, independent of which provider served it.
+///
+/// Providers return these wrapped in an [`anyhow::Error`], so callers that want to
+/// react to a particular failure should `downcast_ref::<CompletionError>()`.
+#[derive(Error, Clone, Debug, PartialEq)]
+pub enum CompletionError {
+    #[error("{provider} rejected the API key: {message}")]
+    Unauthorized {
+        provider: &'static str,
+        message: String,
+    },
+    #[error("{provider} rate limit exceeded{}", format_retry_after(.retry_after))]
+    RateLimited {
+        provider: &'static str,
+        retry_after: Option<Duration>,
+    },
+    #[error("the request exceeds the model's context length: {message}")]
+    ContextLengthExceeded {
+        provider: &'static str,
+        message: String,
+    },
+    #[error("{provider} could not find the requested model: {message}")]
+    ModelNotFound {
+        provider: &'static str,
+        message: String,
+    },
+    #[error("failed to connect to {provider}: {message}")]
+    Network {
+        provider: &'static str,
+        message: String,
+    },
+    #[error("{provider} stopped responding for {timeout:?}")]
+    Timeout {
+        provider: &'static str,
+        timeout: Duration,
+    },
+    #[error("{provider} rejected the request ({status}): {message}")]
+    InvalidRequest {
+        provider: &'static str,
+        status: u16,
+        message: String,
+    },
+    #[error("{provider} encountered an error ({status}): {message}")]
+    Server {
+        provider: &'static str,
+        status: u16,
+        message: String,
+    },
+}
+
+fn format_retry_after(retry_after: &Option<Duration>) -> String {
+    match retry_after {
+        Some(retry_after) => format!(", retry in {}s", retry_after.as_secs().max(1)),
+        None => String::new(),
+    }
+}
+
+impl CompletionError {
+    pub fn network(provider: &'static str, error: impl std::fmt::Display) -> Self {
+        CompletionError::Network {
+            provider,
+            message: error.to_string(),
+        }
+    }
+
+    /// Classifies a non-success response from an OpenAI-compatible API.
+    pub fn from_response(
+        provider: &'static str,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &str,
+    ) -> Self {
+        #[derive(Deserialize)]
+        struct ErrorResponse {
+            error: ErrorBody,
+        }
+
+        #[derive(Deserialize)]
+        struct ErrorBody {
+            message: String,
+            code: Option<serde_json::Value>,
+        }
+
+        // OpenAI nests the error in an `error` object, while vLLM and some other
+        // compatible servers return it at the top level.
+        let (message, code) = serde_json::from_str::<ErrorResponse>(body)
+            .map(|response| response.error)
+            .or_else(|_| serde_json::from_str::<ErrorBody>(body))
+            .map(|error| {
+                let code = error
+                    .code
+                    .and_then(|code| code.as_str().map(|code| code.to_string()));
+                (error.message, code)
+            })
+            .unwrap_or_else(|_| (body.trim().to_string(), None));
+        let message = if message.is_empty() {
+            status.to_string()
+        } else {
+            message
+        };
+
+        if code.as_deref() == Some("context_length_exceeded")
+            || message.contains("maximum context length")
+        {
+            return CompletionError::ContextLengthExceeded { provider, message };
+        }
+
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                CompletionError::Unauthorized { provider, message }
+            }
+            StatusCode::TOO_MANY_REQUESTS => CompletionError::RateLimited {
+                provider,
+                retry_after: retry_after(headers),
+            },
+            StatusCode::NOT_FOUND => CompletionError::ModelNotFound { provider, message },
+            status if status.is_server_error() => CompletionError::Server {
+                provider,
+                status: status.as_u16(),
+                message,
+            },
+            status => {
+                if code.as_deref() == Some("model_not_found") {
+                    CompletionError::ModelNotFound { provider, message }
+                } else {
+                    CompletionError::InvalidRequest {
+                        provider,
+                        status: status.as_u16(),
+                        message,
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn provider(&self) -> &'static str {
+        match self {
+            CompletionError::Unauthorized { provider, .. }
+            | CompletionError::RateLimited { provider, .. }
+            | CompletionError::ContextLengthExceeded { provider, .. }
+            | CompletionError::ModelNotFound { provider, .. }
+            | CompletionError::Network { provider, .. }
+            | CompletionError::Timeout { provider, .. }
+            | CompletionError::InvalidRequest { provider, .. }
+            | CompletionError::Server { provider, .. } => provider,
+        }
+    }
+}
+
+/// Reads how long to wait before retrying from the `retry-after` header, falling back
+/// to OpenAI's `x-ratelimit-reset-*` headers (e.g. `"6m0s"`).
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(seconds) = headers
+        .get("retry-after")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .filter(|seconds| !seconds.is_nan())
+    {
+        // A value too large to be a `Duration` is as good as absent.
+        return Duration::try_from_secs_f64(seconds.max(0.)).ok();
+    }
+
+    ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"]
+        .into_iter()
+        .filter_map(|header| {
+            let value = headers.get(header)?.to_str().ok()?;
+            parse_duration::parse(value).ok()
+        })
+        .max()
+}
+
+/// What a provider reported about its rate limits in the headers of a response,
+/// following OpenAI's `x-ratelimit-*` headers.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateLimitStatus {
+    pub remaining_requests: Option<u64>,
+    pub remaining_tokens: Option<u64>,
+    /// How long until the request limit resets.
+    pub reset_requests: Option<Duration>,
+    /// How long until the token limit resets.
+    pub reset_tokens: Option<Duration>,
+}
+
+impl RateLimitStatus {
+    /// Reads the rate limit headers, returning `None` if the response has none.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name)?.to_str().ok();
+        let count = |name: &str| header(name)?.trim().parse::<u64>().ok();
+        let duration = |name: &str| parse_duration::parse(header(name)?).ok();
+        let status = Self {
+            remaining_requests: count("x-ratelimit-remaining-requests"),
+            remaining_tokens: count("x-ratelimit-remaining-tokens"),
+            reset_requests: duration("x-ratelimit-reset-requests"),
+            reset_tokens: duration("x-ratelimit-reset-tokens"),
+        };
+        (status != Self::default()).then_some(status)
+    }
+
+    /// How long to hold off on further requests because a limit has been used up,
+    /// or `None` if there's room for more.
+    pub fn exhausted_for(&self) -> Option<Duration> {
+        let requests = self
+            .reset_requests
+            .filter(|_| self.remaining_requests == Some(0));
+        let tokens = self
+            .reset_tokens
+            .filter(|_| self.remaining_tokens == Some(0));
+        requests.max(tokens)
+    }
+}
+
+/// Limits on how long to wait for a provider before giving up on a request.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompletionTimeouts {
+    /// How long to wait for a connection to be established.
+    pub connect: Duration,
+    /// How long the connection can go without receiving any data, including while
+    /// waiting for the response to start.
+    pub read: Duration,
+    /// How long a streamed response can go without producing a complete line, which
+    /// catches servers that keep the connection open but have stopped generating.
+    pub stream_idle: Duration,
+}
+
+impl Default for CompletionTimeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(10),
+            read: Duration::from_secs(60),
+            stream_idle: Duration::from_secs(60),
+        }
+    }
+}
+
+/// TLS settings for providers served behind a private certificate authority, such as
+/// self-hosted models behind an internal gateway.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TlsOptions {
+    /// A PEM file of the certificate authorities to verify the server with, used
+    /// instead of the system's certificates.
+    pub ca_bundle: Option<PathBuf>,
+    /// Whether to accept certificates that weren't issued for the server's hostname.
+    pub accept_invalid_hostnames: bool,
+}
+
+/// How a provider sends its requests: how long to wait for them, how to retry them,
+/// and how to secure the connection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnectionOptions {
+    pub retry_policy: RetryPolicy,
+    pub timeouts: CompletionTimeouts,
+    pub tls: TlsOptions,
+    /// Headers added to every request, for gateways that need their own
+    /// authentication or routing headers.
+    pub extra_headers: BTreeMap<String, String>,
+    /// How many streamed events can be buffered before the provider stops reading
+    /// the response and waits for the consumer to catch up.
+    pub stream_buffer_size: usize,
+    /// Whether streamed requests ask for the token usage of the whole response
+    /// in a final event. Servers that don't support it may reject the request,
+    /// so when unset, only endpoints known to support it are asked.
+    pub stream_usage: Option<bool>,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            retry_policy: RetryPolicy::default(),
+            timeouts: CompletionTimeouts::default(),
+            tls: TlsOptions::default(),
+            extra_headers: BTreeMap::new(),
+            stream_buffer_size: 64,
+            stream_usage: None,
+        }
+    }
+}
+
+impl ConnectionOptions {
+    /// Applies the extra headers, timeouts and TLS settings to a request.
+    pub fn configure(&self, mut request: request::Builder) -> request::Builder {
+        for (name, value) in &self.extra_headers {
+            request = request.header(name, value);
+        }
+        let mut request = request
+            .connect_timeout(self.timeouts.connect)
+            .low_speed_timeout(1, self.timeouts.read);
+        if let Some(ca_bundle) = &self.tls.ca_bundle {
+            request = request.ssl_ca_certificate(CaCertificate::file(ca_bundle));
+        }
+        if self.tls.accept_invalid_hostnames {
+            request = request.ssl_options(SslOption::DANGER_ACCEPT_INVALID_HOSTS);
+        }
+        request
+    }
+
+    /// Whether to ask for the token usage at the end of a streamed response, given
+    /// whether the endpoint is known to support it.
+    pub fn includes_stream_usage(&self, supported: bool) -> bool {
+        self.stream_usage.unwrap_or(supported)
+    }
+}
+
+/// Waits for the next line of a streamed response, failing with
+/// [`CompletionError::Timeout`] if the provider sends nothing for `idle_timeout`.
+pub(crate) async fn next_line<S: Stream + Unpin>(
+    lines: &mut S,
+    provider: &'static str,
+    idle_timeout: Duration,
+    executor: &BackgroundExecutor,
+) -> Result<Option<S::Item>, CompletionError> {
+    match future::select(lines.next(), executor.timer(idle_timeout)).await {
+        Either::Left((line, _)) => Ok(line),
+        Either::Right(_) => Err(CompletionError::Timeout {
+            provider,
+            timeout: idle_timeout,
+        }),
+    }
+}
+
+/// How many times, and how patiently, to retry a completion request that failed for
+/// a reason that's likely to be transient: rate limiting, server errors, and dropped
+/// connections.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// The total number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Returns how long to wait before retrying after the given attempt failed, or
+    /// `None` if the error isn't worth retrying or the attempts are exhausted. A
+    /// server asking us to wait longer than `max_delay` gets its error surfaced
+    /// rather than a request that hangs for that long.
+    fn delay_after(&self, attempt: u32, error: &anyhow::Error) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        let retry_after = match error.downcast_ref::<CompletionError>()? {
+            CompletionError::RateLimited {
+                retry_after: Some(retry_after),
+                ..
+            } if *retry_after > self.max_delay => return None,
+            CompletionError::RateLimited { retry_after, .. } => *retry_after,
+            CompletionError::Server { .. }
+            | CompletionError::Network { .. }
+            | CompletionError::Timeout { .. } => None,
+            _ => return None,
+        };
+
+        let backoff = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_delay);
+        // Jitter keeps concurrent requests from retrying in lockstep.
+        let backoff = backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0));
+        Some(retry_after.map_or(backoff, |retry_after| retry_after.max(backoff)))
+    }
+}
+
+/// Sends a request until it succeeds, retrying transient failures according to
+/// `policy`. Only the last error is returned once the attempts are exhausted.
+pub async fn retry_with_backoff<T, F, Fut>(
+    policy: RetryPolicy,
+    executor: &BackgroundExecutor,
+    mut send_request: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match send_request().await {
+            Ok(response) => return Ok(response),
+            Err(error) => {
+                let Some(delay) = policy.delay_after(attempt, &error) else {
+                    return Err(error);
+                };
+                log::warn!("completion attempt {attempt} failed, retrying in {delay:?}: {error}");
+                executor.timer(delay).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Sampling parameters shared by every provider. Each provider's request sends the
+/// ones its API supports and ignores the rest; unset parameters use the provider's
+/// defaults.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SamplingParams {
+    /// The maximum number of tokens to generate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Only sample from the smallest set of tokens whose probabilities add up to `top_p`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Only sample from the `top_k` most likely tokens. Not supported by OpenAI.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<i32>,
+    /// Penalizes tokens that already appeared in the text, encouraging new topics.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// Penalizes tokens in proportion to how often they already appeared.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// Makes specific tokens more or less likely. Perplexity rejects requests that
+    /// set it.
+    #[serde(skip_serializing_if = "LogitBias::is_empty")]
+    pub logit_bias: LogitBias,
+}
+
+/// Biases the likelihood of specific tokens, keyed by their ID in the model's
+/// tokenizer. Biases range from -100, which bans a token, to 100, which makes it
+/// the only choice.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct LogitBias(BTreeMap<u32, f32>);
+
+impl LogitBias {
+    pub const MIN: f32 = -100.;
+    pub const MAX: f32 = 100.;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(mut self, token: u32, bias: f32) -> Self {
+        self.0.insert(token, bias.clamp(Self::MIN, Self::MAX));
+        self
+    }
+
+    /// Bans every token `text` encodes to, such as "```" to keep markdown fences
+    /// out of generated code. Because whole tokens are banned, this also affects
+    /// other text that shares those tokens.
+    pub fn ban_text(mut self, text: &str, bpe: &CoreBPE) -> Self {
+        for token in bpe.encode_ordinary(text) {
+            self.0.insert(token as u32, Self::MIN);
+        }
+        self
+    }
+
+    pub fn get(&self, token: u32) -> Option<f32> {
+        self.0.get(&token).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The number of tokens a completion consumed, as reported by the provider.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+    }
+}
+
+/// Why the model stopped generating.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FinishReason {
+    /// The model finished its answer or hit a stop sequence.
+    Stop,
+    /// The output reached the maximum number of tokens.
+    Length,
+    /// The model stopped to call one or more functions.
+    ToolCalls,
+    /// The output was withheld by the provider's content filter.
+    ContentFilter,
+    Other(String),
+}
+
+impl From<&str> for FinishReason {
+    fn from(reason: &str) -> Self {
+        match reason {
+            "stop" | "eos" => FinishReason::Stop,
+            "length" => FinishReason::Length,
+            "tool_calls" | "function_call" => FinishReason::ToolCalls,
+            "content_filter" => FinishReason::ContentFilter,
+            other => FinishReason::Other(other.to_string()),
+        }
+    }
+}
+
+impl FinishReason {
+    pub fn as_str(&self) -> &str {
+        match self {
+            FinishReason::Stop => "stop",
+            FinishReason::Length => "length",
+            FinishReason::ToolCalls => "tool_calls",
+            FinishReason::ContentFilter => "content_filter",
+            FinishReason::Other(reason) => reason,
+        }
+    }
+}
+
+/// A finished completion, as returned by [`CompletionProvider::complete_once`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Completion {
+    pub text: String,
+    pub usage: Option<TokenUsage>,
+    pub finish_reason: Option<FinishReason>,
+}
+
+/// An event in a streamed completion.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CompletionEvent {
+    /// A piece of the generated message. The role is only set when the provider
+    /// reports it, which is usually on the first delta.
+    Delta {
+        role: Option<Role>,
+        text: String,
+    },
+    /// A piece of a function call. The name arrives once, while the JSON arguments
+    /// are split across deltas with the same index and should be concatenated.
+    FunctionCallDelta {
+        index: usize,
+        id: Option<String>,
+        name: Option<String>,
+        arguments: String,
+    },
+    /// Sources the provider cited while producing the answer.
+    Citations(Vec<String>),
+    Usage(TokenUsage),
+    FinishReason(FinishReason),
+    /// An event for one of the additional choices of a request that asked for more
+    /// than one. Events for the first choice aren't wrapped, so callers that only
+    /// want one completion can ignore these.
+    Choice {
+        index: u32,
+        event: Box<CompletionEvent>,
+    },
+    /// The name of the provider that's answering, sent by
+    /// [`FallbackCompletionProvider`] before the first output.
+    AnsweredBy(String),
+    /// The provider's rate limits, as of the response to this request.
+    RateLimits(RateLimitStatus),
+    /// The request is waiting for other requests to the same provider to finish.
+    /// The next request to start is at position 1.
+    Queued {
+        position: usize,
+    },
+    /// The completion ended successfully. Streams that fail or are cancelled end
+    /// without this event.
+    Done,
+}
+
+/// Keeps only the generated text from a stream of completion events.
+pub fn text_only(
+    events: impl Stream<Item = Result<CompletionEvent>>,
+) -> impl Stream<Item = Result<String>> {
+    events.filter_map(|event| {
+        future::ready(match event {
+            Ok(CompletionEvent::Delta { text, .. }) if !text.is_empty() => Some(Ok(text)),
+            Ok(_) => None,
+            Err(error) => Some(Err(error)),
+        })
+    })
+}
+
+/// Collects the text of every choice in a stream of events, ordered by choice index.
+pub async fn collect_choices(
+    events: impl Stream<Item = Result<CompletionEvent>>,
+) -> Result<Vec<String>> {
+    let mut choices = BTreeMap::<u32, String>::new();
+    let mut events = Box::pin(events);
+    while let Some(event) = events.next().await {
+        let (index, event) = match event? {
+            CompletionEvent::Choice { index, event } => (index, *event),
+            event => (0, event),
+        };
+        if let CompletionEvent::Delta { text, .. } = event {
+            choices.entry(index).or_default().push_str(&text);
+        }
+    }
+    Ok(choices.into_values().collect())
+}
+
+/// Ends a stream of events with [`CompletionEvent::Done`], or stops it at the first
+/// error.
+pub(crate) fn with_done_event(
+    events: BoxStream<'static, Result<CompletionEvent>>,
+) -> BoxStream<'static, Result<CompletionEvent>> {
+    stream::unfold(Some(events), |events| async move {
+        let mut events = events?;
+        match events.next().await {
+            Some(Ok(event)) => Some((Ok(event), Some(events))),
+            Some(Err(error)) => Some((Err(error), None)),
+            None => Some((Ok(CompletionEvent::Done), None)),
+        }
+    })
+    .boxed()
+}
+
+/// Stops an in-flight completion. Cancelling ends the completion's stream, which
+/// in turn drops the request and closes its connection.
+#[derive(Clone, Debug)]
+pub struct CancellationHandle {
+    request: AbortHandle,
+    stream: AbortHandle,
+}
+
+impl CancellationHandle {
+    pub fn cancel(&self) {
+        self.request.abort();
+        self.stream.abort();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.stream.is_aborted()
+    }
+}
+
+/// Keeps the task reading a response alive for as long as the stream it feeds, so
+/// that dropping the stream cancels the task and the request it's reading from.
+pub(crate) fn stream_with_task<S, T>(stream: S, task: Task<T>) -> impl Stream<Item = S::Item>
+where
+    S: Stream + Unpin,
+{
+    stream::unfold((stream, task), |(mut stream, task)| async move {
+        let item = stream.next().await?;
+        Some((item, (stream, task)))
+    })
 }
 
 pub trait CompletionProvider: CredentialProvider {
     fn base_model(&self) -> Box<dyn LanguageModel>;
     fn complete(
         &self,
-        prompt: Box<dyn CompletionRequest>,
-    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<String>>>>;
+        request: ChatRequest,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>>;
+    /// Like [`CompletionProvider::complete`], but also returns a handle that can stop
+    /// the completion. A completion cancelled before the provider responds yields an
+    /// empty stream rather than an error.
+    fn complete_cancellable(
+        &self,
+        request: ChatRequest,
+    ) -> (
+        BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>>,
+        CancellationHandle,
+    ) {
+        let (request_handle, request_registration) = AbortHandle::new_pair();
+        let (stream_handle, stream_registration) = AbortHandle::new_pair();
+        let request = Abortable::new(self.complete(request), request_registration);
+        let response = async move {
+            match request.await {
+                Ok(stream) => Ok(Abortable::new(stream?, stream_registration).boxed()),
+                Err(_) => Ok(stream::empty().boxed()),
+            }
+        }
+        .boxed();
+        let handle = CancellationHandle {
+            request: request_handle,
+            stream: stream_handle,
+        };
+        (response, handle)
+    }
+    /// Completes `request` in one go, for callers that don't need incremental output.
+    /// By default this drains [`CompletionProvider::complete`]; providers override it
+    /// to skip streaming altogether when their API allows.
+    fn complete_once(&self, request: ChatRequest) -> BoxFuture<'static, Result<Completion>> {
+        let events = self.complete(request);
+        async move {
+            let mut events = events.await?;
+            let mut completion = Completion::default();
+            while let Some(event) = events.next().await {
+                match event? {
+                    CompletionEvent::Delta { text, .. } => completion.text.push_str(&text),
+                    CompletionEvent::Usage(usage) => completion.usage = Some(usage),
+                    CompletionEvent::FinishReason(finish_reason) => {
+                        completion.finish_reason = Some(finish_reason)
+                    }
+                    _ => {}
+                }
+            }
+            Ok(completion)
+        }
+        .boxed()
+    }
     fn box_clone(&self) -> Box<dyn CompletionProvider>;
 }
 
+impl dyn CompletionProvider {
+    /// Completes `request` and deserializes the generated text as JSON. If the model
+    /// produces invalid JSON the request is sent once more before giving up.
+    ///
+    /// The request should ask for JSON output, e.g. through its `response_format`.
+    pub async fn complete_typed<T: DeserializeOwned>(&self, request: ChatRequest) -> Result<T> {
+        let mut retried = false;
+        loop {
+            let events = self.complete(request.clone()).await?;
+            let text: String = text_only(events).try_collect().await?;
+            match serde_json::from_str(text.trim()) {
+                Ok(value) => return Ok(value),
+                Err(error) if retried => {
+                    return Err(anyhow!(error).context("completion was not valid JSON"))
+                }
+                Err(error) => {
+                    log::warn!("completion was not valid JSON, retrying: {error}");
+                    retried = true;
+                }
+            }
+        }
+    }
+}
+
 impl Clone for Box<dyn CompletionProvider> {
     fn clone(&self) -> Box<dyn CompletionProvider> {
         self.box_clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{providers::open_ai::OPEN_AI_BPE_TOKENIZER, test::FakeCompletionProvider};
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_cancel_completion() {
+        let provider = FakeCompletionProvider::new();
+        let (response, cancellation) = provider.complete_cancellable(ChatRequest::default());
+        let mut stream = block_on(response).unwrap();
+
+        provider.send_completion("Hello");
+        assert_eq!(
+            block_on(stream.next()).unwrap().unwrap(),
+            CompletionEvent::Delta {
+                role: None,
+                text: "Hello".into()
+            }
+        );
+
+        cancellation.cancel();
+        assert!(cancellation.is_cancelled());
+        assert!(block_on(stream.next()).is_none());
+
+        let (response, cancellation) = provider.complete_cancellable(ChatRequest::default());
+        cancellation.cancel();
+        let mut stream = block_on(response).unwrap();
+        assert!(block_on(stream.next()).is_none());
+    }
+
+    #[test]
+    fn test_complete_typed_retries_invalid_json() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Title {
+            title: String,
+        }
+
+        let fake = FakeCompletionProvider::new();
+        let provider: &dyn CompletionProvider = &fake;
+        let mut response = provider
+            .complete_typed::<Title>(ChatRequest::default())
+            .boxed_local();
+        assert!((&mut response).now_or_never().is_none());
+
+        fake.send_completion("Sure! Here is the title:");
+        fake.finish_completion();
+        assert!((&mut response).now_or_never().is_none());
+
+        fake.send_completion(r#"{"title": "#);
+        fake.send_completion(r#""Zed"}"#);
+        fake.finish_completion();
+        assert_eq!(
+            response.now_or_never().unwrap().unwrap(),
+            Title {
+                title: "Zed".into()
+            }
+        );
+
+        let mut response = provider
+            .complete_typed::<Title>(ChatRequest::default())
+            .boxed_local();
+        assert!((&mut response).now_or_never().is_none());
+        fake.send_completion("not json");
+        fake.finish_completion();
+        assert!((&mut response).now_or_never().is_none());
+        fake.send_completion("still not json");
+        fake.finish_completion();
+        assert!(response.now_or_never().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_complete_once() {
+        let provider = FakeCompletionProvider::new();
+        let mut response = provider.complete_once(ChatRequest::default());
+        assert!((&mut response).now_or_never().is_none());
+        provider.send_completion("Hello");
+        provider.send_completion(" world");
+        provider.finish_completion();
+        assert_eq!(
+            response.now_or_never().unwrap().unwrap(),
+            Completion {
+                text: "Hello world".into(),
+                usage: None,
+                finish_reason: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_logit_bias() {
+        let bias = LogitBias::new().set(1, 250.).set(2, -0.5);
+        assert_eq!(bias.get(1), Some(LogitBias::MAX));
+        assert_eq!(bias.get(2), Some(-0.5));
+        assert_eq!(
+            serde_json::to_string(&bias).unwrap(),
+            r#"{"1":100.0,"2":-0.5}"#
+        );
+
+        let bpe = &*OPEN_AI_BPE_TOKENIZER;
+        let bias = LogitBias::new().ban_text("```", bpe);
+        let tokens = bpe.encode_ordinary("```");
+        assert!(!tokens.is_empty());
+        for token in tokens {
+            assert_eq!(bias.get(token as u32), Some(LogitBias::MIN));
+        }
+    }
+
+    #[test]
+    fn test_done_event() {
+        let delta = || {
+            Ok(CompletionEvent::Delta {
+                role: Some(Role::Assistant),
+                text: "Hi".into(),
+            })
+        };
+
+        let events = block_on(with_done_event(stream::iter([delta()]).boxed()).collect::<Vec<_>>());
+        assert_eq!(
+            events.into_iter().collect::<Result<Vec<_>>>().unwrap(),
+            [delta().unwrap(), CompletionEvent::Done]
+        );
+
+        let events = with_done_event(
+            stream::iter([delta(), Err(anyhow::anyhow!("connection reset")), delta()]).boxed(),
+        );
+        let text = block_on(text_only(events).collect::<Vec<_>>());
+        assert_eq!(text.len(), 2);
+        assert_eq!(text[0].as_ref().unwrap(), "Hi");
+        assert!(text[1].is_err());
+    }
+
+    #[test]
+    fn test_collect_choices() {
+        let delta = |text: &str| CompletionEvent::Delta {
+            role: None,
+            text: text.into(),
+        };
+        let events = stream::iter([
+            Ok(delta("Hello")),
+            Ok(CompletionEvent::Choice {
+                index: 1,
+                event: Box::new(delta("Hi")),
+            }),
+            Ok(delta(" world")),
+            Ok(CompletionEvent::Choice {
+                index: 1,
+                event: Box::new(delta(" there")),
+            }),
+            Ok(CompletionEvent::Done),
+        ]);
+        assert_eq!(
+            block_on(collect_choices(events)).unwrap(),
+            ["Hello world", "Hi there"]
+        );
+    }
+
+    #[test]
+    fn test_classify_error_responses() {
+        let headers = HeaderMap::new();
+        assert_eq!(
+            CompletionError::from_response(
+                "OpenAI",
+                StatusCode::UNAUTHORIZED,
+                &headers,
+                r#"{"error": {"message": "Incorrect API key provided", "type": "invalid_request_error", "code": "invalid_api_key"}}"#,
+            ),
+            CompletionError::Unauthorized {
+                provider: "OpenAI",
+                message: "Incorrect API key provided".into()
+            }
+        );
+        assert_eq!(
+            CompletionError::from_response(
+                "OpenAI",
+                StatusCode::BAD_REQUEST,
+                &headers,
+                r#"{"error": {"message": "too long", "code": "context_length_exceeded"}}"#,
+            ),
+            CompletionError::ContextLengthExceeded {
+                provider: "OpenAI",
+                message: "too long".into()
+            }
+        );
+        assert_eq!(
+            CompletionError::from_response(
+                "vLLM",
+                StatusCode::BAD_REQUEST,
+                &headers,
+                r#"{"object": "error", "message": "This model's maximum context length is 4096 tokens.", "type": "BadRequestError", "code": 400}"#,
+            ),
+            CompletionError::ContextLengthExceeded {
+                provider: "vLLM",
+                message: "This model's maximum context length is 4096 tokens.".into()
+            }
+        );
+        assert_eq!(
+            CompletionError::from_response(
+                "OpenAI",
+                StatusCode::BAD_GATEWAY,
+                &headers,
+                "<html>bad gateway</html>"
+            ),
+            CompletionError::Server {
+                provider: "OpenAI",
+                status: 502,
+                message: "<html>bad gateway</html>".into()
+            }
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "20".parse().unwrap());
+        assert_eq!(
+            CompletionError::from_response("OpenAI", StatusCode::TOO_MANY_REQUESTS, &headers, ""),
+            CompletionError::RateLimited {
+                provider: "OpenAI",
+                retry_after: Some(Duration::from_secs(20))
+            }
+        );
+    }
+
+    #[gpui::test]
+    async fn test_next_line_times_out_when_stalled(cx: &mut gpui::TestAppContext) {
+        let executor = cx.executor();
+        let (tx, mut lines) = futures::channel::mpsc::unbounded::<&str>();
+        tx.unbounded_send("data: {}").unwrap();
+
+        let idle_timeout = Duration::from_secs(30);
+        let stalled = executor.spawn({
+            let executor = executor.clone();
+            async move {
+                let first = next_line(&mut lines, "vLLM", idle_timeout, &executor).await;
+                let second = next_line(&mut lines, "vLLM", idle_timeout, &executor).await;
+                (first, second, tx)
+            }
+        });
+        executor.run_until_parked();
+        executor.advance_clock(idle_timeout);
+
+        let (first, second, _tx) = stalled.await;
+        assert_eq!(first, Ok(Some("data: {}")));
+        assert_eq!(
+            second,
+            Err(CompletionError::Timeout {
+                provider: "vLLM",
+                timeout: idle_timeout
+            })
+        );
+    }
+
+    #[test]
+    fn test_configure_extra_headers() {
+        let options = ConnectionOptions {
+            extra_headers: BTreeMap::from_iter([(
+                "cf-aig-authorization".to_string(),
+                "Bearer gateway-token".to_string(),
+            )]),
+            ..Default::default()
+        };
+        let request = options
+            .configure(isahc::Request::post(
+                "https://gateway.example.com/v1/chat/completions",
+            ))
+            .body(())
+            .unwrap();
+        assert_eq!(
+            request.headers()["cf-aig-authorization"],
+            "Bearer gateway-token"
+        );
+    }
+
+    #[test]
+    fn test_retry_policy_delays() {
+        let policy = RetryPolicy::default();
+        let server_error = anyhow::Error::from(CompletionError::Server {
+            provider: "OpenAI",
+            status: 503,
+            message: String::new(),
+        });
+        for attempt in 1..policy.max_attempts {
+            let delay = policy.delay_after(attempt, &server_error).unwrap();
+            let backoff = policy.initial_delay * 2u32.pow(attempt - 1);
+            assert!(delay >= backoff / 2 && delay <= backoff);
+        }
+        assert_eq!(policy.delay_after(policy.max_attempts, &server_error), None);
+
+        let rate_limited = anyhow::Error::from(CompletionError::RateLimited {
+            provider: "OpenAI",
+            retry_after: Some(Duration::from_secs(20)),
+        });
+        assert_eq!(
+            policy.delay_after(1, &rate_limited),
+            Some(Duration::from_secs(20))
+        );
+        let rate_limited_for_hours = anyhow::Error::from(CompletionError::RateLimited {
+            provider: "OpenAI",
+            retry_after: Some(Duration::from_secs(3 * 60 * 60)),
+        });
+        assert_eq!(policy.delay_after(1, &rate_limited_for_hours), None);
+
+        let unauthorized = anyhow::Error::from(CompletionError::Unauthorized {
+            provider: "OpenAI",
+            message: String::new(),
+        });
+        assert_eq!(policy.delay_after(1, &unauthorized), None);
+        assert_eq!(policy.delay_after(1, &anyhow::anyhow!("bad json")), None);
+        assert_eq!(RetryPolicy::never().delay_after(1, &server_error), None);
+    }
+
+    #[test]
+    fn test_retry_after_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "2.5".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(2500)));
+        headers.insert("retry-after", "-3".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+        for value in ["inf", "NaN", "1e30"] {
+            headers.insert("retry-after", value.parse().unwrap());
+            assert_eq!(retry_after(&headers), None, "retry-after: {value}");
+        }
+    }
+
+    #[test]
+    fn test_retry_after_from_rate_limit_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-reset-requests", "1s".parse().unwrap());
+        headers.insert("x-ratelimit-reset-tokens", "6m0s".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(360)));
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_rate_limit_status_from_headers() {
+        assert_eq!(RateLimitStatus::from_headers(&HeaderMap::new()), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-remaining-requests", "59".parse().unwrap());
+        headers.insert("x-ratelimit-remaining-tokens", "0".parse().unwrap());
+        headers.insert("x-ratelimit-reset-requests", "1s".parse().unwrap());
+        headers.insert("x-ratelimit-reset-tokens", "6m0s".parse().unwrap());
+        let status = RateLimitStatus::from_headers(&headers).unwrap();
+        assert_eq!(
+            status,
+            RateLimitStatus {
+                remaining_requests: Some(59),
+                remaining_tokens: Some(0),
+                reset_requests: Some(Duration::from_secs(1)),
+                reset_tokens: Some(Duration::from_secs(360)),
+            }
+        );
+        assert_eq!(status.exhausted_for(), Some(Duration::from_secs(360)));
+
+        let status = RateLimitStatus {
+            remaining_tokens: Some(1000),
+            ..status
+        };
+        assert_eq!(status.exhausted_for(), None);
+    }
+}
@@ -0,0 +1,409 @@
+use anyhow::Result;
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    FutureExt, StreamExt,
+};
+use gpui::{AppContext, BackgroundExecutor};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+};
+use util::ResultExt;
+
+use crate::{
+    auth::{CredentialProvider, ProviderCredential},
+    chat::{ChatRequest, Role},
+    completion::{CompletionEvent, CompletionProvider, FinishReason},
+    models::LanguageModel,
+};
+
+/// A finished completion, as stored in a [`CompletionCache`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CachedCompletion {
+    pub text: String,
+    pub finish_reason: Option<String>,
+}
+
+impl CachedCompletion {
+    fn into_events(self) -> BoxStream<'static, anyhow::Result<CompletionEvent>> {
+        let mut events = vec![CompletionEvent::Delta {
+            role: Some(Role::Assistant),
+            text: self.text,
+        }];
+        if let Some(reason) = self.finish_reason {
+            events.push(CompletionEvent::FinishReason(reason.as_str().into()));
+        }
+        events.push(CompletionEvent::Done);
+        stream::iter(events.into_iter().map(Ok)).boxed()
+    }
+}
+
+/// Remembers finished completions, keyed on the provider, the model and the request
+/// that produced them. The most recently used entries are kept in memory, and if a
+/// directory is configured every entry is also written to disk so it outlives the
+/// process.
+pub struct CompletionCache {
+    capacity: usize,
+    entries: Mutex<CacheEntries>,
+    directory: Option<PathBuf>,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    completions: HashMap<String, CachedCompletion>,
+    recently_used: VecDeque<String>,
+}
+
+impl CompletionCache {
+    /// Creates a cache that keeps up to `capacity` completions in memory.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(CacheEntries::default()),
+            directory: None,
+        }
+    }
+
+    /// Also persists completions as files in `directory`.
+    pub fn with_directory(mut self, directory: PathBuf) -> Self {
+        self.directory = Some(directory);
+        self
+    }
+
+    /// Computes the key for a request. Object keys are sorted before hashing, so
+    /// requests that only differ in field order share an entry.
+    pub fn key(namespace: &str, model: &str, request: &serde_json::Value) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(namespace.as_bytes());
+        hasher.update([0]);
+        hasher.update(model.as_bytes());
+        hasher.update([0]);
+        hasher.update(normalize(request).to_string().as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Looks up a completion in memory, marking it as recently used.
+    pub fn get(&self, key: &str) -> Option<CachedCompletion> {
+        let mut entries = self.entries.lock();
+        let completion = entries.completions.get(key)?.clone();
+        entries.touch(key);
+        Some(completion)
+    }
+
+    /// Stores a completion in memory, evicting the least recently used entry once
+    /// the cache is full.
+    pub fn insert(&self, key: String, completion: CachedCompletion) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock();
+        if entries
+            .completions
+            .insert(key.clone(), completion)
+            .is_some()
+        {
+            entries.touch(&key);
+            return;
+        }
+        entries.recently_used.push_back(key);
+        while entries.recently_used.len() > self.capacity {
+            if let Some(evicted) = entries.recently_used.pop_front() {
+                entries.completions.remove(&evicted);
+            }
+        }
+    }
+
+    /// Reads a completion from disk and, if it's there, keeps it in memory too.
+    /// This blocks, so it should run on a background thread.
+    pub fn load(&self, key: &str) -> Option<CachedCompletion> {
+        let path = self.directory.as_ref()?.join(format!("{key}.json"));
+        let contents = std::fs::read(path).ok()?;
+        let completion: CachedCompletion = serde_json::from_slice(&contents).log_err()?;
+        self.insert(key.to_string(), completion.clone());
+        Some(completion)
+    }
+
+    /// Writes a completion to disk. This blocks, so it should run on a background
+    /// thread.
+    pub fn save(&self, key: &str, completion: &CachedCompletion) -> Result<()> {
+        let Some(directory) = self.directory.as_ref() else {
+            return Ok(());
+        };
+        std::fs::create_dir_all(directory)?;
+        std::fs::write(
+            directory.join(format!("{key}.json")),
+            serde_json::to_vec(completion)?,
+        )?;
+        Ok(())
+    }
+
+    /// Forgets every completion, both in memory and on disk.
+    pub fn clear(&self) -> Result<()> {
+        *self.entries.lock() = CacheEntries::default();
+        if let Some(directory) = self.directory.as_ref() {
+            if directory.exists() {
+                std::fs::remove_dir_all(directory)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CacheEntries {
+    fn touch(&mut self, key: &str) {
+        if let Some(ix) = self.recently_used.iter().position(|used| used == key) {
+            if let Some(key) = self.recently_used.remove(ix) {
+                self.recently_used.push_back(key);
+            }
+        }
+    }
+}
+
+/// Returns a copy of `value` with the keys of every object in sorted order.
+fn normalize(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(object) => {
+            let mut fields = object.iter().collect::<Vec<_>>();
+            fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+            serde_json::Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| (key.clone(), normalize(value)))
+                    .collect(),
+            )
+        }
+        serde_json::Value::Array(values) => {
+            serde_json::Value::Array(values.iter().map(normalize).collect())
+        }
+        value => value.clone(),
+    }
+}
+
+/// Serves repeated requests from a [`CompletionCache`] instead of the provider.
+/// Only completions that run to the end are cached; a hit replays the whole
+/// response at once and reports no token usage.
+#[derive(Clone)]
+pub struct CachingCompletionProvider {
+    provider: Box<dyn CompletionProvider>,
+    cache: Arc<CompletionCache>,
+    namespace: String,
+    executor: BackgroundExecutor,
+}
+
+impl CachingCompletionProvider {
+    /// `namespace` identifies the provider, e.g. its API URL, so that the same request
+    /// sent to different providers is cached separately.
+    pub fn new(
+        provider: Box<dyn CompletionProvider>,
+        cache: Arc<CompletionCache>,
+        namespace: impl Into<String>,
+        executor: BackgroundExecutor,
+    ) -> Self {
+        Self {
+            provider,
+            cache,
+            namespace: namespace.into(),
+            executor,
+        }
+    }
+}
+
+impl CredentialProvider for CachingCompletionProvider {
+    fn has_credentials(&self) -> bool {
+        self.provider.has_credentials()
+    }
+
+    fn retrieve_credentials(&self, cx: &mut AppContext) -> BoxFuture<ProviderCredential> {
+        self.provider.retrieve_credentials(cx)
+    }
+
+    fn save_credentials(
+        &self,
+        cx: &mut AppContext,
+        credential: ProviderCredential,
+    ) -> BoxFuture<()> {
+        self.provider.save_credentials(cx, credential)
+    }
+
+    fn delete_credentials(&self, cx: &mut AppContext) -> BoxFuture<()> {
+        self.provider.delete_credentials(cx)
+    }
+}
+
+impl CompletionProvider for CachingCompletionProvider {
+    fn base_model(&self) -> Box<dyn LanguageModel> {
+        self.provider.base_model()
+    }
+    fn complete(
+        &self,
+        request: ChatRequest,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let provider = self.provider.clone();
+        let cache = self.cache.clone();
+        let executor = self.executor.clone();
+        let model = provider.base_model().name();
+        let namespace = self.namespace.clone();
+        async move {
+            let key = CompletionCache::key(&namespace, &model, &serde_json::to_value(&request)?);
+
+            let cached = match cache.get(&key) {
+                Some(completion) => Some(completion),
+                None => {
+                    let cache = cache.clone();
+                    let key = key.clone();
+                    executor.spawn(async move { cache.load(&key) }).await
+                }
+            };
+            if let Some(completion) = cached {
+                return Ok(completion.into_events());
+            }
+
+            let events = provider.complete(request).await?;
+            let mut text = String::new();
+            let mut finish_reason = None;
+            Ok(events
+                .inspect(move |event| match event {
+                    Ok(CompletionEvent::Delta { text: delta, .. }) => text.push_str(delta),
+                    Ok(CompletionEvent::FinishReason(reason)) => {
+                        finish_reason = Some(reason.as_str().to_string());
+                    }
+                    Ok(CompletionEvent::Done) => {
+                        let completion = CachedCompletion {
+                            text: std::mem::take(&mut text),
+                            finish_reason: finish_reason.take(),
+                        };
+                        cache.insert(key.clone(), completion.clone());
+                        let cache = cache.clone();
+                        let key = key.clone();
+                        executor
+                            .spawn(async move { cache.save(&key, &completion).log_err() })
+                            .detach();
+                    }
+                    _ => {}
+                })
+                .boxed())
+        }
+        .boxed()
+    }
+    fn box_clone(&self) -> Box<dyn CompletionProvider> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{FakeCompletionProvider, FakeResponse};
+    use gpui::TestAppContext;
+
+    #[test]
+    fn test_cache_key() {
+        let key = CompletionCache::key(
+            "https://api.openai.com/v1",
+            "gpt-4",
+            &serde_json::json!({"model": "gpt-4", "messages": [{"role": "user", "content": "Hi"}]}),
+        );
+        assert_eq!(
+            key,
+            CompletionCache::key(
+                "https://api.openai.com/v1",
+                "gpt-4",
+                &serde_json::json!({"messages": [{"content": "Hi", "role": "user"}], "model": "gpt-4"}),
+            )
+        );
+        assert_ne!(
+            key,
+            CompletionCache::key(
+                "http://localhost:8000/v1",
+                "gpt-4",
+                &serde_json::json!({"model": "gpt-4", "messages": [{"role": "user", "content": "Hi"}]}),
+            )
+        );
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let completion = |text: &str| CachedCompletion {
+            text: text.into(),
+            finish_reason: None,
+        };
+        let cache = CompletionCache::new(2);
+        cache.insert("a".into(), completion("a"));
+        cache.insert("b".into(), completion("b"));
+        assert_eq!(cache.get("a"), Some(completion("a")));
+        cache.insert("c".into(), completion("c"));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(completion("a")));
+        assert_eq!(cache.get("c"), Some(completion("c")));
+    }
+
+    #[gpui::test]
+    async fn test_caching_completion_provider(cx: &mut TestAppContext) {
+        let directory = tempfile::tempdir().unwrap();
+        let fake = FakeCompletionProvider::new();
+        fake.respond_with(FakeResponse::events([
+            CompletionEvent::Delta {
+                role: Some(Role::Assistant),
+                text: "Hello".into(),
+            },
+            CompletionEvent::Delta {
+                role: None,
+                text: " world".into(),
+            },
+            CompletionEvent::FinishReason(FinishReason::Stop),
+        ]));
+        let cache = Arc::new(CompletionCache::new(8).with_directory(directory.path().into()));
+        let provider = CachingCompletionProvider::new(
+            Box::new(fake.clone()),
+            cache.clone(),
+            "test",
+            cx.executor(),
+        );
+        let request = ChatRequest {
+            model: "gpt-4".into(),
+            ..Default::default()
+        };
+        let complete = |provider: CachingCompletionProvider| {
+            let request = request.clone();
+            async move {
+                provider
+                    .complete(request)
+                    .await
+                    .unwrap()
+                    .collect::<Vec<_>>()
+                    .await
+                    .into_iter()
+                    .collect::<Result<Vec<_>>>()
+                    .unwrap()
+            }
+        };
+
+        complete(provider.clone()).await;
+        cx.run_until_parked();
+        let expected = [
+            CompletionEvent::Delta {
+                role: Some(Role::Assistant),
+                text: "Hello world".into(),
+            },
+            CompletionEvent::FinishReason(FinishReason::Stop),
+            CompletionEvent::Done,
+        ];
+        assert_eq!(complete(provider.clone()).await, expected);
+        assert_eq!(fake.requests().len(), 1);
+
+        // A new cache over the same directory reads the completion back from disk.
+        let provider = CachingCompletionProvider::new(
+            Box::new(fake.clone()),
+            Arc::new(CompletionCache::new(8).with_directory(directory.path().into())),
+            "test",
+            cx.executor(),
+        );
+        assert_eq!(complete(provider).await, expected);
+        assert_eq!(fake.requests().len(), 1);
+    }
+}
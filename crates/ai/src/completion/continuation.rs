@@ -0,0 +1,207 @@
+use anyhow::Result;
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    FutureExt, StreamExt,
+};
+use gpui::AppContext;
+
+use crate::{
+    auth::{CredentialProvider, ProviderCredential},
+    chat::{ChatRequest, RequestMessage, Role},
+    completion::{CompletionEvent, CompletionProvider, FinishReason},
+    models::LanguageModel,
+};
+
+/// Continues completions that stop because they reached the maximum number of
+/// tokens. The partial response is sent back as an assistant message and the
+/// follow-up is streamed as part of the same completion, so callers only see
+/// a longer response.
+#[derive(Clone)]
+pub struct ContinuingCompletionProvider {
+    provider: Box<dyn CompletionProvider>,
+    max_continuations: usize,
+}
+
+impl ContinuingCompletionProvider {
+    pub fn new(provider: Box<dyn CompletionProvider>, max_continuations: usize) -> Self {
+        Self {
+            provider,
+            max_continuations,
+        }
+    }
+}
+
+struct Continuation {
+    provider: Box<dyn CompletionProvider>,
+    request: ChatRequest,
+    events: BoxStream<'static, Result<CompletionEvent>>,
+    text: String,
+    continuations_left: usize,
+    continued: bool,
+    truncated: bool,
+}
+
+impl Continuation {
+    async fn next(mut self) -> Option<(Result<CompletionEvent>, Option<Self>)> {
+        loop {
+            match self.events.next().await {
+                Some(Ok(CompletionEvent::Delta { role, text })) => {
+                    self.text.push_str(&text);
+                    // Only the first response introduces the message.
+                    let role = if self.continued { None } else { role };
+                    return Some((Ok(CompletionEvent::Delta { role, text }), Some(self)));
+                }
+                Some(Ok(CompletionEvent::FinishReason(FinishReason::Length)))
+                    if self.continuations_left > 0 =>
+                {
+                    self.truncated = true;
+                }
+                Some(Ok(CompletionEvent::Done)) if self.truncated => {
+                    match self.continue_completion().await {
+                        Ok(events) => {
+                            self.events = events;
+                            self.continuations_left -= 1;
+                            self.continued = true;
+                            self.truncated = false;
+                        }
+                        Err(error) => return Some((Err(error), None)),
+                    }
+                }
+                Some(event) => return Some((event, Some(self))),
+                None => return None,
+            }
+        }
+    }
+
+    fn continue_completion(
+        &self,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let mut request = self.request.clone();
+        request.messages.push(RequestMessage {
+            role: Role::Assistant,
+            content: self.text.clone(),
+        });
+        self.provider.complete(request)
+    }
+}
+
+impl CredentialProvider for ContinuingCompletionProvider {
+    fn has_credentials(&self) -> bool {
+        self.provider.has_credentials()
+    }
+
+    fn retrieve_credentials(&self, cx: &mut AppContext) -> BoxFuture<ProviderCredential> {
+        self.provider.retrieve_credentials(cx)
+    }
+
+    fn save_credentials(
+        &self,
+        cx: &mut AppContext,
+        credential: ProviderCredential,
+    ) -> BoxFuture<()> {
+        self.provider.save_credentials(cx, credential)
+    }
+
+    fn delete_credentials(&self, cx: &mut AppContext) -> BoxFuture<()> {
+        self.provider.delete_credentials(cx)
+    }
+}
+
+impl CompletionProvider for ContinuingCompletionProvider {
+    fn base_model(&self) -> Box<dyn LanguageModel> {
+        self.provider.base_model()
+    }
+    fn complete(
+        &self,
+        request: ChatRequest,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let provider = self.provider.clone();
+        let continuations_left = self.max_continuations;
+        async move {
+            let events = provider.complete(request.clone()).await?;
+            let continuation = Continuation {
+                provider,
+                request,
+                events,
+                text: String::new(),
+                continuations_left,
+                continued: false,
+                truncated: false,
+            };
+            Ok(
+                stream::unfold(Some(continuation), |continuation| async move {
+                    continuation?.next().await
+                })
+                .boxed(),
+            )
+        }
+        .boxed()
+    }
+    fn box_clone(&self) -> Box<dyn CompletionProvider> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{FakeCompletionProvider, FakeResponse};
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_continue_truncated_completion() {
+        let delta = |role, text: &str| CompletionEvent::Delta {
+            role,
+            text: text.into(),
+        };
+        let fake = FakeCompletionProvider::new();
+        fake.respond_with(FakeResponse::events([
+            delta(Some(Role::Assistant), "fn main() {"),
+            CompletionEvent::FinishReason(FinishReason::Length),
+        ]));
+        fake.respond_with(FakeResponse::events([
+            delta(Some(Role::Assistant), "}"),
+            CompletionEvent::FinishReason(FinishReason::Length),
+        ]));
+        let provider = ContinuingCompletionProvider::new(Box::new(fake.clone()), 1);
+
+        let message = |role, content: &str| RequestMessage {
+            role,
+            content: content.into(),
+        };
+        let request = ChatRequest {
+            model: "gpt-4".into(),
+            messages: vec![message(Role::User, "Write main")],
+            ..Default::default()
+        };
+        let events = block_on(async {
+            provider
+                .complete(request)
+                .await
+                .unwrap()
+                .collect::<Vec<_>>()
+                .await
+        });
+        let events = events.into_iter().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(
+            events,
+            [
+                delta(Some(Role::Assistant), "fn main() {"),
+                delta(None, "}"),
+                CompletionEvent::FinishReason(FinishReason::Length),
+                CompletionEvent::Done,
+            ]
+        );
+
+        let requests = fake.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[1].messages,
+            [
+                message(Role::User, "Write main"),
+                message(Role::Assistant, "fn main() {"),
+            ]
+        );
+    }
+}
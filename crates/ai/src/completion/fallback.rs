@@ -0,0 +1,297 @@
+use anyhow::{anyhow, Result};
+use futures::{
+    future::{self, BoxFuture},
+    stream::{self, BoxStream},
+    FutureExt, StreamExt,
+};
+use gpui::AppContext;
+
+use crate::{
+    auth::{CredentialProvider, ProviderCredential},
+    chat::ChatRequest,
+    completion::{CompletionEvent, CompletionProvider},
+    models::LanguageModel,
+};
+
+/// Tries a list of providers in order, moving on to the next one when a provider
+/// fails before it produces any output. Requests are sent to fallbacks with their
+/// `model` replaced by the fallback's own model.
+///
+/// Every completion starts with [`CompletionEvent::AnsweredBy`] once a provider
+/// starts answering, so the UI can show where a response came from.
+#[derive(Clone)]
+pub struct FallbackCompletionProvider {
+    providers: Vec<NamedProvider>,
+}
+
+#[derive(Clone)]
+struct NamedProvider {
+    name: String,
+    provider: Box<dyn CompletionProvider>,
+}
+
+impl FallbackCompletionProvider {
+    pub fn new(name: impl Into<String>, provider: Box<dyn CompletionProvider>) -> Self {
+        Self {
+            providers: vec![NamedProvider {
+                name: name.into(),
+                provider,
+            }],
+        }
+    }
+
+    /// Adds a provider to try after the ones added before it.
+    pub fn fallback(
+        mut self,
+        name: impl Into<String>,
+        provider: Box<dyn CompletionProvider>,
+    ) -> Self {
+        self.providers.push(NamedProvider {
+            name: name.into(),
+            provider,
+        });
+        self
+    }
+
+    fn primary(&self) -> &dyn CompletionProvider {
+        self.providers[0].provider.as_ref()
+    }
+}
+
+struct FallbackCompletion {
+    providers: Vec<NamedProvider>,
+    request: ChatRequest,
+    /// The provider being tried, as an index into `providers`.
+    current: usize,
+    events: Option<BoxStream<'static, Result<CompletionEvent>>>,
+    answering: bool,
+    errors: Vec<String>,
+}
+
+impl FallbackCompletion {
+    async fn next(mut self) -> Option<(Result<CompletionEvent>, Option<Self>)> {
+        loop {
+            let Some(events) = self.events.as_mut() else {
+                match self.start_next_provider().await {
+                    Ok(()) => continue,
+                    Err(error) => return Some((Err(error), None)),
+                }
+            };
+            match events.next().await {
+                // Scheduling events come before the provider has done anything that
+                // could fail, so they're passed on right away.
+                Some(Ok(
+                    event @ (CompletionEvent::Queued { .. } | CompletionEvent::RateLimits(_)),
+                )) => return Some((Ok(event), Some(self))),
+                Some(Err(error)) if !self.answering => {
+                    self.give_up_on_current(error);
+                }
+                Some(event) => {
+                    if !self.answering {
+                        self.answering = true;
+                        let name = self.providers[self.current].name.clone();
+                        self.events = Some(
+                            stream::once(future::ready(event))
+                                .chain(self.events.take()?)
+                                .boxed(),
+                        );
+                        return Some((Ok(CompletionEvent::AnsweredBy(name)), Some(self)));
+                    }
+                    return Some((event, Some(self)));
+                }
+                None => return None,
+            }
+        }
+    }
+
+    /// Sends the request to the next provider that has credentials, failing once
+    /// every provider has been tried.
+    async fn start_next_provider(&mut self) -> Result<()> {
+        while self.current < self.providers.len() {
+            let NamedProvider { name, provider } = &self.providers[self.current];
+            if !provider.has_credentials() {
+                self.errors.push(format!("{name} has no credentials"));
+                self.current += 1;
+                continue;
+            }
+
+            let mut request = self.request.clone();
+            if self.current > 0 {
+                request.model = provider.base_model().name();
+            }
+            match provider.complete(request).await {
+                Ok(events) => {
+                    self.events = Some(events);
+                    return Ok(());
+                }
+                Err(error) => self.give_up_on_current(error),
+            }
+        }
+        Err(anyhow!(
+            "every provider failed to answer: {}",
+            self.errors.join("; ")
+        ))
+    }
+
+    fn give_up_on_current(&mut self, error: anyhow::Error) {
+        let name = &self.providers[self.current].name;
+        if self.current + 1 < self.providers.len() {
+            log::warn!("{name} failed, falling back to the next provider: {error}");
+        }
+        self.errors.push(format!("{name}: {error}"));
+        self.events = None;
+        self.current += 1;
+    }
+}
+
+impl CredentialProvider for FallbackCompletionProvider {
+    /// Whether any of the providers can be used.
+    fn has_credentials(&self) -> bool {
+        self.providers
+            .iter()
+            .any(|provider| provider.provider.has_credentials())
+    }
+
+    /// Retrieves every provider's credentials, returning the primary provider's.
+    fn retrieve_credentials(&self, cx: &mut AppContext) -> BoxFuture<ProviderCredential> {
+        let retrievals = self
+            .providers
+            .iter()
+            .map(|provider| provider.provider.retrieve_credentials(cx))
+            .collect::<Vec<_>>();
+        async move {
+            let mut credentials = future::join_all(retrievals).await;
+            credentials.swap_remove(0)
+        }
+        .boxed()
+    }
+
+    fn save_credentials(
+        &self,
+        cx: &mut AppContext,
+        credential: ProviderCredential,
+    ) -> BoxFuture<()> {
+        self.primary().save_credentials(cx, credential)
+    }
+
+    fn delete_credentials(&self, cx: &mut AppContext) -> BoxFuture<()> {
+        self.primary().delete_credentials(cx)
+    }
+}
+
+impl CompletionProvider for FallbackCompletionProvider {
+    fn base_model(&self) -> Box<dyn LanguageModel> {
+        self.primary().base_model()
+    }
+    fn complete(
+        &self,
+        request: ChatRequest,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let providers = self.providers.clone();
+        async move {
+            let mut completion = FallbackCompletion {
+                providers,
+                request,
+                current: 0,
+                events: None,
+                answering: false,
+                errors: Vec::new(),
+            };
+            completion.start_next_provider().await?;
+            Ok(stream::unfold(Some(completion), |completion| async move {
+                completion?.next().await
+            })
+            .boxed())
+        }
+        .boxed()
+    }
+    fn box_clone(&self) -> Box<dyn CompletionProvider> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chat::Role,
+        completion::CompletionError,
+        test::{FakeCompletionProvider, FakeResponse},
+    };
+    use futures::executor::block_on;
+    use std::time::Duration;
+
+    #[test]
+    fn test_fallback_after_error() {
+        let primary = FakeCompletionProvider::new();
+        primary.fail_next(CompletionError::Timeout {
+            provider: "OpenAI",
+            timeout: Duration::from_secs(30),
+        });
+        let fallback = FakeCompletionProvider::new();
+        fallback.respond_with(FakeResponse::events([CompletionEvent::Delta {
+            role: Some(Role::Assistant),
+            text: "Hi".into(),
+        }]));
+        let provider = FallbackCompletionProvider::new("OpenAI", Box::new(primary.clone()))
+            .fallback("Local", Box::new(fallback.clone()));
+
+        let request = ChatRequest {
+            model: "gpt-4".into(),
+            ..Default::default()
+        };
+        let events = block_on(async {
+            provider
+                .complete(request)
+                .await
+                .unwrap()
+                .collect::<Vec<_>>()
+                .await
+        });
+        assert_eq!(
+            events.into_iter().collect::<Result<Vec<_>>>().unwrap(),
+            [
+                CompletionEvent::AnsweredBy("Local".into()),
+                CompletionEvent::Delta {
+                    role: Some(Role::Assistant),
+                    text: "Hi".into(),
+                },
+                CompletionEvent::Done,
+            ]
+        );
+        assert_eq!(primary.requests().len(), 1);
+        assert_eq!(fallback.requests()[0].model, fallback.base_model().name());
+    }
+
+    #[test]
+    fn test_error_after_output_is_not_retried() {
+        let primary = FakeCompletionProvider::new();
+        primary.respond_with(
+            FakeResponse::new()
+                .event(CompletionEvent::Delta {
+                    role: Some(Role::Assistant),
+                    text: "Hi".into(),
+                })
+                .error("connection reset"),
+        );
+        let fallback = FakeCompletionProvider::new();
+        let provider = FallbackCompletionProvider::new("OpenAI", Box::new(primary.clone()))
+            .fallback("Local", Box::new(fallback.clone()));
+
+        let request = ChatRequest {
+            model: "gpt-4".into(),
+            ..Default::default()
+        };
+        let events = block_on(async {
+            provider
+                .complete(request)
+                .await
+                .unwrap()
+                .collect::<Vec<_>>()
+                .await
+        });
+        assert_eq!(events.len(), 3);
+        assert!(events[2].is_err());
+        assert!(fallback.requests().is_empty());
+    }
+}
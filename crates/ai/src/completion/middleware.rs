@@ -0,0 +1,192 @@
+use anyhow::Result;
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt};
+use gpui::AppContext;
+use std::sync::Arc;
+
+use crate::{
+    auth::{CredentialProvider, ProviderCredential},
+    chat::ChatRequest,
+    completion::{CompletionEvent, CompletionProvider},
+    models::LanguageModel,
+};
+
+/// A layer around a [`CompletionProvider`] that can rewrite requests before they're
+/// sent and post-process the events that come back. Layers are composed with
+/// [`MiddlewareCompletionProvider`], so concerns like redaction, logging and token
+/// budgeting don't need to be built into each provider.
+pub trait CompletionMiddleware: Send + Sync {
+    /// Rewrites a request before it's sent.
+    fn process_request(&self, request: ChatRequest) -> Result<ChatRequest> {
+        Ok(request)
+    }
+
+    /// Post-processes the events of a completion. `request` is the request as it
+    /// was sent, after every layer rewrote it.
+    fn process_events(
+        &self,
+        _request: &ChatRequest,
+        events: BoxStream<'static, Result<CompletionEvent>>,
+    ) -> BoxStream<'static, Result<CompletionEvent>> {
+        events
+    }
+}
+
+/// Runs a provider's completions through a stack of [`CompletionMiddleware`].
+/// Requests pass through the layers in the order they were added, and events pass
+/// through them in reverse, so the first layer sees the final events.
+#[derive(Clone)]
+pub struct MiddlewareCompletionProvider {
+    provider: Box<dyn CompletionProvider>,
+    layers: Vec<Arc<dyn CompletionMiddleware>>,
+}
+
+impl MiddlewareCompletionProvider {
+    pub fn new(provider: Box<dyn CompletionProvider>) -> Self {
+        Self {
+            provider,
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn layer(mut self, layer: impl CompletionMiddleware + 'static) -> Self {
+        self.layers.push(Arc::new(layer));
+        self
+    }
+}
+
+impl CredentialProvider for MiddlewareCompletionProvider {
+    fn has_credentials(&self) -> bool {
+        self.provider.has_credentials()
+    }
+
+    fn retrieve_credentials(&self, cx: &mut AppContext) -> BoxFuture<ProviderCredential> {
+        self.provider.retrieve_credentials(cx)
+    }
+
+    fn save_credentials(
+        &self,
+        cx: &mut AppContext,
+        credential: ProviderCredential,
+    ) -> BoxFuture<()> {
+        self.provider.save_credentials(cx, credential)
+    }
+
+    fn delete_credentials(&self, cx: &mut AppContext) -> BoxFuture<()> {
+        self.provider.delete_credentials(cx)
+    }
+}
+
+impl CompletionProvider for MiddlewareCompletionProvider {
+    fn base_model(&self) -> Box<dyn LanguageModel> {
+        self.provider.base_model()
+    }
+    fn complete(
+        &self,
+        request: ChatRequest,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let request = self
+            .layers
+            .iter()
+            .try_fold(request, |request, layer| layer.process_request(request));
+        let request = match request {
+            Ok(request) => request,
+            Err(error) => return async move { Err(error) }.boxed(),
+        };
+
+        let response = self.provider.complete(request.clone());
+        let layers = self.layers.clone();
+        async move {
+            let mut events = response.await?;
+            for layer in layers.iter().rev() {
+                events = layer.process_events(&request, events);
+            }
+            Ok(events)
+        }
+        .boxed()
+    }
+    fn box_clone(&self) -> Box<dyn CompletionProvider> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{FakeCompletionProvider, FakeResponse};
+    use futures::{executor::block_on, StreamExt};
+
+    struct SetTemperature(f32);
+
+    impl CompletionMiddleware for SetTemperature {
+        fn process_request(&self, mut request: ChatRequest) -> Result<ChatRequest> {
+            request.temperature = self.0;
+            Ok(request)
+        }
+    }
+
+    struct AppendToDeltas(&'static str);
+
+    impl CompletionMiddleware for AppendToDeltas {
+        fn process_events(
+            &self,
+            _request: &ChatRequest,
+            events: BoxStream<'static, Result<CompletionEvent>>,
+        ) -> BoxStream<'static, Result<CompletionEvent>> {
+            let suffix = self.0;
+            events
+                .map(move |event| match event {
+                    Ok(CompletionEvent::Delta { role, text }) => Ok(CompletionEvent::Delta {
+                        role,
+                        text: text + suffix,
+                    }),
+                    event => event,
+                })
+                .boxed()
+        }
+    }
+
+    #[test]
+    fn test_middleware_order() {
+        let fake = FakeCompletionProvider::new();
+        fake.respond_with(FakeResponse::events([CompletionEvent::Delta {
+            role: None,
+            text: "Hi".into(),
+        }]));
+        let provider = MiddlewareCompletionProvider::new(Box::new(fake.clone()))
+            .layer(SetTemperature(0.5))
+            .layer(AppendToDeltas("!"))
+            .layer(SetTemperature(0.2))
+            .layer(AppendToDeltas("?"));
+
+        let request = ChatRequest {
+            model: "gpt-4".into(),
+            ..Default::default()
+        };
+        let events = block_on(async {
+            provider
+                .complete(request)
+                .await
+                .unwrap()
+                .collect::<Vec<_>>()
+                .await
+        });
+        assert_eq!(
+            events.into_iter().collect::<Result<Vec<_>>>().unwrap(),
+            [
+                CompletionEvent::Delta {
+                    role: None,
+                    text: "Hi?!".into()
+                },
+                CompletionEvent::Done
+            ]
+        );
+        assert_eq!(
+            fake.requests(),
+            [ChatRequest {
+                model: "gpt-4".into(),
+                temperature: 0.2,
+                ..Default::default()
+            }]
+        );
+    }
+}
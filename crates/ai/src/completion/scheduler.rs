@@ -0,0 +1,455 @@
+use anyhow::Result;
+use futures::{
+    channel::mpsc,
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    FutureExt, StreamExt,
+};
+use gpui::{AppContext, BackgroundExecutor};
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    auth::{CredentialProvider, ProviderCredential},
+    chat::ChatRequest,
+    completion::{CompletionError, CompletionEvent, CompletionProvider, RateLimitStatus},
+    models::LanguageModel,
+};
+
+/// Caps how many requests can be in flight at once. Requests over the limit wait
+/// in a queue and start in the order they were made.
+///
+/// The scheduler can also be paused when the provider reports that a rate limit
+/// has been used up, so that requests wait for it to reset instead of failing.
+pub struct RequestScheduler {
+    state: Mutex<SchedulerState>,
+}
+
+struct SchedulerState {
+    max_concurrent: usize,
+    running: usize,
+    queue: VecDeque<Waiter>,
+    next_id: usize,
+    paused_until: Option<Instant>,
+}
+
+struct Waiter {
+    id: usize,
+    positions: mpsc::UnboundedSender<usize>,
+}
+
+impl RequestScheduler {
+    /// Creates a scheduler that runs up to `max_concurrent` requests at once. A limit
+    /// of zero is treated as one.
+    pub fn new(max_concurrent: usize) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(SchedulerState {
+                max_concurrent: max_concurrent.max(1),
+                running: 0,
+                queue: VecDeque::new(),
+                next_id: 0,
+                paused_until: None,
+            }),
+        })
+    }
+
+    pub fn max_concurrent(&self) -> usize {
+        self.state.lock().max_concurrent
+    }
+
+    /// Changes the limit. Raising it starts queued requests right away, while
+    /// lowering it lets requests that are already running finish.
+    pub fn set_max_concurrent(&self, max_concurrent: usize) {
+        let mut state = self.state.lock();
+        state.max_concurrent = max_concurrent.max(1);
+        state.start_waiters();
+    }
+
+    /// The number of requests waiting for a slot.
+    pub fn queue_len(&self) -> usize {
+        self.state.lock().queue.len()
+    }
+
+    /// Holds off requests that haven't been sent yet for `duration`. Overlapping
+    /// pauses end when the last one does.
+    pub fn pause_for(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut state = self.state.lock();
+        if state
+            .paused_until
+            .map_or(true, |paused_until| paused_until < until)
+        {
+            log::info!("pausing requests for {duration:?} to respect rate limits");
+            state.paused_until = Some(until);
+        }
+    }
+
+    /// How long requests are paused for, if at all.
+    pub fn pause_remaining(&self) -> Option<Duration> {
+        let mut state = self.state.lock();
+        let remaining = state
+            .paused_until?
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero());
+        if remaining.is_none() {
+            state.paused_until = None;
+        }
+        remaining
+    }
+
+    /// Pauses requests if the provider reported that a limit has been used up.
+    pub fn observe_rate_limits(&self, status: &RateLimitStatus) {
+        if let Some(duration) = status.exhausted_for() {
+            self.pause_for(duration);
+        }
+    }
+
+    /// Pauses requests if the provider rejected one for exceeding its rate limit.
+    pub fn observe_error(&self, error: &anyhow::Error) {
+        if let Some(CompletionError::RateLimited {
+            retry_after: Some(retry_after),
+            ..
+        }) = error.downcast_ref::<CompletionError>()
+        {
+            self.pause_for(*retry_after);
+        }
+    }
+
+    /// Joins the queue. The returned request reports its position until it gets a
+    /// slot, which is held until the [`RequestPermit`] is dropped.
+    pub fn enqueue(self: &Arc<Self>) -> QueuedRequest {
+        let (tx, rx) = mpsc::unbounded();
+        let mut state = self.state.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.queue.push_back(Waiter { id, positions: tx });
+        state.start_waiters();
+        QueuedRequest {
+            id,
+            scheduler: self.clone(),
+            positions: rx,
+            started: false,
+        }
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock();
+        state.running -= 1;
+        state.start_waiters();
+    }
+}
+
+impl SchedulerState {
+    /// Starts as many waiters as there are free slots, and tells the rest where
+    /// they now are in the queue.
+    fn start_waiters(&mut self) {
+        while self.running < self.max_concurrent {
+            let Some(waiter) = self.queue.pop_front() else {
+                break;
+            };
+            if waiter.positions.unbounded_send(0).is_ok() {
+                self.running += 1;
+            }
+        }
+        self.queue.retain(|waiter| !waiter.positions.is_closed());
+        for (ix, waiter) in self.queue.iter().enumerate() {
+            waiter.positions.unbounded_send(ix + 1).ok();
+        }
+    }
+}
+
+/// A request waiting in a [`RequestScheduler`]'s queue.
+pub struct QueuedRequest {
+    id: usize,
+    scheduler: Arc<RequestScheduler>,
+    positions: mpsc::UnboundedReceiver<usize>,
+    started: bool,
+}
+
+/// What happened while waiting in the queue.
+pub enum QueueUpdate {
+    /// The request moved in the queue. The next request to start is at position 1.
+    Position(usize),
+    Started(RequestPermit),
+}
+
+impl QueuedRequest {
+    /// Waits for the request to move in the queue or to be allowed to start.
+    pub async fn next_update(&mut self) -> QueueUpdate {
+        // The scheduler only stops sending once we've dropped the receiver.
+        let mut position = self.positions.next().await.unwrap_or(0);
+        // Only report the most recent position.
+        while let Ok(Some(next)) = self.positions.try_next() {
+            position = next;
+        }
+        if position == 0 {
+            self.started = true;
+            QueueUpdate::Started(RequestPermit {
+                scheduler: self.scheduler.clone(),
+            })
+        } else {
+            QueueUpdate::Position(position)
+        }
+    }
+}
+
+impl Drop for QueuedRequest {
+    fn drop(&mut self) {
+        if self.started {
+            return;
+        }
+        let mut state = self.scheduler.state.lock();
+        if let Some(ix) = state.queue.iter().position(|waiter| waiter.id == self.id) {
+            state.queue.remove(ix);
+        } else {
+            // We were given a slot but never used it.
+            state.running -= 1;
+        }
+        state.start_waiters();
+    }
+}
+
+/// A slot in a [`RequestScheduler`], released when dropped.
+pub struct RequestPermit {
+    scheduler: Arc<RequestScheduler>,
+}
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+/// Sends a provider's requests through a [`RequestScheduler`]. The stream is returned
+/// right away and starts with [`CompletionEvent::Queued`] events while the request
+/// waits, so errors from the provider arrive in the stream rather than from the
+/// returned future. The slot is held until the stream is dropped.
+///
+/// Rate limits the provider reports with [`CompletionEvent::RateLimits`], and rate
+/// limit errors, pause the scheduler.
+#[derive(Clone)]
+pub struct ScheduledCompletionProvider {
+    provider: Box<dyn CompletionProvider>,
+    scheduler: Arc<RequestScheduler>,
+    executor: BackgroundExecutor,
+}
+
+impl ScheduledCompletionProvider {
+    pub fn new(
+        provider: Box<dyn CompletionProvider>,
+        scheduler: Arc<RequestScheduler>,
+        executor: BackgroundExecutor,
+    ) -> Self {
+        Self {
+            provider,
+            scheduler,
+            executor,
+        }
+    }
+}
+
+enum ScheduledCompletion {
+    Queued {
+        provider: Box<dyn CompletionProvider>,
+        scheduler: Arc<RequestScheduler>,
+        executor: BackgroundExecutor,
+        request: ChatRequest,
+        queued: QueuedRequest,
+    },
+    Running {
+        events: BoxStream<'static, Result<CompletionEvent>>,
+        scheduler: Arc<RequestScheduler>,
+        _permit: RequestPermit,
+    },
+}
+
+impl ScheduledCompletion {
+    async fn next(mut self) -> Option<(Result<CompletionEvent>, Self)> {
+        loop {
+            match self {
+                ScheduledCompletion::Queued {
+                    provider,
+                    scheduler,
+                    executor,
+                    request,
+                    mut queued,
+                } => match queued.next_update().await {
+                    QueueUpdate::Position(position) => {
+                        return Some((
+                            Ok(CompletionEvent::Queued { position }),
+                            ScheduledCompletion::Queued {
+                                provider,
+                                scheduler,
+                                executor,
+                                request,
+                                queued,
+                            },
+                        ));
+                    }
+                    QueueUpdate::Started(permit) => {
+                        // Another request may extend the pause while we wait.
+                        while let Some(remaining) = scheduler.pause_remaining() {
+                            executor.timer(remaining).await;
+                        }
+                        let events = match provider.complete(request).await {
+                            Ok(events) => events,
+                            Err(error) => stream::once(async { Err(error) }).boxed(),
+                        };
+                        self = ScheduledCompletion::Running {
+                            events,
+                            scheduler,
+                            _permit: permit,
+                        };
+                    }
+                },
+                ScheduledCompletion::Running {
+                    mut events,
+                    scheduler,
+                    _permit,
+                } => {
+                    let event = events.next().await?;
+                    match &event {
+                        Ok(CompletionEvent::RateLimits(status)) => {
+                            scheduler.observe_rate_limits(status)
+                        }
+                        Err(error) => scheduler.observe_error(error),
+                        _ => {}
+                    }
+                    return Some((
+                        event,
+                        ScheduledCompletion::Running {
+                            events,
+                            scheduler,
+                            _permit,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl CredentialProvider for ScheduledCompletionProvider {
+    fn has_credentials(&self) -> bool {
+        self.provider.has_credentials()
+    }
+
+    fn retrieve_credentials(&self, cx: &mut AppContext) -> BoxFuture<ProviderCredential> {
+        self.provider.retrieve_credentials(cx)
+    }
+
+    fn save_credentials(
+        &self,
+        cx: &mut AppContext,
+        credential: ProviderCredential,
+    ) -> BoxFuture<()> {
+        self.provider.save_credentials(cx, credential)
+    }
+
+    fn delete_credentials(&self, cx: &mut AppContext) -> BoxFuture<()> {
+        self.provider.delete_credentials(cx)
+    }
+}
+
+impl CompletionProvider for ScheduledCompletionProvider {
+    fn base_model(&self) -> Box<dyn LanguageModel> {
+        self.provider.base_model()
+    }
+    fn complete(
+        &self,
+        request: ChatRequest,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let provider = self.provider.clone();
+        let scheduler = self.scheduler.clone();
+        let executor = self.executor.clone();
+        async move {
+            let completion = ScheduledCompletion::Queued {
+                provider,
+                queued: scheduler.enqueue(),
+                scheduler,
+                executor,
+                request,
+            };
+            Ok(stream::unfold(completion, |completion| completion.next()).boxed())
+        }
+        .boxed()
+    }
+    fn box_clone(&self) -> Box<dyn CompletionProvider> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn position(update: QueueUpdate) -> Option<usize> {
+        match update {
+            QueueUpdate::Position(position) => Some(position),
+            QueueUpdate::Started(_) => None,
+        }
+    }
+
+    #[test]
+    fn test_request_scheduler() {
+        let scheduler = RequestScheduler::new(2);
+        let mut first = scheduler.enqueue();
+        let mut second = scheduler.enqueue();
+        let mut third = scheduler.enqueue();
+        let mut fourth = scheduler.enqueue();
+
+        let first_permit = block_on(first.next_update());
+        assert_eq!(position(block_on(third.next_update())), Some(1));
+        assert_eq!(position(block_on(fourth.next_update())), Some(2));
+        assert_eq!(scheduler.queue_len(), 2);
+
+        // Leaving the queue moves everyone behind you forward.
+        drop(third);
+        assert_eq!(position(block_on(fourth.next_update())), Some(1));
+
+        let second_permit = block_on(second.next_update());
+        drop(first_permit);
+        assert_eq!(position(block_on(fourth.next_update())), None);
+        assert_eq!(scheduler.queue_len(), 0);
+        drop(second_permit);
+
+        scheduler.set_max_concurrent(0);
+        assert_eq!(scheduler.max_concurrent(), 1);
+    }
+
+    #[test]
+    fn test_pause_for_rate_limits() {
+        let scheduler = RequestScheduler::new(1);
+        assert_eq!(scheduler.pause_remaining(), None);
+
+        scheduler.observe_rate_limits(&RateLimitStatus {
+            remaining_requests: Some(10),
+            reset_requests: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+        assert_eq!(scheduler.pause_remaining(), None);
+
+        scheduler.observe_rate_limits(&RateLimitStatus {
+            remaining_tokens: Some(0),
+            reset_tokens: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+        let remaining = scheduler.pause_remaining().unwrap();
+        assert!(remaining > Duration::from_secs(50));
+
+        // A shorter pause doesn't cut the current one short.
+        scheduler.observe_error(
+            &CompletionError::RateLimited {
+                provider: "OpenAI",
+                retry_after: Some(Duration::from_secs(1)),
+            }
+            .into(),
+        );
+        assert!(scheduler.pause_remaining().unwrap() > Duration::from_secs(50));
+    }
+}
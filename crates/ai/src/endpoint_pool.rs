@@ -0,0 +1,346 @@
+use anyhow::Result;
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
+use gpui::AppContext;
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    auth::{CredentialProvider, ProviderCredential},
+    chat::ChatRequest,
+    completion::{CompletionError, CompletionEvent, CompletionProvider},
+    models::LanguageModel,
+};
+
+/// How long an endpoint that failed to respond is skipped before it's tried again.
+const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// How an [`EndpointPool`] picks the endpoint for each request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RoutingStrategy {
+    /// Takes turns between the endpoints.
+    #[default]
+    RoundRobin,
+    /// Picks the endpoint with the fewest requests in flight.
+    LeastLoaded,
+}
+
+/// Spreads requests across several endpoints serving the same model, such as
+/// two machines running the same local server. Endpoints that fail to respond
+/// are skipped for a while, and a request that can't reach its endpoint is sent
+/// to the next one.
+#[derive(Clone)]
+pub struct EndpointPool {
+    endpoints: Arc<[Endpoint]>,
+    strategy: RoutingStrategy,
+    state: Arc<Mutex<PoolState>>,
+}
+
+struct Endpoint {
+    url: String,
+    provider: Box<dyn CompletionProvider>,
+}
+
+#[derive(Default)]
+struct PoolState {
+    next: usize,
+    endpoints: Vec<EndpointState>,
+}
+
+#[derive(Clone, Default)]
+struct EndpointState {
+    in_flight: usize,
+    unhealthy_until: Option<Instant>,
+}
+
+impl EndpointState {
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.unhealthy_until.map_or(true, |until| until <= now)
+    }
+}
+
+impl EndpointPool {
+    /// Creates a pool of `(url, provider)` pairs. There must be at least one.
+    pub fn new(
+        endpoints: impl IntoIterator<Item = (String, Box<dyn CompletionProvider>)>,
+        strategy: RoutingStrategy,
+    ) -> Self {
+        let endpoints = endpoints
+            .into_iter()
+            .map(|(url, provider)| Endpoint { url, provider })
+            .collect::<Arc<[_]>>();
+        assert!(!endpoints.is_empty(), "an endpoint pool needs an endpoint");
+        let state = PoolState {
+            next: 0,
+            endpoints: vec![EndpointState::default(); endpoints.len()],
+        };
+        Self {
+            endpoints,
+            strategy,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        self.endpoints.iter().map(|endpoint| endpoint.url.as_str())
+    }
+
+    /// Marks the endpoint at `url` as reachable or not, e.g. after a health check.
+    /// Unreachable endpoints are skipped until they're marked healthy again or a
+    /// cooldown passes.
+    pub fn set_healthy(&self, url: &str, healthy: bool) {
+        if let Some(ix) = self
+            .endpoints
+            .iter()
+            .position(|endpoint| endpoint.url == url)
+        {
+            self.state.lock().endpoints[ix].unhealthy_until =
+                (!healthy).then(|| Instant::now() + UNHEALTHY_COOLDOWN);
+        }
+    }
+
+    pub fn is_healthy(&self, url: &str) -> bool {
+        let now = Instant::now();
+        let state = self.state.lock();
+        self.endpoints
+            .iter()
+            .zip(&state.endpoints)
+            .any(|(endpoint, state)| endpoint.url == url && state.is_healthy(now))
+    }
+
+    /// Picks an endpoint that hasn't been tried yet and counts the request against
+    /// it. Unhealthy endpoints are only picked once every healthy one was tried.
+    fn pick(&self, tried: &[usize]) -> Option<usize> {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+        let candidates = (0..self.endpoints.len())
+            .map(|offset| (state.next + offset) % self.endpoints.len())
+            .filter(|ix| !tried.contains(ix))
+            .collect::<Vec<_>>();
+        let healthy = candidates
+            .iter()
+            .copied()
+            .filter(|ix| state.endpoints[*ix].is_healthy(now))
+            .collect::<Vec<_>>();
+        let candidates = if healthy.is_empty() {
+            candidates
+        } else {
+            healthy
+        };
+
+        let ix = match self.strategy {
+            RoutingStrategy::RoundRobin => candidates.first().copied(),
+            RoutingStrategy::LeastLoaded => candidates
+                .iter()
+                .copied()
+                .min_by_key(|ix| state.endpoints[*ix].in_flight),
+        }?;
+        state.next = (ix + 1) % self.endpoints.len();
+        state.endpoints[ix].in_flight += 1;
+        Some(ix)
+    }
+
+    fn finish(&self, ix: usize) {
+        let mut state = self.state.lock();
+        state.endpoints[ix].in_flight = state.endpoints[ix].in_flight.saturating_sub(1);
+    }
+
+    /// Skips the endpoint for a while if `error` means it couldn't be reached.
+    fn observe_error(&self, ix: usize, error: &anyhow::Error) {
+        if let Some(
+            CompletionError::Network { .. }
+            | CompletionError::Timeout { .. }
+            | CompletionError::Server { .. },
+        ) = error.downcast_ref::<CompletionError>()
+        {
+            log::warn!("{} is unavailable: {error}", self.endpoints[ix].url);
+            self.state.lock().endpoints[ix].unhealthy_until =
+                Some(Instant::now() + UNHEALTHY_COOLDOWN);
+        }
+    }
+}
+
+/// Counts a request against its endpoint until the completion is dropped.
+struct InFlight {
+    pool: EndpointPool,
+    ix: usize,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.pool.finish(self.ix);
+    }
+}
+
+impl CredentialProvider for EndpointPool {
+    fn has_credentials(&self) -> bool {
+        self.endpoints
+            .iter()
+            .any(|endpoint| endpoint.provider.has_credentials())
+    }
+
+    fn retrieve_credentials(&self, cx: &mut AppContext) -> BoxFuture<ProviderCredential> {
+        let retrievals = self
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.provider.retrieve_credentials(cx))
+            .collect::<Vec<_>>();
+        async move {
+            let mut credentials = futures::future::join_all(retrievals).await;
+            credentials.swap_remove(0)
+        }
+        .boxed()
+    }
+
+    fn save_credentials(
+        &self,
+        cx: &mut AppContext,
+        credential: ProviderCredential,
+    ) -> BoxFuture<()> {
+        let saves = self
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.provider.save_credentials(cx, credential.clone()))
+            .collect::<Vec<_>>();
+        futures::future::join_all(saves).map(|_| ()).boxed()
+    }
+
+    fn delete_credentials(&self, cx: &mut AppContext) -> BoxFuture<()> {
+        let deletions = self
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.provider.delete_credentials(cx))
+            .collect::<Vec<_>>();
+        futures::future::join_all(deletions).map(|_| ()).boxed()
+    }
+}
+
+impl CompletionProvider for EndpointPool {
+    fn base_model(&self) -> Box<dyn LanguageModel> {
+        self.endpoints[0].provider.base_model()
+    }
+    fn complete(
+        &self,
+        request: ChatRequest,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let pool = self.clone();
+        async move {
+            let mut tried = Vec::new();
+            loop {
+                let ix = pool
+                    .pick(&tried)
+                    .expect("the pool has at least one endpoint");
+                tried.push(ix);
+                let in_flight = InFlight {
+                    pool: pool.clone(),
+                    ix,
+                };
+                let response = pool.endpoints[ix].provider.complete(request.clone()).await;
+                match response {
+                    Ok(events) => {
+                        return Ok(events
+                            .inspect(move |event| {
+                                if let Err(error) = event {
+                                    in_flight.pool.observe_error(in_flight.ix, error);
+                                }
+                            })
+                            .boxed());
+                    }
+                    Err(error) => {
+                        pool.observe_error(ix, &error);
+                        if tried.len() == pool.endpoints.len() {
+                            return Err(error);
+                        }
+                    }
+                }
+            }
+        }
+        .boxed()
+    }
+    fn box_clone(&self) -> Box<dyn CompletionProvider> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{FakeCompletionProvider, FakeResponse};
+    use futures::executor::block_on;
+
+    fn pool(strategy: RoutingStrategy) -> (EndpointPool, Vec<FakeCompletionProvider>) {
+        let providers = vec![FakeCompletionProvider::new(), FakeCompletionProvider::new()];
+        let pool = EndpointPool::new(
+            providers.iter().enumerate().map(|(ix, provider)| {
+                (
+                    format!("http://192.168.0.{ix}:8000/v1"),
+                    Box::new(provider.clone()) as Box<dyn CompletionProvider>,
+                )
+            }),
+            strategy,
+        );
+        (pool, providers)
+    }
+
+    fn complete(pool: &EndpointPool) -> Result<BoxStream<'static, Result<CompletionEvent>>> {
+        let request = ChatRequest {
+            model: "llama".into(),
+            ..Default::default()
+        };
+        block_on(pool.complete(request))
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let (pool, providers) = pool(RoutingStrategy::RoundRobin);
+        for provider in &providers {
+            provider.respond_with(FakeResponse::new());
+            provider.respond_with(FakeResponse::new());
+        }
+        for _ in 0..4 {
+            complete(&pool).unwrap();
+        }
+        assert_eq!(providers[0].requests().len(), 2);
+        assert_eq!(providers[1].requests().len(), 2);
+    }
+
+    #[test]
+    fn test_least_loaded() {
+        let (pool, providers) = pool(RoutingStrategy::LeastLoaded);
+        for provider in &providers {
+            provider.respond_with(FakeResponse::new());
+            provider.respond_with(FakeResponse::new());
+        }
+        // While the first completion is still streaming, requests go to the idle
+        // endpoint.
+        let _first = complete(&pool).unwrap();
+        drop(complete(&pool).unwrap());
+        drop(complete(&pool).unwrap());
+        assert_eq!(providers[0].requests().len(), 1);
+        assert_eq!(providers[1].requests().len(), 2);
+    }
+
+    #[test]
+    fn test_skip_unreachable_endpoint() {
+        let (pool, providers) = pool(RoutingStrategy::RoundRobin);
+        providers[0].fail_next(CompletionError::Timeout {
+            provider: "vLLM",
+            timeout: Duration::from_secs(30),
+        });
+        providers[1].respond_with(FakeResponse::new());
+        providers[1].respond_with(FakeResponse::new());
+
+        complete(&pool).unwrap();
+        assert!(!pool.is_healthy("http://192.168.0.0:8000/v1"));
+
+        // The unreachable endpoint is skipped until it's healthy again.
+        complete(&pool).unwrap();
+        assert_eq!(providers[0].requests().len(), 1);
+        assert_eq!(providers[1].requests().len(), 2);
+
+        pool.set_healthy("http://192.168.0.0:8000/v1", true);
+        assert!(pool.is_healthy("http://192.168.0.0:8000/v1"));
+    }
+}
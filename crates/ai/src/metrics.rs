@@ -0,0 +1,311 @@
+use anyhow::Result;
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
+use gpui::AppContext;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    auth::{CredentialProvider, ProviderCredential},
+    chat::ChatRequest,
+    completion::{CompletionEvent, CompletionProvider},
+    models::LanguageModel,
+};
+
+/// How many recent completions per model the latency and throughput figures are
+/// computed from.
+const MAX_SAMPLES: usize = 100;
+
+lazy_static! {
+    static ref GLOBAL_METRICS: Arc<CompletionMetrics> = Arc::new(CompletionMetrics::default());
+}
+
+/// Latency, throughput and error counts of completion requests, per model.
+#[derive(Default)]
+pub struct CompletionMetrics {
+    models: Mutex<BTreeMap<String, Samples>>,
+}
+
+#[derive(Default)]
+struct Samples {
+    requests: usize,
+    errors: usize,
+    time_to_first_token: VecDeque<Duration>,
+    tokens_per_second: VecDeque<f32>,
+}
+
+fn push_sample<T>(samples: &mut VecDeque<T>, sample: T) {
+    if samples.len() == MAX_SAMPLES {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+fn median<T: Copy + PartialOrd>(samples: &VecDeque<T>) -> Option<T> {
+    let mut samples = samples.iter().copied().collect::<Vec<_>>();
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    samples.get(samples.len() / 2).copied()
+}
+
+/// A summary of the completions made with one model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelMetrics {
+    pub model: String,
+    pub requests: usize,
+    pub errors: usize,
+    /// How long the median request took to produce its first token.
+    pub time_to_first_token: Option<Duration>,
+    /// How quickly the median response was generated once it started.
+    pub tokens_per_second: Option<f32>,
+}
+
+impl ModelMetrics {
+    pub fn error_rate(&self) -> f32 {
+        if self.requests == 0 {
+            0.
+        } else {
+            self.errors as f32 / self.requests as f32
+        }
+    }
+}
+
+impl CompletionMetrics {
+    /// The metrics every [`MeasuredCompletionProvider`] in the app records to,
+    /// unless it was given its own.
+    pub fn global() -> Arc<Self> {
+        GLOBAL_METRICS.clone()
+    }
+
+    /// Summarizes the completions made so far, ordered by model name.
+    pub fn summary(&self) -> Vec<ModelMetrics> {
+        self.models
+            .lock()
+            .iter()
+            .map(|(model, samples)| ModelMetrics {
+                model: model.clone(),
+                requests: samples.requests,
+                errors: samples.errors,
+                time_to_first_token: median(&samples.time_to_first_token),
+                tokens_per_second: median(&samples.tokens_per_second),
+            })
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        self.models.lock().clear();
+    }
+
+    fn record_success(
+        &self,
+        model: &str,
+        time_to_first_token: Option<Duration>,
+        tokens_per_second: Option<f32>,
+    ) {
+        let mut models = self.models.lock();
+        let samples = models.entry(model.to_string()).or_default();
+        samples.requests += 1;
+        if let Some(time_to_first_token) = time_to_first_token {
+            push_sample(&mut samples.time_to_first_token, time_to_first_token);
+        }
+        if let Some(tokens_per_second) = tokens_per_second {
+            push_sample(&mut samples.tokens_per_second, tokens_per_second);
+        }
+    }
+
+    fn record_error(&self, model: &str) {
+        let mut models = self.models.lock();
+        let samples = models.entry(model.to_string()).or_default();
+        samples.requests += 1;
+        samples.errors += 1;
+    }
+}
+
+/// Records the latency, throughput and errors of a provider's completions in a
+/// [`CompletionMetrics`], keyed by the model each request asked for.
+#[derive(Clone)]
+pub struct MeasuredCompletionProvider {
+    provider: Box<dyn CompletionProvider>,
+    metrics: Arc<CompletionMetrics>,
+}
+
+impl MeasuredCompletionProvider {
+    pub fn new(provider: Box<dyn CompletionProvider>, metrics: Arc<CompletionMetrics>) -> Self {
+        Self { provider, metrics }
+    }
+}
+
+/// Tracks one completion as its events arrive.
+struct Measurement {
+    metrics: Arc<CompletionMetrics>,
+    model_name: String,
+    started_at: Instant,
+    first_token_at: Option<Instant>,
+    /// Streaming APIs send about one token per delta, so this stands in for the
+    /// token count when the provider doesn't report usage.
+    deltas: usize,
+    completion_tokens: Option<u32>,
+    finished: bool,
+}
+
+impl Measurement {
+    fn observe(&mut self, event: &Result<CompletionEvent>) {
+        if self.finished {
+            return;
+        }
+        match event {
+            Ok(CompletionEvent::Delta { .. } | CompletionEvent::FunctionCallDelta { .. }) => {
+                self.first_token_at.get_or_insert_with(Instant::now);
+                self.deltas += 1;
+            }
+            Ok(CompletionEvent::Usage(usage)) => {
+                self.completion_tokens = Some(usage.completion_tokens);
+            }
+            Ok(CompletionEvent::Done) => {
+                self.finished = true;
+                let time_to_first_token = self
+                    .first_token_at
+                    .map(|first_token_at| first_token_at - self.started_at);
+                self.metrics.record_success(
+                    &self.model_name,
+                    time_to_first_token,
+                    self.tokens_per_second(),
+                );
+            }
+            Ok(_) => {}
+            Err(_) => {
+                self.finished = true;
+                self.metrics.record_error(&self.model_name);
+            }
+        }
+    }
+
+    fn tokens_per_second(&self) -> Option<f32> {
+        let elapsed = self.first_token_at?.elapsed().as_secs_f32();
+        let tokens = self
+            .completion_tokens
+            .map_or(self.deltas, |tokens| tokens as usize);
+        (elapsed > 0. && tokens > 1).then(|| tokens as f32 / elapsed)
+    }
+}
+
+impl CredentialProvider for MeasuredCompletionProvider {
+    fn has_credentials(&self) -> bool {
+        self.provider.has_credentials()
+    }
+
+    fn retrieve_credentials(&self, cx: &mut AppContext) -> BoxFuture<ProviderCredential> {
+        self.provider.retrieve_credentials(cx)
+    }
+
+    fn save_credentials(
+        &self,
+        cx: &mut AppContext,
+        credential: ProviderCredential,
+    ) -> BoxFuture<()> {
+        self.provider.save_credentials(cx, credential)
+    }
+
+    fn delete_credentials(&self, cx: &mut AppContext) -> BoxFuture<()> {
+        self.provider.delete_credentials(cx)
+    }
+}
+
+impl CompletionProvider for MeasuredCompletionProvider {
+    fn base_model(&self) -> Box<dyn LanguageModel> {
+        self.provider.base_model()
+    }
+    fn complete(
+        &self,
+        request: ChatRequest,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let model_name = if request.model.is_empty() {
+            self.provider.base_model().name()
+        } else {
+            request.model.clone()
+        };
+        let mut measurement = Measurement {
+            metrics: self.metrics.clone(),
+            model_name,
+            started_at: Instant::now(),
+            first_token_at: None,
+            deltas: 0,
+            completion_tokens: None,
+            finished: false,
+        };
+        let response = self.provider.complete(request);
+        async move {
+            match response.await {
+                Ok(events) => Ok(events
+                    .map(move |event| {
+                        measurement.observe(&event);
+                        event
+                    })
+                    .boxed()),
+                Err(error) => {
+                    measurement.metrics.record_error(&measurement.model_name);
+                    Err(error)
+                }
+            }
+        }
+        .boxed()
+    }
+    fn box_clone(&self) -> Box<dyn CompletionProvider> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        completion::{CompletionError, TokenUsage},
+        test::{FakeCompletionProvider, FakeResponse},
+    };
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_completion_metrics() {
+        let fake = FakeCompletionProvider::new();
+        fake.respond_with(FakeResponse::events([
+            CompletionEvent::Delta {
+                role: None,
+                text: "Hello world".into(),
+            },
+            CompletionEvent::Usage(TokenUsage {
+                prompt_tokens: 10,
+                completion_tokens: 2,
+                total_tokens: 12,
+            }),
+        ]));
+        fake.fail_next(CompletionError::Timeout {
+            provider: "vLLM",
+            timeout: Duration::from_secs(30),
+        });
+        let metrics = Arc::new(CompletionMetrics::default());
+        let provider = MeasuredCompletionProvider::new(Box::new(fake), metrics.clone());
+
+        for _ in 0..2 {
+            let request = ChatRequest {
+                model: "codellama".into(),
+                ..Default::default()
+            };
+            block_on(async {
+                if let Ok(events) = provider.complete(request).await {
+                    events.collect::<Vec<_>>().await;
+                }
+            });
+        }
+
+        let summary = metrics.summary();
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].model, "codellama");
+        assert_eq!(summary[0].requests, 2);
+        assert_eq!(summary[0].errors, 1);
+        assert_eq!(summary[0].error_rate(), 0.5);
+        assert!(summary[0].time_to_first_token.is_some());
+    }
+}
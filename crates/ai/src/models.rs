@@ -1,16 +1,533 @@
+use anyhow::anyhow;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject, SubschemaValidation},
+    JsonSchema,
+};
+use serde::{Deserialize, Serialize};
+use std::{slice, sync::Arc};
+
+use crate::{
+    chat::{RequestMessage, Role},
+    tokenizer::Tokenizer,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum TruncationDirection {
     Start,
     End,
+    /// Keeps the beginning and the end, and cuts from the middle.
+    Middle,
 }
 
 pub trait LanguageModel {
     fn name(&self) -> String;
-    fn count_tokens(&self, content: &str) -> anyhow::Result<usize>;
+    fn tokenizer(&self) -> Arc<dyn Tokenizer>;
+    fn count_tokens(&self, content: &str) -> anyhow::Result<usize> {
+        self.tokenizer().count_tokens(content)
+    }
     fn truncate(
         &self,
         content: &str,
         length: usize,
         direction: TruncationDirection,
-    ) -> anyhow::Result<String>;
+    ) -> anyhow::Result<String> {
+        self.tokenizer().truncate(content, length, direction)
+    }
+    /// Counts the tokens `messages` take up in a chat request, including the ones
+    /// the chat format adds around each message. Defaults to OpenAI's format.
+    fn count_message_tokens(&self, messages: &[RequestMessage]) -> anyhow::Result<usize> {
+        // Each message is wrapped as `<|start|>{role}\n{content}<|end|>\n`, and
+        // the reply is primed with `<|start|>assistant<|message|>`.
+        const TOKENS_PER_MESSAGE: usize = 3;
+        const TOKENS_PER_REPLY: usize = 3;
+        let mut token_count = TOKENS_PER_REPLY;
+        for message in messages {
+            token_count += TOKENS_PER_MESSAGE
+                + self.count_tokens(message.role.as_str())?
+                + self.count_tokens(&message.content)?;
+        }
+        Ok(token_count)
+    }
     fn capacity(&self) -> anyhow::Result<usize>;
+
+    /// The most tokens the model generates in one response, if that's less than
+    /// what's left of its context window.
+    fn max_output_tokens(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Drops the oldest messages until `messages` fit in `max_tokens`, keeping the
+/// system prompt at the start and the most recent message, so a conversation
+/// that has outgrown the model's context loses whole turns rather than being cut
+/// mid-message. If that isn't enough, the longest message other than the system
+/// prompt is cut from the middle.
+pub fn truncate_messages(
+    model: &dyn LanguageModel,
+    messages: Vec<RequestMessage>,
+    max_tokens: usize,
+) -> anyhow::Result<Vec<RequestMessage>> {
+    truncate_messages_keeping(model, messages, &[], max_tokens)
+}
+
+/// Like [`truncate_messages`], but never drops or cuts the messages at the indices
+/// in `pinned`, such as a spec the user wants the model to keep seeing however
+/// long the conversation gets. Returns an error if the system prompt and the
+/// pinned messages alone don't fit.
+pub fn truncate_messages_keeping(
+    model: &dyn LanguageModel,
+    mut messages: Vec<RequestMessage>,
+    pinned: &[usize],
+    max_tokens: usize,
+) -> anyhow::Result<Vec<RequestMessage>> {
+    // The chat format's overhead for the whole request is paid once, so each
+    // message costs what it adds on top of that.
+    let request_overhead = model.count_message_tokens(&[])?;
+    let mut message_tokens = messages
+        .iter()
+        .map(|message| {
+            Ok(model
+                .count_message_tokens(slice::from_ref(message))?
+                .saturating_sub(request_overhead))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut token_count = request_overhead + message_tokens.iter().sum::<usize>();
+
+    let system_prompt_len = messages
+        .iter()
+        .take_while(|message| message.role == Role::System)
+        .count();
+    let mut is_pinned = (0..messages.len())
+        .map(|ix| pinned.contains(&ix))
+        .collect::<Vec<_>>();
+    let mut ix = system_prompt_len;
+    while token_count > max_tokens && ix + 1 < messages.len() {
+        if is_pinned[ix] {
+            ix += 1;
+            continue;
+        }
+        messages.remove(ix);
+        is_pinned.remove(ix);
+        token_count -= message_tokens.remove(ix);
+    }
+
+    if token_count > max_tokens {
+        let longest_unpinned = (system_prompt_len..messages.len())
+            .filter(|ix| !is_pinned[*ix])
+            .max_by_key(|ix| message_tokens[*ix]);
+        if let Some(ix) = longest_unpinned {
+            let message = &mut messages[ix];
+            let content_tokens = model.count_tokens(&message.content)?;
+            let excess_tokens = token_count - max_tokens;
+            message.content = model.truncate(
+                &message.content,
+                content_tokens.saturating_sub(excess_tokens),
+                TruncationDirection::Middle,
+            )?;
+            token_count -= message_tokens[ix];
+            token_count += model
+                .count_message_tokens(slice::from_ref(message))?
+                .saturating_sub(request_overhead);
+        }
+    }
+
+    if token_count > max_tokens {
+        return Err(anyhow!(
+            "the system prompt and pinned messages take up {token_count} tokens, \
+            but only {max_tokens} are available"
+        ));
+    }
+    Ok(messages)
+}
+
+/// The API a known model is served through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModelProvider {
+    OpenAi,
+    Vllm,
+    Perplexity,
+}
+
+/// What a model costs to use, in US dollars per million tokens.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModelPricing {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+impl ModelPricing {
+    /// What the given number of tokens cost, in US dollars.
+    pub fn cost(&self, prompt_tokens: usize, completion_tokens: usize) -> f64 {
+        (prompt_tokens as f64 * self.prompt + completion_tokens as f64 * self.completion)
+            / 1_000_000.
+    }
+}
+
+/// A model that Zed knows about ahead of time, so it can be offered in settings
+/// and labeled in the UI without asking the provider.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModelDefinition {
+    pub provider: ModelProvider,
+    /// The name the provider's API knows the model by.
+    pub id: &'static str,
+    /// A shorter name to show in the assistant panel.
+    pub display_name: &'static str,
+    /// The most tokens the model generates in one response, if that's less than
+    /// what's left of its context window.
+    pub max_output_tokens: Option<usize>,
+    /// What the model costs through its provider's API, or `None` for models
+    /// that are usually self-hosted.
+    pub pricing: Option<ModelPricing>,
+}
+
+pub const GPT_3_5_TURBO: ModelDefinition = ModelDefinition {
+    provider: ModelProvider::OpenAi,
+    id: "gpt-3.5-turbo-0613",
+    display_name: "gpt-3.5-turbo",
+    max_output_tokens: None,
+    pricing: Some(ModelPricing {
+        prompt: 1.5,
+        completion: 2.0,
+    }),
+};
+
+pub const GPT_4: ModelDefinition = ModelDefinition {
+    provider: ModelProvider::OpenAi,
+    id: "gpt-4-0613",
+    display_name: "gpt-4",
+    max_output_tokens: None,
+    pricing: Some(ModelPricing {
+        prompt: 30.0,
+        completion: 60.0,
+    }),
+};
+
+pub const GPT_4_TURBO: ModelDefinition = ModelDefinition {
+    provider: ModelProvider::OpenAi,
+    id: "gpt-4-1106-preview",
+    display_name: "gpt-4-turbo",
+    max_output_tokens: Some(4096),
+    pricing: Some(ModelPricing {
+        prompt: 10.0,
+        completion: 30.0,
+    }),
+};
+
+/// Every model Zed knows about, across all providers.
+pub const KNOWN_MODELS: &[ModelDefinition] = &[
+    GPT_3_5_TURBO,
+    GPT_4,
+    GPT_4_TURBO,
+    ModelDefinition {
+        provider: ModelProvider::Vllm,
+        id: "meta-llama/Meta-Llama-3-8B-Instruct",
+        display_name: "llama-3-8b",
+        max_output_tokens: None,
+        pricing: None,
+    },
+    ModelDefinition {
+        provider: ModelProvider::Vllm,
+        id: "codellama/CodeLlama-13b-Instruct-hf",
+        display_name: "codellama-13b",
+        max_output_tokens: None,
+        pricing: None,
+    },
+    ModelDefinition {
+        provider: ModelProvider::Perplexity,
+        id: "sonar-medium-online",
+        display_name: "sonar-medium-online",
+        max_output_tokens: None,
+        pricing: Some(ModelPricing {
+            prompt: 0.6,
+            completion: 1.8,
+        }),
+    },
+];
+
+/// Models that have been retired by their provider, and the known models that
+/// replace them.
+const RETIRED_MODELS: &[(&str, &ModelDefinition)] = &[
+    ("gpt-3.5-turbo-0301", &GPT_3_5_TURBO),
+    ("gpt-4-0314", &GPT_4),
+    ("gpt-4-vision-preview", &GPT_4_TURBO),
+];
+
+/// Looks up a known model by the name its provider's API uses.
+pub fn find_model(id: &str) -> Option<&'static ModelDefinition> {
+    KNOWN_MODELS.iter().find(|model| model.id == id)
+}
+
+/// The known model of `provider` that costs the least per token, for small
+/// requests that don't need a capable model. Models without pricing aren't
+/// considered, since they may not be served.
+pub fn cheapest_model(provider: ModelProvider) -> Option<&'static ModelDefinition> {
+    KNOWN_MODELS
+        .iter()
+        .filter(|model| model.provider == provider)
+        .filter_map(|model| Some((model, model.pricing?)))
+        .min_by(|(_, a), (_, b)| (a.prompt + a.completion).total_cmp(&(b.prompt + b.completion)))
+        .map(|(model, _)| model)
+}
+
+/// The name of a model, as its provider's API knows it. Any name is accepted, so
+/// a model can be used as soon as a provider serves it; the settings schema only
+/// suggests the ones in [`KNOWN_MODELS`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ModelName(String);
+
+impl ModelName {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    /// The model's definition, if it's one Zed knows about.
+    pub fn definition(&self) -> Option<&'static ModelDefinition> {
+        find_model(&self.0)
+    }
+
+    /// The known model that replaces this one, if it's been retired.
+    pub fn successor(&self) -> Option<Self> {
+        RETIRED_MODELS
+            .iter()
+            .find(|(id, _)| *id == self.0)
+            .map(|(_, model)| (*model).into())
+    }
+
+    pub fn full_name(&self) -> &str {
+        &self.0
+    }
+
+    /// The known model's display name, or the full name for any other model.
+    pub fn short_name(&self) -> &str {
+        self.definition()
+            .map_or(self.0.as_str(), |model| model.display_name)
+    }
+
+    /// The next known OpenAI model, for cycling through models in the assistant
+    /// panel. Models Zed doesn't know about cycle to the first one.
+    pub fn cycle(&self) -> Self {
+        let mut models = KNOWN_MODELS
+            .iter()
+            .filter(|model| model.provider == ModelProvider::OpenAi);
+        let first = models.clone().next().unwrap();
+        let next = models
+            .position(|model| model.id == self.0)
+            .and_then(|_| models.next());
+        next.unwrap_or(first).into()
+    }
+}
+
+impl From<&ModelDefinition> for ModelName {
+    fn from(model: &ModelDefinition) -> Self {
+        Self::new(model.id)
+    }
+}
+
+impl JsonSchema for ModelName {
+    fn schema_name() -> String {
+        "ModelName".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let known_models = SchemaObject {
+            enum_values: Some(KNOWN_MODELS.iter().map(|model| model.id.into()).collect()),
+            ..Default::default()
+        };
+        let any_model = SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            ..Default::default()
+        };
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![known_models.into(), any_model.into()]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        providers::{open_ai::OpenAiLanguageModel, vllm::VllmLanguageModel},
+        test::FakeLanguageModel,
+    };
+
+    #[test]
+    fn test_model_names() {
+        let model: ModelName = serde_json::from_value("gpt-4-0613".into()).unwrap();
+        assert_eq!(model.definition(), Some(&GPT_4));
+        assert_eq!(model.short_name(), "gpt-4");
+        assert_eq!(serde_json::to_value(&model).unwrap(), "gpt-4-0613");
+
+        let model: ModelName = serde_json::from_value("mistral:7b-instruct-q5_K_M".into()).unwrap();
+        assert_eq!(model.definition(), None);
+        assert_eq!(model.short_name(), "mistral:7b-instruct-q5_K_M");
+        assert_eq!(model.successor(), None);
+
+        let model = ModelName::new("gpt-4-0314");
+        assert_eq!(model.successor(), Some(ModelName::new("gpt-4-0613")));
+    }
+
+    #[test]
+    fn test_max_output_tokens() {
+        let model = OpenAiLanguageModel::load("gpt-4-1106-preview");
+        assert_eq!(model.max_output_tokens(), Some(4096));
+        let model = OpenAiLanguageModel::load("gpt-4-0613");
+        assert_eq!(model.max_output_tokens(), None);
+        let model = OpenAiLanguageModel::load("mistral:7b-instruct-q5_K_M");
+        assert_eq!(model.max_output_tokens(), None);
+    }
+
+    #[test]
+    fn test_count_message_tokens() {
+        let messages = [
+            RequestMessage {
+                role: Role::System,
+                content: "You are a helpful assistant.".into(),
+            },
+            RequestMessage {
+                role: Role::User,
+                content: "Write hello world in Rust.".into(),
+            },
+        ];
+
+        let model = OpenAiLanguageModel::load("gpt-4-0613");
+        let tiktoken_messages = messages
+            .iter()
+            .map(|message| tiktoken_rs::ChatCompletionRequestMessage {
+                role: message.role.as_str().into(),
+                content: Some(message.content.clone()),
+                name: None,
+                function_call: None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            model.count_message_tokens(&messages).unwrap(),
+            tiktoken_rs::num_tokens_from_messages("gpt-4-0613", &tiktoken_messages).unwrap()
+        );
+
+        let content_tokens = messages
+            .iter()
+            .map(|message| model.count_tokens(&message.content).unwrap())
+            .sum::<usize>();
+        for name in [
+            "meta-llama/Meta-Llama-3-8B-Instruct",
+            "mistralai/Mistral-7B-Instruct-v0.2",
+            "Qwen/Qwen1.5-7B-Chat",
+        ] {
+            let model = VllmLanguageModel::load(name, None);
+            assert!(model.count_message_tokens(&messages).unwrap() > content_tokens + 6);
+        }
+    }
+
+    #[test]
+    fn test_truncate_messages() {
+        let model = FakeLanguageModel { capacity: 100 };
+        let message = |role, content: &str| RequestMessage {
+            role,
+            content: content.into(),
+        };
+        let messages = vec![
+            message(Role::System, "Be terse."),
+            message(Role::User, "What is Rust?"),
+            message(Role::Assistant, "A systems programming language."),
+            message(Role::User, "Who makes it?"),
+        ];
+
+        let fitted = truncate_messages(&model, messages.clone(), 1000).unwrap();
+        assert_eq!(fitted, messages);
+
+        // The oldest turns are dropped whole, and the system prompt is kept.
+        let max_tokens = model
+            .count_message_tokens(&[messages[0].clone(), messages[3].clone()])
+            .unwrap();
+        let fitted = truncate_messages(&model, messages.clone(), max_tokens).unwrap();
+        assert_eq!(fitted, vec![messages[0].clone(), messages[3].clone()]);
+
+        // When even the latest message is too long, it's cut from the middle.
+        let fitted = truncate_messages(&model, messages.clone(), max_tokens - 4).unwrap();
+        assert_eq!(
+            fitted,
+            vec![messages[0].clone(), message(Role::User, "Who m it?")]
+        );
+        assert_eq!(model.count_message_tokens(&fitted).unwrap(), max_tokens - 4);
+
+        // Pinned messages are kept while the turns around them are dropped.
+        let max_tokens = model
+            .count_message_tokens(&[
+                messages[0].clone(),
+                messages[1].clone(),
+                messages[3].clone(),
+            ])
+            .unwrap();
+        let fitted = truncate_messages_keeping(&model, messages.clone(), &[1], max_tokens).unwrap();
+        assert_eq!(
+            fitted,
+            vec![
+                messages[0].clone(),
+                messages[1].clone(),
+                messages[3].clone()
+            ]
+        );
+
+        // Pinned messages are never cut, however long they are.
+        let max_tokens = model
+            .count_message_tokens(&[
+                messages[0].clone(),
+                messages[2].clone(),
+                messages[3].clone(),
+            ])
+            .unwrap();
+        let fitted =
+            truncate_messages_keeping(&model, messages.clone(), &[2], max_tokens - 4).unwrap();
+        assert_eq!(
+            fitted,
+            vec![
+                messages[0].clone(),
+                messages[2].clone(),
+                message(Role::User, "Who m it?")
+            ]
+        );
+
+        // When the system prompt and pinned messages alone don't fit, there's
+        // nothing left to drop or cut.
+        let max_tokens = model
+            .count_message_tokens(&[messages[0].clone(), messages[3].clone()])
+            .unwrap();
+        assert!(truncate_messages_keeping(&model, messages.clone(), &[3], max_tokens - 4).is_err());
+    }
+
+    #[test]
+    fn test_model_pricing_cost() {
+        let pricing = GPT_4_TURBO.pricing.unwrap();
+        assert_eq!(pricing.cost(0, 0), 0.);
+        assert_eq!(pricing.cost(1_000_000, 0), 10.);
+        assert_eq!(pricing.cost(1_000, 500), 0.025);
+    }
+
+    #[test]
+    fn test_cheapest_model() {
+        assert_eq!(cheapest_model(ModelProvider::OpenAi), Some(&GPT_3_5_TURBO));
+        assert_eq!(cheapest_model(ModelProvider::Vllm), None);
+    }
+
+    #[test]
+    fn test_cycle_model() {
+        let model = ModelName::from(&GPT_3_5_TURBO);
+        let model = model.cycle();
+        assert_eq!(model, ModelName::from(&GPT_4));
+        let model = model.cycle();
+        assert_eq!(model, ModelName::from(&GPT_4_TURBO));
+        let model = model.cycle();
+        assert_eq!(model, ModelName::from(&GPT_3_5_TURBO));
+
+        let model = ModelName::new("gpt-4o-mini");
+        assert_eq!(model.cycle(), ModelName::from(&GPT_3_5_TURBO));
+    }
 }
@@ -0,0 +1,162 @@
+use std::sync::Arc;
+
+use crate::models::{LanguageModel, TruncationDirection};
+use crate::prompts::base::PromptPriority;
+
+struct PromptSection {
+    content: String,
+    priority: PromptPriority,
+    truncation: TruncationDirection,
+}
+
+/// Fits the sections of a prompt, such as the system prompt, retrieved context,
+/// the selection and the conversation history, into a model's context window.
+/// Sections are given tokens in priority order, and are truncated or left out
+/// once the window is full, so features don't each need their own truncation.
+pub struct TokenBudget {
+    model: Arc<dyn LanguageModel>,
+    reserved_tokens: usize,
+    separator: String,
+    sections: Vec<PromptSection>,
+}
+
+/// A prompt whose sections fit the model's context window.
+#[derive(Debug, PartialEq)]
+pub struct FittedPrompt {
+    /// Each section's content after fitting, in the order the sections were
+    /// added. Sections that didn't fit at all are empty.
+    pub sections: Vec<String>,
+    pub prompt: String,
+    pub token_count: usize,
+}
+
+impl TokenBudget {
+    pub fn new(model: Arc<dyn LanguageModel>) -> Self {
+        Self {
+            model,
+            reserved_tokens: 0,
+            separator: "\n".into(),
+            sections: Vec::new(),
+        }
+    }
+
+    /// Leaves `tokens` of the context window free for the model's response.
+    pub fn reserve(mut self, tokens: usize) -> Self {
+        self.reserved_tokens = tokens;
+        self
+    }
+
+    pub fn separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    /// Adds a section to the prompt. Sections appear in the order they're added,
+    /// whatever their priority. Mandatory sections are never truncated.
+    pub fn section(
+        mut self,
+        priority: PromptPriority,
+        truncation: TruncationDirection,
+        content: impl Into<String>,
+    ) -> Self {
+        self.sections.push(PromptSection {
+            content: content.into(),
+            priority,
+            truncation,
+        });
+        self
+    }
+
+    pub fn fit(&self) -> anyhow::Result<FittedPrompt> {
+        let separator_tokens = self.model.count_tokens(&self.separator)?;
+        let mut remaining_tokens = self.model.capacity()?.saturating_sub(self.reserved_tokens);
+
+        let mut section_indices = (0..self.sections.len()).collect::<Vec<_>>();
+        section_indices.sort_by(|&a, &b| {
+            self.sections[b]
+                .priority
+                .partial_cmp(&self.sections[a].priority)
+                .unwrap()
+        });
+
+        let mut sections = vec![String::new(); self.sections.len()];
+        for ix in section_indices {
+            let section = &self.sections[ix];
+            if section.content.is_empty() {
+                continue;
+            }
+
+            let token_count = self.model.count_tokens(&section.content)?;
+            let (content, token_count) = if section.priority == PromptPriority::Mandatory
+                || token_count + separator_tokens <= remaining_tokens
+            {
+                (section.content.clone(), token_count)
+            } else if remaining_tokens > separator_tokens {
+                let max_tokens = remaining_tokens - separator_tokens;
+                let content =
+                    self.model
+                        .truncate(&section.content, max_tokens, section.truncation)?;
+                (content, max_tokens)
+            } else {
+                continue;
+            };
+            remaining_tokens = remaining_tokens.saturating_sub(token_count + separator_tokens);
+            sections[ix] = content;
+        }
+
+        let prompt = sections
+            .iter()
+            .filter(|section| !section.is_empty())
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(&self.separator);
+        let token_count = self.model.count_tokens(&prompt)?;
+        Ok(FittedPrompt {
+            sections,
+            prompt,
+            token_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::FakeLanguageModel;
+
+    #[test]
+    fn test_token_budget() {
+        let model = Arc::new(FakeLanguageModel { capacity: 24 });
+        let budget = TokenBudget::new(model)
+            .reserve(4)
+            .section(
+                PromptPriority::Mandatory,
+                TruncationDirection::End,
+                "You are helpful.",
+            )
+            .section(
+                PromptPriority::Ordered { order: 1 },
+                TruncationDirection::Start,
+                "0123456789",
+            )
+            .section(
+                PromptPriority::Ordered { order: 0 },
+                TruncationDirection::Middle,
+                "abcdefghij",
+            );
+
+        let fitted = budget.fit().unwrap();
+        assert_eq!(
+            fitted,
+            FittedPrompt {
+                sections: vec!["You are helpful.".into(), String::new(), "aj".into()],
+                prompt: "You are helpful.\naj".into(),
+                token_count: 19,
+            }
+        );
+
+        let fitted = budget.reserve(0).fit().unwrap();
+        assert_eq!(fitted.sections[1], "");
+        assert_eq!(fitted.sections[2], "abchij");
+    }
+}
@@ -1,5 +1,7 @@
 pub mod base;
+pub mod budget;
 pub mod file_context;
 pub mod generate;
 pub mod preamble;
 pub mod repository_context;
+pub mod template;
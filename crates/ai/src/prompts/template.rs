@@ -0,0 +1,103 @@
+use crate::models::TruncationDirection;
+use crate::prompts::base::{PromptArguments, PromptTemplate};
+use anyhow::anyhow;
+use std::collections::HashMap;
+
+/// The values substituted into a user-defined prompt template, keyed by the
+/// name used between the braces of a `{{variable}}`.
+#[derive(Clone, Debug, Default)]
+pub struct TemplateVariables {
+    values: HashMap<String, String>,
+}
+
+impl TemplateVariables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.values.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+}
+
+/// Replaces every `{{variable}}` in `template` with its value. Whitespace inside
+/// the braces is ignored, and a variable that hasn't been provided is an error
+/// so a typo in a template doesn't silently reach the model.
+pub fn render_template(template: &str, variables: &TemplateVariables) -> anyhow::Result<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+        let Some(end) = after_open.find("}}") else {
+            return Err(anyhow!("unterminated template variable in {:?}", rest));
+        };
+
+        let name = after_open[..end].trim();
+        let value = variables
+            .get(name)
+            .ok_or_else(|| anyhow!("unknown template variable `{}`", name))?;
+        output.push_str(value);
+        rest = &after_open[end + 2..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// A prompt section whose text comes from a user-defined template rather than
+/// being hardcoded, so users can customize the instructions sent to the model.
+pub struct TemplatedInstructions {
+    pub template: String,
+    pub variables: TemplateVariables,
+}
+
+impl PromptTemplate for TemplatedInstructions {
+    fn generate(
+        &self,
+        args: &PromptArguments,
+        max_token_length: Option<usize>,
+    ) -> anyhow::Result<(String, usize)> {
+        let mut prompt = render_template(&self.template, &self.variables)?;
+        if let Some(max_tokens) = max_token_length {
+            prompt = args
+                .model
+                .truncate(&prompt, max_tokens, TruncationDirection::End)?;
+        }
+
+        let token_count = args.model.count_tokens(&prompt)?;
+        anyhow::Ok((prompt, token_count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let mut variables = TemplateVariables::new();
+        variables
+            .insert("language", "Rust")
+            .insert("selection", "fn main() {}");
+
+        assert_eq!(
+            render_template(
+                "Rewrite this {{language}}:\n{{ selection }}\nin {{language}}.",
+                &variables
+            )
+            .unwrap(),
+            "Rewrite this Rust:\nfn main() {}\nin Rust."
+        );
+        assert_eq!(
+            render_template("No variables here.", &variables).unwrap(),
+            "No variables here."
+        );
+        assert!(render_template("{{file_path}}", &variables).is_err());
+        assert!(render_template("Broken {{language", &variables).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use anyhow::{anyhow, Result};
+use futures::{future, AsyncReadExt};
+use gpui::{AppContext, Context, Model, ModelContext, Task};
+use isahc::http::StatusCode;
+use serde::Deserialize;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use util::http::{AsyncBody, HttpClient, Request};
+
+use crate::auth::ProviderCredential;
+
+/// How long a health check may take before the endpoint counts as disconnected.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The kind of server an endpoint is, which decides how it's pinged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EndpointKind {
+    /// Pinged by listing its models at `/models`.
+    OpenAi,
+    /// Pinged by asking the server for its version at `/version`.
+    Vllm,
+}
+
+impl EndpointKind {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            EndpointKind::OpenAi => "OpenAI",
+            EndpointKind::Vllm => "vLLM",
+        }
+    }
+}
+
+/// An endpoint whose connection status is tracked by [`ProviderStatus`].
+#[derive(Clone, Debug)]
+pub struct StatusEndpoint {
+    pub kind: EndpointKind,
+    pub api_url: String,
+    pub credential: ProviderCredential,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// The endpoint hasn't been checked yet.
+    #[default]
+    Unknown,
+    Connected {
+        /// The server's version, for servers that report one.
+        version: Option<String>,
+    },
+    Disconnected {
+        error: String,
+    },
+}
+
+impl ConnectionStatus {
+    pub fn is_connected(&self) -> bool {
+        matches!(self, ConnectionStatus::Connected { .. })
+    }
+}
+
+/// The result of the latest health check of an endpoint.
+#[derive(Clone, Debug)]
+pub struct EndpointStatus {
+    pub endpoint: StatusEndpoint,
+    pub status: ConnectionStatus,
+    pub checked_at: Option<Instant>,
+}
+
+impl EndpointStatus {
+    /// Describes the status for display, e.g. "vLLM: connected (v0.4.0)".
+    pub fn description(&self) -> String {
+        let name = self.endpoint.kind.display_name();
+        match &self.status {
+            ConnectionStatus::Unknown => format!("{name}: checking…"),
+            ConnectionStatus::Connected { version: None } => format!("{name}: connected"),
+            ConnectionStatus::Connected {
+                version: Some(version),
+            } => format!("{name}: connected (v{version})"),
+            ConnectionStatus::Disconnected { error } => {
+                format!("{name}: disconnected ({error})")
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct VersionResponse {
+    version: String,
+}
+
+/// Pings `endpoint` once, returning whether it could be reached.
+pub async fn check_endpoint(
+    client: &dyn HttpClient,
+    endpoint: &StatusEndpoint,
+) -> ConnectionStatus {
+    match ping(client, endpoint).await {
+        Ok(version) => ConnectionStatus::Connected { version },
+        Err(error) => ConnectionStatus::Disconnected {
+            error: error.to_string(),
+        },
+    }
+}
+
+async fn ping(client: &dyn HttpClient, endpoint: &StatusEndpoint) -> Result<Option<String>> {
+    let api_url = endpoint.api_url.trim_end_matches('/');
+    let url = match endpoint.kind {
+        EndpointKind::OpenAi => format!("{api_url}/models"),
+        // vLLM serves its version next to the OpenAI-compatible API, not under it.
+        EndpointKind::Vllm => format!("{}/version", api_url.trim_end_matches("/v1")),
+    };
+    let mut request = Request::get(url);
+    if let ProviderCredential::Credentials { api_key } = &endpoint.credential {
+        request = request.header("Authorization", format!("Bearer {api_key}"));
+    }
+    let mut response = client.send(request.body(AsyncBody::empty())?).await?;
+
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+    match response.status() {
+        StatusCode::OK => match endpoint.kind {
+            EndpointKind::OpenAi => Ok(None),
+            EndpointKind::Vllm => {
+                let response: VersionResponse = serde_json::from_str(&body)?;
+                Ok(Some(response.version))
+            }
+        },
+        StatusCode::UNAUTHORIZED => Err(anyhow!("invalid API key")),
+        status => Err(anyhow!("unexpected status {status}")),
+    }
+}
+
+/// Periodically checks every configured endpoint and caches the results, so the
+/// UI can show whether a provider is reachable before anything is sent to it.
+pub struct ProviderStatus {
+    client: Arc<dyn HttpClient>,
+    statuses: Vec<EndpointStatus>,
+    pending_check: Option<Task<()>>,
+    _poll: Task<()>,
+}
+
+impl ProviderStatus {
+    pub fn new(
+        client: Arc<dyn HttpClient>,
+        interval: Duration,
+        cx: &mut AppContext,
+    ) -> Model<Self> {
+        cx.new_model(|cx| {
+            let _poll = cx.spawn(|this, mut cx| async move {
+                loop {
+                    cx.background_executor().timer(interval).await;
+                    if this.update(&mut cx, |this, cx| this.refresh(cx)).is_err() {
+                        break;
+                    }
+                }
+            });
+            Self {
+                client,
+                statuses: Vec::new(),
+                pending_check: None,
+                _poll,
+            }
+        })
+    }
+
+    /// Replaces the endpoints being checked and checks them right away. Endpoints
+    /// that were already tracked keep their last status until the check finishes.
+    pub fn set_endpoints(
+        &mut self,
+        endpoints: impl IntoIterator<Item = StatusEndpoint>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let statuses = endpoints
+            .into_iter()
+            .map(|endpoint| {
+                let previous = self
+                    .statuses
+                    .iter()
+                    .find(|status| status.endpoint.api_url == endpoint.api_url);
+                EndpointStatus {
+                    status: previous.map_or(ConnectionStatus::Unknown, |previous| {
+                        previous.status.clone()
+                    }),
+                    checked_at: previous.and_then(|previous| previous.checked_at),
+                    endpoint,
+                }
+            })
+            .collect();
+        self.statuses = statuses;
+        self.pending_check = None;
+        self.refresh(cx);
+        cx.notify();
+    }
+
+    pub fn statuses(&self) -> &[EndpointStatus] {
+        &self.statuses
+    }
+
+    pub fn status_for(&self, api_url: &str) -> Option<&EndpointStatus> {
+        self.statuses
+            .iter()
+            .find(|status| status.endpoint.api_url == api_url)
+    }
+
+    /// Checks every endpoint again, unless a check is already running.
+    pub fn refresh(&mut self, cx: &mut ModelContext<Self>) {
+        if self.pending_check.is_some() || self.statuses.is_empty() {
+            return;
+        }
+
+        let client = self.client.clone();
+        let endpoints = self
+            .statuses
+            .iter()
+            .map(|status| status.endpoint.clone())
+            .collect::<Vec<_>>();
+        let executor = cx.background_executor().clone();
+        self.pending_check = Some(cx.spawn(|this, mut cx| async move {
+            let checks = endpoints.iter().map(|endpoint| {
+                let client = client.clone();
+                let timer = executor.timer(CHECK_TIMEOUT);
+                async move {
+                    let check = check_endpoint(client.as_ref(), endpoint);
+                    futures::pin_mut!(check, timer);
+                    match future::select(check, timer).await {
+                        future::Either::Left((status, _)) => status,
+                        future::Either::Right(_) => ConnectionStatus::Disconnected {
+                            error: "timed out".into(),
+                        },
+                    }
+                }
+            });
+            let results = future::join_all(checks).await;
+
+            this.update(&mut cx, |this, cx| {
+                let checked_at = Instant::now();
+                for (endpoint, status) in endpoints.into_iter().zip(results) {
+                    if let Some(entry) = this
+                        .statuses
+                        .iter_mut()
+                        .find(|entry| entry.endpoint.api_url == endpoint.api_url)
+                    {
+                        entry.status = status;
+                        entry.checked_at = Some(checked_at);
+                    }
+                }
+                this.pending_check = None;
+                cx.notify();
+            })
+            .ok();
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::TestAppContext;
+    use util::http::{FakeHttpClient, Response};
+
+    #[gpui::test]
+    async fn test_provider_status(cx: &mut TestAppContext) {
+        let client = FakeHttpClient::create(|request| async move {
+            let (status, body) = match (request.uri().host(), request.uri().path()) {
+                (Some("localhost"), "/version") => (200, r#"{"version": "0.4.0"}"#),
+                (Some("api.openai.com"), "/v1/models") => (401, "{}"),
+                _ => (404, ""),
+            };
+            Ok(Response::builder()
+                .status(status)
+                .body(AsyncBody::from(body))
+                .unwrap())
+        });
+        let provider_status =
+            cx.update(|cx| ProviderStatus::new(client, Duration::from_secs(60), cx));
+        provider_status.update(cx, |provider_status, cx| {
+            provider_status.set_endpoints(
+                [
+                    StatusEndpoint {
+                        kind: EndpointKind::Vllm,
+                        api_url: "http://localhost:8000/v1".into(),
+                        credential: ProviderCredential::NotNeeded,
+                    },
+                    StatusEndpoint {
+                        kind: EndpointKind::OpenAi,
+                        api_url: "https://api.openai.com/v1".into(),
+                        credential: ProviderCredential::Credentials {
+                            api_key: "sk-invalid".into(),
+                        },
+                    },
+                ],
+                cx,
+            );
+        });
+        cx.run_until_parked();
+
+        provider_status.read_with(cx, |provider_status, _| {
+            let descriptions = provider_status
+                .statuses()
+                .iter()
+                .map(|status| status.description())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                descriptions,
+                [
+                    "vLLM: connected (v0.4.0)",
+                    "OpenAI: disconnected (invalid API key)"
+                ]
+            );
+        });
+    }
+}
@@ -1 +1,3 @@
 pub mod open_ai;
+pub mod perplexity;
+pub mod vllm;
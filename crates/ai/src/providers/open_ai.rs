@@ -1,3 +1,4 @@
+pub mod compatible;
 pub mod completion;
 pub mod embedding;
 pub mod model;
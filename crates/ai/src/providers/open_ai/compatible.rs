@@ -0,0 +1,273 @@
+//! The transport shared by providers that speak OpenAI's chat completions API:
+//! authenticating, sending requests with retries, reading server-sent events and
+//! storing API keys.
+
+use anyhow::{anyhow, Result};
+use futures::{
+    future::BoxFuture, io::BufReader, AsyncBufReadExt, AsyncReadExt, FutureExt, SinkExt, Stream,
+};
+use gpui::{AppContext, BackgroundExecutor};
+use isahc::http::{request::Builder, StatusCode};
+use parking_lot::RwLock;
+use serde::de::DeserializeOwned;
+use std::{env, io, sync::Arc};
+use util::{
+    http::{AsyncBody, HttpClient, Request, Response},
+    ResultExt,
+};
+
+use crate::{
+    auth::{CredentialProvider, ProviderCredential},
+    completion::{
+        next_line, retry_with_backoff, stream_with_task, CompletionError, ConnectionOptions,
+        RateLimitStatus,
+    },
+};
+
+/// Adds the API key in `credential` to `builder`, if there is one.
+pub fn authorized(builder: Builder, credential: &ProviderCredential) -> Builder {
+    match credential {
+        ProviderCredential::Credentials { api_key } => {
+            builder.header("Authorization", format!("Bearer {}", api_key))
+        }
+        _ => builder,
+    }
+}
+
+/// The API key in `credential`, for servers that refuse requests without one.
+pub fn api_key(credential: &ProviderCredential) -> Result<&str> {
+    match credential {
+        ProviderCredential::Credentials { api_key } => Ok(api_key.as_str()),
+        _ => Err(anyhow!("no credentials provider for completion")),
+    }
+}
+
+/// Reads the body of a failed response into a [`CompletionError`].
+pub async fn read_error(
+    provider: &'static str,
+    mut response: Response<AsyncBody>,
+) -> anyhow::Error {
+    let mut body = String::new();
+    if let Err(error) = response.body_mut().read_to_string(&mut body).await {
+        return CompletionError::network(provider, error).into();
+    }
+    CompletionError::from_response(provider, response.status(), response.headers(), &body).into()
+}
+
+/// Posts `json_data` to `url`, retrying failures the retry policy allows.
+pub async fn send_request(
+    client: Arc<dyn HttpClient>,
+    provider: &'static str,
+    url: &str,
+    credential: &ProviderCredential,
+    executor: &BackgroundExecutor,
+    options: &ConnectionOptions,
+    json_data: String,
+) -> Result<Response<AsyncBody>> {
+    retry_with_backoff(options.retry_policy, executor, || {
+        let request = options
+            .configure(authorized(
+                Request::post(url).header("Content-Type", "application/json"),
+                credential,
+            ))
+            .body(json_data.clone().into());
+        let client = client.clone();
+        async move {
+            let response = client
+                .send(request?)
+                .await
+                .map_err(|error| CompletionError::network(provider, error))?;
+            if response.status() == StatusCode::OK {
+                anyhow::Ok(response)
+            } else {
+                Err(read_error(provider, response).await)
+            }
+        }
+    })
+    .await
+}
+
+/// Posts `json_data` to `url` and streams the events the server sends back,
+/// along with the rate limits reported in the response headers.
+pub async fn stream_events<E: DeserializeOwned + Send + 'static>(
+    client: Arc<dyn HttpClient>,
+    provider: &'static str,
+    url: String,
+    credential: ProviderCredential,
+    executor: BackgroundExecutor,
+    options: ConnectionOptions,
+    json_data: String,
+) -> Result<(Option<RateLimitStatus>, impl Stream<Item = Result<E>>)> {
+    let (mut tx, rx) = futures::channel::mpsc::channel::<Result<E>>(options.stream_buffer_size);
+
+    let mut response = send_request(
+        client,
+        provider,
+        &url,
+        &credential,
+        &executor,
+        &options,
+        json_data,
+    )
+    .await?;
+    let rate_limits = RateLimitStatus::from_headers(response.headers());
+
+    let watchdog = executor.clone();
+    let task = executor.spawn(async move {
+        let mut lines = BufReader::new(response.body_mut()).lines();
+
+        fn parse_line<T: DeserializeOwned>(line: Result<String, io::Error>) -> Result<Option<T>> {
+            if let Some(data) = line?.strip_prefix("data: ") {
+                let event = serde_json::from_str(data)?;
+                Ok(Some(event))
+            } else {
+                Ok(None)
+            }
+        }
+
+        loop {
+            let line = match next_line(
+                &mut lines,
+                provider,
+                options.timeouts.stream_idle,
+                &watchdog,
+            )
+            .await
+            {
+                Ok(Some(line)) => line,
+                Ok(None) => break,
+                Err(error) => {
+                    tx.send(Err(error.into())).await.ok();
+                    break;
+                }
+            };
+            // Neither a finish reason nor the end of one of several choices ends
+            // the stream, since usage can follow them, so read until the server
+            // says it's done.
+            if line.as_ref().map_or(false, |line| line == "data: [DONE]") {
+                break;
+            }
+            if let Some(event) = parse_line(line).transpose() {
+                if tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        }
+
+        anyhow::Ok(())
+    });
+
+    Ok((rate_limits, stream_with_task(rx, task)))
+}
+
+/// An API key read from an environment variable or from the system keychain,
+/// where it's saved under the provider's default API URL.
+#[derive(Clone)]
+pub struct ApiKeyCredential {
+    credential: Arc<RwLock<ProviderCredential>>,
+    keychain_url: &'static str,
+    env_var: &'static str,
+    /// The credential when no key is found.
+    without_key: ProviderCredential,
+}
+
+impl ApiKeyCredential {
+    /// A key the server refuses requests without.
+    pub fn required(keychain_url: &'static str, env_var: &'static str) -> Self {
+        Self::new(keychain_url, env_var, ProviderCredential::NoCredentials)
+    }
+
+    /// A key the server only checks when it's been configured to, so a missing
+    /// key isn't an error.
+    pub fn optional(keychain_url: &'static str, env_var: &'static str) -> Self {
+        Self::new(keychain_url, env_var, ProviderCredential::NotNeeded)
+    }
+
+    fn new(
+        keychain_url: &'static str,
+        env_var: &'static str,
+        without_key: ProviderCredential,
+    ) -> Self {
+        Self {
+            credential: Arc::new(RwLock::new(without_key.clone())),
+            keychain_url,
+            env_var,
+            without_key,
+        }
+    }
+
+    pub fn get(&self) -> ProviderCredential {
+        self.credential.read().clone()
+    }
+
+    pub fn set(&self, credential: ProviderCredential) {
+        *self.credential.write() = credential;
+    }
+}
+
+impl CredentialProvider for ApiKeyCredential {
+    fn has_credentials(&self) -> bool {
+        !matches!(*self.credential.read(), ProviderCredential::NoCredentials)
+    }
+
+    fn retrieve_credentials(&self, cx: &mut AppContext) -> BoxFuture<ProviderCredential> {
+        let existing_credential = self.get();
+        if let ProviderCredential::Credentials { .. } = existing_credential {
+            return async move { existing_credential }.boxed();
+        }
+
+        let credential = self.credential.clone();
+        let without_key = self.without_key.clone();
+        let env_api_key = env::var(self.env_var).ok();
+        let keychain_credentials = env_api_key
+            .is_none()
+            .then(|| cx.read_credentials(self.keychain_url));
+        async move {
+            let api_key = match keychain_credentials {
+                Some(credentials) => credentials
+                    .await
+                    .log_err()
+                    .flatten()
+                    .and_then(|(_, api_key)| String::from_utf8(api_key).log_err()),
+                None => env_api_key,
+            };
+            let retrieved_credential = match api_key {
+                Some(api_key) => ProviderCredential::Credentials { api_key },
+                None => without_key,
+            };
+            *credential.write() = retrieved_credential.clone();
+            retrieved_credential
+        }
+        .boxed()
+    }
+
+    fn save_credentials(
+        &self,
+        cx: &mut AppContext,
+        credential: ProviderCredential,
+    ) -> BoxFuture<()> {
+        self.set(credential.clone());
+        let write_credentials = match credential {
+            ProviderCredential::Credentials { api_key } => {
+                Some(cx.write_credentials(self.keychain_url, "Bearer", api_key.as_bytes()))
+            }
+            _ => None,
+        };
+
+        async move {
+            if let Some(write_credentials) = write_credentials {
+                write_credentials.await.log_err();
+            }
+        }
+        .boxed()
+    }
+
+    fn delete_credentials(&self, cx: &mut AppContext) -> BoxFuture<()> {
+        self.set(self.without_key.clone());
+        let delete_credentials = cx.delete_credentials(self.keychain_url);
+        async move {
+            delete_credentials.await.log_err();
+        }
+        .boxed()
+    }
+}
@@ -1,74 +1,104 @@
 use anyhow::{anyhow, Result};
 use futures::{
-    future::BoxFuture, io::BufReader, stream::BoxStream, AsyncBufReadExt, AsyncReadExt, FutureExt,
-    Stream, StreamExt,
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    AsyncReadExt, FutureExt, Stream, StreamExt,
 };
 use gpui::{AppContext, BackgroundExecutor};
-use isahc::{http::StatusCode, Request, RequestExt};
-use parking_lot::RwLock;
+use isahc::http::StatusCode;
 use serde::{Deserialize, Serialize};
-use std::{
-    env,
-    fmt::{self, Display},
-    io,
-    sync::Arc,
-};
-use util::ResultExt;
+use std::{collections::BTreeMap, sync::Arc};
+use util::http::{AsyncBody, HttpClient, Request};
 
 use crate::{
     auth::{CredentialProvider, ProviderCredential},
-    completion::{CompletionProvider, CompletionRequest},
+    chat::{ChatRequest, RequestMessage, ResponseFormat, Role, Tool, ToolChoice},
+    completion::{
+        with_done_event, Completion, CompletionError, CompletionEvent, CompletionProvider,
+        CompletionTimeouts, ConnectionOptions, FinishReason, LogitBias, RateLimitStatus,
+        RetryPolicy, SamplingParams, TlsOptions, TokenUsage,
+    },
     models::LanguageModel,
 };
 
-use crate::providers::open_ai::{OpenAiLanguageModel, OPEN_AI_API_URL};
-
-#[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq)]
-#[serde(rename_all = "lowercase")]
-pub enum Role {
-    User,
-    Assistant,
-    System,
-}
-
-impl Role {
-    pub fn cycle(&mut self) {
-        *self = match self {
-            Role::User => Role::Assistant,
-            Role::Assistant => Role::System,
-            Role::System => Role::User,
-        }
-    }
-}
-
-impl Display for Role {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Role::User => write!(f, "User"),
-            Role::Assistant => write!(f, "Assistant"),
-            Role::System => write!(f, "System"),
-        }
-    }
-}
+use crate::providers::open_ai::{
+    compatible::{self, ApiKeyCredential},
+    OpenAiLanguageModel, OPEN_AI_API_URL,
+};
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-pub struct RequestMessage {
-    pub role: Role,
-    pub content: String,
-}
+const PROVIDER_NAME: &str = "OpenAI";
 
 #[derive(Debug, Default, Serialize)]
 pub struct OpenAiRequest {
     pub model: String,
     pub messages: Vec<RequestMessage>,
     pub stream: bool,
+    /// Asks for a final event with the token usage of the whole completion.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
     pub stop: Vec<String>,
     pub temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "LogitBias::is_empty")]
+    pub logit_bias: LogitBias,
+    /// Makes sampling deterministic on a best-effort basis: requests with the same
+    /// seed and parameters should return the same result.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// How many choices to generate. Events for choices after the first arrive
+    /// wrapped in [`CompletionEvent::Choice`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
-impl CompletionRequest for OpenAiRequest {
-    fn data(&self) -> serde_json::Result<String> {
-        serde_json::to_string(self)
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct StreamOptions {
+    pub include_usage: bool,
+}
+
+impl OpenAiRequest {
+    pub fn with_sampling_params(mut self, params: &SamplingParams) -> Self {
+        self.max_tokens = params.max_tokens;
+        self.top_p = params.top_p;
+        self.presence_penalty = params.presence_penalty;
+        self.frequency_penalty = params.frequency_penalty;
+        self.logit_bias = params.logit_bias.clone();
+        self
+    }
+}
+
+impl From<ChatRequest> for OpenAiRequest {
+    fn from(request: ChatRequest) -> Self {
+        OpenAiRequest {
+            model: request.model,
+            messages: request.messages,
+            stream: request.stream,
+            stop: request.stop,
+            temperature: request.temperature,
+            seed: request.seed,
+            n: request.n,
+            tools: request.tools,
+            tool_choice: request.tool_choice,
+            response_format: request.response_format,
+            extra: request.extra,
+            ..Default::default()
+        }
+        .with_sampling_params(&request.sampling)
     }
 }
 
@@ -76,6 +106,23 @@ impl CompletionRequest for OpenAiRequest {
 pub struct ResponseMessage {
     pub role: Option<Role>,
     pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<FunctionCallDelta>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCallDelta>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ToolCallDelta {
+    pub index: usize,
+    pub id: Option<String>,
+    pub function: Option<FunctionCallDelta>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct FunctionCallDelta {
+    pub name: Option<String>,
+    pub arguments: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -92,6 +139,21 @@ pub struct ChatChoiceDelta {
     pub finish_reason: Option<String>,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct ChatChoice {
+    pub index: u32,
+    pub message: ResponseMessage,
+    pub finish_reason: Option<String>,
+}
+
+/// The response to a request that wasn't streamed.
+#[derive(Deserialize, Debug)]
+pub struct OpenAiResponse {
+    pub id: Option<String>,
+    pub choices: Vec<ChatChoice>,
+    pub usage: Option<OpenAiUsage>,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct OpenAiResponseStreamEvent {
     pub id: Option<String>,
@@ -102,162 +164,263 @@ pub struct OpenAiResponseStreamEvent {
     pub usage: Option<OpenAiUsage>,
 }
 
-pub async fn stream_completion(
-    api_url: String,
-    credential: ProviderCredential,
-    executor: BackgroundExecutor,
-    request: Box<dyn CompletionRequest>,
-) -> Result<impl Stream<Item = Result<OpenAiResponseStreamEvent>>> {
-    let api_key = match credential {
-        ProviderCredential::Credentials { api_key } => api_key,
-        _ => {
-            return Err(anyhow!("no credentials provider for completion"));
-        }
-    };
-
-    let (tx, rx) = futures::channel::mpsc::unbounded::<Result<OpenAiResponseStreamEvent>>();
-
-    let json_data = request.data()?;
-    let mut response = Request::post(format!("{api_url}/chat/completions"))
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .body(json_data)?
-        .send_async()
-        .await?;
-
-    let status = response.status();
-    if status == StatusCode::OK {
-        executor
-            .spawn(async move {
-                let mut lines = BufReader::new(response.body_mut()).lines();
-
-                fn parse_line(
-                    line: Result<String, io::Error>,
-                ) -> Result<Option<OpenAiResponseStreamEvent>> {
-                    if let Some(data) = line?.strip_prefix("data: ") {
-                        let event = serde_json::from_str(data)?;
-                        Ok(Some(event))
-                    } else {
-                        Ok(None)
-                    }
-                }
+/// A model listed by an OpenAI-compatible API.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct OpenAiModelInfo {
+    pub id: String,
+    /// Not reported by every OpenAI-compatible server.
+    #[serde(default)]
+    pub owned_by: String,
+}
 
-                while let Some(line) = lines.next().await {
-                    if let Some(event) = parse_line(line).transpose() {
-                        let done = event.as_ref().map_or(false, |event| {
-                            event
-                                .choices
-                                .last()
-                                .map_or(false, |choice| choice.finish_reason.is_some())
-                        });
-                        if tx.unbounded_send(event).is_err() {
-                            break;
-                        }
-
-                        if done {
-                            break;
-                        }
-                    }
-                }
+#[derive(Deserialize)]
+struct OpenAiModelList {
+    data: Vec<OpenAiModelInfo>,
+}
 
-                anyhow::Ok(())
-            })
-            .detach();
+impl From<OpenAiUsage> for TokenUsage {
+    fn from(usage: OpenAiUsage) -> Self {
+        TokenUsage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+        }
+    }
+}
 
-        Ok(rx)
-    } else {
-        let mut body = String::new();
-        response.body_mut().read_to_string(&mut body).await?;
+impl OpenAiResponseStreamEvent {
+    pub fn into_completion_events(self) -> Vec<CompletionEvent> {
+        let mut events = Vec::new();
+        for choice in self.choices {
+            let mut choice_events = Vec::new();
+            let ResponseMessage {
+                role,
+                content,
+                function_call,
+                tool_calls,
+            } = choice.delta;
+            if role.is_some() || content.is_some() {
+                choice_events.push(CompletionEvent::Delta {
+                    role,
+                    text: content.unwrap_or_default(),
+                });
+            }
+            if let Some(function_call) = function_call {
+                choice_events.push(CompletionEvent::FunctionCallDelta {
+                    index: 0,
+                    id: None,
+                    name: function_call.name,
+                    arguments: function_call.arguments.unwrap_or_default(),
+                });
+            }
+            for tool_call in tool_calls {
+                let (name, arguments) = tool_call
+                    .function
+                    .map_or((None, None), |function| (function.name, function.arguments));
+                choice_events.push(CompletionEvent::FunctionCallDelta {
+                    index: tool_call.index,
+                    id: tool_call.id,
+                    name,
+                    arguments: arguments.unwrap_or_default(),
+                });
+            }
+            if let Some(finish_reason) = choice.finish_reason {
+                choice_events.push(CompletionEvent::FinishReason(finish_reason.as_str().into()));
+            }
 
-        #[derive(Deserialize)]
-        struct OpenAiResponse {
-            error: OpenAiError,
+            if choice.index == 0 {
+                events.extend(choice_events);
+            } else {
+                events.extend(
+                    choice_events
+                        .into_iter()
+                        .map(|event| CompletionEvent::Choice {
+                            index: choice.index,
+                            event: Box::new(event),
+                        }),
+                );
+            }
         }
-
-        #[derive(Deserialize)]
-        struct OpenAiError {
-            message: String,
+        if let Some(usage) = self.usage {
+            events.push(CompletionEvent::Usage(usage.into()));
         }
+        events
+    }
+}
 
-        match serde_json::from_str::<OpenAiResponse>(&body) {
-            Ok(response) if !response.error.message.is_empty() => Err(anyhow!(
-                "Failed to connect to OpenAI API: {}",
-                response.error.message,
-            )),
-
-            _ => Err(anyhow!(
-                "Failed to connect to OpenAI API: {} {}",
-                response.status(),
-                body,
-            )),
-        }
+pub async fn stream_completion(
+    client: Arc<dyn HttpClient>,
+    api_url: String,
+    credential: ProviderCredential,
+    executor: BackgroundExecutor,
+    options: ConnectionOptions,
+    mut request: OpenAiRequest,
+) -> Result<(
+    Option<RateLimitStatus>,
+    impl Stream<Item = Result<OpenAiResponseStreamEvent>>,
+)> {
+    compatible::api_key(&credential)?;
+    if request.stream && options.includes_stream_usage(api_url == OPEN_AI_API_URL) {
+        request.stream_options = Some(StreamOptions {
+            include_usage: true,
+        });
     }
+    let json_data = serde_json::to_string(&request)?;
+    compatible::stream_events(
+        client,
+        PROVIDER_NAME,
+        format!("{api_url}/chat/completions"),
+        credential,
+        executor,
+        options,
+        json_data,
+    )
+    .await
+}
+
+/// Lists the models served by the OpenAI-compatible API at `api_url`.
+pub async fn list_models(
+    client: &dyn HttpClient,
+    api_url: &str,
+    credential: &ProviderCredential,
+    options: &ConnectionOptions,
+) -> Result<Vec<OpenAiModelInfo>> {
+    let request = options
+        .configure(compatible::authorized(
+            Request::get(format!("{api_url}/models")),
+            credential,
+        ))
+        .body(AsyncBody::empty())?;
+    let mut response = client
+        .send(request)
+        .await
+        .map_err(|error| CompletionError::network(PROVIDER_NAME, error))?;
+
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+    if response.status() == StatusCode::OK {
+        let models: OpenAiModelList = serde_json::from_str(&body)?;
+        Ok(models.data)
+    } else {
+        Err(CompletionError::from_response(
+            PROVIDER_NAME,
+            response.status(),
+            response.headers(),
+            &body,
+        )
+        .into())
+    }
+}
+
+/// Completes `request` in a single response rather than a stream, regardless of
+/// the request's `stream` field.
+pub async fn complete_once(
+    client: Arc<dyn HttpClient>,
+    api_url: String,
+    credential: ProviderCredential,
+    executor: BackgroundExecutor,
+    options: ConnectionOptions,
+    mut request: OpenAiRequest,
+) -> Result<Completion> {
+    compatible::api_key(&credential)?;
+    request.stream = false;
+    request.stream_options = None;
+
+    let mut response = compatible::send_request(
+        client,
+        PROVIDER_NAME,
+        &format!("{api_url}/chat/completions"),
+        &credential,
+        &executor,
+        &options,
+        serde_json::to_string(&request)?,
+    )
+    .await?;
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+    let response: OpenAiResponse = serde_json::from_str(&body)?;
+
+    let choice = response
+        .choices
+        .into_iter()
+        .find(|choice| choice.index == 0)
+        .ok_or_else(|| anyhow!("{PROVIDER_NAME} returned no choices"))?;
+    Ok(Completion {
+        text: choice.message.content.unwrap_or_default(),
+        usage: response.usage.map(Into::into),
+        finish_reason: choice.finish_reason.as_deref().map(Into::into),
+    })
 }
 
 #[derive(Clone)]
 pub struct OpenAiCompletionProvider {
     api_url: String,
+    client: Arc<dyn HttpClient>,
     model: OpenAiLanguageModel,
-    credential: Arc<RwLock<ProviderCredential>>,
+    credential: ApiKeyCredential,
     executor: BackgroundExecutor,
+    options: ConnectionOptions,
 }
 
 impl OpenAiCompletionProvider {
-    pub async fn new(api_url: String, model_name: String, executor: BackgroundExecutor) -> Self {
+    pub async fn new(
+        api_url: String,
+        model_name: String,
+        client: Arc<dyn HttpClient>,
+        executor: BackgroundExecutor,
+    ) -> Self {
         let model = executor
             .spawn(async move { OpenAiLanguageModel::load(&model_name) })
             .await;
-        let credential = Arc::new(RwLock::new(ProviderCredential::NoCredentials));
+        let credential = ApiKeyCredential::required(OPEN_AI_API_URL, "OPENAI_API_KEY");
         Self {
             api_url,
+            client,
             model,
             credential,
             executor,
+            options: ConnectionOptions::default(),
         }
     }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.options.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn with_timeouts(mut self, timeouts: CompletionTimeouts) -> Self {
+        self.options.timeouts = timeouts;
+        self
+    }
+
+    pub fn with_tls(mut self, tls: TlsOptions) -> Self {
+        self.options.tls = tls;
+        self
+    }
+
+    pub fn with_extra_headers(mut self, extra_headers: BTreeMap<String, String>) -> Self {
+        self.options.extra_headers = extra_headers;
+        self
+    }
+
+    pub fn with_stream_buffer_size(mut self, stream_buffer_size: usize) -> Self {
+        self.options.stream_buffer_size = stream_buffer_size;
+        self
+    }
+
+    pub fn with_stream_usage(mut self, stream_usage: Option<bool>) -> Self {
+        self.options.stream_usage = stream_usage;
+        self
+    }
 }
 
 impl CredentialProvider for OpenAiCompletionProvider {
     fn has_credentials(&self) -> bool {
-        match *self.credential.read() {
-            ProviderCredential::Credentials { .. } => true,
-            _ => false,
-        }
+        self.credential.has_credentials()
     }
 
     fn retrieve_credentials(&self, cx: &mut AppContext) -> BoxFuture<ProviderCredential> {
-        let existing_credential = self.credential.read().clone();
-        let retrieved_credential = match existing_credential {
-            ProviderCredential::Credentials { .. } => {
-                return async move { existing_credential }.boxed()
-            }
-            _ => {
-                if let Some(api_key) = env::var("OPENAI_API_KEY").log_err() {
-                    async move { ProviderCredential::Credentials { api_key } }.boxed()
-                } else {
-                    let credentials = cx.read_credentials(OPEN_AI_API_URL);
-                    async move {
-                        if let Some(Some((_, api_key))) = credentials.await.log_err() {
-                            if let Some(api_key) = String::from_utf8(api_key).log_err() {
-                                ProviderCredential::Credentials { api_key }
-                            } else {
-                                ProviderCredential::NoCredentials
-                            }
-                        } else {
-                            ProviderCredential::NoCredentials
-                        }
-                    }
-                    .boxed()
-                }
-            }
-        };
-
-        async move {
-            let retrieved_credential = retrieved_credential.await;
-            *self.credential.write() = retrieved_credential.clone();
-            retrieved_credential
-        }
-        .boxed()
+        self.credential.retrieve_credentials(cx)
     }
 
     fn save_credentials(
@@ -265,30 +428,11 @@ impl CredentialProvider for OpenAiCompletionProvider {
         cx: &mut AppContext,
         credential: ProviderCredential,
     ) -> BoxFuture<()> {
-        *self.credential.write() = credential.clone();
-        let credential = credential.clone();
-        let write_credentials = match credential {
-            ProviderCredential::Credentials { api_key } => {
-                Some(cx.write_credentials(OPEN_AI_API_URL, "Bearer", api_key.as_bytes()))
-            }
-            _ => None,
-        };
-
-        async move {
-            if let Some(write_credentials) = write_credentials {
-                write_credentials.await.log_err();
-            }
-        }
-        .boxed()
+        self.credential.save_credentials(cx, credential)
     }
 
     fn delete_credentials(&self, cx: &mut AppContext) -> BoxFuture<()> {
-        *self.credential.write() = ProviderCredential::NoCredentials;
-        let delete_credentials = cx.delete_credentials(OPEN_AI_API_URL);
-        async move {
-            delete_credentials.await.log_err();
-        }
-        .boxed()
+        self.credential.delete_credentials(cx)
     }
 }
 
@@ -299,30 +443,482 @@ impl CompletionProvider for OpenAiCompletionProvider {
     }
     fn complete(
         &self,
-        prompt: Box<dyn CompletionRequest>,
-    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<String>>>> {
-        // Currently the CompletionRequest for OpenAI, includes a 'model' parameter
-        // This means that the model is determined by the CompletionRequest and not the CompletionProvider,
+        request: ChatRequest,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        // Currently the ChatRequest for OpenAI, includes a 'model' parameter
+        // This means that the model is determined by the ChatRequest and not the CompletionProvider,
         // which is currently model based, due to the language model.
         // At some point in the future we should rectify this.
-        let credential = self.credential.read().clone();
+        let credential = self.credential.get();
         let api_url = self.api_url.clone();
-        let request = stream_completion(api_url, credential, self.executor.clone(), prompt);
+        let request = stream_completion(
+            self.client.clone(),
+            api_url,
+            credential,
+            self.executor.clone(),
+            self.options.clone(),
+            request.into(),
+        );
         async move {
-            let response = request.await?;
-            let stream = response
-                .filter_map(|response| async move {
-                    match response {
-                        Ok(mut response) => Some(Ok(response.choices.pop()?.delta.content?)),
-                        Err(error) => Some(Err(error)),
-                    }
-                })
+            let (rate_limits, response) = request.await?;
+            let rate_limits = rate_limits.map(|status| Ok(CompletionEvent::RateLimits(status)));
+            let events = stream::iter(rate_limits)
+                .chain(response.flat_map(|response| {
+                    let events = match response {
+                        Ok(response) => response
+                            .into_completion_events()
+                            .into_iter()
+                            .map(Ok)
+                            .collect(),
+                        Err(error) => vec![Err(error)],
+                    };
+                    stream::iter(events)
+                }))
                 .boxed();
-            Ok(stream)
+            Ok(with_done_event(events))
         }
         .boxed()
     }
+    fn complete_once(&self, request: ChatRequest) -> BoxFuture<'static, Result<Completion>> {
+        complete_once(
+            self.client.clone(),
+            self.api_url.clone(),
+            self.credential.get(),
+            self.executor.clone(),
+            self.options.clone(),
+            request.into(),
+        )
+        .boxed()
+    }
     fn box_clone(&self) -> Box<dyn CompletionProvider> {
         Box::new((*self).clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cassette::CassetteClient,
+        chat::{FunctionDefinition, JsonSchemaFormat},
+        test::collect_events,
+    };
+    use gpui::TestAppContext;
+    use std::env;
+    use util::http::FakeHttpClient;
+
+    #[test]
+    fn test_serialize_tools() {
+        let request = OpenAiRequest {
+            model: "gpt-4-1106-preview".into(),
+            tools: vec![Tool::Function {
+                function: FunctionDefinition {
+                    name: "get_weather".into(),
+                    description: None,
+                    parameters: serde_json::json!({"type": "object", "properties": {}}),
+                },
+            }],
+            tool_choice: Some(ToolChoice::Function("get_weather".into())),
+            ..Default::default()
+        };
+        let json: serde_json::Value = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json["tools"],
+            serde_json::json!([{
+                "type": "function",
+                "function": {"name": "get_weather", "parameters": {"type": "object", "properties": {}}}
+            }])
+        );
+        assert_eq!(
+            json["tool_choice"],
+            serde_json::json!({"type": "function", "function": {"name": "get_weather"}})
+        );
+
+        let json = serde_json::to_value(OpenAiRequest::default()).unwrap();
+        assert!(json.get("tools").is_none());
+        assert!(json.get("tool_choice").is_none());
+        assert!(json.get("response_format").is_none());
+    }
+
+    #[test]
+    fn test_sampling_params() {
+        let request = OpenAiRequest::from(ChatRequest {
+            sampling: SamplingParams {
+                max_tokens: Some(256),
+                top_p: Some(0.5),
+                top_k: Some(40),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let json: serde_json::Value = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["max_tokens"], 256);
+        assert_eq!(json["top_p"], 0.5);
+        assert!(json.get("top_k").is_none());
+        assert!(json.get("presence_penalty").is_none());
+        assert!(json.get("frequency_penalty").is_none());
+        assert!(json.get("logit_bias").is_none());
+
+        let request = OpenAiRequest::from(ChatRequest {
+            sampling: SamplingParams {
+                logit_bias: LogitBias::new().set(74694, LogitBias::MIN),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let json: serde_json::Value = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["logit_bias"], serde_json::json!({"74694": -100.0}));
+    }
+
+    #[test]
+    fn test_serialize_response_format() {
+        let request = OpenAiRequest {
+            response_format: Some(ResponseFormat::JsonObject),
+            ..Default::default()
+        };
+        let json: serde_json::Value = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json["response_format"],
+            serde_json::json!({"type": "json_object"})
+        );
+
+        let request = OpenAiRequest {
+            response_format: Some(ResponseFormat::JsonSchema {
+                json_schema: JsonSchemaFormat {
+                    name: "title".into(),
+                    schema: serde_json::json!({"type": "string"}),
+                    strict: Some(true),
+                },
+            }),
+            ..Default::default()
+        };
+        let json: serde_json::Value = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            json["response_format"],
+            serde_json::json!({
+                "type": "json_schema",
+                "json_schema": {"name": "title", "schema": {"type": "string"}, "strict": true}
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_chat_request() {
+        let mut extra = serde_json::Map::new();
+        extra.insert("user".into(), "zed".into());
+        let request = OpenAiRequest::from(ChatRequest {
+            model: "gpt-4-1106-preview".into(),
+            stream: true,
+            seed: Some(7),
+            extra,
+            ..Default::default()
+        });
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["model"], "gpt-4-1106-preview");
+        assert_eq!(json["seed"], 7);
+        assert_eq!(json["user"], "zed");
+        assert!(request.stream_options.is_none());
+    }
+
+    #[gpui::test]
+    async fn test_stream_usage(cx: &mut TestAppContext) {
+        async fn sent_stream_options(
+            api_url: &str,
+            stream_usage: Option<bool>,
+            cx: &mut TestAppContext,
+        ) -> serde_json::Value {
+            let (tx, mut rx) = futures::channel::mpsc::unbounded();
+            let client = FakeHttpClient::create(move |request| {
+                let tx = tx.clone();
+                async move {
+                    let mut body = String::new();
+                    request.into_body().read_to_string(&mut body).await.unwrap();
+                    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+                    tx.unbounded_send(body["stream_options"].clone()).unwrap();
+                    Ok(Response::builder()
+                        .status(200)
+                        .body(AsyncBody::from("data: [DONE]\n\n"))
+                        .unwrap())
+                }
+            });
+            let provider = OpenAiCompletionProvider::new(
+                api_url.into(),
+                "gpt-4-1106-preview".into(),
+                client,
+                cx.executor(),
+            )
+            .await
+            .with_stream_usage(stream_usage);
+            provider.credential.set(ProviderCredential::Credentials {
+                api_key: "sk-test".into(),
+            });
+            let request = ChatRequest {
+                model: "gpt-4-1106-preview".into(),
+                stream: true,
+                ..Default::default()
+            };
+            collect_events(provider.complete(request).await.unwrap()).await;
+            rx.next().await.unwrap()
+        }
+
+        let include_usage = serde_json::json!({"include_usage": true});
+        assert_eq!(
+            sent_stream_options(OPEN_AI_API_URL, None, cx).await,
+            include_usage
+        );
+        assert_eq!(
+            sent_stream_options("http://localhost:11434/v1", None, cx).await,
+            serde_json::Value::Null
+        );
+        assert_eq!(
+            sent_stream_options("http://localhost:11434/v1", Some(true), cx).await,
+            include_usage
+        );
+        assert_eq!(
+            sent_stream_options(OPEN_AI_API_URL, Some(false), cx).await,
+            serde_json::Value::Null
+        );
+    }
+
+    #[gpui::test]
+    async fn test_complete_once(cx: &mut TestAppContext) {
+        let client = FakeHttpClient::create(|request| async move {
+            let mut body = String::new();
+            request.into_body().read_to_string(&mut body).await.unwrap();
+            let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(body["stream"], false);
+            Ok(Response::builder()
+                .status(200)
+                .body(AsyncBody::from(
+                    r#"{
+                        "id": "chatcmpl-1",
+                        "object": "chat.completion",
+                        "created": 1710000000,
+                        "model": "gpt-4-1106-preview",
+                        "choices": [{"index": 0, "message": {"role": "assistant", "content": "Fix typo"}, "finish_reason": "stop"}],
+                        "usage": {"prompt_tokens": 12, "completion_tokens": 3, "total_tokens": 15}
+                    }"#,
+                ))
+                .unwrap())
+        });
+        let provider = OpenAiCompletionProvider::new(
+            OPEN_AI_API_URL.into(),
+            "gpt-4-1106-preview".into(),
+            client,
+            cx.executor(),
+        )
+        .await;
+        provider.credential.set(ProviderCredential::Credentials {
+            api_key: "sk-test".into(),
+        });
+
+        let request = ChatRequest {
+            model: "gpt-4-1106-preview".into(),
+            stream: true,
+            ..Default::default()
+        };
+        let completion = provider.complete_once(request).await.unwrap();
+        assert_eq!(
+            completion,
+            Completion {
+                text: "Fix typo".into(),
+                usage: Some(TokenUsage {
+                    prompt_tokens: 12,
+                    completion_tokens: 3,
+                    total_tokens: 15,
+                }),
+                finish_reason: Some(FinishReason::Stop),
+            }
+        );
+    }
+
+    #[gpui::test]
+    async fn test_list_models(_: &mut TestAppContext) {
+        let client = FakeHttpClient::create(|request| async move {
+            assert_eq!(request.uri().path(), "/v1/models");
+            assert_eq!(request.headers()["Authorization"], "Bearer sk-test");
+            Ok(Response::builder()
+                .status(200)
+                .body(AsyncBody::from(
+                    r#"{
+                        "object": "list",
+                        "data": [
+                            {"id": "gpt-4-0613", "object": "model", "owned_by": "openai"},
+                            {"id": "mistral:7b-instruct-q5_K_M", "object": "model"}
+                        ]
+                    }"#,
+                ))
+                .unwrap())
+        });
+        let credential = ProviderCredential::Credentials {
+            api_key: "sk-test".into(),
+        };
+
+        let models = list_models(
+            client.as_ref(),
+            OPEN_AI_API_URL,
+            &credential,
+            &ConnectionOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            models,
+            vec![
+                OpenAiModelInfo {
+                    id: "gpt-4-0613".into(),
+                    owned_by: "openai".into(),
+                },
+                OpenAiModelInfo {
+                    id: "mistral:7b-instruct-q5_K_M".into(),
+                    owned_by: String::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiple_choices() {
+        let event: OpenAiResponseStreamEvent = serde_json::from_str(
+            r#"{
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1710000000,
+                "model": "gpt-4-1106-preview",
+                "choices": [
+                    {"index": 0, "delta": {"content": "A"}, "finish_reason": null},
+                    {"index": 2, "delta": {"content": "C"}, "finish_reason": "stop"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            event.into_completion_events(),
+            [
+                CompletionEvent::Delta {
+                    role: None,
+                    text: "A".into()
+                },
+                CompletionEvent::Choice {
+                    index: 2,
+                    event: Box::new(CompletionEvent::Delta {
+                        role: None,
+                        text: "C".into()
+                    })
+                },
+                CompletionEvent::Choice {
+                    index: 2,
+                    event: Box::new(CompletionEvent::FinishReason(FinishReason::Stop))
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tool_call_deltas() {
+        let event: OpenAiResponseStreamEvent = serde_json::from_str(
+            r#"{
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1710000000,
+                "model": "gpt-4-1106-preview",
+                "choices": [{
+                    "index": 0,
+                    "delta": {"tool_calls": [{"index": 0, "id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": ""}}]},
+                    "finish_reason": null
+                }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            event.into_completion_events(),
+            [CompletionEvent::FunctionCallDelta {
+                index: 0,
+                id: Some("call_1".into()),
+                name: Some("get_weather".into()),
+                arguments: String::new(),
+            }]
+        );
+
+        let event: OpenAiResponseStreamEvent = serde_json::from_str(
+            r#"{
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1710000000,
+                "model": "gpt-4-1106-preview",
+                "choices": [{
+                    "index": 0,
+                    "delta": {"tool_calls": [{"index": 0, "function": {"arguments": "{\"city\":"}}]},
+                    "finish_reason": "tool_calls"
+                }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            event.into_completion_events(),
+            [
+                CompletionEvent::FunctionCallDelta {
+                    index: 0,
+                    id: None,
+                    name: None,
+                    arguments: "{\"city\":".into(),
+                },
+                CompletionEvent::FinishReason(FinishReason::ToolCalls),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_replay_chat_stream(cx: &mut TestAppContext) {
+        let client = CassetteClient::fixture("open_ai_chat_stream", util::http::client);
+        let provider = OpenAiCompletionProvider::new(
+            OPEN_AI_API_URL.into(),
+            "gpt-4-1106-preview".into(),
+            client.clone(),
+            cx.executor(),
+        )
+        .await;
+        provider.credential.set(ProviderCredential::Credentials {
+            api_key: env::var("OPENAI_API_KEY").unwrap_or_else(|_| "sk-test".into()),
+        });
+
+        let request = ChatRequest {
+            model: "gpt-4-1106-preview".into(),
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: "Say hello world".into(),
+            }],
+            stream: true,
+            ..Default::default()
+        };
+        let events = collect_events(provider.complete(request).await.unwrap()).await;
+        client.finish().unwrap();
+        assert_eq!(
+            events
+                .into_iter()
+                .filter(|event| !matches!(event, CompletionEvent::RateLimits(_)))
+                .collect::<Vec<_>>(),
+            [
+                CompletionEvent::Delta {
+                    role: Some(Role::Assistant),
+                    text: "".into()
+                },
+                CompletionEvent::Delta {
+                    role: None,
+                    text: "Hello".into()
+                },
+                CompletionEvent::Delta {
+                    role: None,
+                    text: " world".into()
+                },
+                CompletionEvent::FinishReason(FinishReason::Stop),
+                CompletionEvent::Usage(TokenUsage {
+                    prompt_tokens: 9,
+                    completion_tokens: 2,
+                    total_tokens: 11,
+                }),
+                CompletionEvent::Done,
+            ]
+        );
+    }
+}
@@ -26,6 +26,7 @@ use crate::auth::{CredentialProvider, ProviderCredential};
 use crate::embedding::{Embedding, EmbeddingProvider};
 use crate::models::LanguageModel;
 use crate::providers::open_ai::OpenAiLanguageModel;
+use crate::trace::{self, TraceDirection, TraceKind};
 
 use crate::providers::open_ai::OPEN_AI_API_URL;
 
@@ -145,20 +146,37 @@ impl OpenAiEmbeddingProvider {
             .redirect_policy(isahc::config::RedirectPolicy::Follow)
             .timeout(Duration::from_secs(request_timeout))
             .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", api_key))
-            .body(
-                serde_json::to_string(&OpenAiEmbeddingRequest {
-                    input: spans.clone(),
-                    model: "text-embedding-ada-002",
-                })
-                .unwrap()
-                .into(),
-            )?;
+            .header("Authorization", format!("Bearer {}", api_key));
+        let body = serde_json::to_string(&OpenAiEmbeddingRequest {
+            input: spans.clone(),
+            model: "text-embedding-ada-002",
+        })
+        .unwrap();
+        if let Some(tracer) = trace::tracer() {
+            tracer.record(
+                TraceKind::Embedding,
+                TraceDirection::Request,
+                api_url,
+                &body,
+            );
+        }
+        let request = request.body(body.into())?;
 
         Ok(self.client.send(request).await?)
     }
 }
 
+fn trace_response(api_url: &str, body: &str) {
+    if let Some(tracer) = trace::tracer() {
+        tracer.record(
+            TraceKind::Embedding,
+            TraceDirection::Response,
+            api_url,
+            body,
+        );
+    }
+}
+
 impl CredentialProvider for OpenAiEmbeddingProvider {
     fn has_credentials(&self) -> bool {
         match *self.credential.read() {
@@ -279,6 +297,7 @@ impl EmbeddingProvider for OpenAiEmbeddingProvider {
                 StatusCode::OK => {
                     let mut body = String::new();
                     response.body_mut().read_to_string(&mut body).await?;
+                    trace_response(api_url, &body);
                     let response: OpenAiEmbeddingResponse = serde_json::from_str(&body)?;
 
                     log::trace!(
@@ -302,6 +321,7 @@ impl EmbeddingProvider for OpenAiEmbeddingProvider {
                     rate_limiting = true;
                     let mut body = String::new();
                     response.body_mut().read_to_string(&mut body).await?;
+                    trace_response(api_url, &body);
 
                     let delay_duration = {
                         let delay = Duration::from_secs(BACKOFF_SECONDS[request_number - 1] as u64);
@@ -332,6 +352,7 @@ impl EmbeddingProvider for OpenAiEmbeddingProvider {
                 _ => {
                     let mut body = String::new();
                     response.body_mut().read_to_string(&mut body).await?;
+                    trace_response(api_url, &body);
                     return Err(anyhow!(
                         "open ai bad request: {:?} {:?}",
                         &response.status(),
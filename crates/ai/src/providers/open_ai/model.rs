@@ -1,14 +1,16 @@
-use anyhow::anyhow;
-use tiktoken_rs::CoreBPE;
+use std::sync::Arc;
 
-use crate::models::{LanguageModel, TruncationDirection};
+use crate::{
+    models::{find_model, LanguageModel},
+    tokenizer::Tokenizer,
+};
 
 use super::OPEN_AI_BPE_TOKENIZER;
 
 #[derive(Clone)]
 pub struct OpenAiLanguageModel {
     name: String,
-    bpe: Option<CoreBPE>,
+    tokenizer: Arc<dyn Tokenizer>,
 }
 
 impl OpenAiLanguageModel {
@@ -17,7 +19,7 @@ impl OpenAiLanguageModel {
             tiktoken_rs::get_bpe_from_model(model_name).unwrap_or(OPEN_AI_BPE_TOKENIZER.to_owned());
         OpenAiLanguageModel {
             name: model_name.to_string(),
-            bpe: Some(bpe),
+            tokenizer: Arc::new(bpe),
         }
     }
 }
@@ -26,34 +28,13 @@ impl LanguageModel for OpenAiLanguageModel {
     fn name(&self) -> String {
         self.name.clone()
     }
-    fn count_tokens(&self, content: &str) -> anyhow::Result<usize> {
-        if let Some(bpe) = &self.bpe {
-            anyhow::Ok(bpe.encode_with_special_tokens(content).len())
-        } else {
-            Err(anyhow!("bpe for open ai model was not retrieved"))
-        }
-    }
-    fn truncate(
-        &self,
-        content: &str,
-        length: usize,
-        direction: TruncationDirection,
-    ) -> anyhow::Result<String> {
-        if let Some(bpe) = &self.bpe {
-            let tokens = bpe.encode_with_special_tokens(content);
-            if tokens.len() > length {
-                match direction {
-                    TruncationDirection::End => bpe.decode(tokens[..length].to_vec()),
-                    TruncationDirection::Start => bpe.decode(tokens[length..].to_vec()),
-                }
-            } else {
-                bpe.decode(tokens)
-            }
-        } else {
-            Err(anyhow!("bpe for open ai model was not retrieved"))
-        }
+    fn tokenizer(&self) -> Arc<dyn Tokenizer> {
+        self.tokenizer.clone()
     }
     fn capacity(&self) -> anyhow::Result<usize> {
         anyhow::Ok(tiktoken_rs::model::get_context_size(&self.name))
     }
+    fn max_output_tokens(&self) -> Option<usize> {
+        find_model(&self.name).and_then(|model| model.max_output_tokens)
+    }
 }
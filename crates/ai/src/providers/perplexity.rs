@@ -0,0 +1,7 @@
+pub mod completion;
+pub mod model;
+
+pub use completion::*;
+pub use model::PerplexityLanguageModel;
+
+pub const PERPLEXITY_API_URL: &'static str = "https://api.perplexity.ai";
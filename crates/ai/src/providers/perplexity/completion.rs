@@ -0,0 +1,418 @@
+use anyhow::{anyhow, Result};
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    FutureExt, Stream, StreamExt,
+};
+use gpui::{AppContext, BackgroundExecutor};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+use util::http::HttpClient;
+
+use crate::{
+    auth::{CredentialProvider, ProviderCredential},
+    chat::{ChatRequest, RequestMessage, ResponseFormat},
+    completion::{
+        with_done_event, CompletionEvent, CompletionProvider, CompletionTimeouts,
+        ConnectionOptions, RetryPolicy, SamplingParams, TlsOptions,
+    },
+    models::LanguageModel,
+    providers::open_ai::{
+        compatible::{self, ApiKeyCredential},
+        OpenAiUsage, ResponseMessage,
+    },
+};
+
+use crate::providers::perplexity::{PerplexityLanguageModel, PERPLEXITY_API_URL};
+
+const PROVIDER_NAME: &str = "Perplexity";
+
+#[derive(Debug, Default, Serialize)]
+pub struct PerplexityRequest {
+    pub model: String,
+    pub messages: Vec<RequestMessage>,
+    pub stream: bool,
+    pub temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl PerplexityRequest {
+    pub fn with_sampling_params(mut self, params: &SamplingParams) -> Self {
+        self.max_tokens = params.max_tokens;
+        self.top_p = params.top_p;
+        self.top_k = params.top_k;
+        self.presence_penalty = params.presence_penalty;
+        self.frequency_penalty = params.frequency_penalty;
+        self
+    }
+}
+
+/// Fails for requests that use what Perplexity doesn't support, rather than
+/// sending them without it: stop sequences, seeds, several choices, tools,
+/// logit biases and structured output.
+impl TryFrom<ChatRequest> for PerplexityRequest {
+    type Error = anyhow::Error;
+
+    fn try_from(request: ChatRequest) -> Result<Self> {
+        let unsupported = if !request.stop.is_empty() {
+            Some("stop sequences")
+        } else if request.seed.is_some() {
+            Some("seeds")
+        } else if request.n.map_or(false, |n| n > 1) {
+            Some("more than one choice")
+        } else if !request.tools.is_empty() || request.tool_choice.is_some() {
+            Some("tools")
+        } else if !request.sampling.logit_bias.is_empty() {
+            Some("logit biases")
+        } else if !matches!(request.response_format, None | Some(ResponseFormat::Text)) {
+            Some("response formats")
+        } else {
+            None
+        };
+        if let Some(unsupported) = unsupported {
+            return Err(anyhow!("{PROVIDER_NAME} doesn't support {unsupported}"));
+        }
+
+        Ok(PerplexityRequest {
+            model: request.model,
+            messages: request.messages,
+            stream: request.stream,
+            temperature: request.temperature,
+            extra: request.extra,
+            ..Default::default()
+        }
+        .with_sampling_params(&request.sampling))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PerplexityChoiceDelta {
+    pub index: u32,
+    pub delta: ResponseMessage,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PerplexityResponseStreamEvent {
+    pub id: Option<String>,
+    pub object: String,
+    pub created: u32,
+    pub model: String,
+    pub choices: Vec<PerplexityChoiceDelta>,
+    pub usage: Option<OpenAiUsage>,
+    /// The URLs of the sources used to produce the answer. Only the `-online`
+    /// models populate this, and every event repeats the full list.
+    #[serde(default)]
+    pub citations: Vec<String>,
+}
+
+pub async fn stream_completion(
+    client: Arc<dyn HttpClient>,
+    api_url: String,
+    credential: ProviderCredential,
+    executor: BackgroundExecutor,
+    options: ConnectionOptions,
+    request: PerplexityRequest,
+) -> Result<impl Stream<Item = Result<PerplexityResponseStreamEvent>>> {
+    compatible::api_key(&credential)?;
+    let json_data = serde_json::to_string(&request)?;
+    let (_, events) = compatible::stream_events(
+        client,
+        PROVIDER_NAME,
+        format!("{api_url}/chat/completions"),
+        credential,
+        executor,
+        options,
+        json_data,
+    )
+    .await?;
+    Ok(events)
+}
+
+#[derive(Clone)]
+pub struct PerplexityCompletionProvider {
+    api_url: String,
+    client: Arc<dyn HttpClient>,
+    model: PerplexityLanguageModel,
+    credential: ApiKeyCredential,
+    executor: BackgroundExecutor,
+    options: ConnectionOptions,
+}
+
+impl PerplexityCompletionProvider {
+    pub fn new(
+        api_url: String,
+        model_name: String,
+        client: Arc<dyn HttpClient>,
+        executor: BackgroundExecutor,
+    ) -> Self {
+        let model = PerplexityLanguageModel::load(&model_name);
+        let credential = ApiKeyCredential::required(PERPLEXITY_API_URL, "PERPLEXITY_API_KEY");
+        Self {
+            api_url,
+            client,
+            model,
+            credential,
+            executor,
+            options: ConnectionOptions::default(),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.options.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn with_timeouts(mut self, timeouts: CompletionTimeouts) -> Self {
+        self.options.timeouts = timeouts;
+        self
+    }
+
+    pub fn with_tls(mut self, tls: TlsOptions) -> Self {
+        self.options.tls = tls;
+        self
+    }
+
+    pub fn with_extra_headers(mut self, extra_headers: BTreeMap<String, String>) -> Self {
+        self.options.extra_headers = extra_headers;
+        self
+    }
+
+    pub fn with_stream_buffer_size(mut self, stream_buffer_size: usize) -> Self {
+        self.options.stream_buffer_size = stream_buffer_size;
+        self
+    }
+}
+
+impl CredentialProvider for PerplexityCompletionProvider {
+    fn has_credentials(&self) -> bool {
+        self.credential.has_credentials()
+    }
+
+    fn retrieve_credentials(&self, cx: &mut AppContext) -> BoxFuture<ProviderCredential> {
+        self.credential.retrieve_credentials(cx)
+    }
+
+    fn save_credentials(
+        &self,
+        cx: &mut AppContext,
+        credential: ProviderCredential,
+    ) -> BoxFuture<()> {
+        self.credential.save_credentials(cx, credential)
+    }
+
+    fn delete_credentials(&self, cx: &mut AppContext) -> BoxFuture<()> {
+        self.credential.delete_credentials(cx)
+    }
+}
+
+impl CompletionProvider for PerplexityCompletionProvider {
+    fn base_model(&self) -> Box<dyn LanguageModel> {
+        let model: Box<dyn LanguageModel> = Box::new(self.model.clone());
+        model
+    }
+    fn complete(
+        &self,
+        request: ChatRequest,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let request = match PerplexityRequest::try_from(request) {
+            Ok(request) => request,
+            Err(error) => return async move { Err(error) }.boxed(),
+        };
+        let request = stream_completion(
+            self.client.clone(),
+            self.api_url.clone(),
+            self.credential.get(),
+            self.executor.clone(),
+            self.options.clone(),
+            request,
+        );
+        async move {
+            let response = request.await?;
+            // Every event repeats the full list of citations, so only report them
+            // when they change, which in practice means once.
+            let mut last_citations = Vec::new();
+            let events = response
+                .flat_map(move |response| {
+                    let events = match response {
+                        Ok(mut response) => {
+                            let mut events = Vec::new();
+                            if response.citations != last_citations {
+                                last_citations = response.citations.clone();
+                                events.push(Ok(CompletionEvent::Citations(response.citations)));
+                            }
+                            if let Some(choice) = response.choices.pop() {
+                                if choice.delta.role.is_some() || choice.delta.content.is_some() {
+                                    events.push(Ok(CompletionEvent::Delta {
+                                        role: choice.delta.role,
+                                        text: choice.delta.content.unwrap_or_default(),
+                                    }));
+                                }
+                                if let Some(finish_reason) = choice.finish_reason {
+                                    events.push(Ok(CompletionEvent::FinishReason(
+                                        finish_reason.as_str().into(),
+                                    )));
+                                }
+                            }
+                            if let Some(usage) = response.usage {
+                                events.push(Ok(CompletionEvent::Usage(usage.into())));
+                            }
+                            events
+                        }
+                        Err(error) => vec![Err(error)],
+                    };
+                    stream::iter(events)
+                })
+                .boxed();
+            Ok(with_done_event(events))
+        }
+        .boxed()
+    }
+    fn box_clone(&self) -> Box<dyn CompletionProvider> {
+        Box::new((*self).clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cassette::CassetteClient,
+        chat::{Role, ToolChoice},
+        completion::{FinishReason, LogitBias, TokenUsage},
+        test::collect_events,
+    };
+    use gpui::TestAppContext;
+    use std::env;
+
+    #[test]
+    fn test_parse_stream_event_with_citations() {
+        let event: PerplexityResponseStreamEvent = serde_json::from_str(
+            r#"{
+                "id": "3c90c3cc",
+                "model": "sonar-medium-online",
+                "created": 1710000000,
+                "object": "chat.completion",
+                "citations": ["https://zed.dev/docs", "https://github.com/zed-industries/zed"],
+                "choices": [{"index": 0, "finish_reason": null, "delta": {"role": "assistant", "content": "Zed"}}]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            event.citations,
+            [
+                "https://zed.dev/docs",
+                "https://github.com/zed-industries/zed"
+            ]
+        );
+        assert_eq!(event.choices[0].delta.content.as_deref(), Some("Zed"));
+
+        let event: PerplexityResponseStreamEvent = serde_json::from_str(
+            r#"{"id": null, "model": "sonar-medium-chat", "created": 1710000000, "object": "chat.completion", "choices": []}"#,
+        )
+        .unwrap();
+        assert!(event.citations.is_empty());
+    }
+
+    #[test]
+    fn test_reject_unsupported_parameters() {
+        let request = ChatRequest {
+            model: "sonar-medium-online".into(),
+            n: Some(1),
+            response_format: Some(ResponseFormat::Text),
+            ..Default::default()
+        };
+        assert!(PerplexityRequest::try_from(request.clone()).is_ok());
+
+        let unsupported = [
+            ChatRequest {
+                stop: vec!["\n".into()],
+                ..request.clone()
+            },
+            ChatRequest {
+                seed: Some(7),
+                ..request.clone()
+            },
+            ChatRequest {
+                n: Some(2),
+                ..request.clone()
+            },
+            ChatRequest {
+                tool_choice: Some(ToolChoice::None),
+                ..request.clone()
+            },
+            ChatRequest {
+                sampling: SamplingParams {
+                    logit_bias: LogitBias::new().set(1, -100.),
+                    ..Default::default()
+                },
+                ..request.clone()
+            },
+            ChatRequest {
+                response_format: Some(ResponseFormat::JsonObject),
+                ..request
+            },
+        ];
+        for request in unsupported {
+            assert!(PerplexityRequest::try_from(request).is_err());
+        }
+    }
+
+    #[gpui::test]
+    async fn test_replay_chat_stream(cx: &mut TestAppContext) {
+        let client = CassetteClient::fixture("perplexity_chat_stream", util::http::client);
+        let provider = PerplexityCompletionProvider::new(
+            PERPLEXITY_API_URL.into(),
+            "sonar-medium-online".into(),
+            client.clone(),
+            cx.executor(),
+        );
+        provider.credential.set(ProviderCredential::Credentials {
+            api_key: env::var("PERPLEXITY_API_KEY").unwrap_or_else(|_| "pplx-test".into()),
+        });
+
+        let request = ChatRequest {
+            model: "sonar-medium-online".into(),
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: "What is Zed?".into(),
+            }],
+            stream: true,
+            ..Default::default()
+        };
+        let events = collect_events(provider.complete(request).await.unwrap()).await;
+        client.finish().unwrap();
+        assert_eq!(
+            events,
+            [
+                CompletionEvent::Citations(vec!["https://zed.dev/docs".into()]),
+                CompletionEvent::Delta {
+                    role: Some(Role::Assistant),
+                    text: "Zed".into()
+                },
+                CompletionEvent::Delta {
+                    role: Some(Role::Assistant),
+                    text: " is an editor.".into()
+                },
+                CompletionEvent::FinishReason(FinishReason::Stop),
+                CompletionEvent::Usage(TokenUsage {
+                    prompt_tokens: 4,
+                    completion_tokens: 5,
+                    total_tokens: 9,
+                }),
+                CompletionEvent::Done,
+            ]
+        );
+    }
+}
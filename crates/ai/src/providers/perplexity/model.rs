@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use crate::models::LanguageModel;
+use crate::providers::open_ai::OPEN_AI_BPE_TOKENIZER;
+use crate::tokenizer::Tokenizer;
+
+pub const PERPLEXITY_DEFAULT_MODEL: &'static str = "sonar-medium-online";
+
+/// Perplexity doesn't publish its tokenizers, so token counts are estimated
+/// with OpenAI's `cl100k_base` encoding, which is close enough for budgeting.
+#[derive(Clone)]
+pub struct PerplexityLanguageModel {
+    name: String,
+    tokenizer: Arc<dyn Tokenizer>,
+}
+
+impl PerplexityLanguageModel {
+    pub fn load(model_name: &str) -> Self {
+        PerplexityLanguageModel {
+            name: model_name.to_string(),
+            tokenizer: Arc::new(OPEN_AI_BPE_TOKENIZER.to_owned()),
+        }
+    }
+
+    /// Whether this model searches the web and returns citations alongside its answer.
+    pub fn is_online(&self) -> bool {
+        self.name.ends_with("-online")
+    }
+}
+
+impl LanguageModel for PerplexityLanguageModel {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+    fn tokenizer(&self) -> Arc<dyn Tokenizer> {
+        self.tokenizer.clone()
+    }
+    fn capacity(&self) -> anyhow::Result<usize> {
+        // Online models reserve part of their context window for search results.
+        if self.is_online() {
+            anyhow::Ok(12000)
+        } else {
+            anyhow::Ok(16384)
+        }
+    }
+}
@@ -0,0 +1,12 @@
+pub mod completion;
+pub mod fim;
+pub mod grammar;
+pub mod model;
+pub mod tokenizer;
+
+pub use completion::*;
+pub use fim::{fim_template, FimTemplate};
+pub use grammar::Grammar;
+pub use model::VllmLanguageModel;
+
+pub const VLLM_API_URL: &'static str = "http://localhost:8000/v1";
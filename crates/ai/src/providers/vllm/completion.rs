@@ -0,0 +1,999 @@
+use anyhow::{anyhow, Result};
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    AsyncReadExt, FutureExt, Stream, StreamExt,
+};
+use gpui::{AppContext, BackgroundExecutor};
+use isahc::http::StatusCode;
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+use util::http::{AsyncBody, HttpClient, Request};
+
+use crate::{
+    auth::{CredentialProvider, ProviderCredential},
+    chat::{ChatRequest, RequestMessage, ResponseFormat, Tool, ToolChoice},
+    completion::{
+        with_done_event, CompletionError, CompletionEvent, CompletionProvider, CompletionTimeouts,
+        ConnectionOptions, LogitBias, RateLimitStatus, RetryPolicy, SamplingParams, TlsOptions,
+    },
+    models::LanguageModel,
+    providers::open_ai::{
+        compatible::{authorized, read_error, stream_events, ApiKeyCredential},
+        OpenAiResponseStreamEvent, OpenAiUsage, StreamOptions,
+    },
+};
+
+use crate::providers::vllm::{fim_template, tokenizer, VllmLanguageModel, VLLM_API_URL};
+
+const PROVIDER_NAME: &str = "vLLM";
+
+lazy_static! {
+    /// The context lengths servers have reported, by API URL and model, so that
+    /// providers created later start with the right capacity.
+    static ref MAX_MODEL_LENS: RwLock<HashMap<(String, String), usize>> = Default::default();
+}
+
+/// Constrains the output of a vLLM completion. Only one kind of guide can be
+/// used per request.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GuidedDecoding {
+    /// The output must be valid JSON matching this JSON schema.
+    GuidedJson(serde_json::Value),
+    /// The output must match this regular expression.
+    GuidedRegex(String),
+    /// The output must be exactly one of these strings.
+    GuidedChoice(Vec<String>),
+    /// The output must match this context-free grammar, in EBNF form.
+    GuidedGrammar(String),
+}
+
+/// A chat completion request for vLLM's OpenAI-compatible server, which accepts
+/// a number of sampling parameters that OpenAI itself doesn't.
+#[derive(Debug, Default, Serialize)]
+pub struct VllmRequest {
+    pub model: String,
+    pub messages: Vec<RequestMessage>,
+    pub stream: bool,
+    /// Asks for a final event with the token usage of the whole completion.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    pub stop: Vec<String>,
+    pub temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "LogitBias::is_empty")]
+    pub logit_bias: LogitBias,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// How many choices to generate. Events for choices after the first arrive
+    /// wrapped in [`CompletionEvent::Choice`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repetition_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_of: Option<u32>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub guided_decoding: Option<GuidedDecoding>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl VllmRequest {
+    pub fn with_sampling_params(mut self, params: &SamplingParams) -> Self {
+        self.max_tokens = params.max_tokens;
+        self.top_p = params.top_p;
+        self.top_k = params.top_k;
+        self.presence_penalty = params.presence_penalty;
+        self.frequency_penalty = params.frequency_penalty;
+        self.logit_bias = params.logit_bias.clone();
+        self
+    }
+}
+
+impl From<ChatRequest> for VllmRequest {
+    fn from(request: ChatRequest) -> Self {
+        VllmRequest {
+            model: request.model,
+            messages: request.messages,
+            stream: request.stream,
+            stop: request.stop,
+            temperature: request.temperature,
+            seed: request.seed,
+            n: request.n,
+            tools: request.tools,
+            tool_choice: request.tool_choice,
+            response_format: request.response_format,
+            extra: request.extra,
+            ..Default::default()
+        }
+        .with_sampling_params(&request.sampling)
+    }
+}
+
+impl ChatRequest {
+    /// Constrains the output with vLLM's guided decoding. Other providers don't
+    /// accept the resulting parameters, so only use this for requests to vLLM.
+    pub fn with_guided_decoding(mut self, guide: GuidedDecoding) -> Self {
+        if let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(guide) {
+            self.extra.extend(fields);
+        }
+        self
+    }
+}
+
+/// A plain completion request for vLLM's `/completions` endpoint. The prompt is
+/// sent as is, without the model's chat template, which fill-in-the-middle prompts
+/// and models that have no chat template need. llama.cpp's server accepts the
+/// same requests.
+#[derive(Debug, Default, Serialize)]
+pub struct VllmRawRequest {
+    pub model: String,
+    pub prompt: String,
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    pub temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "LogitBias::is_empty")]
+    pub logit_bias: LogitBias,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Asks llama.cpp to reuse the cached prefix of the previous prompt. vLLM
+    /// ignores it, and reuses prefixes on its own when started with
+    /// `--enable-prefix-caching`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_prompt: Option<bool>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl VllmRawRequest {
+    pub fn new(model: impl Into<String>, prompt: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            prompt: prompt.into(),
+            stream: true,
+            ..Default::default()
+        }
+    }
+
+    /// Asks `model` for the code between `prefix` and `suffix`, laid out in the
+    /// model's fill-in-the-middle format and stopping at its end-of-middle tokens.
+    pub fn fill_in_the_middle(model: &str, prefix: &str, suffix: &str) -> Result<Self> {
+        let template = fim_template(model)
+            .ok_or_else(|| anyhow!("{model} doesn't support fill-in-the-middle"))?;
+        let mut request = Self::new(model, template.prompt(prefix, suffix));
+        request.stop = template.stop.iter().map(|stop| stop.to_string()).collect();
+        // Successive requests share most of their prefix as the user types.
+        request.cache_prompt = Some(true);
+        Ok(request)
+    }
+
+    pub fn with_sampling_params(mut self, params: &SamplingParams) -> Self {
+        self.max_tokens = params.max_tokens;
+        self.top_p = params.top_p;
+        self.top_k = params.top_k;
+        self.presence_penalty = params.presence_penalty;
+        self.frequency_penalty = params.frequency_penalty;
+        self.logit_bias = params.logit_bias.clone();
+        self
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VllmRawChoice {
+    pub index: u32,
+    pub text: String,
+    pub finish_reason: Option<String>,
+}
+
+/// A chunk of a streamed response from the `/completions` endpoint.
+#[derive(Deserialize, Debug)]
+pub struct VllmRawStreamEvent {
+    pub choices: Vec<VllmRawChoice>,
+    pub usage: Option<OpenAiUsage>,
+}
+
+impl VllmRawStreamEvent {
+    pub fn into_completion_events(self) -> Vec<CompletionEvent> {
+        let mut events = Vec::new();
+        for choice in self.choices {
+            let mut choice_events = vec![CompletionEvent::Delta {
+                role: None,
+                text: choice.text,
+            }];
+            if let Some(finish_reason) = choice.finish_reason {
+                choice_events.push(CompletionEvent::FinishReason(finish_reason.as_str().into()));
+            }
+
+            if choice.index == 0 {
+                events.extend(choice_events);
+            } else {
+                events.extend(
+                    choice_events
+                        .into_iter()
+                        .map(|event| CompletionEvent::Choice {
+                            index: choice.index,
+                            event: Box::new(event),
+                        }),
+                );
+            }
+        }
+        if let Some(usage) = self.usage {
+            events.push(CompletionEvent::Usage(usage.into()));
+        }
+        events
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct VllmModel {
+    pub id: String,
+    pub owned_by: String,
+    /// Only reported by newer versions of vLLM.
+    pub max_model_len: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct VllmModelList {
+    data: Vec<VllmModel>,
+}
+
+/// Lists the models served by the vLLM server at `api_url`.
+pub async fn list_models(
+    client: &dyn HttpClient,
+    api_url: &str,
+    credential: &ProviderCredential,
+    options: &ConnectionOptions,
+) -> Result<Vec<VllmModel>> {
+    let request = options
+        .configure(authorized(
+            Request::get(format!("{api_url}/models")),
+            credential,
+        ))
+        .body(AsyncBody::empty())?;
+    let mut response = client
+        .send(request)
+        .await
+        .map_err(|error| CompletionError::network(PROVIDER_NAME, error))?;
+
+    if response.status() == StatusCode::OK {
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+        let models: VllmModelList = serde_json::from_str(&body)?;
+        Ok(models.data)
+    } else {
+        Err(read_error(PROVIDER_NAME, response).await)
+    }
+}
+
+#[derive(Serialize)]
+struct TokenizeRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+}
+
+#[derive(Deserialize)]
+struct TokenizeResponse {
+    count: Option<usize>,
+    #[serde(default)]
+    tokens: Vec<serde_json::Value>,
+}
+
+/// Counts the tokens `model` splits `prompt` into, with the `/tokenize`
+/// endpoint of the vLLM server at `api_url`.
+pub async fn count_tokens(
+    client: &dyn HttpClient,
+    api_url: &str,
+    credential: &ProviderCredential,
+    options: &ConnectionOptions,
+    model: &str,
+    prompt: &str,
+) -> Result<usize> {
+    // The endpoint is served next to the OpenAI-compatible API, not under it.
+    let server_url = api_url.trim_end_matches('/').trim_end_matches("/v1");
+    let request = options
+        .configure(authorized(
+            Request::post(format!("{server_url}/tokenize"))
+                .header("Content-Type", "application/json"),
+            credential,
+        ))
+        .body(serde_json::to_string(&TokenizeRequest { model, prompt })?.into())?;
+    let mut response = client
+        .send(request)
+        .await
+        .map_err(|error| CompletionError::network(PROVIDER_NAME, error))?;
+
+    if response.status() == StatusCode::OK {
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+        let response: TokenizeResponse = serde_json::from_str(&body)?;
+        Ok(response.count.unwrap_or(response.tokens.len()))
+    } else {
+        Err(read_error(PROVIDER_NAME, response).await)
+    }
+}
+
+pub async fn stream_completion(
+    client: Arc<dyn HttpClient>,
+    api_url: String,
+    credential: ProviderCredential,
+    executor: BackgroundExecutor,
+    options: ConnectionOptions,
+    mut request: VllmRequest,
+) -> Result<(
+    Option<RateLimitStatus>,
+    impl Stream<Item = Result<OpenAiResponseStreamEvent>>,
+)> {
+    request.stream_options = stream_options(request.stream, &options);
+    let json_data = serde_json::to_string(&request)?;
+    stream_events(
+        client,
+        PROVIDER_NAME,
+        format!("{api_url}/chat/completions"),
+        credential,
+        executor,
+        options,
+        json_data,
+    )
+    .await
+}
+
+/// Streams a plain completion of `request.prompt`, without a chat template.
+pub async fn stream_raw_completion(
+    client: Arc<dyn HttpClient>,
+    api_url: String,
+    credential: ProviderCredential,
+    executor: BackgroundExecutor,
+    options: ConnectionOptions,
+    mut request: VllmRawRequest,
+) -> Result<(
+    Option<RateLimitStatus>,
+    impl Stream<Item = Result<VllmRawStreamEvent>>,
+)> {
+    request.stream_options = stream_options(request.stream, &options);
+    let json_data = serde_json::to_string(&request)?;
+    stream_events(
+        client,
+        PROVIDER_NAME,
+        format!("{api_url}/completions"),
+        credential,
+        executor,
+        options,
+        json_data,
+    )
+    .await
+}
+
+/// Asks for the token usage at the end of streamed responses only when it's
+/// enabled, since the servers this provider talks to don't all accept it.
+fn stream_options(stream: bool, options: &ConnectionOptions) -> Option<StreamOptions> {
+    (stream && options.includes_stream_usage(false)).then_some(StreamOptions {
+        include_usage: true,
+    })
+}
+
+#[derive(Clone)]
+pub struct VllmCompletionProvider {
+    api_url: String,
+    client: Arc<dyn HttpClient>,
+    model: Arc<RwLock<VllmLanguageModel>>,
+    credential: ApiKeyCredential,
+    executor: BackgroundExecutor,
+    options: ConnectionOptions,
+    /// Parameters added to the body of every request, for server options the
+    /// requests don't otherwise set.
+    extra_body: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Adds `extra_body` to a request's extra parameters, keeping the ones the
+/// request already sets.
+fn add_extra_body(
+    extra: &mut serde_json::Map<String, serde_json::Value>,
+    extra_body: &serde_json::Map<String, serde_json::Value>,
+) {
+    for (key, value) in extra_body {
+        extra.entry(key.clone()).or_insert_with(|| value.clone());
+    }
+}
+
+impl VllmCompletionProvider {
+    pub fn new(
+        api_url: String,
+        model_name: String,
+        client: Arc<dyn HttpClient>,
+        executor: BackgroundExecutor,
+    ) -> Self {
+        let max_model_len = MAX_MODEL_LENS
+            .read()
+            .get(&(api_url.clone(), model_name.clone()))
+            .copied();
+        let model = Arc::new(RwLock::new(VllmLanguageModel::load(
+            &model_name,
+            max_model_len,
+        )));
+        // vLLM only checks API keys when started with `--api-key`, so a missing
+        // key isn't an error.
+        let credential = ApiKeyCredential::optional(VLLM_API_URL, "VLLM_API_KEY");
+        Self {
+            api_url,
+            client,
+            model,
+            credential,
+            executor,
+            options: ConnectionOptions::default(),
+            extra_body: Default::default(),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.options.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn with_timeouts(mut self, timeouts: CompletionTimeouts) -> Self {
+        self.options.timeouts = timeouts;
+        self
+    }
+
+    pub fn with_tls(mut self, tls: TlsOptions) -> Self {
+        self.options.tls = tls;
+        self
+    }
+
+    pub fn with_extra_headers(mut self, extra_headers: BTreeMap<String, String>) -> Self {
+        self.options.extra_headers = extra_headers;
+        self
+    }
+
+    pub fn with_stream_buffer_size(mut self, stream_buffer_size: usize) -> Self {
+        self.options.stream_buffer_size = stream_buffer_size;
+        self
+    }
+
+    pub fn with_stream_usage(mut self, stream_usage: Option<bool>) -> Self {
+        self.options.stream_usage = stream_usage;
+        self
+    }
+
+    pub fn with_extra_body(
+        mut self,
+        extra_body: serde_json::Map<String, serde_json::Value>,
+    ) -> Self {
+        self.extra_body = extra_body;
+        self
+    }
+
+    /// Lists the models available on the server, updating and remembering the
+    /// context length of the current model if the server reports it.
+    pub fn available_models(&self) -> BoxFuture<'static, Result<Vec<VllmModel>>> {
+        let client = self.client.clone();
+        let api_url = self.api_url.clone();
+        let credential = self.credential.get();
+        let model = self.model.clone();
+        let options = self.options.clone();
+        async move {
+            let models = list_models(client.as_ref(), &api_url, &credential, &options).await?;
+            let name = model.read().name();
+            if let Some(served_model) = models.iter().find(|served| served.id == name) {
+                *model.write() = VllmLanguageModel::load(&name, served_model.max_model_len);
+                if let Some(max_model_len) = served_model.max_model_len {
+                    MAX_MODEL_LENS
+                        .write()
+                        .insert((api_url, name), max_model_len);
+                }
+            }
+            Ok(models)
+        }
+        .boxed()
+    }
+
+    /// Measures the model's tokenizer with the server's `/tokenize` endpoint, so
+    /// its tokens are counted closely instead of with `cl100k_base` alone.
+    pub fn load_tokenizer(&self) -> BoxFuture<'static, Result<()>> {
+        let client = self.client.clone();
+        let api_url = self.api_url.clone();
+        let credential = self.credential.get();
+        let options = self.options.clone();
+        let model = self.model.clone();
+        async move {
+            let name = model.read().name();
+            let tokenizer = match tokenizer::cached_tokenizer(&name) {
+                Some(tokenizer) => tokenizer,
+                None => {
+                    let count = count_tokens(
+                        client.as_ref(),
+                        &api_url,
+                        &credential,
+                        &options,
+                        &name,
+                        tokenizer::CALIBRATION_TEXT,
+                    )
+                    .await?;
+                    let tokenizer = Arc::new(tokenizer::ScaledTokenizer::calibrated(count)?);
+                    tokenizer::cache_tokenizer(&name, tokenizer.clone());
+                    tokenizer
+                }
+            };
+            model.write().set_tokenizer(tokenizer);
+            Ok(())
+        }
+        .boxed()
+    }
+
+    /// Whether the current model can fill in code between a prefix and a suffix.
+    pub fn supports_fim(&self) -> bool {
+        fim_template(&self.model.read().name()).is_some()
+    }
+
+    /// Streams the code that belongs between `prefix` and `suffix`, for inline
+    /// code completion.
+    pub fn complete_fim(
+        &self,
+        prefix: &str,
+        suffix: &str,
+        sampling: &SamplingParams,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let model = self.model.read().name();
+        match VllmRawRequest::fill_in_the_middle(&model, prefix, suffix) {
+            Ok(request) => self.complete_raw(request.with_sampling_params(sampling)),
+            Err(error) => async move { Err(error) }.boxed(),
+        }
+    }
+
+    /// Completes `request.prompt` as plain text, without applying the model's chat
+    /// template. Used for fill-in-the-middle and for base models.
+    pub fn complete_raw(
+        &self,
+        mut request: VllmRawRequest,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        add_extra_body(&mut request.extra, &self.extra_body);
+        let credential = self.credential.get();
+        let request = stream_raw_completion(
+            self.client.clone(),
+            self.api_url.clone(),
+            credential,
+            self.executor.clone(),
+            self.options.clone(),
+            request,
+        );
+        async move {
+            let (rate_limits, response) = request.await?;
+            let rate_limits = rate_limits.map(|status| Ok(CompletionEvent::RateLimits(status)));
+            let events = stream::iter(rate_limits)
+                .chain(response.flat_map(|response| {
+                    let events = match response {
+                        Ok(response) => response
+                            .into_completion_events()
+                            .into_iter()
+                            .map(Ok)
+                            .collect(),
+                        Err(error) => vec![Err(error)],
+                    };
+                    stream::iter(events)
+                }))
+                .boxed();
+            Ok(with_done_event(events))
+        }
+        .boxed()
+    }
+}
+
+impl CredentialProvider for VllmCompletionProvider {
+    fn has_credentials(&self) -> bool {
+        self.credential.has_credentials()
+    }
+
+    fn retrieve_credentials(&self, cx: &mut AppContext) -> BoxFuture<ProviderCredential> {
+        self.credential.retrieve_credentials(cx)
+    }
+
+    fn save_credentials(
+        &self,
+        cx: &mut AppContext,
+        credential: ProviderCredential,
+    ) -> BoxFuture<()> {
+        self.credential.save_credentials(cx, credential)
+    }
+
+    fn delete_credentials(&self, cx: &mut AppContext) -> BoxFuture<()> {
+        self.credential.delete_credentials(cx)
+    }
+}
+
+impl CompletionProvider for VllmCompletionProvider {
+    fn base_model(&self) -> Box<dyn LanguageModel> {
+        let model: Box<dyn LanguageModel> = Box::new(self.model.read().clone());
+        model
+    }
+    fn complete(
+        &self,
+        request: ChatRequest,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<CompletionEvent>>>> {
+        let credential = self.credential.get();
+        let api_url = self.api_url.clone();
+        let mut request = VllmRequest::from(request);
+        add_extra_body(&mut request.extra, &self.extra_body);
+        let request = stream_completion(
+            self.client.clone(),
+            api_url,
+            credential,
+            self.executor.clone(),
+            self.options.clone(),
+            request,
+        );
+        async move {
+            let (rate_limits, response) = request.await?;
+            let rate_limits = rate_limits.map(|status| Ok(CompletionEvent::RateLimits(status)));
+            let events = stream::iter(rate_limits)
+                .chain(response.flat_map(|response| {
+                    let events = match response {
+                        Ok(response) => response
+                            .into_completion_events()
+                            .into_iter()
+                            .map(Ok)
+                            .collect(),
+                        Err(error) => vec![Err(error)],
+                    };
+                    stream::iter(events)
+                }))
+                .boxed();
+            Ok(with_done_event(events))
+        }
+        .boxed()
+    }
+    fn box_clone(&self) -> Box<dyn CompletionProvider> {
+        Box::new((*self).clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cassette::CassetteClient, chat::Role, completion::FinishReason,
+        providers::open_ai::OPEN_AI_BPE_TOKENIZER, test::collect_events,
+    };
+    use gpui::TestAppContext;
+    use util::http::{FakeHttpClient, Response};
+
+    #[gpui::test]
+    async fn test_complete_with_fake_client(cx: &mut TestAppContext) {
+        let client = FakeHttpClient::create(|mut request| async move {
+            assert_eq!(request.uri().path(), "/v1/chat/completions");
+            let mut body = String::new();
+            request.body_mut().read_to_string(&mut body).await.unwrap();
+            let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(body["min_p"], 0.05);
+            assert_eq!(body["guided_regex"], "[a-z]+");
+
+            let body = [
+                r#"data: {"id": "cmpl-1", "object": "chat.completion.chunk", "created": 1710000000, "model": "mistral", "choices": [{"index": 0, "delta": {"role": "assistant", "content": "Hello"}, "finish_reason": null}]}"#,
+                r#"data: {"id": "cmpl-1", "object": "chat.completion.chunk", "created": 1710000000, "model": "mistral", "choices": [{"index": 0, "delta": {"content": " world"}, "finish_reason": "stop"}]}"#,
+                "data: [DONE]",
+            ]
+            .join("\n\n");
+            Ok(Response::builder()
+                .status(200)
+                .body(AsyncBody::from(body))
+                .unwrap())
+        });
+        let provider = VllmCompletionProvider::new(
+            VLLM_API_URL.into(),
+            "mistral".into(),
+            client,
+            cx.executor(),
+        )
+        .with_extra_body(
+            serde_json::json!({ "min_p": 0.05, "guided_regex": "[0-9]+" })
+                .as_object()
+                .unwrap()
+                .clone(),
+        );
+
+        let request = ChatRequest {
+            model: "mistral".into(),
+            stream: true,
+            ..Default::default()
+        }
+        .with_guided_decoding(GuidedDecoding::GuidedRegex("[a-z]+".into()));
+        let events = provider
+            .complete(request)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            events,
+            [
+                CompletionEvent::Delta {
+                    role: Some(Role::Assistant),
+                    text: "Hello".into()
+                },
+                CompletionEvent::Delta {
+                    role: None,
+                    text: " world".into()
+                },
+                CompletionEvent::FinishReason(FinishReason::Stop),
+                CompletionEvent::Done,
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_complete_raw(cx: &mut TestAppContext) {
+        let client = FakeHttpClient::create(|mut request| async move {
+            assert_eq!(request.uri().path(), "/v1/completions");
+            let mut body = String::new();
+            request.body_mut().read_to_string(&mut body).await.unwrap();
+            let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(body["prompt"], "fn main(");
+            assert!(body.get("messages").is_none());
+
+            let body = [
+                r#"data: {"id": "cmpl-2", "object": "text_completion", "created": 1710000000, "model": "codellama", "choices": [{"index": 0, "text": ")", "logprobs": null, "finish_reason": null}]}"#,
+                r#"data: {"id": "cmpl-2", "object": "text_completion", "created": 1710000000, "model": "codellama", "choices": [{"index": 0, "text": " {", "logprobs": null, "finish_reason": "length"}]}"#,
+                "data: [DONE]",
+            ]
+            .join("\n\n");
+            Ok(Response::builder()
+                .status(200)
+                .body(AsyncBody::from(body))
+                .unwrap())
+        });
+        let provider = VllmCompletionProvider::new(
+            VLLM_API_URL.into(),
+            "codellama".into(),
+            client,
+            cx.executor(),
+        );
+
+        let events = collect_events(
+            provider
+                .complete_raw(VllmRawRequest::new("codellama", "fn main("))
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(
+            events,
+            [
+                CompletionEvent::Delta {
+                    role: None,
+                    text: ")".into()
+                },
+                CompletionEvent::Delta {
+                    role: None,
+                    text: " {".into()
+                },
+                CompletionEvent::FinishReason(FinishReason::Length),
+                CompletionEvent::Done,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fill_in_the_middle_request() {
+        let request = VllmRawRequest::fill_in_the_middle(
+            "codellama/CodeLlama-7b-hf",
+            "fn add(a: i32, b: i32) -> i32 {\n    ",
+            "\n}",
+        )
+        .unwrap();
+        assert_eq!(
+            request.prompt,
+            "<PRE> fn add(a: i32, b: i32) -> i32 {\n     <SUF>\n} <MID>"
+        );
+        assert_eq!(request.stop, ["<EOT>"]);
+        assert_eq!(
+            serde_json::to_value(&request).unwrap()["cache_prompt"],
+            true
+        );
+
+        assert!(
+            VllmRawRequest::fill_in_the_middle("meta-llama/Meta-Llama-3-8B-Instruct", "", "")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_serialize_vllm_request() {
+        let request = VllmRequest {
+            model: "codellama/CodeLlama-13b-Instruct-hf".into(),
+            stream: true,
+            temperature: 0.2,
+            top_k: Some(40),
+            guided_decoding: Some(GuidedDecoding::GuidedChoice(vec![
+                "yes".into(),
+                "no".into(),
+            ])),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["top_k"], 40);
+        assert_eq!(json["guided_choice"], serde_json::json!(["yes", "no"]));
+        assert!(json.get("repetition_penalty").is_none());
+        assert!(json.get("best_of").is_none());
+        assert!(json.get("seed").is_none());
+        assert!(json.get("guided_decoding").is_none());
+        assert!(json.get("tools").is_none());
+        assert!(json.get("tool_choice").is_none());
+
+        let request = VllmRequest::from(
+            ChatRequest {
+                model: "codellama/CodeLlama-13b-Instruct-hf".into(),
+                ..Default::default()
+            }
+            .with_guided_decoding(GuidedDecoding::GuidedRegex("[0-9]+".into())),
+        );
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["guided_regex"], "[0-9]+");
+    }
+
+    #[test]
+    fn test_parse_model_list() {
+        let models: VllmModelList = serde_json::from_str(
+            r#"{
+                "object": "list",
+                "data": [
+                    {"id": "mistralai/Mistral-7B-Instruct-v0.2", "object": "model", "created": 1710000000, "owned_by": "vllm", "max_model_len": 32768},
+                    {"id": "codellama/CodeLlama-13b-Instruct-hf", "object": "model", "created": 1710000000, "owned_by": "vllm"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(models.data[0].max_model_len, Some(32768));
+        assert_eq!(models.data[1].max_model_len, None);
+    }
+
+    #[gpui::test]
+    async fn test_load_tokenizer(cx: &mut TestAppContext) {
+        let model = "zed-industries/test-model";
+        let client = FakeHttpClient::create(|mut request| async move {
+            assert_eq!(request.uri().path(), "/tokenize");
+            let mut body = String::new();
+            request.body_mut().read_to_string(&mut body).await.unwrap();
+            let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(body["model"], "zed-industries/test-model");
+            // The model splits text into twice as many tokens as cl100k_base.
+            let count = 2 * OPEN_AI_BPE_TOKENIZER
+                .encode_with_special_tokens(body["prompt"].as_str().unwrap())
+                .len();
+            Ok(Response::builder()
+                .status(200)
+                .body(AsyncBody::from(format!(r#"{{"count": {count}}}"#)))
+                .unwrap())
+        });
+        let provider =
+            VllmCompletionProvider::new(VLLM_API_URL.into(), model.into(), client, cx.executor());
+        provider.load_tokenizer().await.unwrap();
+
+        let content = "fn main() {}";
+        let base_tokens = OPEN_AI_BPE_TOKENIZER
+            .encode_with_special_tokens(content)
+            .len();
+        assert_eq!(
+            provider.base_model().count_tokens(content).unwrap(),
+            2 * base_tokens
+        );
+        assert!(tokenizer::cached_tokenizer(model).is_some());
+    }
+
+    #[gpui::test]
+    async fn test_context_length(cx: &mut TestAppContext) {
+        let api_url = "http://context-length.test/v1";
+        let model = "codellama/CodeLlama-13b-Instruct-hf";
+        let client = FakeHttpClient::create(|request| async move {
+            assert_eq!(request.uri().path(), "/v1/models");
+            Ok(Response::builder()
+                .status(200)
+                .body(AsyncBody::from(
+                    r#"{"object": "list", "data": [{"id": "codellama/CodeLlama-13b-Instruct-hf", "owned_by": "vllm", "max_model_len": 4096}]}"#,
+                ))
+                .unwrap())
+        });
+
+        // Until the server is asked, the context length is guessed from the model's family.
+        let provider = VllmCompletionProvider::new(
+            api_url.into(),
+            model.into(),
+            client.clone(),
+            cx.executor(),
+        );
+        assert_eq!(provider.base_model().capacity().unwrap(), 16384);
+
+        provider.available_models().await.unwrap();
+        assert_eq!(provider.base_model().capacity().unwrap(), 4096);
+
+        let provider =
+            VllmCompletionProvider::new(api_url.into(), model.into(), client, cx.executor());
+        assert_eq!(provider.base_model().capacity().unwrap(), 4096);
+    }
+
+    #[gpui::test]
+    async fn test_replay_chat_stream(cx: &mut TestAppContext) {
+        let model = "mistralai/Mistral-7B-Instruct-v0.2";
+        let client = CassetteClient::fixture("vllm_chat_stream", util::http::client);
+        let provider = VllmCompletionProvider::new(
+            VLLM_API_URL.into(),
+            model.into(),
+            client.clone(),
+            cx.executor(),
+        );
+
+        let request = ChatRequest {
+            model: model.into(),
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: "Write the signature of Rust's entry point".into(),
+            }],
+            stream: true,
+            sampling: SamplingParams {
+                max_tokens: Some(3),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let events = collect_events(provider.complete(request).await.unwrap()).await;
+        client.finish().unwrap();
+        assert_eq!(
+            events,
+            [
+                CompletionEvent::Delta {
+                    role: Some(Role::Assistant),
+                    text: "".into()
+                },
+                CompletionEvent::Delta {
+                    role: None,
+                    text: "fn".into()
+                },
+                CompletionEvent::Delta {
+                    role: None,
+                    text: " main()".into()
+                },
+                CompletionEvent::Delta {
+                    role: None,
+                    text: "".into()
+                },
+                CompletionEvent::FinishReason(FinishReason::Length),
+                CompletionEvent::Done,
+            ]
+        );
+    }
+}
@@ -0,0 +1,94 @@
+/// How a code model expects a fill-in-the-middle prompt to be laid out. The model
+/// generates the code that belongs between `prefix` and `suffix`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FimTemplate {
+    pub prefix: &'static str,
+    pub suffix: &'static str,
+    pub middle: &'static str,
+    /// Sequences the model emits once the middle is complete.
+    pub stop: &'static [&'static str],
+}
+
+impl FimTemplate {
+    pub fn prompt(&self, prefix: &str, suffix: &str) -> String {
+        format!(
+            "{}{prefix}{}{suffix}{}",
+            self.prefix, self.suffix, self.middle
+        )
+    }
+}
+
+const CODE_LLAMA: FimTemplate = FimTemplate {
+    prefix: "<PRE> ",
+    suffix: " <SUF>",
+    middle: " <MID>",
+    stop: &["<EOT>"],
+};
+
+const DEEPSEEK_CODER: FimTemplate = FimTemplate {
+    prefix: "<｜fim▁begin｜>",
+    suffix: "<｜fim▁hole｜>",
+    middle: "<｜fim▁end｜>",
+    stop: &["<｜end▁of▁sentence｜>", "<|EOT|>"],
+};
+
+const STARCODER: FimTemplate = FimTemplate {
+    prefix: "<fim_prefix>",
+    suffix: "<fim_suffix>",
+    middle: "<fim_middle>",
+    stop: &["<|endoftext|>", "<file_sep>"],
+};
+
+const QWEN_CODER: FimTemplate = FimTemplate {
+    prefix: "<|fim_prefix|>",
+    suffix: "<|fim_suffix|>",
+    middle: "<|fim_middle|>",
+    stop: &["<|endoftext|>", "<|fim_pad|>", "<|file_sep|>"],
+};
+
+/// Fill-in-the-middle templates of the code model families trained for it.
+/// Matched against the lowercased model name in order, so more specific names
+/// come first.
+const FAMILY_FIM_TEMPLATES: &[(&str, FimTemplate)] = &[
+    ("codellama", CODE_LLAMA),
+    ("deepseek-coder", DEEPSEEK_CODER),
+    ("starcoder", STARCODER),
+    ("qwen2.5-coder", QWEN_CODER),
+];
+
+/// The fill-in-the-middle template for `model_name`, or `None` if the model
+/// isn't known to support it.
+pub fn fim_template(model_name: &str) -> Option<FimTemplate> {
+    let model_name = model_name.to_lowercase();
+    FAMILY_FIM_TEMPLATES
+        .iter()
+        .find(|(family, _)| model_name.contains(family))
+        .map(|(_, template)| *template)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fim_template() {
+        assert_eq!(
+            fim_template("codellama/CodeLlama-7b-hf")
+                .unwrap()
+                .prompt("fn main() {\n", "\n}"),
+            "<PRE> fn main() {\n <SUF>\n} <MID>"
+        );
+        assert_eq!(
+            fim_template("bigcode/starcoder2-3b")
+                .unwrap()
+                .prompt("a", "b"),
+            "<fim_prefix>a<fim_suffix>b<fim_middle>"
+        );
+        assert_eq!(
+            fim_template("deepseek-ai/deepseek-coder-6.7b-base"),
+            Some(DEEPSEEK_CODER)
+        );
+        assert_eq!(fim_template("Qwen/Qwen2.5-Coder-7B"), Some(QWEN_CODER));
+        assert_eq!(fim_template("meta-llama/Meta-Llama-3-8B-Instruct"), None);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::providers::vllm::GuidedDecoding;
+
+/// Builds an EBNF grammar for [`GuidedDecoding::GuidedGrammar`]. The first rule added
+/// is the start rule, and every rule name must be a lowercase identifier.
+///
+/// The constructors cover the shapes we most often want to extract from local models;
+/// anything else can be written out with [`Grammar::rule`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Grammar {
+    rules: Vec<(String, String)>,
+}
+
+impl Grammar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rule, replacing any existing rule with the same name.
+    pub fn rule(mut self, name: impl Into<String>, expression: impl Into<String>) -> Self {
+        let name = name.into();
+        let expression = expression.into();
+        if let Some(rule) = self
+            .rules
+            .iter_mut()
+            .find(|(existing, _)| *existing == name)
+        {
+            rule.1 = expression;
+        } else {
+            self.rules.push((name, expression));
+        }
+        self
+    }
+
+    /// Matches exactly one of the given strings.
+    pub fn choice<T: AsRef<str>>(options: impl IntoIterator<Item = T>) -> Self {
+        let alternatives = options
+            .into_iter()
+            .map(|option| literal(option.as_ref()))
+            .collect::<Vec<_>>();
+        Self::new().rule("start", alternatives.join(" | "))
+    }
+
+    /// Matches "yes" or "no".
+    pub fn yes_no() -> Self {
+        Self::choice(["yes", "no"])
+    }
+
+    /// Matches an optionally negative integer.
+    pub fn integer() -> Self {
+        Self::new()
+            .rule("start", r#""-"? digit+"#)
+            .rule("digit", r#""0".."9""#)
+    }
+
+    /// Matches one or more lines, each starting with `- ` followed by text that
+    /// doesn't span lines.
+    pub fn bullet_list() -> Self {
+        Self::new()
+            .rule("start", "item+")
+            .rule("item", r#""- " text "\n""#)
+            .rule("text", r#"/[^\n]+/"#)
+    }
+
+    /// Renders the grammar in the EBNF dialect vLLM accepts.
+    pub fn build(&self) -> String {
+        let mut grammar = String::new();
+        for (name, expression) in &self.rules {
+            grammar.push_str(name);
+            grammar.push_str(": ");
+            grammar.push_str(expression);
+            grammar.push('\n');
+        }
+        grammar
+    }
+}
+
+impl From<Grammar> for GuidedDecoding {
+    fn from(grammar: Grammar) -> Self {
+        GuidedDecoding::GuidedGrammar(grammar.build())
+    }
+}
+
+/// Quotes `text` as a grammar terminal.
+fn literal(text: &str) -> String {
+    // EBNF string terminals use the same escapes as JSON strings.
+    serde_json::to_string(text).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_grammar() {
+        assert_eq!(Grammar::yes_no().build(), "start: \"yes\" | \"no\"\n");
+        assert_eq!(
+            Grammar::choice(["say \"hi\"", "a\nb"]).build(),
+            "start: \"say \\\"hi\\\"\" | \"a\\nb\"\n"
+        );
+        assert_eq!(
+            Grammar::integer().rule("start", "digit+").build(),
+            "start: digit+\ndigit: \"0\"..\"9\"\n"
+        );
+        assert_eq!(
+            GuidedDecoding::from(Grammar::bullet_list()),
+            GuidedDecoding::GuidedGrammar(
+                "start: item+\nitem: \"- \" text \"\\n\"\ntext: /[^\\n]+/\n".into()
+            )
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use crate::chat::{RequestMessage, Role};
+use crate::models::LanguageModel;
+use crate::providers::open_ai::OPEN_AI_BPE_TOKENIZER;
+use crate::providers::vllm::tokenizer::cached_tokenizer;
+use crate::tokenizer::Tokenizer;
+
+/// The context length vLLM falls back to when a model's config doesn't specify one.
+const DEFAULT_MAX_MODEL_LEN: usize = 4096;
+
+/// Context lengths of common model families, for servers too old to report
+/// `max_model_len`. Matched against the lowercased model name in order, so more
+/// specific names come first.
+const FAMILY_CONTEXT_LENGTHS: &[(&str, usize)] = &[
+    ("codellama", 16384),
+    ("llama-3", 8192),
+    ("llama-2", 4096),
+    ("mixtral", 32768),
+    ("mistral", 8192),
+    ("deepseek-coder", 16384),
+    ("starcoder2", 16384),
+    ("qwen", 32768),
+];
+
+fn family_context_length(model_name: &str) -> usize {
+    let model_name = model_name.to_lowercase();
+    FAMILY_CONTEXT_LENGTHS
+        .iter()
+        .find(|(family, _)| model_name.contains(family))
+        .map_or(DEFAULT_MAX_MODEL_LEN, |(_, context_length)| *context_length)
+}
+
+/// A model served by vLLM. vLLM can serve arbitrary HuggingFace models, so tokens are
+/// counted with `cl100k_base`, scaled to the model's own tokenizer once the server
+/// has measured it. The context length is taken from the server's
+/// model list when it's known, or guessed from the model's family otherwise.
+#[derive(Clone)]
+pub struct VllmLanguageModel {
+    name: String,
+    tokenizer: Arc<dyn Tokenizer>,
+    max_model_len: Option<usize>,
+}
+
+impl VllmLanguageModel {
+    pub fn load(model_name: &str, max_model_len: Option<usize>) -> Self {
+        let tokenizer = match cached_tokenizer(model_name) {
+            Some(tokenizer) => tokenizer as Arc<dyn Tokenizer>,
+            None => Arc::new(OPEN_AI_BPE_TOKENIZER.to_owned()),
+        };
+        VllmLanguageModel {
+            name: model_name.to_string(),
+            tokenizer,
+            max_model_len,
+        }
+    }
+
+    pub fn set_tokenizer(&mut self, tokenizer: Arc<dyn Tokenizer>) {
+        self.tokenizer = tokenizer;
+    }
+
+    /// Lays `messages` out the way the model's chat template would, so the tokens
+    /// the template adds are counted too. Models from other families are assumed
+    /// to use ChatML.
+    fn render_chat(&self, messages: &[RequestMessage]) -> String {
+        let name = self.name.to_lowercase();
+        let mut prompt = String::new();
+        if name.contains("llama-3") {
+            prompt.push_str("<|begin_of_text|>");
+            for message in messages {
+                prompt.push_str(&format!(
+                    "<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>",
+                    message.role.as_str(),
+                    message.content
+                ));
+            }
+            prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+        } else if ["llama-2", "codellama", "mistral", "mixtral"]
+            .iter()
+            .any(|family| name.contains(family))
+        {
+            prompt.push_str("<s>");
+            for message in messages {
+                match message.role {
+                    Role::System => prompt.push_str(&format!(
+                        "[INST] <<SYS>>\n{}\n<</SYS>>\n\n [/INST]",
+                        message.content
+                    )),
+                    Role::User => prompt.push_str(&format!("[INST] {} [/INST]", message.content)),
+                    Role::Assistant => prompt.push_str(&format!(" {}</s>", message.content)),
+                }
+            }
+        } else {
+            for message in messages {
+                prompt.push_str(&format!(
+                    "<|im_start|>{}\n{}<|im_end|>\n",
+                    message.role.as_str(),
+                    message.content
+                ));
+            }
+            prompt.push_str("<|im_start|>assistant\n");
+        }
+        prompt
+    }
+}
+
+impl LanguageModel for VllmLanguageModel {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+    fn tokenizer(&self) -> Arc<dyn Tokenizer> {
+        self.tokenizer.clone()
+    }
+    fn count_message_tokens(&self, messages: &[RequestMessage]) -> anyhow::Result<usize> {
+        self.count_tokens(&self.render_chat(messages))
+    }
+    fn capacity(&self) -> anyhow::Result<usize> {
+        anyhow::Ok(
+            self.max_model_len
+                .unwrap_or_else(|| family_context_length(&self.name)),
+        )
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use parking_lot::RwLock;
+use std::{collections::HashMap, sync::Arc};
+use tiktoken_rs::CoreBPE;
+
+use crate::{
+    models::TruncationDirection, providers::open_ai::OPEN_AI_BPE_TOKENIZER, tokenizer::Tokenizer,
+};
+
+/// Text the server tokenizes to measure a model's tokenizer against
+/// `cl100k_base`: a mix of prose and code, like the prompts the assistant sends.
+pub const CALIBRATION_TEXT: &str = "\
+Explain what this function does, and rewrite it so that it doesn't allocate.
+
+fn word_counts(text: &str) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for word in text.split_whitespace() {
+        *counts.entry(word.to_lowercase()).or_insert(0) += 1;
+    }
+    counts
+}
+
+The function is called for every line of a large log file, so it's worth
+keeping it fast. Return the counts sorted by frequency, most frequent first.";
+
+lazy_static! {
+    static ref TOKENIZERS: RwLock<HashMap<String, Arc<ScaledTokenizer>>> = Default::default();
+}
+
+/// Counts a model's tokens with `cl100k_base`, scaled by how many tokens the
+/// model's own tokenizer produces for the same text. Llama-family tokenizers
+/// produce noticeably more tokens than OpenAI's, so unscaled counts overfill
+/// their context.
+#[derive(Debug)]
+pub struct ScaledTokenizer {
+    /// The model's tokens per `cl100k_base` token.
+    ratio: f64,
+}
+
+impl ScaledTokenizer {
+    /// A tokenizer for a model whose tokenizer produced `model_tokens` for
+    /// [`CALIBRATION_TEXT`].
+    pub fn calibrated(model_tokens: usize) -> Result<Self> {
+        let base_tokens = OPEN_AI_BPE_TOKENIZER
+            .encode_with_special_tokens(CALIBRATION_TEXT)
+            .len();
+        if model_tokens == 0 {
+            return Err(anyhow!("the server counted no tokens"));
+        }
+        Ok(Self {
+            ratio: model_tokens as f64 / base_tokens as f64,
+        })
+    }
+}
+
+impl Tokenizer for ScaledTokenizer {
+    fn encode(&self, content: &str) -> Result<Vec<usize>> {
+        Ok(OPEN_AI_BPE_TOKENIZER.encode_with_special_tokens(content))
+    }
+
+    fn decode(&self, tokens: Vec<usize>) -> Result<String> {
+        OPEN_AI_BPE_TOKENIZER.decode(tokens)
+    }
+
+    fn count_tokens(&self, content: &str) -> Result<usize> {
+        let base_tokens = self.encode(content)?.len();
+        Ok((base_tokens as f64 * self.ratio).ceil() as usize)
+    }
+
+    fn truncate(
+        &self,
+        content: &str,
+        length: usize,
+        direction: TruncationDirection,
+    ) -> Result<String> {
+        let base_length = (length as f64 / self.ratio).floor() as usize;
+        <CoreBPE as Tokenizer>::truncate(&OPEN_AI_BPE_TOKENIZER, content, base_length, direction)
+    }
+}
+
+/// The tokenizer for `model_name`, if it has already been measured.
+pub fn cached_tokenizer(model_name: &str) -> Option<Arc<ScaledTokenizer>> {
+    TOKENIZERS.read().get(model_name).cloned()
+}
+
+/// Remembers `model_name`'s tokenizer, so models created later start with it.
+pub fn cache_tokenizer(model_name: &str, tokenizer: Arc<ScaledTokenizer>) {
+    TOKENIZERS.write().insert(model_name.to_string(), tokenizer);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scaled_tokenizer() {
+        let base_tokens = OPEN_AI_BPE_TOKENIZER
+            .encode_with_special_tokens(CALIBRATION_TEXT)
+            .len();
+        let tokenizer = ScaledTokenizer::calibrated(base_tokens * 2).unwrap();
+        let content = "fn main() { println!(\"hello\"); }";
+        let content_tokens = OPEN_AI_BPE_TOKENIZER
+            .encode_with_special_tokens(content)
+            .len();
+        assert_eq!(tokenizer.count_tokens(content).unwrap(), content_tokens * 2);
+
+        let truncated = tokenizer
+            .truncate(content, 6, TruncationDirection::End)
+            .unwrap();
+        assert!(tokenizer.count_tokens(&truncated).unwrap() <= 6);
+        assert!(ScaledTokenizer::calibrated(0).is_err());
+    }
+}
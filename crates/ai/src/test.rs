@@ -1,20 +1,53 @@
 use std::{
-    sync::atomic::{self, AtomicUsize, Ordering},
-    time::Instant,
+    collections::VecDeque,
+    sync::{
+        atomic::{self, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
+use anyhow::anyhow;
 use async_trait::async_trait;
-use futures::{channel::mpsc, future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
-use gpui::AppContext;
+use futures::{
+    channel::mpsc,
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    FutureExt, StreamExt,
+};
+use gpui::{AppContext, BackgroundExecutor};
 use parking_lot::Mutex;
 
 use crate::{
     auth::{CredentialProvider, ProviderCredential},
-    completion::{CompletionProvider, CompletionRequest},
+    chat::ChatRequest,
+    completion::{with_done_event, CompletionEvent, CompletionProvider},
     embedding::{Embedding, EmbeddingProvider},
     models::{LanguageModel, TruncationDirection},
+    tokenizer::Tokenizer,
 };
 
+/// Treats every character as a token.
+pub struct FakeTokenizer;
+
+impl Tokenizer for FakeTokenizer {
+    fn encode(&self, content: &str) -> anyhow::Result<Vec<usize>> {
+        Ok(content
+            .chars()
+            .map(|character| character as usize)
+            .collect())
+    }
+
+    fn decode(&self, tokens: Vec<usize>) -> anyhow::Result<String> {
+        tokens
+            .into_iter()
+            .map(|token| {
+                char::from_u32(token as u32).ok_or_else(|| anyhow!("invalid token {token}"))
+            })
+            .collect()
+    }
+}
+
 #[derive(Clone)]
 pub struct FakeLanguageModel {
     pub capacity: usize,
@@ -24,6 +57,9 @@ impl LanguageModel for FakeLanguageModel {
     fn name(&self) -> String {
         "dummy".to_string()
     }
+    fn tokenizer(&self) -> Arc<dyn Tokenizer> {
+        Arc::new(FakeTokenizer)
+    }
     fn count_tokens(&self, content: &str) -> anyhow::Result<usize> {
         anyhow::Ok(content.chars().collect::<Vec<char>>().len())
     }
@@ -47,6 +83,14 @@ impl LanguageModel for FakeLanguageModel {
             TruncationDirection::Start => content.chars().collect::<Vec<char>>()[length..]
                 .into_iter()
                 .collect::<String>(),
+            TruncationDirection::Middle => {
+                let chars = content.chars().collect::<Vec<char>>();
+                let tail_length = length / 2;
+                chars[..length - tail_length]
+                    .iter()
+                    .chain(&chars[chars.len() - tail_length..])
+                    .collect::<String>()
+            }
         })
     }
     fn capacity(&self) -> anyhow::Result<usize> {
@@ -143,33 +187,173 @@ impl EmbeddingProvider for FakeEmbeddingProvider {
     }
 }
 
-pub struct FakeCompletionProvider {
-    last_completion_tx: Mutex<Option<mpsc::Sender<String>>>,
+/// One step of a scripted [`FakeCompletionProvider`] response.
+#[derive(Debug)]
+pub enum FakeStep {
+    Event(CompletionEvent),
+    /// Fails the stream at this point.
+    Error(String),
+    /// Waits before the next step, on the executor given to
+    /// [`FakeCompletionProvider::with_executor`].
+    Delay(Duration),
 }
 
-impl Clone for FakeCompletionProvider {
-    fn clone(&self) -> Self {
-        Self {
-            last_completion_tx: Mutex::new(None),
-        }
+/// A response for [`FakeCompletionProvider::respond_with`], built up step by step.
+#[derive(Debug, Default)]
+pub struct FakeResponse {
+    steps: Vec<FakeStep>,
+}
+
+impl FakeResponse {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A response that streams `chunks` as assistant deltas.
+    pub fn chunks<'a>(chunks: impl IntoIterator<Item = &'a str>) -> Self {
+        chunks
+            .into_iter()
+            .fold(Self::new(), |response, chunk| response.text(chunk))
     }
+
+    /// A response that streams `events` as they are.
+    pub fn events(events: impl IntoIterator<Item = CompletionEvent>) -> Self {
+        events
+            .into_iter()
+            .fold(Self::new(), |response, event| response.event(event))
+    }
+
+    pub fn text(self, text: impl Into<String>) -> Self {
+        self.event(CompletionEvent::Delta {
+            role: None,
+            text: text.into(),
+        })
+    }
+
+    pub fn event(mut self, event: CompletionEvent) -> Self {
+        self.steps.push(FakeStep::Event(event));
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.steps.push(FakeStep::Delay(delay));
+        self
+    }
+
+    /// Ends the stream with an error instead of [`CompletionEvent::Done`].
+    pub fn error(mut self, message: impl Into<String>) -> Self {
+        self.steps.push(FakeStep::Error(message.into()));
+        self
+    }
+}
+
+#[derive(Default)]
+struct FakeCompletionState {
+    last_completion_tx: Option<mpsc::UnboundedSender<String>>,
+    responses: VecDeque<anyhow::Result<FakeResponse>>,
+    requests: Vec<ChatRequest>,
+}
+
+/// A deterministic completion provider for tests. Requests are answered with the
+/// responses queued with [`Self::respond_with`] and [`Self::fail_next`], in order.
+/// When none are queued, the test streams the completion by hand with
+/// [`Self::send_completion`] and [`Self::finish_completion`].
+///
+/// Clones share their queued responses and recorded requests.
+#[derive(Clone, Default)]
+pub struct FakeCompletionProvider {
+    state: Arc<Mutex<FakeCompletionState>>,
+    executor: Option<BackgroundExecutor>,
 }
 
 impl FakeCompletionProvider {
     pub fn new() -> Self {
-        Self {
-            last_completion_tx: Mutex::new(None),
-        }
+        Self::default()
+    }
+
+    /// Sets the executor that [`FakeStep::Delay`]s wait on, which lets tests
+    /// control time with `advance_clock`.
+    pub fn with_executor(mut self, executor: BackgroundExecutor) -> Self {
+        self.executor = Some(executor);
+        self
+    }
+
+    pub fn respond_with(&self, response: FakeResponse) {
+        self.state.lock().responses.push_back(Ok(response));
+    }
+
+    /// Makes the next request fail before streaming anything.
+    pub fn fail_next(&self, error: impl Into<anyhow::Error>) {
+        self.state.lock().responses.push_back(Err(error.into()));
+    }
+
+    /// The requests sent so far.
+    pub fn requests(&self) -> Vec<ChatRequest> {
+        self.state.lock().requests.clone()
     }
 
     pub fn send_completion(&self, completion: impl Into<String>) {
-        let mut tx = self.last_completion_tx.lock();
-        tx.as_mut().unwrap().try_send(completion.into()).unwrap();
+        self.state
+            .lock()
+            .last_completion_tx
+            .as_ref()
+            .unwrap()
+            .unbounded_send(completion.into())
+            .unwrap();
     }
 
     pub fn finish_completion(&self) {
-        self.last_completion_tx.lock().take().unwrap();
+        self.state.lock().last_completion_tx.take().unwrap();
     }
+
+    fn scripted_stream(
+        &self,
+        response: FakeResponse,
+    ) -> BoxStream<'static, anyhow::Result<CompletionEvent>> {
+        let executor = self.executor.clone();
+        let steps = stream::iter(response.steps).filter_map(move |step| {
+            let executor = executor.clone();
+            async move {
+                match step {
+                    FakeStep::Event(event) => Some(Ok(event)),
+                    FakeStep::Error(message) => Some(Err(anyhow!(message))),
+                    FakeStep::Delay(delay) => {
+                        executor
+                            .expect("delays need FakeCompletionProvider::with_executor")
+                            .timer(delay)
+                            .await;
+                        None
+                    }
+                }
+            }
+        });
+        with_done_event(steps.boxed())
+    }
+}
+
+/// Collects a completion's events, panicking if the completion fails.
+pub async fn collect_events(
+    events: BoxStream<'static, anyhow::Result<CompletionEvent>>,
+) -> Vec<CompletionEvent> {
+    events
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<anyhow::Result<Vec<_>>>()
+        .expect("completion failed")
+}
+
+/// Collects the text of a completion's assistant deltas, panicking if the
+/// completion fails.
+pub async fn collect_text(events: BoxStream<'static, anyhow::Result<CompletionEvent>>) -> String {
+    collect_events(events)
+        .await
+        .into_iter()
+        .filter_map(|event| match event {
+            CompletionEvent::Delta { text, .. } => Some(text),
+            _ => None,
+        })
+        .collect()
 }
 
 impl CredentialProvider for FakeCompletionProvider {
@@ -201,13 +385,87 @@ impl CompletionProvider for FakeCompletionProvider {
     }
     fn complete(
         &self,
-        _prompt: Box<dyn CompletionRequest>,
-    ) -> BoxFuture<'static, anyhow::Result<BoxStream<'static, anyhow::Result<String>>>> {
-        let (tx, rx) = mpsc::channel(1);
-        *self.last_completion_tx.lock() = Some(tx);
-        async move { Ok(rx.map(|rx| Ok(rx)).boxed()) }.boxed()
+        request: ChatRequest,
+    ) -> BoxFuture<'static, anyhow::Result<BoxStream<'static, anyhow::Result<CompletionEvent>>>>
+    {
+        let mut state = self.state.lock();
+        state.requests.push(request);
+
+        let response = state.responses.pop_front();
+        match response {
+            Some(Ok(response)) => {
+                drop(state);
+                let events = self.scripted_stream(response);
+                async move { Ok(events) }.boxed()
+            }
+            Some(Err(error)) => async move { Err(error) }.boxed(),
+            None => {
+                let (tx, rx) = mpsc::unbounded();
+                state.last_completion_tx = Some(tx);
+                let events = rx.map(|text| Ok(CompletionEvent::Delta { role: None, text }));
+                async move { Ok(with_done_event(events.boxed())) }.boxed()
+            }
+        }
     }
     fn box_clone(&self) -> Box<dyn CompletionProvider> {
-        Box::new((*self).clone())
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::completion::CompletionError;
+    use gpui::TestAppContext;
+
+    fn request(model: &str) -> ChatRequest {
+        ChatRequest {
+            model: model.into(),
+            ..Default::default()
+        }
+    }
+
+    #[gpui::test]
+    async fn test_fake_completion_provider(cx: &mut TestAppContext) {
+        let provider = FakeCompletionProvider::new().with_executor(cx.executor());
+        provider.respond_with(
+            FakeResponse::chunks(["Hello", " world"])
+                .delay(Duration::from_secs(1))
+                .text("!"),
+        );
+        provider.fail_next(CompletionError::Timeout {
+            provider: "fake",
+            timeout: Duration::from_secs(30),
+        });
+        provider.respond_with(FakeResponse::new().text("Hi").error("connection reset"));
+
+        let events = provider.complete(request("first")).await.unwrap();
+        let text = cx.background_executor.spawn(collect_text(events));
+        cx.background_executor.run_until_parked();
+        cx.background_executor.advance_clock(Duration::from_secs(1));
+        assert_eq!(text.await, "Hello world!");
+
+        assert!(provider.complete(request("second")).await.is_err());
+
+        let events = provider
+            .complete(request("third"))
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(events.len(), 2);
+        assert!(events[1].is_err());
+
+        assert_eq!(
+            provider.requests(),
+            [request("first"), request("second"), request("third")]
+        );
+
+        // Completions streamed by hand can be sent before the test reads them.
+        let events = provider.complete(request("fourth")).await.unwrap();
+        provider.send_completion("Hello");
+        provider.send_completion(" world");
+        provider.finish_completion();
+        assert_eq!(collect_text(events).await, "Hello world");
     }
 }
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use tiktoken_rs::CoreBPE;
+
+use crate::models::TruncationDirection;
+
+/// Turns text into the tokens a model sees, and back. Models supply their own,
+/// so token counts match what the provider will charge for.
+pub trait Tokenizer: Send + Sync {
+    fn encode(&self, content: &str) -> Result<Vec<usize>>;
+    fn decode(&self, tokens: Vec<usize>) -> Result<String>;
+
+    fn count_tokens(&self, content: &str) -> Result<usize> {
+        Ok(self.encode(content)?.len())
+    }
+
+    fn truncate(
+        &self,
+        content: &str,
+        length: usize,
+        direction: TruncationDirection,
+    ) -> Result<String> {
+        let tokens = self.encode(content)?;
+        if tokens.len() > length {
+            match direction {
+                TruncationDirection::End => self.decode(tokens[..length].to_vec()),
+                TruncationDirection::Start => self.decode(tokens[length..].to_vec()),
+                TruncationDirection::Middle => {
+                    let tail_length = length / 2;
+                    let head_length = length - tail_length;
+                    let mut kept_tokens = tokens[..head_length].to_vec();
+                    kept_tokens.extend_from_slice(&tokens[tokens.len() - tail_length..]);
+                    self.decode(kept_tokens)
+                }
+            }
+        } else {
+            Ok(content.to_string())
+        }
+    }
+}
+
+/// OpenAI's byte-pair encodings, via tiktoken.
+impl Tokenizer for CoreBPE {
+    fn encode(&self, content: &str) -> Result<Vec<usize>> {
+        Ok(self.encode_with_special_tokens(content))
+    }
+
+    fn decode(&self, tokens: Vec<usize>) -> Result<String> {
+        CoreBPE::decode(self, tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{providers::open_ai::OPEN_AI_BPE_TOKENIZER, test::FakeTokenizer};
+
+    #[test]
+    fn test_truncate() {
+        let tokenizer: &dyn Tokenizer = &FakeTokenizer;
+        assert_eq!(tokenizer.count_tokens("hello").unwrap(), 5);
+        assert_eq!(
+            tokenizer
+                .truncate("hello", 3, TruncationDirection::End)
+                .unwrap(),
+            "hel"
+        );
+        assert_eq!(
+            tokenizer
+                .truncate("hello", 10, TruncationDirection::End)
+                .unwrap(),
+            "hello"
+        );
+        assert_eq!(
+            tokenizer
+                .truncate("hello world", 5, TruncationDirection::Middle)
+                .unwrap(),
+            "helld"
+        );
+
+        let tokenizer: &dyn Tokenizer = &*OPEN_AI_BPE_TOKENIZER;
+        let content = "fn main() { println!(\"hello\"); }";
+        let tokens = tokenizer.encode(content).unwrap();
+        assert_eq!(tokenizer.count_tokens(content).unwrap(), tokens.len());
+        assert_eq!(tokenizer.decode(tokens).unwrap(), content);
+    }
+}
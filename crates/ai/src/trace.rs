@@ -0,0 +1,323 @@
+use anyhow::Result;
+use futures::{stream::BoxStream, StreamExt};
+use lazy_static::lazy_static;
+use parking_lot::{Mutex, RwLock};
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use util::ResultExt;
+
+use crate::{
+    chat::ChatRequest,
+    completion::{CompletionEvent, CompletionMiddleware},
+};
+
+/// Secrets that are scrubbed from every trace, whether or not they're configured.
+const BUILT_IN_PATTERNS: &[&str] = &[
+    // OpenAI-style API keys.
+    r"sk-[A-Za-z0-9_\-]{8,}",
+    // Bearer tokens, e.g. in echoed headers.
+    r"(?i)bearer\s+[A-Za-z0-9._~+/\-]+=*",
+];
+
+const REDACTED: &str = "[REDACTED]";
+
+lazy_static! {
+    static ref TRACER: RwLock<Option<Arc<RequestTracer>>> = RwLock::new(None);
+}
+
+/// Sets the tracer that completion and embedding requests are recorded with, or
+/// stops tracing when `tracer` is `None`.
+pub fn set_tracer(tracer: Option<Arc<RequestTracer>>) {
+    *TRACER.write() = tracer;
+}
+
+/// The tracer requests should be recorded with, if tracing is enabled.
+pub fn tracer() -> Option<Arc<RequestTracer>> {
+    TRACER.read().clone()
+}
+
+/// Scrubs secrets from text before it's written to a trace.
+pub struct Redactor {
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    /// Creates a redactor for the built-in secret patterns and `patterns`, which
+    /// are regular expressions.
+    pub fn new<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let patterns = BUILT_IN_PATTERNS
+            .iter()
+            .copied()
+            .chain(patterns)
+            .map(Regex::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { patterns })
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for pattern in &self.patterns {
+            if let std::borrow::Cow::Owned(redacted) = pattern.replace_all(&text, REDACTED) {
+                text = redacted;
+            }
+        }
+        text
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceKind {
+    Completion,
+    Embedding,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceDirection {
+    Request,
+    Response,
+}
+
+#[derive(Serialize)]
+struct TraceEntry<'a> {
+    /// Milliseconds since the Unix epoch.
+    timestamp: u128,
+    kind: TraceKind,
+    direction: TraceDirection,
+    target: &'a str,
+    body: &'a str,
+}
+
+/// Writes the bodies of completion and embedding requests and their responses to
+/// a log file, one JSON entry per line, with secrets scrubbed. The file is rotated
+/// once it reaches `max_file_size` bytes, keeping `max_files` old files.
+pub struct RequestTracer {
+    path: PathBuf,
+    max_file_size: u64,
+    max_files: usize,
+    redactor: Redactor,
+    file: Mutex<Option<File>>,
+}
+
+impl RequestTracer {
+    pub const DEFAULT_MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+    pub const DEFAULT_MAX_FILES: usize = 3;
+
+    pub fn new(path: impl Into<PathBuf>, redactor: Redactor) -> Self {
+        Self {
+            path: path.into(),
+            max_file_size: Self::DEFAULT_MAX_FILE_SIZE,
+            max_files: Self::DEFAULT_MAX_FILES,
+            redactor,
+            file: Mutex::new(None),
+        }
+    }
+
+    pub fn with_rotation(mut self, max_file_size: u64, max_files: usize) -> Self {
+        self.max_file_size = max_file_size;
+        self.max_files = max_files;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records a request or response body. `target` identifies where it was sent,
+    /// such as the model or URL. Failing to write the trace is logged, not returned.
+    pub fn record(&self, kind: TraceKind, direction: TraceDirection, target: &str, body: &str) {
+        let body = self.redactor.redact(body);
+        let target = self.redactor.redact(target);
+        let entry = TraceEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_millis()),
+            kind,
+            direction,
+            target: &target,
+            body: &body,
+        };
+        self.write_line(&entry).log_err();
+    }
+
+    fn write_line(&self, entry: &TraceEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut file = self.file.lock();
+        if let Some(open_file) = file.as_ref() {
+            if open_file.metadata()?.len() + line.len() as u64 > self.max_file_size {
+                *file = None;
+                self.rotate()?;
+            }
+        }
+        let file = match file.as_mut() {
+            Some(file) => file,
+            None => {
+                if let Some(dir) = self.path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                file.insert(
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&self.path)?,
+                )
+            }
+        };
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Moves `trace.log` to `trace.log.1`, `trace.log.1` to `trace.log.2` and so
+    /// on, dropping the oldest file.
+    fn rotate(&self) -> Result<()> {
+        let rotated = |ix: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{ix}"));
+            PathBuf::from(path)
+        };
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+            return Ok(());
+        }
+        for ix in (1..self.max_files).rev() {
+            if rotated(ix).exists() {
+                fs::rename(rotated(ix), rotated(ix + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated(1))?;
+        Ok(())
+    }
+}
+
+/// Records every completion request and the response it gets with the tracer set
+/// by [`set_tracer`]. Does nothing while tracing is disabled.
+pub struct TracingMiddleware;
+
+#[derive(Default, Serialize)]
+struct TracedResponse {
+    text: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    events: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Collects a completion's events and records them once the completion ends or
+/// is dropped.
+struct ResponseRecorder {
+    tracer: Arc<RequestTracer>,
+    target: String,
+    response: TracedResponse,
+}
+
+impl ResponseRecorder {
+    fn observe(&mut self, event: &Result<CompletionEvent>) {
+        match event {
+            Ok(CompletionEvent::Delta { text, .. }) => self.response.text.push_str(text),
+            Ok(event) => self.response.events.push(format!("{event:?}")),
+            Err(error) => self.response.error = Some(error.to_string()),
+        }
+    }
+}
+
+impl Drop for ResponseRecorder {
+    fn drop(&mut self) {
+        if let Some(response) = serde_json::to_string(&self.response).log_err() {
+            self.tracer.record(
+                TraceKind::Completion,
+                TraceDirection::Response,
+                &self.target,
+                &response,
+            );
+        }
+    }
+}
+
+impl CompletionMiddleware for TracingMiddleware {
+    // Requests are recorded before they're sent, so they're in the trace even if
+    // the provider fails to answer.
+    fn process_request(&self, request: ChatRequest) -> Result<ChatRequest> {
+        if let Some(tracer) = tracer() {
+            tracer.record(
+                TraceKind::Completion,
+                TraceDirection::Request,
+                &request.model,
+                &serde_json::to_string(&request)?,
+            );
+        }
+        Ok(request)
+    }
+
+    fn process_events(
+        &self,
+        request: &ChatRequest,
+        events: BoxStream<'static, Result<CompletionEvent>>,
+    ) -> BoxStream<'static, Result<CompletionEvent>> {
+        let Some(tracer) = tracer() else {
+            return events;
+        };
+        let mut recorder = ResponseRecorder {
+            tracer,
+            target: request.model.clone(),
+            response: TracedResponse::default(),
+        };
+        events
+            .map(move |event| {
+                recorder.observe(&event);
+                event
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redaction() {
+        let redactor = Redactor::new(["acme-[0-9]+"]).unwrap();
+        assert_eq!(
+            redactor.redact(r#"{"key": "sk-abcdefghijklmnop", "project": "acme-1234"}"#),
+            r#"{"key": "[REDACTED]", "project": "[REDACTED]"}"#
+        );
+        assert_eq!(
+            redactor.redact("Authorization: Bearer abc.def-123"),
+            "Authorization: [REDACTED]"
+        );
+    }
+
+    #[test]
+    fn test_trace_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace.log");
+        let tracer = RequestTracer::new(&path, Redactor::new([]).unwrap()).with_rotation(200, 2);
+        for _ in 0..5 {
+            tracer.record(
+                TraceKind::Embedding,
+                TraceDirection::Request,
+                "text-embedding-ada-002",
+                r#"{"input": ["fn main() {}"]}"#,
+            );
+        }
+
+        assert!(path.exists());
+        assert!(dir.path().join("trace.log.1").exists());
+        assert!(dir.path().join("trace.log.2").exists());
+        assert!(!dir.path().join("trace.log.3").exists());
+        for entry in fs::read_to_string(&path).unwrap().lines() {
+            let entry: serde_json::Value = serde_json::from_str(entry).unwrap();
+            assert_eq!(entry["kind"], "embedding");
+        }
+    }
+}
@@ -1,22 +1,60 @@
 pub mod assistant_panel;
 pub mod assistant_settings;
+mod code_actions;
+mod code_block;
 mod codegen;
+mod conflicts;
+mod conversation_export;
+mod conversation_history;
+mod conversation_import;
+mod conversation_store;
+mod conversation_template;
+mod file_reference;
+mod inline_completion;
+mod metrics_view;
+mod model_indicator;
+mod model_switcher;
+mod prompt_library;
 mod prompts;
+mod repository;
+pub mod slash_command;
 mod streaming_diff;
+mod terminal_assistant;
 
-use ai::providers::open_ai::Role;
+use ai::{
+    chat::Role,
+    completion::TokenUsage,
+    models::ModelName,
+    trace::{self, Redactor, RequestTracer},
+};
 use anyhow::Result;
 pub use assistant_panel::AssistantPanel;
-use assistant_settings::OpenAiModel;
+use assistant_settings::{AssistantSettings, ConversationRetentionSettings, ProviderKind};
 use chrono::{DateTime, Local};
-use collections::HashMap;
+use code_actions::AssistantCodeActionProvider;
+use collections::{HashMap, HashSet};
+use conversation_store::CONVERSATION_STORE;
+use editor::InlineCompletionProvider;
 use fs::Fs;
 use futures::StreamExt;
 use gpui::{actions, AppContext, SharedString};
+use inline_completion::LocalInlineCompletionProvider;
+pub use model_indicator::ModelIndicator;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::{cmp::Reverse, ffi::OsStr, path::PathBuf, sync::Arc};
-use util::paths::CONVERSATIONS_DIR;
+use settings::{Settings, SettingsStore};
+pub use slash_command::{SlashCommand, SlashCommandOutput, SlashCommandRegistry};
+use std::{
+    cmp::Reverse,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use util::{
+    http::{self, HttpClient, Uri},
+    paths::{AI_TRACE_LOG, CONVERSATIONS_DIR},
+    ResultExt,
+};
 
 actions!(
     assistant,
@@ -25,12 +63,42 @@ actions!(
         Assist,
         Split,
         CycleMessageRole,
+        RegenerateWithSameSeed,
         QuoteSelection,
+        ExplainCode,
+        ExplainTerminalOutput,
+        GenerateTests,
+        GenerateCommitMessage,
+        GeneratePullRequestDescription,
+        TerminalAssist,
         ToggleFocus,
         ResetKey,
         InlineAssist,
+        FixDiagnostic,
+        ResolveConflict,
         ToggleIncludeConversation,
         ToggleRetrieveContext,
+        ShowCompletionMetrics,
+        ShowConversationHistory,
+        ShowPromptLibrary,
+        NewPrompt,
+        ExportConversation,
+        CopyConversationAsMarkdown,
+        CopyConversationAsJson,
+        ImportConversations,
+        RenameConversation,
+        RegenerateFromHere,
+        BranchFromHere,
+        StopGeneration,
+        ContinueGeneration,
+        TogglePinnedMessage,
+        SummarizeOlderMessages,
+        NextMessage,
+        PreviousMessage,
+        SelectMessage,
+        ToggleMessageFold,
+        JumpToLatest,
+        SwitchModel,
     ]
 );
 
@@ -44,21 +112,69 @@ struct MessageMetadata {
     role: Role,
     sent_at: DateTime<Local>,
     status: MessageStatus,
+    /// The seed an assistant message was sampled with, so it can be regenerated
+    /// deterministically.
+    #[serde(default)]
+    seed: Option<u64>,
+    /// The provider that answered an assistant message, if fallback providers
+    /// are configured.
+    #[serde(default)]
+    answered_by: Option<SharedString>,
+    /// Whether the message is always sent as context, even once older messages
+    /// are dropped to fit the model's context.
+    #[serde(default)]
+    pinned: bool,
+    /// The URLs of the sources an assistant message was answered from, for
+    /// providers that search the web.
+    #[serde(default)]
+    citations: Vec<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 enum MessageStatus {
     Pending,
+    /// The request is waiting for other requests to the same provider to finish.
+    Queued(usize),
     Done,
+    /// The response stopped because it reached the maximum number of tokens, and
+    /// can be continued.
+    Truncated,
+    /// The response was stopped by the user before it finished, and can be
+    /// continued.
+    Stopped,
     Error(SharedString),
 }
 
-#[derive(Serialize, Deserialize)]
+impl MessageStatus {
+    /// Whether the message should be sent as context with later requests.
+    fn is_complete(&self) -> bool {
+        matches!(
+            self,
+            MessageStatus::Done | MessageStatus::Truncated | MessageStatus::Stopped
+        )
+    }
+
+    /// Whether the response stopped early and can be picked up where it left off.
+    fn is_continuable(&self) -> bool {
+        matches!(self, MessageStatus::Truncated | MessageStatus::Stopped)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct SavedMessage {
     id: MessageId,
     start: usize,
 }
 
+/// A version of a conversation, kept when the conversation is forked at an
+/// earlier message so it can be switched back to.
+#[derive(Clone, Serialize, Deserialize)]
+struct SavedBranch {
+    text: String,
+    messages: Vec<SavedMessage>,
+    message_metadata: HashMap<MessageId, MessageMetadata>,
+}
+
 #[derive(Serialize, Deserialize)]
 struct SavedConversation {
     id: Option<String>,
@@ -68,14 +184,30 @@ struct SavedConversation {
     messages: Vec<SavedMessage>,
     message_metadata: HashMap<MessageId, MessageMetadata>,
     summary: String,
+    /// The kind of provider the conversation's model is served by. Older
+    /// conversations were always sent to OpenAI.
+    #[serde(default)]
+    provider: ProviderKind,
     api_url: Option<String>,
-    model: OpenAiModel,
+    model: ModelName,
+    #[serde(default)]
+    usage: TokenUsage,
+    /// The temperature to sample responses at, if not the default one.
+    #[serde(default)]
+    temperature: Option<f32>,
+    /// Every version of a forked conversation. The version at `active_branch`
+    /// is the one in `text`, and its entry here is out of date.
+    #[serde(default)]
+    branches: Vec<SavedBranch>,
+    #[serde(default)]
+    active_branch: usize,
 }
 
 impl SavedConversation {
     const VERSION: &'static str = "0.1.0";
 }
 
+#[derive(Clone)]
 struct SavedConversationMetadata {
     title: String,
     path: PathBuf,
@@ -94,18 +226,10 @@ impl SavedConversationMetadata {
                 continue;
             }
 
-            let pattern = r" - \d+.zed.json$";
-            let re = Regex::new(pattern).unwrap();
-
             let metadata = fs.metadata(&path).await?;
-            if let Some((file_name, metadata)) = path
-                .file_name()
-                .and_then(|name| name.to_str())
-                .zip(metadata)
-            {
-                let title = re.replace(file_name, "");
+            if let Some((title, metadata)) = conversation_title(&path).zip(metadata) {
                 conversations.push(Self {
-                    title: title.into_owned(),
+                    title,
                     path,
                     mtime: metadata.mtime.into(),
                 });
@@ -115,10 +239,155 @@ impl SavedConversationMetadata {
 
         Ok(conversations)
     }
+
+    /// The conversations past `retention`, out of `conversations` sorted newest
+    /// first.
+    fn expired<'a>(
+        conversations: &'a [Self],
+        retention: &ConversationRetentionSettings,
+        now: DateTime<Local>,
+    ) -> impl Iterator<Item = &'a Self> {
+        let max_age = retention
+            .max_age_days
+            .map(|days| chrono::Duration::days(days.into()));
+        let max_conversations = retention.max_conversations.unwrap_or(usize::MAX);
+        conversations
+            .iter()
+            .enumerate()
+            .filter(move |(index, conversation)| {
+                *index >= max_conversations
+                    || max_age.map_or(false, |max_age| now - conversation.mtime > max_age)
+            })
+            .map(|(_, conversation)| conversation)
+    }
+
+    /// Deletes the saved conversations past the retention policy in the settings,
+    /// and returns the rest.
+    pub async fn prune(
+        conversations: Vec<Self>,
+        retention: &ConversationRetentionSettings,
+        fs: &dyn Fs,
+    ) -> Vec<Self> {
+        let expired = Self::expired(&conversations, retention, Local::now())
+            .map(|conversation| conversation.path.clone())
+            .collect::<HashSet<_>>();
+        for path in &expired {
+            fs.remove_file(path, Default::default()).await.log_err();
+            CONVERSATION_STORE
+                .delete_conversation(path.clone())
+                .await
+                .log_err();
+        }
+        conversations
+            .into_iter()
+            .filter(|conversation| !expired.contains(&conversation.path))
+            .collect()
+    }
+}
+
+/// The title of the conversation saved at `path`, which is its file name without
+/// the discriminant and extension.
+fn conversation_title(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_str()?;
+    let re = Regex::new(r" - \d+.zed.json$").unwrap();
+    Some(re.replace(file_name, "").into_owned())
+}
+
+/// `title` as it appears in the name of the file a conversation is saved to.
+fn file_title(title: &str) -> String {
+    title.trim().replace(['/', '\\'], "-")
 }
 
-pub fn init(cx: &mut AppContext) {
+/// A path in the conversations directory that isn't taken yet, for saving a
+/// conversation titled `title`.
+async fn new_conversation_path(title: &str, fs: &dyn Fs) -> PathBuf {
+    let title = file_title(title);
+    let mut discriminant = 1;
+    loop {
+        let path = CONVERSATIONS_DIR.join(format!("{title} - {discriminant}.zed.json"));
+        if fs.is_file(&path).await {
+            discriminant += 1;
+        } else {
+            return path;
+        }
+    }
+}
+
+pub fn init(http_client: Arc<dyn HttpClient>, cx: &mut AppContext) {
     assistant_panel::init(cx);
+    metrics_view::init(cx);
+    conversation_history::init(cx);
+    prompt_library::init(cx);
+    model_switcher::init(cx);
+    slash_command::init(cx);
+    editor::set_code_action_provider(Some(Arc::new(AssistantCodeActionProvider)), cx);
+
+    let mut trace_settings = None;
+    let mut update_tracer = move |cx: &mut AppContext| {
+        let settings = &AssistantSettings::get_global(cx).debug_trace;
+        if trace_settings.as_ref() == Some(settings) {
+            return;
+        }
+        trace_settings = Some(settings.clone());
+
+        let tracer = if settings.enabled {
+            Redactor::new(settings.redaction_patterns.iter().map(String::as_str))
+                .log_err()
+                .map(|redactor| Arc::new(RequestTracer::new(AI_TRACE_LOG.as_path(), redactor)))
+        } else {
+            None
+        };
+        trace::set_tracer(tracer);
+    };
+    update_tracer(cx);
+    cx.observe_global::<SettingsStore>(update_tracer).detach();
+
+    let mut inline_completion_settings = None;
+    let mut update_inline_completions = move |cx: &mut AppContext| {
+        let assistant_settings = AssistantSettings::get_global(cx);
+        let settings = (
+            assistant_settings.inline_completions.clone(),
+            assistant_settings.model_routes.clone(),
+        );
+        if inline_completion_settings.as_ref() == Some(&settings) {
+            return;
+        }
+        inline_completion_settings = Some(settings.clone());
+
+        let (settings, routes) = settings;
+        let provider = if settings.enabled {
+            let provider = LocalInlineCompletionProvider::new(
+                settings,
+                &routes,
+                crate::http_client(http_client.clone(), cx),
+                cx.background_executor().clone(),
+            );
+            Some(Arc::new(provider) as Arc<dyn InlineCompletionProvider>)
+        } else {
+            None
+        };
+        editor::set_inline_completion_provider(provider, cx);
+    };
+    update_inline_completions(cx);
+    cx.observe_global::<SettingsStore>(update_inline_completions)
+        .detach();
+}
+
+/// Returns the client to send completion and embedding requests with, which goes
+/// through the proxy from the assistant settings if one is configured, and
+/// reaches the hosts listed in `NO_PROXY` directly.
+pub fn http_client(default_client: Arc<dyn HttpClient>, cx: &AppContext) -> Arc<dyn HttpClient> {
+    let configured_proxy = AssistantSettings::get_global(cx).proxy.as_deref();
+    let proxy = match configured_proxy {
+        Some(proxy) => proxy.parse::<Uri>().log_err(),
+        None => util::http_proxy_from_env(),
+    };
+    let no_proxy = util::no_proxy_from_env();
+    if proxy.is_none() || (configured_proxy.is_none() && no_proxy.is_empty()) {
+        return default_client;
+    }
+
+    http::client_with_proxy(proxy, no_proxy)
 }
 
 #[cfg(test)]
@@ -128,3 +397,44 @@ fn init_logger() {
         env_logger::init();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired_conversations() {
+        let now = Local::now();
+        let conversation = |title: &str, days_old: i64| SavedConversationMetadata {
+            title: title.into(),
+            path: PathBuf::from(format!("/conversations/{title} - 1.zed.json")),
+            mtime: now - chrono::Duration::days(days_old),
+        };
+        let conversations = vec![
+            conversation("new", 0),
+            conversation("recent", 3),
+            conversation("old", 10),
+        ];
+        let expired = |retention: ConversationRetentionSettings| {
+            SavedConversationMetadata::expired(&conversations, &retention, now)
+                .map(|conversation| conversation.title.as_str())
+                .collect::<Vec<_>>()
+        };
+
+        assert!(expired(ConversationRetentionSettings::default()).is_empty());
+        assert_eq!(
+            expired(ConversationRetentionSettings {
+                max_age_days: Some(7),
+                ..Default::default()
+            }),
+            ["old"]
+        );
+        assert_eq!(
+            expired(ConversationRetentionSettings {
+                max_conversations: Some(1),
+                ..Default::default()
+            }),
+            ["recent", "old"]
+        );
+    }
+}
@@ -1,73 +1,174 @@
+mod branching;
+mod code_block_toolbar;
+mod message_actions;
+mod model_choice;
+mod summarization;
+
+use message_actions::{render_message_actions, render_role_menu};
+pub(crate) use model_choice::ModelChoice;
+use model_choice::{build_completion_provider, RequestOptions};
+
 use crate::{
-    assistant_settings::{AssistantDockPosition, AssistantSettings, OpenAiModel},
+    assistant_settings::{
+        AssistantDockPosition, AssistantSettings, FallbackProviderSettings,
+        ProjectAssistantSettings, ProviderKind, SystemPrompt, VllmSettings,
+    },
+    code_block::{code_blocks, replace_rows, CodeBlock},
     codegen::{self, Codegen, CodegenKind},
-    prompts::generate_content_prompt,
-    Assist, CycleMessageRole, InlineAssist, MessageId, MessageMetadata, MessageStatus,
-    NewConversation, QuoteSelection, ResetKey, Role, SavedConversation, SavedConversationMetadata,
-    SavedMessage, Split, ToggleFocus, ToggleIncludeConversation, ToggleRetrieveContext,
+    conflicts::find_conflicts,
+    conversation_export::{
+        ExportFormat, ExportedConversation, ExportedMessage, ExportedParameters,
+    },
+    conversation_import::{import_conversations, save_imported_conversation},
+    conversation_store::{IndexedConversation, CONVERSATION_STORE},
+    conversation_template::{load_conversation_templates, ConversationTemplate, TemplateMessage},
+    conversation_title,
+    file_reference::{
+        file_references, format_file_reference, project_path_for_reference, FileReference,
+    },
+    file_title, new_conversation_path,
+    prompt_library::split_front_matter,
+    prompts::{
+        commit_message_prompt, conventional_test_path, conversation_summary_message,
+        diff_summary_prompt, explain_terminal_output_prompt, fix_diagnostic_prompt,
+        generate_content_prompt, generate_tests_prompt, load_generate_tests_template,
+        load_prompt_template, pull_request_prompt, resolve_conflict_prompt, source_imports,
+        split_diff, summarize_conversation_prompt, truncate_diff, INLINE_ASSIST_TEMPLATE,
+    },
+    repository::{dot_git_for_path, pull_request_template, repository_for_path},
+    slash_command::{
+        SlashCommand, SlashCommandCompletionProvider, SlashCommandLine, SlashCommandOutput,
+        SlashCommandRegistry,
+    },
+    terminal_assistant::{
+        focused_terminal, shell_name, TerminalAssistant, EXPLAINED_TERMINAL_LINES,
+    },
+    Assist, BranchFromHere, ContinueGeneration, CopyConversationAsJson, CopyConversationAsMarkdown,
+    CycleMessageRole, ExplainCode, ExplainTerminalOutput, ExportConversation, FixDiagnostic,
+    GenerateCommitMessage, GeneratePullRequestDescription, GenerateTests, ImportConversations,
+    InlineAssist, JumpToLatest, MessageId, MessageMetadata, MessageStatus, NewConversation,
+    NextMessage, PreviousMessage, QuoteSelection, RegenerateFromHere, RegenerateWithSameSeed,
+    RenameConversation, ResetKey, ResolveConflict, Role, SavedBranch, SavedConversation,
+    SavedConversationMetadata, SavedMessage, SelectMessage, Split, StopGeneration,
+    SummarizeOlderMessages, TerminalAssist, ToggleFocus, ToggleIncludeConversation,
+    ToggleMessageFold, TogglePinnedMessage, ToggleRetrieveContext,
 };
 use ai::prompts::repository_context::PromptCodeSnippet;
-use ai::providers::open_ai::OPEN_AI_API_URL;
 use ai::{
     auth::ProviderCredential,
-    completion::{CompletionProvider, CompletionRequest},
-    providers::open_ai::{OpenAiCompletionProvider, OpenAiRequest, RequestMessage},
+    chat::{ChatRequest, RequestMessage},
+    completion::{
+        text_only, CachingCompletionProvider, CancellationHandle, CompletionCache, CompletionError,
+        CompletionEvent, CompletionProvider, ConnectionOptions, ContinuingCompletionProvider,
+        FallbackCompletionProvider, FinishReason, MiddlewareCompletionProvider, RequestScheduler,
+        SamplingParams, ScheduledCompletionProvider, TlsOptions, TokenUsage,
+    },
+    endpoint_pool::{EndpointPool, RoutingStrategy},
+    metrics::{CompletionMetrics, MeasuredCompletionProvider},
+    models::{
+        cheapest_model, truncate_messages_keeping, LanguageModel, ModelName, ModelPricing,
+        ModelProvider, KNOWN_MODELS,
+    },
+    provider_status::{EndpointKind, ProviderStatus, StatusEndpoint},
+    providers::{
+        open_ai::{self, OpenAiCompletionProvider, OpenAiLanguageModel},
+        perplexity::{PerplexityCompletionProvider, PerplexityLanguageModel, PERPLEXITY_API_URL},
+        vllm::{VllmCompletionProvider, VllmLanguageModel, VLLM_API_URL},
+    },
+    trace::TracingMiddleware,
 };
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context as _, Result};
 use chrono::{DateTime, Local};
 use collections::{hash_map, HashMap, HashSet, VecDeque};
+use db::kvp::KEY_VALUE_STORE;
 use editor::{
-    actions::{MoveDown, MoveUp},
+    actions::{MoveDown, MoveUp, Newline},
     display_map::{
         BlockContext, BlockDisposition, BlockId, BlockProperties, BlockStyle, ToDisplayPoint,
     },
     scroll::{Autoscroll, AutoscrollStrategy},
-    Anchor, Editor, EditorElement, EditorEvent, EditorStyle, MultiBufferSnapshot, ToOffset,
-    ToPoint,
+    Anchor, Editor, EditorElement, EditorEvent, EditorStyle, MultiBuffer, MultiBufferSnapshot,
+    ToOffset, ToPoint,
 };
 use fs::Fs;
-use futures::StreamExt;
+use futures::{future, StreamExt, TryStreamExt};
 use gpui::{
-    canvas, div, point, relative, rems, uniform_list, Action, AnyElement, AppContext,
-    AsyncAppContext, AsyncWindowContext, AvailableSpace, ClipboardItem, Context, EventEmitter,
-    FocusHandle, FocusableView, FontStyle, FontWeight, HighlightStyle, InteractiveElement,
-    IntoElement, Model, ModelContext, ParentElement, Pixels, PromptLevel, Render, SharedString,
-    StatefulInteractiveElement, Styled, Subscription, Task, TextStyle, UniformListScrollHandle,
-    View, ViewContext, VisualContext, WeakModel, WeakView, WhiteSpace, WindowContext,
+    canvas, div, point, relative, rems, uniform_list, Action, AnchorCorner, AnyElement, AppContext,
+    AsyncAppContext, AsyncWindowContext, AvailableSpace, BackgroundExecutor, ClickEvent,
+    ClipboardItem, Context, EntityId, EventEmitter, FocusHandle, FocusableView, FontStyle,
+    FontWeight, HighlightStyle, InteractiveElement, IntoElement, Model, ModelContext, MouseButton,
+    ParentElement, PathPromptOptions, Pixels, PromptLevel, Render, SharedString,
+    StatefulInteractiveElement, Styled, Subscription, Task, TextStyle, UnderlineStyle,
+    UniformListScrollHandle, View, ViewContext, VisualContext, WeakModel, WeakView, WhiteSpace,
+    WindowContext,
+};
+use language::{
+    language_settings::SoftWrap, Bias, Buffer, BufferId, DiagnosticSeverity, LanguageRegistry,
+    Point, Selection, ToOffset as _,
 };
-use language::{language_settings::SoftWrap, Buffer, BufferId, LanguageRegistry, ToOffset as _};
-use project::Project;
+use project::{Project, ProjectPath};
 use search::{buffer_search::DivRegistrar, BufferSearchBar};
 use semantic_index::{SemanticIndex, SemanticIndexStatus};
-use settings::Settings;
+use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsStore};
 use std::{
     cell::Cell,
     cmp,
+    collections::BTreeMap,
     fmt::Write,
-    iter,
+    iter, mem,
     ops::Range,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     rc::Rc,
-    sync::Arc,
+    sync::{Arc, Mutex, OnceLock},
     time::{Duration, Instant},
 };
 use telemetry_events::AssistantKind;
 use theme::ThemeSettings;
 use ui::{
+    popover_menu,
     prelude::*,
     utils::{DateTimeType, FormatDistance},
-    ButtonLike, Tab, TabBar, Tooltip,
+    ButtonLike, ContextMenu, IconButtonShape, Indicator, Tab, TabBar, TabPosition, Tooltip,
+};
+use util::{
+    http::HttpClient,
+    paths::{COMPLETIONS_CACHE_DIR, CONVERSATIONS_DIR, CONVERSATION_TEMPLATES_DIR, HOME},
+    post_inc, ResultExt, TryFutureExt,
 };
-use util::{paths::CONVERSATIONS_DIR, post_inc, ResultExt, TryFutureExt};
 use uuid::Uuid;
 use workspace::{
     dock::{DockPosition, Panel, PanelEvent},
+    pane::{ActivateNextItem, ActivatePrevItem, CloseActiveItem},
     searchable::Direction,
     Save, Toast, ToggleZoom, Toolbar, Workspace,
 };
 
+const ASSISTANT_PANEL_KEY: &str = "AssistantPanel";
+const SETTINGS_MIGRATIONS_KEY: &str = "AssistantSettingsMigrations";
+
+/// The editor for a conversation's title, shown in place of its tab's label.
+struct TitleEditor {
+    conversation_editor: EntityId,
+    editor: View<Editor>,
+    _subscription: Subscription,
+}
+
+/// What's restored when the panel is loaded again, such as after a restart.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct SerializedAssistantPanel {
+    width: Option<Pixels>,
+    height: Option<Pixels>,
+    /// The saved conversation that was active.
+    active_conversation: Option<PathBuf>,
+    /// The saved conversations that were open as tabs, in order.
+    #[serde(default)]
+    open_conversations: Vec<PathBuf>,
+}
+
 pub fn init(cx: &mut AppContext) {
     AssistantSettings::register(cx);
+    ProjectAssistantSettings::register(cx);
     cx.observe_new_views(
         |workspace: &mut Workspace, _cx: &mut ViewContext<Workspace>| {
             workspace
@@ -75,8 +176,17 @@ pub fn init(cx: &mut AppContext) {
                     workspace.toggle_panel_focus::<AssistantPanel>(cx);
                 })
                 .register_action(AssistantPanel::inline_assist)
+                .register_action(AssistantPanel::fix_diagnostic)
+                .register_action(AssistantPanel::resolve_conflicts)
+                .register_action(AssistantPanel::generate_tests)
+                .register_action(AssistantPanel::generate_commit_message)
+                .register_action(AssistantPanel::generate_pull_request_description)
+                .register_action(AssistantPanel::terminal_assist)
+                .register_action(AssistantPanel::import_conversations)
                 .register_action(AssistantPanel::cancel_last_inline_assist)
-                .register_action(ConversationEditor::quote_selection);
+                .register_action(ConversationEditor::quote_selection)
+                .register_action(ConversationEditor::explain_code)
+                .register_action(ConversationEditor::explain_terminal_output);
         },
     )
     .detach();
@@ -88,16 +198,28 @@ pub struct AssistantPanel {
     height: Option<Pixels>,
     active_editor_index: Option<usize>,
     prev_active_editor_index: Option<usize>,
+    /// The open conversations, shown as tabs.
     editors: Vec<View<ConversationEditor>>,
+    editor_subscriptions: HashMap<EntityId, [Subscription; 2]>,
+    title_editor: Option<TitleEditor>,
     saved_conversations: Vec<SavedConversationMetadata>,
     saved_conversations_scroll_handle: UniformListScrollHandle,
     zoomed: bool,
     focus_handle: FocusHandle,
     toolbar: View<Toolbar>,
     completion_provider: Arc<dyn CompletionProvider>,
+    pending_completion_provider: Task<Option<()>>,
+    /// The default model and request options the completion provider was
+    /// built from, to tell when the settings change them.
+    default_model: ModelChoice,
+    request_options: RequestOptions,
     api_key_editor: Option<View<Editor>>,
     languages: Arc<LanguageRegistry>,
     fs: Arc<dyn Fs>,
+    http_client: Arc<dyn HttpClient>,
+    provider_status: Model<ProviderStatus>,
+    /// The endpoints the panel sends requests to, as `(kind, api_url)` pairs.
+    provider_endpoints: Vec<(EndpointKind, String)>,
     subscriptions: Vec<Subscription>,
     next_inline_assist_id: usize,
     pending_inline_assists: HashMap<usize, PendingInlineAssist>,
@@ -105,42 +227,113 @@ pub struct AssistantPanel {
     include_conversation_in_next_inline_assist: bool,
     inline_prompt_history: VecDeque<String>,
     _watch_saved_conversations: Task<Result<()>>,
+    /// The templates new conversations can be created from.
+    conversation_templates: Vec<ConversationTemplate>,
+    _watch_conversation_templates: Task<Result<()>>,
+    /// The contents of the system prompt files in the settings, by path.
+    system_prompt_files: HashMap<PathBuf, String>,
+    _watch_system_prompt_files: Task<Result<()>>,
     semantic_index: Option<Model<SemanticIndex>>,
     retrieve_context_in_next_inline_assist: bool,
+    serialized_panel: SerializedAssistantPanel,
+    pending_serialization: Task<Option<()>>,
 }
 
 impl AssistantPanel {
     const INLINE_PROMPT_HISTORY_MAX_LEN: usize = 20;
+    const PROVIDER_STATUS_INTERVAL: Duration = Duration::from_secs(60);
 
     pub fn load(
         workspace: WeakView<Workspace>,
         cx: AsyncWindowContext,
     ) -> Task<Result<View<Self>>> {
         cx.spawn(|mut cx| async move {
-            let fs = workspace.update(&mut cx, |workspace, _| workspace.app_state().fs.clone())?;
+            let (fs, http_client) = workspace.update(&mut cx, |workspace, cx| {
+                let app_state = workspace.app_state();
+                let http_client = crate::http_client(app_state.client.http_client(), cx);
+                (app_state.fs.clone(), http_client)
+            })?;
+            let serialized_panel = cx
+                .background_executor()
+                .spawn(async move { KEY_VALUE_STORE.read_kvp(ASSISTANT_PANEL_KEY) })
+                .await
+                .log_err()
+                .flatten()
+                .and_then(|panel| {
+                    serde_json::from_str::<SerializedAssistantPanel>(&panel).log_err()
+                })
+                .unwrap_or_default();
+            let (default_model, request_options, retention) =
+                workspace.update(&mut cx, |workspace, cx| {
+                    let settings = AssistantSettings::get_global(cx);
+                    (
+                        ModelChoice::default_for(Some(workspace.project().read(cx)), cx),
+                        RequestOptions::new(settings),
+                        settings.conversation_retention.clone(),
+                    )
+                })?;
             let saved_conversations = SavedConversationMetadata::list(fs.clone())
                 .await
                 .log_err()
                 .unwrap_or_default();
-            let (api_url, model_name) = cx.update(|cx| {
-                let settings = AssistantSettings::get_global(cx);
-                (
-                    settings.openai_api_url.clone(),
-                    settings.default_open_ai_model.full_name().to_string(),
-                )
-            })?;
-            let completion_provider = OpenAiCompletionProvider::new(
-                api_url,
-                model_name,
+            let saved_conversations =
+                SavedConversationMetadata::prune(saved_conversations, &retention, fs.as_ref())
+                    .await;
+            let indexed_conversations = saved_conversations
+                .iter()
+                .map(|conversation| (conversation.path.clone(), conversation.mtime))
+                .collect();
+            cx.background_executor()
+                .spawn({
+                    let fs = fs.clone();
+                    async move {
+                        CONVERSATION_STORE
+                            .reindex(indexed_conversations, fs.as_ref())
+                            .await
+                    }
+                })
+                .detach();
+            let conversation_templates = load_conversation_templates(fs.clone())
+                .await
+                .log_err()
+                .unwrap_or_default();
+            let provider_endpoints = request_options.endpoints(&default_model.api_url);
+            let completion_provider = build_completion_provider(
+                default_model.provider,
+                default_model.api_url.clone(),
+                default_model.model.full_name().into(),
+                request_options.clone(),
+                http_client.clone(),
                 cx.background_executor().clone(),
             )
             .await;
 
-            // TODO: deserialize state.
             let workspace_handle = workspace.clone();
-            workspace.update(&mut cx, |workspace, cx| {
+            let panel = workspace.update(&mut cx, |workspace, cx| {
                 cx.new_view::<Self>(|cx| {
                     const CONVERSATION_WATCH_DURATION: Duration = Duration::from_millis(100);
+                    let _watch_conversation_templates = cx.spawn({
+                        let fs = fs.clone();
+                        move |this, mut cx| async move {
+                            let mut events = fs
+                                .watch(&CONVERSATION_TEMPLATES_DIR, CONVERSATION_WATCH_DURATION)
+                                .await;
+                            while events.next().await.is_some() {
+                                let conversation_templates =
+                                    load_conversation_templates(fs.clone())
+                                        .await
+                                        .log_err()
+                                        .unwrap_or_default();
+                                this.update(&mut cx, |this, cx| {
+                                    this.conversation_templates = conversation_templates;
+                                    cx.notify();
+                                })
+                                .ok();
+                            }
+
+                            anyhow::Ok(())
+                        }
+                    });
                     let _watch_saved_conversations = cx.spawn(move |this, mut cx| async move {
                         let mut events = fs
                             .watch(&CONVERSATIONS_DIR, CONVERSATION_WATCH_DURATION)
@@ -169,41 +362,118 @@ impl AssistantPanel {
 
                     let semantic_index = SemanticIndex::global(cx);
 
+                    let provider_status = ProviderStatus::new(
+                        http_client.clone(),
+                        Self::PROVIDER_STATUS_INTERVAL,
+                        cx,
+                    );
+                    let subscriptions = vec![
+                        cx.observe(&provider_status, |_, _, cx| cx.notify()),
+                        cx.observe_global::<SettingsStore>(Self::load_system_prompt_files),
+                        cx.observe_global::<SettingsStore>(Self::notify_settings_migrations),
+                        cx.observe_global::<SettingsStore>(Self::apply_settings),
+                    ];
+
                     let focus_handle = cx.focus_handle();
                     cx.on_focus_in(&focus_handle, Self::focus_in).detach();
                     cx.on_focus_out(&focus_handle, Self::focus_out).detach();
 
-                    Self {
+                    let mut this = Self {
                         workspace: workspace_handle,
                         active_editor_index: Default::default(),
                         prev_active_editor_index: Default::default(),
                         editors: Default::default(),
+                        editor_subscriptions: Default::default(),
+                        title_editor: None,
                         saved_conversations,
                         saved_conversations_scroll_handle: Default::default(),
                         zoomed: false,
                         focus_handle,
                         toolbar,
-                        completion_provider: Arc::new(completion_provider),
+                        completion_provider,
+                        pending_completion_provider: Task::ready(None),
+                        default_model,
+                        request_options,
                         api_key_editor: None,
                         languages: workspace.app_state().languages.clone(),
                         fs: workspace.app_state().fs.clone(),
-                        width: None,
-                        height: None,
-                        subscriptions: Default::default(),
+                        http_client,
+                        provider_status,
+                        provider_endpoints,
+                        width: serialized_panel.width,
+                        height: serialized_panel.height,
+                        subscriptions,
                         next_inline_assist_id: 0,
                         pending_inline_assists: Default::default(),
                         pending_inline_assist_ids_by_editor: Default::default(),
                         include_conversation_in_next_inline_assist: false,
                         inline_prompt_history: Default::default(),
                         _watch_saved_conversations,
+                        conversation_templates,
+                        _watch_conversation_templates,
+                        system_prompt_files: Default::default(),
+                        _watch_system_prompt_files: Task::ready(Ok(())),
                         semantic_index,
                         retrieve_context_in_next_inline_assist: false,
-                    }
+                        serialized_panel: serialized_panel.clone(),
+                        pending_serialization: Task::ready(None),
+                    };
+                    this.load_system_prompt_files(cx);
+                    this.notify_settings_migrations(cx);
+                    this
                 })
-            })
+            })?;
+
+            for path in serialized_panel.open_conversations {
+                panel
+                    .update(&mut cx, |panel, cx| panel.load_conversation(path, cx))?
+                    .await
+                    .log_err();
+            }
+            if let Some(path) = serialized_panel.active_conversation {
+                panel
+                    .update(&mut cx, |panel, cx| panel.load_conversation(path, cx))?
+                    .await
+                    .log_err();
+            }
+            Ok(panel)
         })
     }
 
+    /// Remembers the panel's size and open conversation, if they've changed, so
+    /// they're restored the next time it's loaded.
+    fn serialize(&mut self, cx: &mut ViewContext<Self>) {
+        let serialized_panel = SerializedAssistantPanel {
+            width: self.width,
+            height: self.height,
+            active_conversation: self
+                .active_editor()
+                .and_then(|editor| editor.read(cx).conversation.read(cx).path.clone()),
+            open_conversations: self
+                .editors
+                .iter()
+                .filter_map(|editor| editor.read(cx).conversation.read(cx).path.clone())
+                .collect(),
+        };
+        if serialized_panel == self.serialized_panel {
+            return;
+        }
+
+        self.serialized_panel = serialized_panel.clone();
+        self.pending_serialization = cx.background_executor().spawn(
+            async move {
+                KEY_VALUE_STORE
+                    .write_kvp(
+                        ASSISTANT_PANEL_KEY.into(),
+                        serde_json::to_string(&serialized_panel)?,
+                    )
+                    .await?;
+                anyhow::Ok(())
+            }
+            .log_err(),
+        );
+    }
+
     fn focus_in(&mut self, cx: &mut ViewContext<Self>) {
         self.toolbar
             .update(cx, |toolbar, cx| toolbar.focus_changed(true, cx));
@@ -271,39 +541,568 @@ impl AssistantPanel {
         }
     }
 
+    /// Starts an inline assist that fixes the most severe diagnostic at the cursor,
+    /// leaving the edit to be reviewed like any other.
+    pub fn fix_diagnostic(
+        workspace: &mut Workspace,
+        _: &FixDiagnostic,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let Some(assistant) = workspace.panel::<AssistantPanel>(cx) else {
+            return;
+        };
+        let Some(active_editor) = workspace
+            .active_item(cx)
+            .and_then(|item| item.act_as::<Editor>(cx))
+        else {
+            return;
+        };
+        if !assistant.read(cx).has_credentials() {
+            workspace.focus_panel::<AssistantPanel>(cx);
+            return;
+        }
+
+        let snapshot = active_editor.read(cx).buffer().read(cx).snapshot(cx);
+        let cursor = active_editor.read(cx).selections.newest::<usize>(cx).head();
+        let Some(entry) = snapshot
+            .diagnostics_in_range::<_, usize>(cursor..cursor, false)
+            .filter(|entry| entry.diagnostic.is_primary)
+            .min_by_key(|entry| {
+                (
+                    entry.diagnostic.severity != DiagnosticSeverity::ERROR,
+                    entry.diagnostic.severity != DiagnosticSeverity::WARNING,
+                )
+            })
+        else {
+            return;
+        };
+
+        let prompt = fix_diagnostic_prompt(&entry.diagnostic);
+        let project = workspace.project().clone();
+        active_editor.update(cx, |editor, cx| {
+            editor.change_selections(None, cx, |selections| {
+                selections.select_ranges([entry.range.clone()])
+            });
+        });
+        assistant.update(cx, |assistant, cx| {
+            if let Some(inline_assistant) =
+                assistant.new_inline_assist(&active_editor, cx, &project)
+            {
+                inline_assistant.update(cx, |inline_assistant, cx| {
+                    inline_assistant.prompt_editor.update(cx, |editor, cx| {
+                        editor.set_text(prompt, cx);
+                    });
+                    inline_assistant.confirm(&menu::Confirm, cx);
+                });
+            }
+        });
+    }
+
+    /// Proposes a resolution for each merge conflict under the cursor, or for
+    /// every conflict in the buffer if the cursor isn't in one. Each conflict is
+    /// resolved by its own inline assist, so they can be accepted or rejected
+    /// one at a time.
+    pub fn resolve_conflicts(
+        workspace: &mut Workspace,
+        _: &ResolveConflict,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let Some(assistant) = workspace.panel::<AssistantPanel>(cx) else {
+            return;
+        };
+        let Some(active_editor) = workspace
+            .active_item(cx)
+            .and_then(|item| item.act_as::<Editor>(cx))
+        else {
+            return;
+        };
+        if !assistant.read(cx).has_credentials() {
+            workspace.focus_panel::<AssistantPanel>(cx);
+            return;
+        }
+
+        let Some(buffer) = active_editor.read(cx).buffer().read(cx).as_singleton() else {
+            return;
+        };
+        let conflicts = find_conflicts(&buffer.read(cx).text());
+        let selection = active_editor
+            .read(cx)
+            .selections
+            .newest::<usize>(cx)
+            .range();
+        let mut selected = conflicts
+            .iter()
+            .filter(|conflict| {
+                conflict.range.start <= selection.end && selection.start <= conflict.range.end
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if selected.is_empty() {
+            selected = conflicts;
+        }
+
+        // Later conflicts come first so that resolving one doesn't shift the
+        // offsets of those still to be selected.
+        let project = workspace.project().clone();
+        for conflict in selected.into_iter().rev() {
+            active_editor.update(cx, |editor, cx| {
+                editor.change_selections(None, cx, |selections| {
+                    selections.select_ranges([conflict.range.clone()])
+                });
+            });
+            assistant.update(cx, |assistant, cx| {
+                if let Some(inline_assistant) =
+                    assistant.new_inline_assist(&active_editor, cx, &project)
+                {
+                    inline_assistant.update(cx, |inline_assistant, cx| {
+                        inline_assistant.prompt_editor.update(cx, |editor, cx| {
+                            editor.set_text(resolve_conflict_prompt(&conflict), cx);
+                        });
+                        inline_assistant.confirm(&menu::Confirm, cx);
+                    });
+                }
+            });
+        }
+    }
+
+    /// Asks the model to write tests for the selected code, streaming them into
+    /// the language's usual test file if the project has one, or a new buffer.
+    pub fn generate_tests(
+        workspace: &mut Workspace,
+        _: &GenerateTests,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let Some(assistant) = workspace.panel::<AssistantPanel>(cx) else {
+            return;
+        };
+        let Some(active_editor) = workspace
+            .active_item(cx)
+            .and_then(|item| item.act_as::<Editor>(cx))
+        else {
+            return;
+        };
+        if !assistant.read(cx).has_credentials() {
+            workspace.focus_panel::<AssistantPanel>(cx);
+            return;
+        }
+
+        let editor = active_editor.read(cx);
+        let Some(buffer) = editor.buffer().read(cx).as_singleton() else {
+            return;
+        };
+        let range = editor.selections.newest::<Point>(cx).range();
+        if range.is_empty() {
+            return;
+        }
+        let buffer = buffer.read(cx);
+        let snapshot = buffer.snapshot();
+        let selection = snapshot
+            .text_for_range(
+                Point::new(range.start.row, 0)
+                    ..Point::new(range.end.row, snapshot.line_len(range.end.row)),
+            )
+            .collect::<String>();
+        let imports = source_imports(&snapshot);
+        let language = snapshot.language_at(range.start).cloned();
+        let language_name = language
+            .as_ref()
+            .map(|language| language.name().to_string())
+            .unwrap_or_default();
+        let file = project::File::from_dyn(buffer.file());
+        let file_path = file
+            .map(|file| file.path.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        // Tests go in the language's usual test file if the project has one.
+        let project = workspace.project().clone();
+        let test_path = file.and_then(|file| {
+            let test_path = conventional_test_path(&file.path, &language_name)?;
+            let project_path = ProjectPath {
+                worktree_id: file.worktree_id(cx),
+                path: test_path.into(),
+            };
+            project
+                .read(cx)
+                .entry_for_path(&project_path, cx)
+                .map(|_| project_path)
+        });
+        let target_editor = if let Some(test_path) = test_path {
+            let open_item = workspace.open_path(test_path, None, true, cx);
+            cx.spawn(|_, _| async move {
+                open_item
+                    .await?
+                    .downcast::<Editor>()
+                    .ok_or_else(|| anyhow!("test file isn't a text file"))
+            })
+        } else {
+            let buffer = match project.update(cx, |project, cx| {
+                project.create_buffer("", language.clone(), cx)
+            }) {
+                Ok(buffer) => buffer,
+                Err(error) => {
+                    log::error!("failed to create a buffer for generated tests: {error}");
+                    return;
+                }
+            };
+            let buffer = cx.new_model(|cx| {
+                MultiBuffer::singleton(buffer, cx).with_title("Generated Tests".into())
+            });
+            let editor =
+                cx.new_view(|cx| Editor::for_multibuffer(buffer, Some(project.clone()), cx));
+            workspace.add_item(Box::new(editor.clone()), cx);
+            Task::ready(Ok(editor))
+        };
+
+        let prompt_templates = AssistantSettings::get_global(cx).prompt_templates.clone();
+        let fs = assistant.read(cx).fs.clone();
+        let prompt = cx.background_executor().spawn(async move {
+            let template =
+                load_generate_tests_template(fs.as_ref(), &language_name, &prompt_templates).await;
+            generate_tests_prompt(&template, &language_name, &file_path, &imports, &selection)
+        });
+
+        cx.spawn(|_, mut cx| async move {
+            let editor = target_editor.await?;
+            let prompt = prompt.await?;
+            assistant.update(&mut cx, |assistant, cx| {
+                let request = assistant.request_for(prompt, cx);
+                // Start the tests on a line of their own after what's already there.
+                let position = editor.update(cx, |editor, cx| {
+                    let snapshot = editor.buffer().read(cx).snapshot(cx);
+                    let len = snapshot.len();
+                    if len > 0 {
+                        let separator = if snapshot.text().ends_with('\n') {
+                            "\n"
+                        } else {
+                            "\n\n"
+                        };
+                        editor.buffer().update(cx, |buffer, cx| {
+                            buffer.edit([(len..len, separator)], None, cx)
+                        });
+                    }
+                    let snapshot = editor.buffer().read(cx).snapshot(cx);
+                    snapshot.anchor_after(snapshot.len())
+                });
+                assistant.generate_into_editor(&editor, position, request, &project, cx);
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Writes a commit message for the changes staged in the repository of the
+    /// active editor's file, streaming it to the start of the buffer. Meant for
+    /// the `COMMIT_EDITMSG` file git opens when committing.
+    pub fn generate_commit_message(
+        workspace: &mut Workspace,
+        _: &GenerateCommitMessage,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        const COMMIT_MESSAGE_TOAST_ID: usize = 0x636f6d6d6974;
+
+        let Some(assistant) = workspace.panel::<AssistantPanel>(cx) else {
+            return;
+        };
+        let Some(editor) = workspace
+            .active_item(cx)
+            .and_then(|item| item.act_as::<Editor>(cx))
+        else {
+            return;
+        };
+        if !assistant.read(cx).has_credentials() {
+            workspace.focus_panel::<AssistantPanel>(cx);
+            return;
+        }
+        let Some(abs_path) = editor
+            .read(cx)
+            .buffer()
+            .read(cx)
+            .as_singleton()
+            .and_then(|buffer| {
+                let file = buffer.read(cx).file()?.as_local()?;
+                Some(file.abs_path(cx))
+            })
+        else {
+            return;
+        };
+
+        let prompt_templates = AssistantSettings::get_global(cx).prompt_templates.clone();
+        let fs = assistant.read(cx).fs.clone();
+        let provider = assistant.read(cx).completion_provider.clone();
+        let project = workspace.project().clone();
+        let prompt = cx.background_executor().spawn(async move {
+            let repository = repository_for_path(fs.as_ref(), &abs_path)
+                .await
+                .ok_or_else(|| anyhow!("{abs_path:?} isn't in a git repository"))?;
+            let diff = repository.lock().staged_diff()?;
+            if diff.trim().is_empty() {
+                return Err(anyhow!("There are no staged changes to describe."));
+            }
+            let diff = truncate_diff(&diff, provider.base_model().as_ref())?;
+            commit_message_prompt(fs.as_ref(), &diff, &prompt_templates).await
+        });
+
+        cx.spawn(|workspace, mut cx| async move {
+            let prompt = match prompt.await {
+                Ok(prompt) => prompt,
+                Err(error) => {
+                    return workspace.update(&mut cx, |workspace, cx| {
+                        workspace
+                            .show_toast(Toast::new(COMMIT_MESSAGE_TOAST_ID, error.to_string()), cx)
+                    });
+                }
+            };
+            assistant.update(&mut cx, |assistant, cx| {
+                let request = assistant.request_for(prompt, cx);
+                let position = editor
+                    .read(cx)
+                    .buffer()
+                    .read(cx)
+                    .snapshot(cx)
+                    .anchor_before(0);
+                assistant.generate_into_editor(&editor, position, request, &project, cx);
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Describes the changes on the current branch since it diverged from its
+    /// upstream as a pull request title and description, and copies them to the
+    /// clipboard. Diffs too large for the model are summarized a piece at a time
+    /// first.
+    pub fn generate_pull_request_description(
+        workspace: &mut Workspace,
+        _: &GeneratePullRequestDescription,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        const PULL_REQUEST_TOAST_ID: usize = 0x70756c6c;
+
+        let Some(assistant) = workspace.panel::<AssistantPanel>(cx) else {
+            return;
+        };
+        if !assistant.read(cx).has_credentials() {
+            workspace.focus_panel::<AssistantPanel>(cx);
+            return;
+        }
+        let active_path = workspace
+            .active_item(cx)
+            .and_then(|item| item.act_as::<Editor>(cx))
+            .and_then(|editor| {
+                let buffer = editor.read(cx).buffer().read(cx).as_singleton()?;
+                let file = buffer.read(cx).file()?.as_local()?;
+                Some(file.abs_path(cx))
+            });
+        let Some(abs_path) = active_path.or_else(|| {
+            let project = workspace.project().read(cx);
+            let worktree = project.visible_worktrees(cx).next()?;
+            Some(worktree.read(cx).abs_path().to_path_buf())
+        }) else {
+            return;
+        };
+
+        let prompt_templates = AssistantSettings::get_global(cx).prompt_templates.clone();
+        let fs = assistant.read(cx).fs.clone();
+        let provider = assistant.read(cx).completion_provider.clone();
+        let request_template = assistant.read(cx).request_for(String::new(), cx);
+        let description = cx.background_executor().spawn(async move {
+            let dot_git = dot_git_for_path(fs.as_ref(), &abs_path)
+                .await
+                .ok_or_else(|| anyhow!("{abs_path:?} isn't in a git repository"))?;
+            let repository = fs
+                .open_repo(&dot_git)
+                .ok_or_else(|| anyhow!("couldn't open the repository at {dot_git:?}"))?;
+            let (branch, diff) = {
+                let repository = repository.lock();
+                (
+                    repository.branch_name().unwrap_or_default(),
+                    repository.upstream_diff()?,
+                )
+            };
+            if diff.trim().is_empty() {
+                return Err(anyhow!(
+                    "There are no changes on this branch since its upstream."
+                ));
+            }
+            let repository_template = match dot_git.parent() {
+                Some(work_directory) => pull_request_template(fs.as_ref(), work_directory).await,
+                None => None,
+            };
+
+            let request = |content: String| ChatRequest {
+                messages: vec![RequestMessage {
+                    role: Role::User,
+                    content,
+                }],
+                ..request_template.clone()
+            };
+            let chunks = split_diff(&diff, provider.base_model().as_ref())?;
+            let changes = if let [diff] = chunks.as_slice() {
+                format!("```diff\n{}\n```", diff.trim_end())
+            } else {
+                let summaries = future::try_join_all(chunks.iter().map(|chunk| {
+                    let events = provider.complete(request(diff_summary_prompt(chunk)));
+                    async move { text_only(events.await?).try_collect::<String>().await }
+                }))
+                .await?;
+                summaries.join("\n\n")
+            };
+
+            let prompt = pull_request_prompt(
+                fs.as_ref(),
+                &branch,
+                &changes,
+                repository_template.as_deref(),
+                &prompt_templates,
+            )
+            .await?;
+            let events = provider.complete(request(prompt)).await?;
+            let description = text_only(events).try_collect::<String>().await?;
+            anyhow::Ok(description.trim().to_string())
+        });
+
+        cx.spawn(|workspace, mut cx| async move {
+            let message = match description.await {
+                Ok(description) => {
+                    cx.update(|cx| cx.write_to_clipboard(ClipboardItem::new(description)))?;
+                    "Copied the pull request title and description to the clipboard.".to_string()
+                }
+                Err(error) => error.to_string(),
+            };
+            workspace.update(&mut cx, |workspace, cx| {
+                workspace.show_toast(Toast::new(PULL_REQUEST_TOAST_ID, message), cx)
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Saves the conversations in the chosen export files, from the assistant or
+    /// from ChatGPT, as native conversations. ChatGPT conversations continue with
+    /// the default model.
+    pub fn import_conversations(
+        workspace: &mut Workspace,
+        _: &ImportConversations,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        const IMPORT_TOAST_ID: usize = 0x696d706f;
+
+        let fs = workspace.app_state().fs.clone();
+        let settings = AssistantSettings::get_global(cx);
+        let model = settings.openai.model.clone();
+        let api_url = settings.openai.api_url.clone();
+        let paths = cx.prompt_for_paths(PathPromptOptions {
+            files: true,
+            directories: false,
+            multiple: true,
+        });
+        cx.spawn(|workspace, mut cx| async move {
+            let Some(paths) = paths.await.log_err().flatten() else {
+                return Ok(());
+            };
+
+            let mut imported_count = 0;
+            let imported = async {
+                for path in paths {
+                    let json = fs.load(&path).await?;
+                    let conversations = import_conversations(&json, &model, &api_url)
+                        .with_context(|| format!("importing {path:?}"))?;
+                    for conversation in conversations {
+                        save_imported_conversation(conversation, fs.as_ref()).await?;
+                        imported_count += 1;
+                    }
+                }
+                anyhow::Ok(())
+            };
+            let message = match imported.await {
+                Ok(()) => format!("Imported {imported_count} conversations."),
+                Err(error) => {
+                    format!("Imported {imported_count} conversations before failing: {error:#}")
+                }
+            };
+            workspace.update(&mut cx, |workspace, cx| {
+                workspace.show_toast(Toast::new(IMPORT_TOAST_ID, message), cx)
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Asks the model for a shell command to run in the focused terminal, which
+    /// is shown for review before it's run.
+    pub fn terminal_assist(
+        workspace: &mut Workspace,
+        _: &TerminalAssist,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let Some(assistant) = workspace.panel::<AssistantPanel>(cx) else {
+            return;
+        };
+        let Some(terminal_view) = focused_terminal(workspace, cx) else {
+            return;
+        };
+        if !assistant.read(cx).has_credentials() {
+            workspace.focus_panel::<AssistantPanel>(cx);
+            return;
+        }
+
+        let default_model = assistant.read(cx).default_model.clone();
+        let sampling = AssistantSettings::get_global(cx).sampling_for(default_model.provider);
+        let model = default_model.model;
+        let provider = assistant.read(cx).completion_provider.clone();
+        let terminal = terminal_view.read(cx).terminal().clone();
+        workspace.toggle_modal(cx, |cx| {
+            TerminalAssistant::new(terminal, provider, model, sampling, cx)
+        });
+    }
+
+    /// Streams the response to `request` into `editor` at `position`. Unlike an
+    /// inline assist there's no prompt to review it from, so it's accepted once
+    /// it's done, unless cancelled before then.
+    fn generate_into_editor(
+        &mut self,
+        editor: &View<Editor>,
+        position: Anchor,
+        request: ChatRequest,
+        project: &Model<Project>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let inline_assist_id = post_inc(&mut self.next_inline_assist_id);
+        let provider = self.completion_provider.clone();
+        let codegen = cx.new_model(|cx| {
+            Codegen::new(
+                editor.read(cx).buffer().clone(),
+                CodegenKind::Generate { position },
+                provider,
+                cx,
+            )
+        });
+        let subscriptions =
+            self.subscribe_to_inline_assist_codegen(inline_assist_id, editor, &codegen, cx);
+        self.pending_inline_assists.insert(
+            inline_assist_id,
+            PendingInlineAssist {
+                editor: editor.downgrade(),
+                inline_assistant: None,
+                codegen: codegen.clone(),
+                project: project.downgrade(),
+                leader_id: None,
+                follower_ids: Vec::new(),
+                _subscriptions: subscriptions,
+            },
+        );
+        self.pending_inline_assist_ids_by_editor
+            .entry(editor.downgrade())
+            .or_default()
+            .push(inline_assist_id);
+        codegen.update(cx, |codegen, cx| codegen.start(request, cx));
+    }
+
     fn new_inline_assist(
         &mut self,
         editor: &View<Editor>,
         cx: &mut ViewContext<Self>,
         project: &Model<Project>,
-    ) {
+    ) -> Option<View<InlineAssistant>> {
         let selection = editor.read(cx).selections.newest_anchor().clone();
-        if selection.start.excerpt_id != selection.end.excerpt_id {
-            return;
-        }
         let snapshot = editor.read(cx).buffer().read(cx).snapshot(cx);
-
-        // Extend the selection to the start and the end of the line.
-        let mut point_selection = selection.map(|selection| selection.to_point(&snapshot));
-        if point_selection.end > point_selection.start {
-            point_selection.start.column = 0;
-            // If the selection ends at the start of the line, we don't want to include it.
-            if point_selection.end.column == 0 {
-                point_selection.end.row -= 1;
-            }
-            point_selection.end.column = snapshot.line_len(point_selection.end.row);
-        }
-
-        let codegen_kind = if point_selection.start == point_selection.end {
-            CodegenKind::Generate {
-                position: snapshot.anchor_after(point_selection.start),
-            }
-        } else {
-            CodegenKind::Transform {
-                range: snapshot.anchor_before(point_selection.start)
-                    ..snapshot.anchor_after(point_selection.end),
-            }
-        };
+        let (codegen_kind, point_selection) = inline_assist_kind(&selection, &snapshot)?;
 
         let inline_assist_id = post_inc(&mut self.next_inline_assist_id);
         let provider = self.completion_provider.clone();
@@ -312,6 +1111,43 @@ impl AssistantPanel {
             Codegen::new(editor.read(cx).buffer().clone(), codegen_kind, provider, cx)
         });
 
+        // Every other selection gets an assist of its own that follows the prompt
+        // typed for the newest one.
+        let selections = editor.read(cx).selections.disjoint_anchors();
+        let mut follower_ids = Vec::new();
+        for follower in selections
+            .iter()
+            .filter(|follower| follower.id != selection.id)
+        {
+            let Some((codegen_kind, _)) = inline_assist_kind(follower, &snapshot) else {
+                continue;
+            };
+            let follower_id = post_inc(&mut self.next_inline_assist_id);
+            let provider = self.completion_provider.clone();
+            let codegen = cx.new_model(|cx| {
+                Codegen::new(editor.read(cx).buffer().clone(), codegen_kind, provider, cx)
+            });
+            let subscriptions =
+                self.subscribe_to_inline_assist_codegen(follower_id, editor, &codegen, cx);
+            self.pending_inline_assists.insert(
+                follower_id,
+                PendingInlineAssist {
+                    editor: editor.downgrade(),
+                    inline_assistant: None,
+                    codegen,
+                    project: project.downgrade(),
+                    leader_id: Some(inline_assist_id),
+                    follower_ids: Vec::new(),
+                    _subscriptions: subscriptions,
+                },
+            );
+            self.pending_inline_assist_ids_by_editor
+                .entry(editor.downgrade())
+                .or_default()
+                .push(follower_id);
+            follower_ids.push(follower_id);
+        }
+
         if let Some(semantic_index) = self.semantic_index.clone() {
             let project = project.clone();
             cx.spawn(|_, mut cx| async move {
@@ -378,6 +1214,27 @@ impl AssistantPanel {
             )[0]
         });
 
+        let mut subscriptions = vec![
+            cx.subscribe(&inline_assistant, Self::handle_inline_assistant_event),
+            cx.subscribe(editor, {
+                let inline_assistant = inline_assistant.downgrade();
+                move |_, editor, event, cx| {
+                    if let Some(inline_assistant) = inline_assistant.upgrade() {
+                        if let EditorEvent::SelectionsChanged { local } = event {
+                            if *local && inline_assistant.focus_handle(cx).contains_focused(cx) {
+                                cx.focus_view(&editor);
+                            }
+                        }
+                    }
+                }
+            }),
+        ];
+        subscriptions.extend(self.subscribe_to_inline_assist_codegen(
+            inline_assist_id,
+            editor,
+            &codegen,
+            cx,
+        ));
         self.pending_inline_assists.insert(
             inline_assist_id,
             PendingInlineAssist {
@@ -385,66 +1242,9 @@ impl AssistantPanel {
                 inline_assistant: Some((block_id, inline_assistant.clone())),
                 codegen: codegen.clone(),
                 project: project.downgrade(),
-                _subscriptions: vec![
-                    cx.subscribe(&inline_assistant, Self::handle_inline_assistant_event),
-                    cx.subscribe(editor, {
-                        let inline_assistant = inline_assistant.downgrade();
-                        move |_, editor, event, cx| {
-                            if let Some(inline_assistant) = inline_assistant.upgrade() {
-                                if let EditorEvent::SelectionsChanged { local } = event {
-                                    if *local
-                                        && inline_assistant.focus_handle(cx).contains_focused(cx)
-                                    {
-                                        cx.focus_view(&editor);
-                                    }
-                                }
-                            }
-                        }
-                    }),
-                    cx.observe(&codegen, {
-                        let editor = editor.downgrade();
-                        move |this, _, cx| {
-                            if let Some(editor) = editor.upgrade() {
-                                this.update_highlights_for_editor(&editor, cx);
-                            }
-                        }
-                    }),
-                    cx.subscribe(&codegen, move |this, codegen, event, cx| match event {
-                        codegen::Event::Undone => {
-                            this.finish_inline_assist(inline_assist_id, false, cx)
-                        }
-                        codegen::Event::Finished => {
-                            let pending_assist = if let Some(pending_assist) =
-                                this.pending_inline_assists.get(&inline_assist_id)
-                            {
-                                pending_assist
-                            } else {
-                                return;
-                            };
-
-                            let error = codegen
-                                .read(cx)
-                                .error()
-                                .map(|error| format!("Inline assistant error: {}", error));
-                            if let Some(error) = error {
-                                if pending_assist.inline_assistant.is_none() {
-                                    if let Some(workspace) = this.workspace.upgrade() {
-                                        workspace.update(cx, |workspace, cx| {
-                                            workspace.show_toast(
-                                                Toast::new(inline_assist_id, error),
-                                                cx,
-                                            );
-                                        })
-                                    }
-
-                                    this.finish_inline_assist(inline_assist_id, false, cx);
-                                }
-                            } else {
-                                this.finish_inline_assist(inline_assist_id, false, cx);
-                            }
-                        }
-                    }),
-                ],
+                leader_id: None,
+                follower_ids,
+                _subscriptions: subscriptions,
             },
         );
         self.pending_inline_assist_ids_by_editor
@@ -452,6 +1252,60 @@ impl AssistantPanel {
             .or_default()
             .push(inline_assist_id);
         self.update_highlights_for_editor(&editor, cx);
+        Some(inline_assistant)
+    }
+
+    fn subscribe_to_inline_assist_codegen(
+        &mut self,
+        inline_assist_id: usize,
+        editor: &View<Editor>,
+        codegen: &Model<Codegen>,
+        cx: &mut ViewContext<Self>,
+    ) -> Vec<Subscription> {
+        vec![
+            cx.observe(codegen, {
+                let editor = editor.downgrade();
+                move |this, _, cx| {
+                    if let Some(editor) = editor.upgrade() {
+                        this.update_highlights_for_editor(&editor, cx);
+                    }
+                }
+            }),
+            cx.subscribe(codegen, move |this, codegen, event, cx| match event {
+                codegen::Event::Undone => this.finish_inline_assist(inline_assist_id, false, cx),
+                codegen::Event::Finished => {
+                    let Some(pending_assist) = this.pending_inline_assists.get(&inline_assist_id)
+                    else {
+                        return;
+                    };
+                    let leader_id = pending_assist.leader_id.unwrap_or(inline_assist_id);
+                    let Some(leader) = this.pending_inline_assists.get(&leader_id) else {
+                        return;
+                    };
+
+                    // While the inline assistant is showing, the edits stay pending
+                    // until the user accepts or rejects them.
+                    if leader.inline_assistant.is_some() {
+                        return;
+                    }
+
+                    let error = codegen
+                        .read(cx)
+                        .error()
+                        .map(|error| format!("Inline assistant error: {}", error));
+                    if let Some(error) = error {
+                        if let Some(workspace) = this.workspace.upgrade() {
+                            workspace.update(cx, |workspace, cx| {
+                                workspace.show_toast(Toast::new(inline_assist_id, error), cx);
+                            })
+                        }
+                    }
+                    if this.inline_assist_idle(leader_id, cx) {
+                        this.finish_inline_assist(leader_id, false, cx);
+                    }
+                }
+            }),
+        ]
     }
 
     fn handle_inline_assistant_event(
@@ -467,19 +1321,39 @@ impl AssistantPanel {
                 include_conversation,
                 retrieve_context,
             } => {
-                self.confirm_inline_assist(
-                    assist_id,
-                    prompt,
-                    *include_conversation,
-                    cx,
-                    *retrieve_context,
-                );
+                // `/prompt <name>` uses a prompt from the library as the instructions.
+                let saved_prompt_name = SlashCommandLine::parse(prompt)
+                    .filter(|command_line| command_line.name == "prompt")
+                    .and_then(|command_line| command_line.argument)
+                    .map(str::to_string);
+                if let Some(name) = saved_prompt_name {
+                    self.confirm_inline_assist_with_saved_prompt(
+                        assist_id,
+                        name,
+                        *include_conversation,
+                        *retrieve_context,
+                        cx,
+                    );
+                } else {
+                    self.confirm_inline_assist(
+                        assist_id,
+                        prompt,
+                        *include_conversation,
+                        cx,
+                        *retrieve_context,
+                    );
+                }
             }
             InlineAssistantEvent::Canceled => {
                 self.finish_inline_assist(assist_id, true, cx);
             }
             InlineAssistantEvent::Dismissed => {
-                self.hide_inline_assist(assist_id, cx);
+                // Once the edits are complete, dismissing the assistant accepts them.
+                if self.inline_assist_idle(assist_id, cx) {
+                    self.finish_inline_assist(assist_id, false, cx);
+                } else {
+                    self.hide_inline_assist(assist_id, cx);
+                }
             }
             InlineAssistantEvent::IncludeConversationToggled {
                 include_conversation,
@@ -523,10 +1397,28 @@ impl AssistantPanel {
         cx.propagate();
     }
 
+    /// Whether the assist and the ones following it for other selections have all
+    /// finished generating.
+    fn inline_assist_idle(&self, assist_id: usize, cx: &AppContext) -> bool {
+        let Some(pending_assist) = self.pending_inline_assists.get(&assist_id) else {
+            return false;
+        };
+        pending_assist.codegen.read(cx).idle()
+            && pending_assist.follower_ids.iter().all(|follower_id| {
+                self.pending_inline_assists
+                    .get(follower_id)
+                    .map_or(true, |follower| follower.codegen.read(cx).idle())
+            })
+    }
+
     fn finish_inline_assist(&mut self, assist_id: usize, undo: bool, cx: &mut ViewContext<Self>) {
         self.hide_inline_assist(assist_id, cx);
 
         if let Some(pending_assist) = self.pending_inline_assists.remove(&assist_id) {
+            for follower_id in &pending_assist.follower_ids {
+                self.finish_inline_assist(*follower_id, undo, cx);
+            }
+
             if let hash_map::Entry::Occupied(mut entry) = self
                 .pending_inline_assist_ids_by_editor
                 .entry(pending_assist.editor.clone())
@@ -571,6 +1463,75 @@ impl AssistantPanel {
         include_conversation: bool,
         cx: &mut ViewContext<Self>,
         retrieve_context: bool,
+    ) {
+        let Some(pending_assist) = self.pending_inline_assists.get(&inline_assist_id) else {
+            return;
+        };
+        let follower_ids = pending_assist.follower_ids.clone();
+
+        self.inline_prompt_history
+            .retain(|prompt| prompt != user_prompt);
+        self.inline_prompt_history.push_back(user_prompt.into());
+        if self.inline_prompt_history.len() > Self::INLINE_PROMPT_HISTORY_MAX_LEN {
+            self.inline_prompt_history.pop_front();
+        }
+
+        // Every selection is sent as a request of its own, and the provider's
+        // scheduler caps how many of them run at once.
+        for assist_id in iter::once(inline_assist_id).chain(follower_ids) {
+            self.start_inline_assist(
+                assist_id,
+                user_prompt,
+                include_conversation,
+                retrieve_context,
+                cx,
+            );
+        }
+    }
+
+    fn confirm_inline_assist_with_saved_prompt(
+        &mut self,
+        inline_assist_id: usize,
+        name: String,
+        include_conversation: bool,
+        retrieve_context: bool,
+        cx: &mut ViewContext<Self>,
+    ) {
+        const MISSING_PROMPT_TOAST_ID: usize = 0x70726f6d7074;
+
+        let fs = self.fs.clone();
+        let templates = AssistantSettings::get_global(cx).prompt_templates.clone();
+        cx.spawn(|this, mut cx| async move {
+            let prompt = load_prompt_template(fs.as_ref(), &name, &templates).await;
+            this.update(&mut cx, |this, cx| match prompt {
+                Some(prompt) => this.confirm_inline_assist(
+                    inline_assist_id,
+                    &prompt,
+                    include_conversation,
+                    cx,
+                    retrieve_context,
+                ),
+                None => {
+                    this.finish_inline_assist(inline_assist_id, true, cx);
+                    this.workspace
+                        .update(cx, |workspace, cx| {
+                            let message = format!("No prompt named \"{name}\" in the library");
+                            workspace.show_toast(Toast::new(MISSING_PROMPT_TOAST_ID, message), cx)
+                        })
+                        .ok();
+                }
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    fn start_inline_assist(
+        &mut self,
+        inline_assist_id: usize,
+        user_prompt: &str,
+        include_conversation: bool,
+        retrieve_context: bool,
+        cx: &mut ViewContext<Self>,
     ) {
         let conversation = if include_conversation {
             self.active_editor()
@@ -606,13 +1567,6 @@ impl AssistantPanel {
             None
         };
 
-        self.inline_prompt_history
-            .retain(|prompt| prompt != user_prompt);
-        self.inline_prompt_history.push_back(user_prompt.into());
-        if self.inline_prompt_history.len() > Self::INLINE_PROMPT_HISTORY_MAX_LEN {
-            self.inline_prompt_history.pop_front();
-        }
-
         let codegen = pending_assist.codegen.clone();
         let snapshot = editor.read(cx).buffer().read(cx).snapshot(cx);
         let range = codegen.read(cx).range();
@@ -690,13 +1644,44 @@ impl AssistantPanel {
             Task::ready(Ok(Vec::new()))
         };
 
-        let mut model = AssistantSettings::get_global(cx)
-            .default_open_ai_model
-            .clone();
-        let model_name = model.full_name();
+        let settings = AssistantSettings::get_global(cx);
+        // A routing rule for the file can send the assist to another model.
+        let mut route = settings
+            .model_route(
+                language_name.as_deref(),
+                buffer.file().map(|file| &**file.path()),
+            )
+            .map(|route| ModelChoice {
+                provider: route.provider,
+                model: route.model.clone(),
+                api_url: settings.route_api_url(route),
+            });
+        let default_model = self.default_model.clone();
+        let mut model = route
+            .as_ref()
+            .map_or_else(|| default_model.model.clone(), |route| route.model.clone());
+        let request_options = RequestOptions::new(settings);
+        let model_name = model.full_name().to_string();
+        let sampling_settings = settings.sampling_for(
+            route
+                .as_ref()
+                .map_or(ProviderKind::OpenAi, |route| route.provider),
+        );
+        let temperature = sampling_settings.temperature.unwrap_or(temperature);
+        let mut stop = vec!["|END|>".to_string()];
+        stop.extend(sampling_settings.stop_sequences.clone().unwrap_or_default());
+        let sampling = sampling_settings.to_params();
+        let prompt_templates = settings.prompt_templates.clone();
+        let system_message = self
+            .project_settings(cx)
+            .system_message(&self.system_prompt_files);
+        let include_preamble = system_message.is_none();
+        let fs = self.fs.clone();
 
         let prompt = cx.background_executor().spawn(async move {
             let snippets = snippets.await?;
+            let template =
+                load_prompt_template(fs.as_ref(), INLINE_ASSIST_TEMPLATE, &prompt_templates).await;
 
             let language_name = language_name.as_deref();
             generate_content_prompt(
@@ -705,12 +1690,18 @@ impl AssistantPanel {
                 buffer,
                 range,
                 snippets,
-                model_name,
+                &model_name,
                 project_name,
+                template,
+                include_preamble,
             )
         });
 
         let mut messages = Vec::new();
+        messages.extend(system_message.map(|content| RequestMessage {
+            role: Role::System,
+            content,
+        }));
         if let Some(conversation) = conversation {
             let conversation = conversation.read(cx);
             let buffer = conversation.buffer.read(cx);
@@ -719,9 +1710,30 @@ impl AssistantPanel {
                     .messages(cx)
                     .map(|message| message.to_open_ai_message(buffer)),
             );
-            model = conversation.model.clone();
+            // Inline assists are sent to the panel's provider, which can only
+            // serve the conversation's model if it's an OpenAI one.
+            if conversation.provider == ProviderKind::OpenAi {
+                model = conversation.model.clone();
+                route = None;
+            }
         }
 
+        // A route to another provider or endpoint needs a provider of its own.
+        let routed_provider = route
+            .filter(|route| {
+                route.provider != default_model.provider || route.api_url != default_model.api_url
+            })
+            .map(|route| {
+                build_completion_provider(
+                    route.provider,
+                    route.api_url,
+                    route.model.full_name().into(),
+                    request_options,
+                    self.http_client.clone(),
+                    cx.background_executor().clone(),
+                )
+            });
+
         cx.spawn(|_, mut cx| async move {
             // I Don't know if we want to return a ? here.
             let prompt = prompt.await?;
@@ -731,15 +1743,27 @@ impl AssistantPanel {
                 content: prompt,
             });
 
-            let request = Box::new(OpenAiRequest {
+            let request = ChatRequest {
                 model: model.full_name().into(),
                 messages,
                 stream: true,
-                stop: vec!["|END|>".to_string()],
+                stop,
                 temperature,
-            });
+                sampling,
+                ..Default::default()
+            };
 
-            codegen.update(&mut cx, |codegen, cx| codegen.start(request, cx))?;
+            if let Some(routed_provider) = routed_provider {
+                let provider = routed_provider.await;
+                cx.update(|cx| provider.retrieve_credentials(cx))?.await;
+                codegen.update(&mut cx, |codegen, _| codegen.set_provider(provider))?;
+            }
+
+            codegen.update(&mut cx, |codegen, cx| {
+                // Running the assist again replaces the edit it made before.
+                codegen.revert(cx);
+                codegen.start(request, cx)
+            })?;
             anyhow::Ok(())
         })
         .detach();
@@ -796,6 +1820,93 @@ impl AssistantPanel {
     }
 
     fn new_conversation(&mut self, cx: &mut ViewContext<Self>) -> View<ConversationEditor> {
+        self.new_conversation_from_template(&ConversationTemplate::default(), cx)
+    }
+
+    /// The assistant settings the workspace's project sets in its
+    /// `.zed/settings.json`.
+    fn project_settings<'a>(&self, cx: &'a AppContext) -> &'a ProjectAssistantSettings {
+        match self.workspace.upgrade() {
+            Some(workspace) => {
+                ProjectAssistantSettings::for_project(workspace.read(cx).project().read(cx), cx)
+            }
+            None => ProjectAssistantSettings::get_global(cx),
+        }
+    }
+
+    /// Reads the system prompt files named in the user's and the project's
+    /// settings, and reads them again whenever they change.
+    fn load_system_prompt_files(&mut self, cx: &mut ViewContext<Self>) {
+        const SYSTEM_PROMPT_WATCH_DURATION: Duration = Duration::from_millis(100);
+
+        let mut paths = Vec::new();
+        for settings in [
+            ProjectAssistantSettings::get_global(cx),
+            self.project_settings(cx),
+        ] {
+            if let Some(path) = settings
+                .system_prompt
+                .as_ref()
+                .and_then(SystemPrompt::file_path)
+            {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+
+        let fs = self.fs.clone();
+        self._watch_system_prompt_files = cx.spawn(|this, mut cx| async move {
+            let mut events = futures::stream::select_all(
+                future::join_all(
+                    paths
+                        .iter()
+                        .map(|path| fs.watch(path, SYSTEM_PROMPT_WATCH_DURATION)),
+                )
+                .await,
+            );
+            loop {
+                let mut files = HashMap::default();
+                for path in &paths {
+                    if let Some(text) = fs.load(path).await.log_err() {
+                        let (_, prompt) = split_front_matter(&text);
+                        files.insert(path.clone(), prompt.to_string());
+                    }
+                }
+                this.update(&mut cx, |this, _| this.system_prompt_files = files)?;
+                if events.next().await.is_none() {
+                    return Ok(());
+                }
+            }
+        });
+    }
+
+    /// Opens a new conversation that starts with the template's messages and
+    /// uses its model and temperature. The project's system prompt is used if
+    /// the template doesn't set its own.
+    fn new_conversation_from_template(
+        &mut self,
+        template: &ConversationTemplate,
+        cx: &mut ViewContext<Self>,
+    ) -> View<ConversationEditor> {
+        let choice = template.model.clone().map(|model| {
+            ModelChoice::available(cx)
+                .into_iter()
+                .find(|choice| choice.model == model)
+                .unwrap_or_else(|| ModelChoice {
+                    provider: ProviderKind::OpenAi,
+                    model,
+                    api_url: self.default_model.api_url.clone(),
+                })
+        });
+        let template = ConversationTemplate {
+            system_prompt: template.system_prompt.clone().or_else(|| {
+                self.project_settings(cx)
+                    .system_message(&self.system_prompt_files)
+            }),
+            ..template.clone()
+        };
+
         let editor = cx.new_view(|cx| {
             ConversationEditor::new(
                 self.completion_provider.clone(),
@@ -806,22 +1917,117 @@ impl AssistantPanel {
             )
         });
         self.add_conversation(editor.clone(), cx);
+        editor.update(cx, |editor, cx| {
+            if let Some(choice) = choice {
+                editor.set_model(choice, cx);
+            }
+            editor.conversation.update(cx, |conversation, cx| {
+                conversation.apply_template(&template, cx)
+            });
+            let len = editor.conversation.read(cx).buffer.read(cx).len();
+            editor.move_cursor_to(len, cx);
+        });
         editor
     }
 
-    fn add_conversation(&mut self, editor: View<ConversationEditor>, cx: &mut ViewContext<Self>) {
-        self.subscriptions
-            .push(cx.subscribe(&editor, Self::handle_conversation_editor_event));
+    /// Inserts `text` at the cursor in the active conversation, starting a new
+    /// one if none is open.
+    pub(crate) fn insert_into_active_conversation(
+        &mut self,
+        text: &str,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let conversation = self
+            .active_editor()
+            .cloned()
+            .unwrap_or_else(|| self.new_conversation(cx));
+        conversation.update(cx, |conversation, cx| {
+            conversation
+                .editor
+                .update(cx, |editor, cx| editor.insert(text, cx))
+        });
+    }
 
+    fn add_conversation(&mut self, editor: View<ConversationEditor>, cx: &mut ViewContext<Self>) {
         let conversation = editor.read(cx).conversation.clone();
-        self.subscriptions
-            .push(cx.observe(&conversation, |_, _, cx| cx.notify()));
+        let subscriptions = [
+            cx.subscribe(&editor, Self::handle_conversation_editor_event),
+            cx.observe(&conversation, |this, _, cx| {
+                // A new conversation only has a path once it's first saved.
+                this.serialize(cx);
+                cx.notify();
+            }),
+        ];
+        self.editor_subscriptions
+            .insert(editor.entity_id(), subscriptions);
 
         let index = self.editors.len();
         self.editors.push(editor);
         self.set_active_editor_index(Some(index), cx);
     }
 
+    /// Closes the conversation open at `index`, after saving any changes to it.
+    /// A response that's still being generated is cancelled.
+    fn close_conversation(&mut self, index: usize, cx: &mut ViewContext<Self>) {
+        if index >= self.editors.len() {
+            return;
+        }
+
+        let editor = self.editors.remove(index);
+        self.editor_subscriptions.remove(&editor.entity_id());
+        let conversation = editor.read(cx).conversation.clone();
+        let save = conversation.update(cx, |conversation, cx| {
+            conversation.save(None, self.fs.clone(), cx);
+            mem::replace(&mut conversation.pending_save, Task::ready(Ok(())))
+        });
+        // Keep the conversation alive until it's been saved.
+        cx.spawn(|_, _| async move {
+            let _conversation = conversation;
+            save.await
+        })
+        .detach_and_log_err(cx);
+
+        let active_index = match self.active_editor_index {
+            Some(active_index) if active_index > index => Some(active_index - 1),
+            Some(active_index) if active_index == index => {
+                if self.editors.is_empty() {
+                    None
+                } else {
+                    Some(index.min(self.editors.len() - 1))
+                }
+            }
+            active_index => active_index,
+        };
+        self.set_active_editor_index(active_index, cx);
+        self.prev_active_editor_index = active_index;
+    }
+
+    fn close_active_conversation(&mut self, _: &CloseActiveItem, cx: &mut ViewContext<Self>) {
+        if let Some(index) = self.active_editor_index {
+            self.close_conversation(index, cx);
+        }
+    }
+
+    fn activate_next_conversation(&mut self, _: &ActivateNextItem, cx: &mut ViewContext<Self>) {
+        if !self.editors.is_empty() {
+            let index = self
+                .active_editor_index
+                .map_or(0, |index| (index + 1) % self.editors.len());
+            self.set_active_editor_index(Some(index), cx);
+        }
+    }
+
+    fn activate_prev_conversation(&mut self, _: &ActivatePrevItem, cx: &mut ViewContext<Self>) {
+        if !self.editors.is_empty() {
+            let index = self
+                .active_editor_index
+                .map_or(self.editors.len() - 1, |index| {
+                    (index + self.editors.len() - 1) % self.editors.len()
+                });
+            self.set_active_editor_index(Some(index), cx);
+        }
+    }
+
     fn set_active_editor_index(&mut self, index: Option<usize>, cx: &mut ViewContext<Self>) {
         self.prev_active_editor_index = self.active_editor_index;
         self.active_editor_index = index;
@@ -839,6 +2045,72 @@ impl AssistantPanel {
             });
         }
 
+        self.serialize(cx);
+        cx.notify();
+    }
+
+    /// Replaces the label of the active conversation's tab with an editor for
+    /// its title.
+    fn rename_conversation(&mut self, _: &RenameConversation, cx: &mut ViewContext<Self>) {
+        let Some(conversation_editor) = self.active_editor().cloned() else {
+            return;
+        };
+        let title = conversation_editor.read(cx).title(cx);
+        let editor = cx.new_view(|cx| {
+            let mut editor = Editor::single_line(cx);
+            editor.set_text(title, cx);
+            editor.select_all(&Default::default(), cx);
+            editor
+        });
+        let subscription = cx.subscribe(&editor, |this, _, event, cx| {
+            if let EditorEvent::Blurred = event {
+                this.finish_rename(true, cx);
+            }
+        });
+        cx.focus_view(&editor);
+        self.title_editor = Some(TitleEditor {
+            conversation_editor: conversation_editor.entity_id(),
+            editor,
+            _subscription: subscription,
+        });
+        cx.notify();
+    }
+
+    fn confirm_rename(&mut self, _: &menu::Confirm, cx: &mut ViewContext<Self>) {
+        if self.title_editor.is_some() {
+            self.finish_rename(true, cx);
+        } else {
+            cx.propagate();
+        }
+    }
+
+    /// Puts the label back on the tab being renamed, giving the conversation the
+    /// title that was entered if `confirm` is true.
+    fn finish_rename(&mut self, confirm: bool, cx: &mut ViewContext<Self>) {
+        let Some(title_editor) = self.title_editor.take() else {
+            return;
+        };
+        let Some(conversation_editor) = self
+            .editors
+            .iter()
+            .find(|editor| editor.entity_id() == title_editor.conversation_editor)
+            .cloned()
+        else {
+            cx.notify();
+            return;
+        };
+
+        let title = title_editor.editor.read(cx).text(cx);
+        if confirm && !title.trim().is_empty() {
+            conversation_editor.update(cx, |editor, cx| {
+                editor.conversation.update(cx, |conversation, cx| {
+                    conversation.set_summary(title.trim().to_string(), cx)
+                });
+            });
+        }
+        if title_editor.editor.focus_handle(cx).is_focused(cx) {
+            cx.focus_view(&conversation_editor);
+        }
         cx.notify();
     }
 
@@ -850,6 +2122,11 @@ impl AssistantPanel {
     ) {
         match event {
             ConversationEditorEvent::TabContentChanged => cx.notify(),
+            ConversationEditorEvent::CredentialsRejected => {
+                self.build_api_key_editor(cx);
+                self.focus_handle.focus(cx);
+                cx.notify();
+            }
         }
     }
 
@@ -872,6 +2149,7 @@ impl AssistantPanel {
                     this.update(&mut cx, |this, cx| {
                         this.api_key_editor.take();
                         this.focus_handle.focus(cx);
+                        this.update_provider_status(cx);
                         cx.notify();
                     })
                 })
@@ -925,6 +2203,10 @@ impl AssistantPanel {
     }
 
     fn handle_editor_cancel(&mut self, _: &editor::actions::Cancel, cx: &mut ViewContext<Self>) {
+        if self.title_editor.is_some() {
+            self.finish_rename(false, cx);
+            return;
+        }
         if let Some(search_bar) = self.toolbar.read(cx).item_of_type::<BufferSearchBar>() {
             if !search_bar.read(cx).is_dismissed() {
                 search_bar.update(cx, |search_bar, cx| {
@@ -952,7 +2234,30 @@ impl AssistantPanel {
         self.editors.get(self.active_editor_index?)
     }
 
-    fn render_api_key_editor(
+    pub(crate) fn active_conversation(&self, cx: &AppContext) -> Option<Model<Conversation>> {
+        Some(self.active_editor()?.read(cx).conversation.clone())
+    }
+
+    /// The model of the active conversation, if there is one.
+    pub(crate) fn active_model(&self, cx: &AppContext) -> Option<ModelChoice> {
+        Some(self.active_conversation(cx)?.read(cx).model_choice())
+    }
+
+    /// Switches the active conversation to `choice`, starting a new
+    /// conversation if there isn't one.
+    pub(crate) fn set_active_model(&mut self, choice: ModelChoice, cx: &mut ViewContext<Self>) {
+        let editor = match self.active_editor() {
+            Some(editor) => editor.clone(),
+            None => self.new_conversation(cx),
+        };
+        editor.update(cx, |editor, cx| editor.set_model(choice, cx));
+    }
+
+    pub(crate) fn http_client(&self) -> Arc<dyn HttpClient> {
+        self.http_client.clone()
+    }
+
+    fn render_single_line_editor(
         &self,
         editor: &View<Editor>,
         cx: &mut ViewContext<Self>,
@@ -1047,13 +2352,53 @@ impl AssistantPanel {
             .tooltip(|cx| Tooltip::for_action("Quote Selection", &QuoteSelection, cx))
     }
 
-    fn render_plus_button(cx: &mut ViewContext<Self>) -> impl IntoElement {
-        IconButton::new("plus_button", IconName::Plus)
-            .on_click(cx.listener(|this, _event, cx| {
-                this.new_conversation(cx);
-            }))
-            .icon_size(IconSize::Small)
-            .tooltip(|cx| Tooltip::for_action("New Conversation", &NewConversation, cx))
+    fn render_plus_button(&self, cx: &mut ViewContext<Self>) -> AnyElement {
+        if self.conversation_templates.is_empty() {
+            return IconButton::new("plus_button", IconName::Plus)
+                .on_click(cx.listener(|this, _event, cx| {
+                    this.new_conversation(cx);
+                }))
+                .icon_size(IconSize::Small)
+                .tooltip(|cx| Tooltip::for_action("New Conversation", &NewConversation, cx))
+                .into_any_element();
+        }
+
+        let this = cx.view().downgrade();
+        let templates = self.conversation_templates.clone();
+        popover_menu("new_conversation_menu")
+            .menu(move |cx| {
+                let this = this.clone();
+                let templates = templates.clone();
+                Some(ContextMenu::build(cx, move |mut menu, _| {
+                    menu = menu.entry("New Conversation", Some(Box::new(NewConversation)), {
+                        let this = this.clone();
+                        move |cx| {
+                            this.update(cx, |this, cx| {
+                                this.new_conversation(cx);
+                            })
+                            .ok();
+                        }
+                    });
+                    menu = menu.separator().header("Templates");
+                    for template in templates {
+                        let this = this.clone();
+                        menu = menu.entry(template.name.clone(), None, move |cx| {
+                            this.update(cx, |this, cx| {
+                                this.new_conversation_from_template(&template, cx);
+                            })
+                            .ok();
+                        });
+                    }
+                    menu
+                }))
+            })
+            .anchor(AnchorCorner::TopRight)
+            .trigger(
+                IconButton::new("plus_button", IconName::Plus)
+                    .icon_size(IconSize::Small)
+                    .tooltip(|cx| Tooltip::text("New Conversation", cx)),
+            )
+            .into_any_element()
     }
 
     fn render_zoom_button(&self, cx: &mut ViewContext<Self>) -> impl IntoElement {
@@ -1070,6 +2415,60 @@ impl AssistantPanel {
             })
     }
 
+    fn render_conversation_tab(
+        &self,
+        index: usize,
+        cx: &mut ViewContext<Self>,
+    ) -> impl IntoElement {
+        let editor = &self.editors[index];
+        let active_index = self.active_editor_index;
+        let conversation = editor.read(cx).conversation.read(cx);
+        let generating = !conversation.pending_completions.is_empty();
+        let model = conversation.model.short_name().to_string();
+        let title = SharedString::from(editor.read(cx).title(cx));
+        let label = match &self.title_editor {
+            Some(title_editor) if title_editor.conversation_editor == editor.entity_id() => div()
+                .w(rems(8.))
+                .child(self.render_single_line_editor(&title_editor.editor, cx))
+                .into_any_element(),
+            _ => Label::new(title.clone()).into_any_element(),
+        };
+
+        Tab::new(("conversation_tab", index))
+            .position(if index == 0 {
+                TabPosition::First
+            } else if index == self.editors.len() - 1 {
+                TabPosition::Last
+            } else {
+                TabPosition::Middle(
+                    active_index
+                        .map_or(cmp::Ordering::Less, |active_index| index.cmp(&active_index)),
+                )
+            })
+            .selected(active_index == Some(index))
+            .on_click(cx.listener(move |this, event: &ClickEvent, cx| {
+                this.set_active_editor_index(Some(index), cx);
+                if event.up.click_count == 2 {
+                    this.rename_conversation(&RenameConversation, cx);
+                }
+            }))
+            .on_mouse_down(
+                MouseButton::Middle,
+                cx.listener(move |this, _, cx| this.close_conversation(index, cx)),
+            )
+            .tooltip(move |cx| Tooltip::with_meta(title.clone(), None, model.clone(), cx))
+            .start_slot(generating.then(|| Indicator::dot().color(Color::Accent)))
+            .end_slot(
+                IconButton::new(("close_conversation", index), IconName::Close)
+                    .shape(IconButtonShape::Square)
+                    .icon_color(Color::Muted)
+                    .size(ButtonSize::None)
+                    .icon_size(IconSize::XSmall)
+                    .on_click(cx.listener(move |this, _, cx| this.close_conversation(index, cx))),
+            )
+            .child(label)
+    }
+
     fn render_saved_conversation(
         &mut self,
         index: usize,
@@ -1098,9 +2497,25 @@ impl AssistantPanel {
             )
     }
 
-    fn open_conversation(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) -> Task<Result<()>> {
+    pub(crate) fn open_conversation(
+        &mut self,
+        path: PathBuf,
+        cx: &mut ViewContext<Self>,
+    ) -> Task<Result<()>> {
         cx.focus(&self.focus_handle);
+        self.load_conversation(path, cx)
+    }
+
+    pub(crate) fn saved_conversations(&self) -> &[SavedConversationMetadata] {
+        &self.saved_conversations
+    }
 
+    pub(crate) fn completion_provider(&self) -> Arc<dyn CompletionProvider> {
+        self.completion_provider.clone()
+    }
+
+    /// Opens the conversation saved at `path`, without focusing the panel.
+    fn load_conversation(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) -> Task<Result<()>> {
         if let Some(ix) = self.editor_index_for_path(&path, cx) {
             self.set_active_editor_index(Some(ix), cx);
             return Task::ready(Ok(()));
@@ -1109,12 +2524,18 @@ impl AssistantPanel {
         let fs = self.fs.clone();
         let workspace = self.workspace.clone();
         let languages = self.languages.clone();
+        let http_client = self.http_client.clone();
         cx.spawn(|this, mut cx| async move {
             let saved_conversation = fs.load(&path).await?;
             let saved_conversation = serde_json::from_str(&saved_conversation)?;
-            let conversation =
-                Conversation::deserialize(saved_conversation, path.clone(), languages, &mut cx)
-                    .await?;
+            let conversation = Conversation::deserialize(
+                saved_conversation,
+                path.clone(),
+                languages,
+                http_client,
+                &mut cx,
+            )
+            .await?;
 
             this.update(&mut cx, |this, cx| {
                 // If, by the time we've loaded the conversation, the user has already opened
@@ -1142,17 +2563,248 @@ impl AssistantPanel {
         self.completion_provider.has_credentials()
     }
 
+    /// A streaming request of `content` to the panel's default model, sampled
+    /// with the settings for its provider. For generations outside of a
+    /// conversation, such as commit messages.
+    pub(crate) fn request_for(&self, content: String, cx: &AppContext) -> ChatRequest {
+        let sampling_settings =
+            AssistantSettings::get_global(cx).sampling_for(self.default_model.provider);
+        ChatRequest {
+            model: self.default_model.model.full_name().into(),
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content,
+            }],
+            stream: true,
+            stop: sampling_settings.stop_sequences.clone().unwrap_or_default(),
+            temperature: sampling_settings
+                .temperature
+                .unwrap_or(GENERATION_TEMPERATURE),
+            sampling: sampling_settings.to_params(),
+            ..Default::default()
+        }
+    }
+
     fn load_credentials(&mut self, cx: &mut ViewContext<Self>) -> Task<()> {
         let completion_provider = self.completion_provider.clone();
-        cx.spawn(|_, mut cx| async move {
+        cx.spawn(|this, mut cx| async move {
             if let Some(retrieve_credentials) = cx
                 .update(|cx| completion_provider.retrieve_credentials(cx))
                 .log_err()
             {
-                retrieve_credentials.await;
+                let credential = retrieve_credentials.await;
+                this.update(&mut cx, |this, cx| {
+                    this.update_provider_status(cx);
+                    this.check_default_model(credential, cx);
+                })
+                .ok();
             }
         })
     }
+
+    /// Warns when the default model isn't one the OpenAI API serves, since the
+    /// setting accepts any model name.
+    fn check_default_model(&mut self, credential: ProviderCredential, cx: &mut ViewContext<Self>) {
+        const UNKNOWN_MODEL_TOAST_ID: usize = 0x6d6f64656c;
+
+        if matches!(credential, ProviderCredential::NoCredentials) {
+            return;
+        }
+        let settings = AssistantSettings::get_global(cx);
+        let model = settings.openai.model.clone();
+        let api_url = settings.openai.api_url.clone();
+        let options = ConnectionOptions {
+            tls: settings.openai.tls.to_options(),
+            extra_headers: settings.openai.headers(),
+            ..Default::default()
+        };
+        let http_client = self.http_client.clone();
+        cx.spawn(|this, mut cx| async move {
+            let models =
+                open_ai::list_models(http_client.as_ref(), &api_url, &credential, &options).await?;
+            if models.iter().any(|served| served.id == model.full_name()) {
+                return Ok(());
+            }
+
+            this.update(&mut cx, |this, cx| {
+                let message = format!(
+                    "The assistant's default model, {}, isn't served by {}",
+                    model.full_name(),
+                    api_url
+                );
+                if let Some(workspace) = this.workspace.upgrade() {
+                    workspace.update(cx, |workspace, cx| {
+                        workspace.show_toast(Toast::new(UNKNOWN_MODEL_TOAST_ID, message), cx)
+                    });
+                }
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Tells the user how legacy assistant settings were migrated, once for
+    /// each change, so they can update their settings file.
+    fn notify_settings_migrations(&mut self, cx: &mut ViewContext<Self>) {
+        const SETTINGS_MIGRATION_TOAST_ID: usize = 0x6d696772617465;
+
+        let migrations = AssistantSettings::get_global(cx).migrations.clone();
+        if migrations.is_empty() {
+            return;
+        }
+        let workspace = self.workspace.clone();
+        cx.spawn(|_, mut cx| async move {
+            let mut notified = cx
+                .background_executor()
+                .spawn(async { KEY_VALUE_STORE.read_kvp(SETTINGS_MIGRATIONS_KEY) })
+                .await?
+                .map(|notified| serde_json::from_str::<Vec<String>>(&notified))
+                .transpose()?
+                .unwrap_or_default();
+            let new_migrations = migrations
+                .into_iter()
+                .filter(|migration| !notified.contains(migration))
+                .collect::<Vec<_>>();
+            if new_migrations.is_empty() {
+                return Ok(());
+            }
+
+            let mut message = "Some assistant settings were migrated:".to_string();
+            for migration in &new_migrations {
+                message.push_str(&format!("\n- {migration}"));
+            }
+            notified.extend(new_migrations);
+            KEY_VALUE_STORE
+                .write_kvp(
+                    SETTINGS_MIGRATIONS_KEY.into(),
+                    serde_json::to_string(&notified)?,
+                )
+                .await?;
+            workspace.update(&mut cx, |workspace, cx| {
+                workspace.show_toast(Toast::new(SETTINGS_MIGRATION_TOAST_ID, message), cx)
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Rebuilds the completion provider when the settings change the default
+    /// model or how requests are made. Open conversations on the previous
+    /// default model move to the new one, and the others keep their model but
+    /// pick up the new request options.
+    fn apply_settings(&mut self, cx: &mut ViewContext<Self>) {
+        let settings = AssistantSettings::get_global(cx);
+        let project = self
+            .workspace
+            .upgrade()
+            .map(|workspace| workspace.read(cx).project().clone());
+        let default_model =
+            ModelChoice::default_for(project.as_ref().map(|project| project.read(cx)), cx);
+        let request_options = RequestOptions::new(settings);
+        if default_model == self.default_model && request_options == self.request_options {
+            return;
+        }
+
+        let previous_default_model = mem::replace(&mut self.default_model, default_model.clone());
+        self.request_options = request_options.clone();
+        self.provider_endpoints = request_options.endpoints(&default_model.api_url);
+        let http_client = self.http_client.clone();
+        let executor = cx.background_executor().clone();
+        self.pending_completion_provider = cx.spawn({
+            let default_model = default_model.clone();
+            let http_client = http_client.clone();
+            |this, mut cx| {
+                async move {
+                    let completion_provider = build_completion_provider(
+                        default_model.provider,
+                        default_model.api_url,
+                        default_model.model.full_name().into(),
+                        request_options,
+                        http_client,
+                        executor,
+                    )
+                    .await;
+                    cx.update(|cx| completion_provider.retrieve_credentials(cx))?
+                        .await;
+                    this.update(&mut cx, |this, cx| {
+                        this.completion_provider = completion_provider;
+                        this.update_provider_status(cx);
+                        cx.notify();
+                    })
+                }
+                .log_err()
+            }
+        });
+
+        for editor in &self.editors {
+            editor.update(cx, |editor, cx| {
+                let choice = editor.conversation.read(cx).model_choice();
+                if choice == previous_default_model {
+                    editor.set_model(default_model.clone(), cx);
+                } else {
+                    editor.conversation.update(cx, |conversation, cx| {
+                        conversation.set_model(choice, http_client.clone(), cx)
+                    });
+                }
+            });
+        }
+    }
+
+    /// Starts checking the panel's endpoints with the current credentials.
+    fn update_provider_status(&mut self, cx: &mut ViewContext<Self>) {
+        let completion_provider = self.completion_provider.clone();
+        let provider_endpoints = self.provider_endpoints.clone();
+        let provider_status = self.provider_status.clone();
+        cx.spawn(|_, mut cx| async move {
+            let credential = cx
+                .update(|cx| completion_provider.retrieve_credentials(cx))?
+                .await;
+            provider_status.update(&mut cx, |provider_status, cx| {
+                provider_status.set_endpoints(
+                    provider_endpoints
+                        .into_iter()
+                        .map(|(kind, api_url)| StatusEndpoint {
+                            credential: match kind {
+                                EndpointKind::OpenAi => credential.clone(),
+                                EndpointKind::Vllm => ProviderCredential::NotNeeded,
+                            },
+                            kind,
+                            api_url,
+                        }),
+                    cx,
+                )
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    fn render_provider_status(&self, cx: &mut ViewContext<Self>) -> Option<impl IntoElement> {
+        let provider_status = self.provider_status.read(cx);
+        let statuses = provider_status.statuses();
+        let primary = statuses.first()?;
+        let color = if statuses.iter().all(|status| status.status.is_connected()) {
+            Color::Success
+        } else if statuses.iter().any(|status| status.status.is_connected()) {
+            Color::Warning
+        } else if primary.checked_at.is_none() {
+            Color::Muted
+        } else {
+            Color::Error
+        };
+        let description = statuses
+            .iter()
+            .map(|status| status.description())
+            .collect::<Vec<_>>()
+            .join("\n");
+        Some(
+            div()
+                .id("provider_status")
+                .child(Indicator::dot().color(color))
+                .tooltip(move |cx| Tooltip::text(description.clone(), cx))
+                .on_click(cx.listener(|this, _, cx| {
+                    this.provider_status
+                        .update(cx, |provider_status, cx| provider_status.refresh(cx));
+                })),
+        )
+    }
 }
 
 fn build_api_key_editor(cx: &mut WindowContext) -> View<Editor> {
@@ -1191,7 +2843,7 @@ impl Render for AssistantPanel {
                         .py_1()
                         .bg(cx.theme().colors().editor_background)
                         .rounded_md()
-                        .child(self.render_api_key_editor(&api_key_editor, cx)),
+                        .child(self.render_single_line_editor(&api_key_editor, cx)),
                 )
                 .child(
                     h_flex()
@@ -1206,15 +2858,14 @@ impl Render for AssistantPanel {
         } else {
             let header = TabBar::new("assistant_header")
                 .start_child(
-                    h_flex().gap_1().child(Self::render_hamburger_button(cx)), // .children(title),
-                )
-                .children(self.active_editor().map(|editor| {
                     h_flex()
-                        .h(rems(Tab::CONTAINER_HEIGHT_IN_REMS))
-                        .flex_1()
-                        .px_2()
-                        .child(Label::new(editor.read(cx).title(cx)).into_element())
-                }))
+                        .gap_1()
+                        .child(Self::render_hamburger_button(cx))
+                        .children(self.render_provider_status(cx)), // .children(title),
+                )
+                .children(
+                    (0..self.editors.len()).map(|index| self.render_conversation_tab(index, cx)),
+                )
                 .when(self.focus_handle.contains_focused(cx), |this| {
                     this.end_child(
                         h_flex()
@@ -1230,7 +2881,7 @@ impl Render for AssistantPanel {
                             .child(
                                 h_flex()
                                     .gap_1()
-                                    .child(Self::render_plus_button(cx))
+                                    .child(self.render_plus_button(cx))
                                     .child(self.render_zoom_button(cx)),
                             ),
                     )
@@ -1258,6 +2909,11 @@ impl Render for AssistantPanel {
                 .on_action(cx.listener(AssistantPanel::select_next_match))
                 .on_action(cx.listener(AssistantPanel::select_prev_match))
                 .on_action(cx.listener(AssistantPanel::handle_editor_cancel))
+                .on_action(cx.listener(AssistantPanel::close_active_conversation))
+                .on_action(cx.listener(AssistantPanel::activate_next_conversation))
+                .on_action(cx.listener(AssistantPanel::activate_prev_conversation))
+                .on_action(cx.listener(AssistantPanel::rename_conversation))
+                .on_action(cx.listener(AssistantPanel::confirm_rename))
                 .track_focus(&self.focus_handle)
                 .child(header)
                 .children(if self.toolbar.read(cx).hidden() {
@@ -1344,6 +3000,7 @@ impl Panel for AssistantPanel {
             DockPosition::Left | DockPosition::Right => self.width = size,
             DockPosition::Bottom => self.height = size,
         }
+        self.serialize(cx);
         cx.notify();
     }
 
@@ -1398,6 +3055,7 @@ enum ConversationEvent {
     MessagesEdited,
     SummaryChanged,
     StreamedCompletion,
+    CredentialsRejected,
 }
 
 #[derive(Default)]
@@ -1406,7 +3064,17 @@ struct Summary {
     done: bool,
 }
 
-struct Conversation {
+/// The temperature conversation messages are sampled with.
+const CONVERSATION_TEMPERATURE: f32 = 1.0;
+
+/// The temperature generations outside of conversations, such as tests and
+/// commit messages, are sampled with unless the settings set one.
+const GENERATION_TEMPERATURE: f32 = 0.5;
+
+/// The most tokens a generated conversation title can take.
+const TITLE_MAX_TOKENS: u32 = 20;
+
+pub(crate) struct Conversation {
     id: Option<String>,
     buffer: Model<Buffer>,
     message_anchors: Vec<MessageAnchor>,
@@ -1416,43 +3084,88 @@ struct Conversation {
     pending_summary: Task<Option<()>>,
     completion_count: usize,
     pending_completions: Vec<PendingCompletion>,
-    model: OpenAiModel,
+    /// The provider, model and API URL the conversation is pinned to, which
+    /// start out as the defaults in the settings but can be changed from its
+    /// header.
+    provider: ProviderKind,
+    model: ModelName,
     api_url: Option<String>,
     token_count: Option<usize>,
     max_token_count: usize,
     pending_token_count: Task<Option<()>>,
+    /// The tokens used by every completion in this conversation, as reported by
+    /// the provider.
+    usage: TokenUsage,
+    /// The temperature responses are sampled at, when the conversation was
+    /// created from a template that sets one.
+    temperature: Option<f32>,
     pending_save: Task<Result<()>>,
     path: Option<PathBuf>,
     _subscriptions: Vec<Subscription>,
     completion_provider: Arc<dyn CompletionProvider>,
+    pending_completion_provider: Task<Option<()>>,
+    /// Every version of the conversation once it's been forked. The messages in
+    /// the buffer are the version at `active_branch`, whose entry here is only
+    /// brought up to date when switching to another branch.
+    branches: Vec<SavedBranch>,
+    active_branch: usize,
+    /// The older messages being summarized to make room in the model's context.
+    pending_compaction: Option<Task<()>>,
 }
 
 impl EventEmitter<ConversationEvent> for Conversation {}
 
+/// Highlights a conversation's buffer as Markdown, with its code blocks in the
+/// languages they're declared as.
+fn highlight_as_markdown(
+    buffer: &mut Buffer,
+    language_registry: Arc<LanguageRegistry>,
+    cx: &mut ModelContext<Buffer>,
+) {
+    let markdown = language_registry.language_for_name("Markdown");
+    let mut languages_changed = language_registry.subscribe();
+    buffer.set_language_registry(language_registry);
+    cx.spawn(|buffer, mut cx| async move {
+        let markdown = markdown.await?;
+        buffer.update(&mut cx, |buffer, cx| {
+            buffer.set_language(Some(markdown), cx)
+        })?;
+
+        // A code block's language starts loading when the block is first parsed,
+        // and the block isn't highlighted until it's parsed again after that.
+        // Projects reparse their buffers when languages load, but conversations
+        // aren't in one, so without this a block streamed in before its language
+        // loaded would stay plain once the response stopped changing the buffer.
+        while languages_changed.next().await.is_some() {
+            let reparsed = buffer.update(&mut cx, |buffer, cx| {
+                if buffer.contains_unknown_injections() {
+                    buffer.reparse(cx);
+                }
+            });
+            if reparsed.is_err() {
+                break;
+            }
+        }
+        anyhow::Ok(())
+    })
+    .detach_and_log_err(cx);
+}
+
 impl Conversation {
     fn new(
         language_registry: Arc<LanguageRegistry>,
         cx: &mut ModelContext<Self>,
         completion_provider: Arc<dyn CompletionProvider>,
     ) -> Self {
-        let markdown = language_registry.language_for_name("Markdown");
         let buffer = cx.new_model(|cx| {
             let mut buffer = Buffer::new(0, BufferId::new(cx.entity_id().as_u64()).unwrap(), "");
-            buffer.set_language_registry(language_registry);
-            cx.spawn(|buffer, mut cx| async move {
-                let markdown = markdown.await?;
-                buffer.update(&mut cx, |buffer: &mut Buffer, cx| {
-                    buffer.set_language(Some(markdown), cx)
-                })?;
-                anyhow::Ok(())
-            })
-            .detach_and_log_err(cx);
+            highlight_as_markdown(&mut buffer, language_registry, cx);
             buffer
         });
 
         let settings = AssistantSettings::get_global(cx);
-        let model = settings.default_open_ai_model.clone();
-        let api_url = settings.openai_api_url.clone();
+        let model = settings.openai.model.clone();
+        let api_url = settings.openai.api_url.clone();
 
         let mut this = Self {
             id: Some(Uuid::new_v4().to_string()),
@@ -1466,6 +3179,9 @@ impl Conversation {
             token_count: None,
             max_token_count: tiktoken_rs::model::get_context_size(&model.full_name()),
             pending_token_count: Task::ready(None),
+            usage: TokenUsage::default(),
+            temperature: None,
+            provider: ProviderKind::OpenAi,
             api_url: Some(api_url),
             model: model.clone(),
             _subscriptions: vec![cx.subscribe(&buffer, Self::handle_buffer_event)],
@@ -1473,6 +3189,10 @@ impl Conversation {
             path: None,
             buffer,
             completion_provider,
+            pending_completion_provider: Task::ready(None),
+            pending_compaction: None,
+            branches: Vec::new(),
+            active_branch: 0,
         };
         let message = MessageAnchor {
             id: MessageId(post_inc(&mut this.next_message_id.0)),
@@ -1485,6 +3205,10 @@ impl Conversation {
                 role: Role::User,
                 sent_at: Local::now(),
                 status: MessageStatus::Done,
+                seed: None,
+                answered_by: None,
+                pinned: false,
+                citations: Vec::new(),
             },
         );
 
@@ -1492,6 +3216,44 @@ impl Conversation {
         this
     }
 
+    /// The conversation with its messages and the parameters they were sent with,
+    /// for exporting to another format.
+    fn export(&self, title: String, cx: &AppContext) -> ExportedConversation {
+        let buffer = self.buffer.read(cx);
+        ExportedConversation {
+            version: ExportedConversation::VERSION.into(),
+            title,
+            model: self.model.clone(),
+            provider: self.provider,
+            api_url: self.api_url.clone(),
+            parameters: ExportedParameters {
+                temperature: self.sampling_temperature(cx),
+                sampling: AssistantSettings::get_global(cx).sampling_for(self.provider),
+            },
+            usage: self.usage,
+            messages: self
+                .messages(cx)
+                .map(|message| ExportedMessage {
+                    role: message.role,
+                    content: buffer
+                        .text_for_range(message.offset_range.clone())
+                        .collect::<String>()
+                        .trim_end()
+                        .to_string(),
+                    sent_at: message.sent_at,
+                    status: message.status,
+                    seed: self
+                        .messages_metadata
+                        .get(&message.id)
+                        .and_then(|metadata| metadata.seed),
+                    answered_by: message.answered_by,
+                    pinned: message.pinned,
+                    citations: message.citations,
+                })
+                .collect(),
+        }
+    }
+
     fn serialize(&self, cx: &AppContext) -> SavedConversation {
         SavedConversation {
             id: self.id.clone(),
@@ -1511,8 +3273,13 @@ impl Conversation {
                 .as_ref()
                 .map(|summary| summary.text.clone())
                 .unwrap_or_default(),
+            provider: self.provider,
             model: self.model.clone(),
             api_url: self.api_url.clone(),
+            usage: self.usage,
+            temperature: self.temperature,
+            branches: self.branches.clone(),
+            active_branch: self.active_branch,
         }
     }
 
@@ -1520,28 +3287,32 @@ impl Conversation {
         saved_conversation: SavedConversation,
         path: PathBuf,
         language_registry: Arc<LanguageRegistry>,
+        http_client: Arc<dyn HttpClient>,
         cx: &mut AsyncAppContext,
     ) -> Result<Model<Self>> {
         let id = match saved_conversation.id {
             Some(id) => Some(id),
             None => Some(Uuid::new_v4().to_string()),
         };
+        let provider = saved_conversation.provider;
         let model = saved_conversation.model;
         let api_url = saved_conversation.api_url;
-        let completion_provider: Arc<dyn CompletionProvider> = Arc::new(
-            OpenAiCompletionProvider::new(
-                api_url
-                    .clone()
-                    .unwrap_or_else(|| OPEN_AI_API_URL.to_string()),
-                model.full_name().into(),
-                cx.background_executor().clone(),
-            )
-            .await,
-        );
+        let request_options =
+            cx.update(|cx| RequestOptions::new(AssistantSettings::get_global(cx)))?;
+        let completion_provider = build_completion_provider(
+            provider,
+            api_url
+                .clone()
+                .unwrap_or_else(|| provider.default_api_url().to_string()),
+            model.full_name().into(),
+            request_options,
+            http_client,
+            cx.background_executor().clone(),
+        )
+        .await;
         cx.update(|cx| completion_provider.retrieve_credentials(cx))?
             .await;
 
-        let markdown = language_registry.language_for_name("Markdown");
         let mut message_anchors = Vec::new();
         let mut next_message_id = MessageId(0);
         let buffer = cx.new_model(|cx| {
@@ -1557,15 +3328,7 @@ impl Conversation {
                 });
                 next_message_id = cmp::max(next_message_id, MessageId(message.id.0 + 1));
             }
-            buffer.set_language_registry(language_registry);
-            cx.spawn(|buffer, mut cx| async move {
-                let markdown = markdown.await?;
-                buffer.update(&mut cx, |buffer: &mut Buffer, cx| {
-                    buffer.set_language(Some(markdown), cx)
-                })?;
-                anyhow::Ok(())
-            })
-            .detach_and_log_err(cx);
+            highlight_as_markdown(&mut buffer, language_registry, cx);
             buffer
         })?;
 
@@ -1583,8 +3346,11 @@ impl Conversation {
                 completion_count: Default::default(),
                 pending_completions: Default::default(),
                 token_count: None,
-                max_token_count: tiktoken_rs::model::get_context_size(&model.full_name()),
+                max_token_count: 0,
                 pending_token_count: Task::ready(None),
+                usage: saved_conversation.usage,
+                temperature: saved_conversation.temperature,
+                provider,
                 api_url,
                 model,
                 _subscriptions: vec![cx.subscribe(&buffer, Self::handle_buffer_event)],
@@ -1592,7 +3358,14 @@ impl Conversation {
                 path: Some(path),
                 buffer,
                 completion_provider,
+                pending_completion_provider: Task::ready(None),
+                pending_compaction: None,
+                active_branch: saved_conversation
+                    .active_branch
+                    .min(saved_conversation.branches.len().saturating_sub(1)),
+                branches: saved_conversation.branches,
             };
+            this.max_token_count = this.context_size();
             this.count_remaining_tokens(cx);
             this
         })
@@ -1614,27 +3387,12 @@ impl Conversation {
     }
 
     fn count_remaining_tokens(&mut self, cx: &mut ModelContext<Self>) {
+        let buffer = self.buffer.read(cx);
         let messages = self
             .messages(cx)
-            .into_iter()
-            .filter_map(|message| {
-                Some(tiktoken_rs::ChatCompletionRequestMessage {
-                    role: match message.role {
-                        Role::User => "user".into(),
-                        Role::Assistant => "assistant".into(),
-                        Role::System => "system".into(),
-                    },
-                    content: Some(
-                        self.buffer
-                            .read(cx)
-                            .text_for_range(message.offset_range)
-                            .collect(),
-                    ),
-                    name: None,
-                    function_call: None,
-                })
-            })
+            .map(|message| message.to_open_ai_message(buffer))
             .collect::<Vec<_>>();
+        let provider = self.provider;
         let model = self.model.clone();
         self.pending_token_count = cx.spawn(|this, mut cx| {
             async move {
@@ -1644,14 +3402,28 @@ impl Conversation {
                 let token_count = cx
                     .background_executor()
                     .spawn(async move {
-                        tiktoken_rs::num_tokens_from_messages(&model.full_name(), &messages)
+                        match provider {
+                            ProviderKind::OpenAi => OpenAiLanguageModel::load(model.full_name())
+                                .count_message_tokens(&messages),
+                            ProviderKind::Vllm => VllmLanguageModel::load(model.full_name(), None)
+                                .count_message_tokens(&messages),
+                            ProviderKind::Perplexity => {
+                                PerplexityLanguageModel::load(model.full_name())
+                                    .count_message_tokens(&messages)
+                            }
+                        }
                     })
                     .await?;
 
                 this.update(&mut cx, |this, cx| {
-                    this.max_token_count =
-                        tiktoken_rs::model::get_context_size(&this.model.full_name());
+                    this.max_token_count = this.context_size();
                     this.token_count = Some(token_count);
+                    if this.is_near_capacity()
+                        && this.pending_completions.is_empty()
+                        && AssistantSettings::get_global(cx).auto_summarize
+                    {
+                        this.summarize_older_messages(cx);
+                    }
                     cx.notify()
                 })?;
                 anyhow::Ok(())
@@ -1664,10 +3436,21 @@ impl Conversation {
         Some(self.max_token_count as isize - self.token_count? as isize)
     }
 
-    fn set_model(&mut self, model: OpenAiModel, cx: &mut ModelContext<Self>) {
-        self.model = model;
-        self.count_remaining_tokens(cx);
-        cx.notify();
+    /// The temperature messages are sampled at: the template's, or else the
+    /// one in the settings for the conversation's provider.
+    fn sampling_temperature(&self, cx: &AppContext) -> f32 {
+        self.temperature
+            .or_else(|| {
+                AssistantSettings::get_global(cx)
+                    .sampling_for(self.provider)
+                    .temperature
+            })
+            .unwrap_or(CONVERSATION_TEMPERATURE)
+    }
+
+    /// Whether a response is being streamed into the conversation.
+    pub(crate) fn is_generating(&self) -> bool {
+        !self.pending_completions.is_empty()
     }
 
     fn assist(
@@ -1718,102 +3501,371 @@ impl Conversation {
                 return Default::default();
             }
 
-            let request: Box<dyn CompletionRequest> = Box::new(OpenAiRequest {
-                model: self.model.full_name().to_string(),
-                messages: self
-                    .messages(cx)
-                    .filter(|message| matches!(message.status, MessageStatus::Done))
-                    .map(|message| message.to_open_ai_message(self.buffer.read(cx)))
-                    .collect(),
-                stream: true,
-                stop: vec![],
-                temperature: 1.0,
-            });
-
-            let stream = self.completion_provider.complete(request);
+            let messages = self
+                .messages(cx)
+                .filter(|message| message.status.is_complete())
+                .collect();
+            let seed = rand::random();
             let assistant_message = self
                 .insert_message_after(last_message_id, Role::Assistant, MessageStatus::Pending, cx)
                 .unwrap();
+            if let Some(metadata) = self.messages_metadata.get_mut(&assistant_message.id) {
+                metadata.seed = Some(seed);
+            }
+
+            // Queue up the user's next reply.
+            let user_message = self
+                .insert_message_after(assistant_message.id, Role::User, MessageStatus::Done, cx)
+                .unwrap();
+            user_messages.push(user_message);
+
+            self.stream_completion(assistant_message, messages, seed, cx);
+        }
+
+        user_messages
+    }
+
+    /// Replaces the text of an assistant message by sending the messages before it
+    /// again with the seed it was generated with, so that changes to the prompt can
+    /// be compared without sampling noise. Returns false for messages that weren't
+    /// generated by the assistant.
+    fn regenerate_with_same_seed(
+        &mut self,
+        message_id: MessageId,
+        cx: &mut ModelContext<Self>,
+    ) -> bool {
+        let Some(seed) = self
+            .messages_metadata
+            .get(&message_id)
+            .filter(|metadata| {
+                metadata.role == Role::Assistant
+                    && !matches!(
+                        metadata.status,
+                        MessageStatus::Pending | MessageStatus::Queued(_)
+                    )
+            })
+            .and_then(|metadata| metadata.seed)
+        else {
+            return false;
+        };
+        if !self.completion_provider.has_credentials() {
+            log::info!("completion provider has no credentials");
+            return false;
+        }
+
+        let mut messages = Vec::new();
+        let mut message_range = None;
+        let mut all_messages = self.messages(cx).peekable();
+        while let Some(message) = all_messages.next() {
+            if message.id == message_id {
+                let mut range = message.offset_range;
+                // Keep the newline that separates this message from the next one.
+                if all_messages.peek().is_some() {
+                    range.end -= 1;
+                }
+                message_range = Some((message.anchor, range));
+                break;
+            } else if message.status.is_complete() {
+                messages.push(message);
+            }
+        }
+        let Some((anchor, range)) = message_range else {
+            return false;
+        };
+
+        self.buffer
+            .update(cx, |buffer, cx| buffer.edit([(range, "")], None, cx));
+        if let Some(metadata) = self.messages_metadata.get_mut(&message_id) {
+            metadata.status = MessageStatus::Pending;
+        }
+        cx.emit(ConversationEvent::MessagesEdited);
+
+        let assistant_message = MessageAnchor {
+            id: message_id,
+            start: anchor,
+        };
+        self.stream_completion(assistant_message, messages, seed, cx);
+        true
+    }
+
+    /// Resumes an assistant message that reached the maximum number of tokens or
+    /// was stopped, appending the rest of the response to it.
+    fn continue_message(&mut self, message_id: MessageId, cx: &mut ModelContext<Self>) -> bool {
+        let Some(metadata) = self
+            .messages_metadata
+            .get_mut(&message_id)
+            .filter(|metadata| metadata.status.is_continuable())
+        else {
+            return false;
+        };
+        metadata.status = MessageStatus::Pending;
+        let seed = metadata.seed.unwrap_or_else(rand::random);
+
+        let mut messages = Vec::new();
+        let mut assistant_message = None;
+        for message in self.messages(cx) {
+            if message.id == message_id {
+                assistant_message = Some(MessageAnchor {
+                    id: message.id,
+                    start: message.anchor,
+                });
+                messages.push(message);
+                break;
+            } else if message.status.is_complete() {
+                messages.push(message);
+            }
+        }
+        let Some(assistant_message) = assistant_message else {
+            return false;
+        };
 
-            // Queue up the user's next reply.
-            let user_message = self
-                .insert_message_after(assistant_message.id, Role::User, MessageStatus::Done, cx)
-                .unwrap();
-            user_messages.push(user_message);
+        cx.emit(ConversationEvent::MessagesEdited);
+        self.stream_completion(assistant_message, messages, seed, cx);
+        true
+    }
 
-            let task = cx.spawn({
-                |this, mut cx| async move {
-                    let assistant_message_id = assistant_message.id;
-                    let stream_completion = async {
-                        let mut messages = stream.await?;
+    /// Streams a response to `context` into `assistant_message`.
+    fn stream_completion(
+        &mut self,
+        assistant_message: MessageAnchor,
+        context: Vec<Message>,
+        seed: u64,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let sampling_settings = AssistantSettings::get_global(cx).sampling_for(self.provider);
+        let mut sampling = sampling_settings.to_params();
+        let model = self.completion_provider.base_model();
+        // Never ask for more than the model can write in one response, so the
+        // request isn't rejected, and leave room in the context for all of it.
+        if let Some(max_output_tokens) = model.max_output_tokens() {
+            let max_output_tokens = max_output_tokens as u32;
+            sampling.max_tokens = Some(
+                sampling
+                    .max_tokens
+                    .unwrap_or(max_output_tokens)
+                    .min(max_output_tokens),
+            );
+        }
+        let temperature = self.sampling_temperature(cx);
+        let buffer = self.buffer.read(cx);
+        let mut messages = context
+            .iter()
+            .map(|message| message.to_open_ai_message(buffer))
+            .collect::<Vec<_>>();
+        let pinned = context
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, message)| message.pinned.then_some(ix))
+            .collect::<Vec<_>>();
+        // Once the conversation outgrows the model's context, drop its oldest turns
+        // rather than letting the request fail. Pinned messages are always kept, so
+        // if they don't fit on their own there's no point sending the request.
+        if self
+            .remaining_tokens()
+            .map_or(false, |remaining_tokens| remaining_tokens <= 0)
+        {
+            let max_tokens = self
+                .max_token_count
+                .saturating_sub(sampling.max_tokens.unwrap_or(0) as usize);
+            match truncate_messages_keeping(model.as_ref(), messages, &pinned, max_tokens) {
+                Ok(truncated_messages) => messages = truncated_messages,
+                Err(error) => {
+                    self.set_message_status(
+                        assistant_message.id,
+                        MessageStatus::Error(
+                            format!("{error}. Unpin some messages to continue.").into(),
+                        ),
+                        cx,
+                    );
+                    return;
+                }
+            }
+        }
+        let request = ChatRequest {
+            model: self.model.full_name().to_string(),
+            messages,
+            stream: true,
+            stop: sampling_settings.stop_sequences.unwrap_or_default(),
+            temperature,
+            sampling,
+            seed: self.provider.supports_seed().then_some(seed),
+            ..Default::default()
+        };
 
-                        while let Some(message) = messages.next().await {
-                            let text = message?;
+        let (stream, cancellation) = self.completion_provider.complete_cancellable(request);
+        let task = cx.spawn({
+            let cancellation = cancellation.clone();
+            |this, mut cx| async move {
+                let assistant_message_id = assistant_message.id;
+                let stream_completion = async {
+                    let mut events = stream.await?;
+                    let mut finish_reason = None;
+
+                    while let Some(event) = events.next().await {
+                        let text = match event? {
+                            CompletionEvent::Delta { text, .. } if !text.is_empty() => text,
+                            CompletionEvent::FinishReason(reason) => {
+                                finish_reason = Some(reason);
+                                continue;
+                            }
+                            CompletionEvent::Usage(usage) => {
+                                this.update(&mut cx, |this, cx| {
+                                    this.usage += usage;
+                                    cx.notify();
+                                })?;
+                                continue;
+                            }
+                            CompletionEvent::AnsweredBy(provider) => {
+                                this.update(&mut cx, |this, cx| {
+                                    if let Some(metadata) =
+                                        this.messages_metadata.get_mut(&assistant_message_id)
+                                    {
+                                        metadata.answered_by = Some(provider.into());
+                                        cx.emit(ConversationEvent::MessagesEdited);
+                                        cx.notify();
+                                    }
+                                })?;
+                                continue;
+                            }
+                            CompletionEvent::Citations(citations) => {
+                                this.update(&mut cx, |this, cx| {
+                                    if let Some(metadata) =
+                                        this.messages_metadata.get_mut(&assistant_message_id)
+                                    {
+                                        metadata.citations = citations;
+                                        cx.emit(ConversationEvent::MessagesEdited);
+                                        cx.notify();
+                                    }
+                                })?;
+                                continue;
+                            }
+                            CompletionEvent::Queued { position } => {
+                                this.update(&mut cx, |this, cx| {
+                                    this.set_message_status(
+                                        assistant_message_id,
+                                        MessageStatus::Queued(position),
+                                        cx,
+                                    );
+                                })?;
+                                continue;
+                            }
+                            _ => continue,
+                        };
 
-                            this.update(&mut cx, |this, cx| {
-                                let message_ix = this
-                                    .message_anchors
+                        this.update(&mut cx, |this, cx| {
+                            if let Some(MessageStatus::Queued(_)) = this
+                                .messages_metadata
+                                .get(&assistant_message_id)
+                                .map(|metadata| &metadata.status)
+                            {
+                                this.set_message_status(
+                                    assistant_message_id,
+                                    MessageStatus::Pending,
+                                    cx,
+                                );
+                            }
+                            let message_ix = this
+                                .message_anchors
+                                .iter()
+                                .position(|message| message.id == assistant_message_id)?;
+                            this.buffer.update(cx, |buffer, cx| {
+                                let offset = this.message_anchors[message_ix + 1..]
                                     .iter()
-                                    .position(|message| message.id == assistant_message_id)?;
-                                this.buffer.update(cx, |buffer, cx| {
-                                    let offset = this.message_anchors[message_ix + 1..]
-                                        .iter()
-                                        .find(|message| message.start.is_valid(buffer))
-                                        .map_or(buffer.len(), |message| {
-                                            message.start.to_offset(buffer).saturating_sub(1)
-                                        });
-                                    buffer.edit([(offset..offset, text)], None, cx);
-                                });
-                                cx.emit(ConversationEvent::StreamedCompletion);
-
-                                Some(())
-                            })?;
-                            smol::future::yield_now().await;
-                        }
+                                    .find(|message| message.start.is_valid(buffer))
+                                    .map_or(buffer.len(), |message| {
+                                        message.start.to_offset(buffer).saturating_sub(1)
+                                    });
+                                buffer.edit([(offset..offset, text)], None, cx);
+                            });
+                            cx.emit(ConversationEvent::StreamedCompletion);
 
-                        this.update(&mut cx, |this, cx| {
-                            this.pending_completions
-                                .retain(|completion| completion.id != this.completion_count);
-                            this.summarize(cx);
+                            Some(())
                         })?;
+                        smol::future::yield_now().await;
+                    }
 
-                        anyhow::Ok(())
-                    };
+                    this.update(&mut cx, |this, cx| {
+                        this.pending_completions
+                            .retain(|completion| completion.id != this.completion_count);
+                        this.summarize(cx);
+                    })?;
 
-                    let result = stream_completion.await;
+                    anyhow::Ok(finish_reason)
+                };
 
-                    this.update(&mut cx, |this, cx| {
-                        if let Some(metadata) =
-                            this.messages_metadata.get_mut(&assistant_message.id)
-                        {
-                            match result {
-                                Ok(_) => {
-                                    metadata.status = MessageStatus::Done;
-                                }
-                                Err(error) => {
-                                    metadata.status = MessageStatus::Error(SharedString::from(
-                                        error.to_string().trim().to_string(),
-                                    ));
+                let result = stream_completion.await;
+
+                this.update(&mut cx, |this, cx| {
+                    if let Some(metadata) = this.messages_metadata.get_mut(&assistant_message.id) {
+                        match result {
+                            Ok(_) if cancellation.is_cancelled() => {
+                                metadata.status = MessageStatus::Stopped;
+                            }
+                            Ok(Some(FinishReason::Length)) => {
+                                metadata.status = MessageStatus::Truncated;
+                            }
+                            Ok(Some(FinishReason::ContentFilter)) => {
+                                metadata.status = MessageStatus::Error(
+                                    "The response was stopped by the provider's content filter"
+                                        .into(),
+                                );
+                            }
+                            Ok(_) => {
+                                metadata.status = MessageStatus::Done;
+                            }
+                            Err(error) => {
+                                // The panel can't take Perplexity API keys, which are
+                                // read from `PERPLEXITY_API_KEY`.
+                                if let Some(CompletionError::Unauthorized { .. }) =
+                                    error.downcast_ref::<CompletionError>()
+                                {
+                                    if this.provider != ProviderKind::Perplexity {
+                                        cx.emit(ConversationEvent::CredentialsRejected);
+                                    }
                                 }
+                                metadata.status = MessageStatus::Error(SharedString::from(
+                                    completion_error_message(&error),
+                                ));
                             }
-                            cx.notify();
                         }
-                    })
-                    .ok();
-                }
-            });
-
-            self.pending_completions.push(PendingCompletion {
-                id: post_inc(&mut self.completion_count),
-                _task: task,
-            });
-        }
+                        cx.emit(ConversationEvent::MessagesEdited);
+                        cx.notify();
+                    }
+                })
+                .ok();
+            }
+        });
 
-        user_messages
+        self.pending_completions.push(PendingCompletion {
+            id: post_inc(&mut self.completion_count),
+            cancellation,
+            task,
+        });
     }
 
     fn cancel_last_assist(&mut self) -> bool {
-        self.pending_completions.pop().is_some()
+        if let Some(completion) = self.pending_completions.pop() {
+            // Let the task finish on its own so the message is marked as stopped
+            // with whatever was streamed before cancelling.
+            completion.cancellation.cancel();
+            completion.task.detach();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn set_message_status(
+        &mut self,
+        message_id: MessageId,
+        status: MessageStatus,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if let Some(metadata) = self.messages_metadata.get_mut(&message_id) {
+            metadata.status = status;
+            cx.emit(ConversationEvent::MessagesEdited);
+            cx.notify();
+        }
     }
 
     fn cycle_message_roles(&mut self, ids: HashSet<MessageId>, cx: &mut ModelContext<Self>) {
@@ -1826,6 +3878,16 @@ impl Conversation {
         }
     }
 
+    fn toggle_message_pins(&mut self, ids: HashSet<MessageId>, cx: &mut ModelContext<Self>) {
+        for id in ids {
+            if let Some(metadata) = self.messages_metadata.get_mut(&id) {
+                metadata.pinned = !metadata.pinned;
+                cx.emit(ConversationEvent::MessagesEdited);
+                cx.notify();
+            }
+        }
+    }
+
     fn insert_message_after(
         &mut self,
         message_id: MessageId,
@@ -1867,6 +3929,10 @@ impl Conversation {
                     role,
                     sent_at: Local::now(),
                     status,
+                    seed: None,
+                    answered_by: None,
+                    pinned: false,
+                    citations: Vec::new(),
                 },
             );
             cx.emit(ConversationEvent::MessagesEdited);
@@ -1876,6 +3942,37 @@ impl Conversation {
         }
     }
 
+    /// Replaces the empty first message of a new conversation with the
+    /// template's messages, ending with a user message to continue from.
+    fn apply_template(&mut self, template: &ConversationTemplate, cx: &mut ModelContext<Self>) {
+        let mut messages = template.initial_messages();
+        if messages
+            .last()
+            .map_or(true, |message| message.role != Role::User)
+        {
+            messages.push(TemplateMessage {
+                role: Role::User,
+                content: String::new(),
+            });
+        }
+
+        let mut message_id = self.message_anchors[0].id;
+        for (ix, message) in messages.into_iter().enumerate() {
+            if ix == 0 {
+                self.set_message_role(message_id, message.role, cx);
+            } else if let Some(anchor) =
+                self.insert_message_after(message_id, message.role, MessageStatus::Done, cx)
+            {
+                message_id = anchor.id;
+            }
+            self.buffer.update(cx, |buffer, cx| {
+                let end = buffer.len();
+                buffer.edit([(end..end, message.content)], None, cx)
+            });
+        }
+        self.temperature = template.temperature;
+    }
+
     fn split_message(
         &mut self,
         range: Range<usize>,
@@ -1927,6 +4024,10 @@ impl Conversation {
                     role,
                     sent_at: Local::now(),
                     status: MessageStatus::Done,
+                    seed: None,
+                    answered_by: None,
+                    pinned: false,
+                    citations: Vec::new(),
                 },
             );
 
@@ -1972,6 +4073,10 @@ impl Conversation {
                             role,
                             sent_at: Local::now(),
                             status: MessageStatus::Done,
+                            seed: None,
+                            answered_by: None,
+                            pinned: false,
+                            citations: Vec::new(),
                         },
                     );
                     (Some(selection), Some(suffix))
@@ -2001,18 +4106,23 @@ impl Conversation {
                     content: "Summarize the conversation into a short title without punctuation"
                         .into(),
                 }));
-            let request: Box<dyn CompletionRequest> = Box::new(OpenAiRequest {
-                model: self.model.full_name().to_string(),
+            let request = ChatRequest {
+                model: self.title_model().full_name().to_string(),
                 messages: messages.collect(),
                 stream: true,
                 stop: vec![],
                 temperature: 1.0,
-            });
+                sampling: SamplingParams {
+                    max_tokens: Some(TITLE_MAX_TOKENS),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
 
             let stream = self.completion_provider.complete(request);
             self.pending_summary = cx.spawn(|this, mut cx| {
                 async move {
-                    let mut messages = stream.await?;
+                    let mut messages = text_only(stream.await?);
 
                     while let Some(message) = messages.next().await {
                         let text = message?;
@@ -2027,6 +4137,7 @@ impl Conversation {
 
                     this.update(&mut cx, |this, cx| {
                         if let Some(summary) = this.summary.as_mut() {
+                            summary.text = summary.text.trim().trim_matches('"').to_string();
                             summary.done = true;
                             cx.emit(ConversationEvent::SummaryChanged);
                         }
@@ -2039,6 +4150,25 @@ impl Conversation {
         }
     }
 
+    /// The model that titles the conversation: the cheapest known model of the
+    /// conversation's provider when it's using a paid one, since any model can
+    /// write a title, or otherwise the conversation's own model.
+    fn title_model(&self) -> ModelName {
+        let cheapest_model = self
+            .model
+            .definition()
+            .filter(|model| model.pricing.is_some())
+            .and_then(|model| cheapest_model(model.provider));
+        cheapest_model.map_or_else(|| self.model.clone(), ModelName::from)
+    }
+
+    /// Replaces the conversation's title, stopping one that's being generated.
+    fn set_summary(&mut self, text: String, cx: &mut ModelContext<Self>) {
+        self.pending_summary = Task::ready(None);
+        self.summary = Some(Summary { text, done: true });
+        cx.emit(ConversationEvent::SummaryChanged);
+    }
+
     fn message_for_offset(&self, offset: usize, cx: &AppContext) -> Option<Message> {
         self.messages_for_offsets([offset], cx).pop()
     }
@@ -2105,6 +4235,9 @@ impl Conversation {
                     role: metadata.role,
                     sent_at: metadata.sent_at,
                     status: metadata.status.clone(),
+                    answered_by: metadata.answered_by.clone(),
+                    pinned: metadata.pinned,
+                    citations: metadata.citations.clone(),
                 });
             }
             None
@@ -2138,30 +4271,40 @@ impl Conversation {
 
             if let Some(summary) = summary {
                 let conversation = this.read_with(&cx, |this, cx| this.serialize(cx))?;
-                let path = if let Some(old_path) = old_path {
-                    old_path
-                } else {
-                    let mut discriminant = 1;
-                    let mut new_path;
-                    loop {
-                        new_path = CONVERSATIONS_DIR.join(&format!(
-                            "{} - {}.zed.json",
-                            summary.trim(),
-                            discriminant
-                        ));
-                        if fs.is_file(&new_path).await {
-                            discriminant += 1;
-                        } else {
-                            break;
-                        }
-                    }
-                    new_path
+                // Conversations are saved to files named after their title, so a
+                // renamed conversation moves to a new file.
+                let renamed_from = old_path
+                    .clone()
+                    .filter(|path| conversation_title(path) != Some(file_title(&summary)));
+                let path = match old_path {
+                    Some(old_path) if renamed_from.is_none() => old_path,
+                    _ => new_conversation_path(&summary, fs.as_ref()).await,
                 };
 
                 fs.create_dir(CONVERSATIONS_DIR.as_ref()).await?;
                 fs.atomic_write(path.clone(), serde_json::to_string(&conversation).unwrap())
                     .await?;
-                this.update(&mut cx, |this, _| this.path = Some(path))?;
+                CONVERSATION_STORE
+                    .save_conversation(IndexedConversation::new(
+                        path.clone(),
+                        &conversation,
+                        Local::now(),
+                    ))
+                    .await
+                    .log_err();
+                if let Some(renamed_from) = renamed_from {
+                    fs.remove_file(&renamed_from, Default::default())
+                        .await
+                        .log_err();
+                    CONVERSATION_STORE
+                        .delete_conversation(renamed_from)
+                        .await
+                        .log_err();
+                }
+                this.update(&mut cx, |this, cx| {
+                    this.path = Some(path);
+                    cx.notify();
+                })?;
             }
 
             Ok(())
@@ -2169,13 +4312,31 @@ impl Conversation {
     }
 }
 
+fn completion_error_message(error: &anyhow::Error) -> String {
+    let message = match error.downcast_ref::<CompletionError>() {
+        Some(CompletionError::Unauthorized { .. }) => {
+            format!("{error}. Enter a new API key to continue.")
+        }
+        Some(CompletionError::ContextLengthExceeded { .. }) => format!(
+            "{error}. Remove earlier messages or switch to a model with a larger context window."
+        ),
+        Some(CompletionError::ModelNotFound { .. }) => {
+            format!("{error}. Click the model name to choose a different model.")
+        }
+        _ => error.to_string(),
+    };
+    message.trim().to_string()
+}
+
 struct PendingCompletion {
     id: usize,
-    _task: Task<()>,
+    cancellation: CancellationHandle,
+    task: Task<()>,
 }
 
 enum ConversationEditorEvent {
     TabContentChanged,
+    CredentialsRejected,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -2190,6 +4351,7 @@ struct ConversationEditor {
     workspace: WeakView<Workspace>,
     editor: View<Editor>,
     blocks: HashSet<BlockId>,
+    code_block_toolbars: HashSet<BlockId>,
     scroll_position: Option<ScrollPosition>,
     _subscriptions: Vec<Subscription>,
 }
@@ -2218,8 +4380,16 @@ impl ConversationEditor {
             editor.set_soft_wrap_mode(SoftWrap::EditorWidth, cx);
             editor.set_show_gutter(false, cx);
             editor.set_show_wrap_guides(false, cx);
+            editor.set_completion_provider(Box::new(SlashCommandCompletionProvider::new(
+                workspace.clone(),
+            )));
             editor
         });
+        conversation.update(cx, |conversation, cx| {
+            conversation.buffer.update(cx, |buffer, cx| {
+                buffer.set_completion_triggers(vec!["/".into(), " ".into()], cx)
+            })
+        });
 
         let _subscriptions = vec![
             cx.observe(&conversation, |_, _, cx| cx.notify()),
@@ -2231,12 +4401,15 @@ impl ConversationEditor {
             conversation,
             editor,
             blocks: Default::default(),
+            code_block_toolbars: Default::default(),
             scroll_position: None,
             fs,
             workspace,
             _subscriptions,
         };
         this.update_message_headers(cx);
+        this.update_code_block_toolbars(cx);
+        this.highlight_file_references(cx);
         this
     }
 
@@ -2258,6 +4431,16 @@ impl ConversationEditor {
                 .collect();
             conversation.assist(selected_messages, cx)
         });
+        self.select_user_messages(user_messages, cx);
+    }
+
+    /// Moves the cursors to the start of `user_messages`, which were queued up for
+    /// the user's next reply.
+    fn select_user_messages(
+        &mut self,
+        user_messages: Vec<MessageAnchor>,
+        cx: &mut ViewContext<Self>,
+    ) {
         let new_selections = user_messages
             .iter()
             .map(|message| {
@@ -2289,16 +4472,296 @@ impl ConversationEditor {
         }
     }
 
+    /// Stops the most recent response that is still streaming.
+    fn stop_generation(&mut self, _: &StopGeneration, cx: &mut ViewContext<Self>) {
+        self.conversation
+            .update(cx, |conversation, _| conversation.cancel_last_assist());
+    }
+
+    /// Continues the stopped response under the cursor, or otherwise the last
+    /// one in the conversation.
+    fn continue_generation(&mut self, _: &ContinueGeneration, cx: &mut ViewContext<Self>) {
+        let cursor = self.cursors(cx).pop();
+        self.conversation.update(cx, |conversation, cx| {
+            let message = cursor
+                .and_then(|cursor| conversation.message_for_offset(cursor, cx))
+                .filter(|message| message.status.is_continuable())
+                .or_else(|| {
+                    conversation
+                        .messages(cx)
+                        .filter(|message| message.status.is_continuable())
+                        .last()
+                });
+            if let Some(message) = message {
+                conversation.continue_message(message.id, cx);
+            }
+        });
+    }
+
+    fn regenerate_with_same_seed(
+        &mut self,
+        _: &RegenerateWithSameSeed,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let cursors = self.cursors(cx);
+        self.conversation.update(cx, |conversation, cx| {
+            let messages = conversation.messages_for_offsets(cursors, cx);
+            for message in messages {
+                conversation.regenerate_with_same_seed(message.id, cx);
+            }
+        });
+    }
+
     fn cycle_message_role(&mut self, _: &CycleMessageRole, cx: &mut ViewContext<Self>) {
         let cursors = self.cursors(cx);
         self.conversation.update(cx, |conversation, cx| {
             let messages = conversation
                 .messages_for_offsets(cursors, cx)
                 .into_iter()
-                .map(|message| message.id)
-                .collect();
-            conversation.cycle_message_roles(messages, cx)
+                .map(|message| message.id)
+                .collect();
+            conversation.cycle_message_roles(messages, cx)
+        });
+    }
+
+    fn toggle_pinned_message(&mut self, _: &TogglePinnedMessage, cx: &mut ViewContext<Self>) {
+        let cursors = self.cursors(cx);
+        self.conversation.update(cx, |conversation, cx| {
+            let messages = conversation
+                .messages_for_offsets(cursors, cx)
+                .into_iter()
+                .map(|message| message.id)
+                .collect();
+            conversation.toggle_message_pins(messages, cx)
+        });
+    }
+
+    /// Moves the cursor to where the reply to the response being generated
+    /// goes, which scrolls to the end of the response and follows it again as
+    /// it grows.
+    fn jump_to_latest(&mut self, _: &JumpToLatest, cx: &mut ViewContext<Self>) {
+        let conversation = self.conversation.read(cx);
+        let len = conversation.buffer.read(cx).len();
+        let latest = conversation
+            .messages(cx)
+            .filter(|message| {
+                message.role == Role::Assistant && matches!(message.status, MessageStatus::Pending)
+            })
+            .last()
+            .map_or(len, |message| message.offset_range.end);
+        self.editor.update(cx, |editor, cx| {
+            editor.change_selections(Some(Autoscroll::fit()), cx, |selections| {
+                selections.select_ranges([latest..latest])
+            });
+        });
+    }
+
+    /// Moves the cursor to the start of the next message.
+    fn next_message(&mut self, _: &NextMessage, cx: &mut ViewContext<Self>) {
+        let Some(cursor) = self.cursors(cx).pop() else {
+            return;
+        };
+        let next_start = self
+            .conversation
+            .read(cx)
+            .messages(cx)
+            .map(|message| message.offset_range.start)
+            .find(|start| *start > cursor);
+        if let Some(next_start) = next_start {
+            self.move_cursor_to(next_start, cx);
+        }
+    }
+
+    /// Moves the cursor to the start of the message it's in, or to the start of
+    /// the previous message if it's already there.
+    fn previous_message(&mut self, _: &PreviousMessage, cx: &mut ViewContext<Self>) {
+        let Some(cursor) = self.cursors(cx).first().copied() else {
+            return;
+        };
+        let previous_start = self
+            .conversation
+            .read(cx)
+            .messages(cx)
+            .map(|message| message.offset_range.start)
+            .take_while(|start| *start < cursor)
+            .last();
+        if let Some(previous_start) = previous_start {
+            self.move_cursor_to(previous_start, cx);
+        }
+    }
+
+    fn move_cursor_to(&mut self, offset: usize, cx: &mut ViewContext<Self>) {
+        self.editor.update(cx, |editor, cx| {
+            editor.change_selections(Some(Autoscroll::center()), cx, |selections| {
+                selections.select_ranges([offset..offset])
+            });
+        });
+    }
+
+    /// Selects the text of each message with a cursor in it.
+    fn select_message(&mut self, _: &SelectMessage, cx: &mut ViewContext<Self>) {
+        let cursors = self.cursors(cx);
+        let conversation = self.conversation.read(cx);
+        let len = conversation.buffer.read(cx).len();
+        let ranges = conversation
+            .messages_for_offsets(cursors, cx)
+            .into_iter()
+            .map(|message| {
+                // Leave out the newline that the next message starts after.
+                let end = if message.offset_range.end < len {
+                    message.offset_range.end - 1
+                } else {
+                    message.offset_range.end
+                };
+                message.offset_range.start..end
+            })
+            .collect::<Vec<_>>();
+        self.editor.update(cx, |editor, cx| {
+            editor.change_selections(Some(Autoscroll::fit()), cx, |selections| {
+                selections.select_ranges(ranges)
+            });
+        });
+    }
+
+    /// Folds the code block the cursor is in below its first line, or the
+    /// message the cursor is in when it isn't in a code block. If anything in
+    /// there is already folded, such as a slash command's output, it's unfolded
+    /// instead.
+    fn toggle_message_fold(&mut self, _: &ToggleMessageFold, cx: &mut ViewContext<Self>) {
+        let Some(cursor) = self.cursors(cx).pop() else {
+            return;
+        };
+        let conversation = self.conversation.read(cx);
+        let Some(message) = conversation.message_for_offset(cursor, cx) else {
+            return;
+        };
+        let buffer = conversation.buffer.read(cx);
+        let message_start = message.offset_range.start;
+        let message_end = if message.offset_range.end < buffer.len() {
+            message.offset_range.end - 1
+        } else {
+            message.offset_range.end
+        };
+        let text = buffer
+            .text_for_range(message_start..message_end)
+            .collect::<String>();
+        let range = code_blocks(&text)
+            .into_iter()
+            .map(|block| message_start + block.range.start..message_start + block.range.end)
+            .find(|range| range.start <= cursor && cursor <= range.end)
+            .unwrap_or(message_start..message_end);
+        let first_row = buffer.offset_to_point(range.start).row;
+        let fold_start = buffer.point_to_offset(Point::new(first_row, buffer.line_len(first_row)));
+
+        self.editor.update(cx, |editor, cx| {
+            let is_folded = editor
+                .snapshot(cx)
+                .folds_in_range(range.clone())
+                .next()
+                .is_some();
+            if is_folded {
+                editor.unfold_ranges([range], true, true, cx);
+            } else if fold_start < range.end {
+                editor.fold_ranges([fold_start..range.end], true, cx);
+            }
+        });
+    }
+
+    /// Runs the slash command on the cursor's line, replacing it with the
+    /// command's output. Otherwise inserts a newline as usual.
+    fn run_slash_command(&mut self, _: &Newline, cx: &mut ViewContext<Self>) {
+        const SLASH_COMMAND_TOAST_ID: usize = 0x736c617368;
+
+        let Some((range, command, argument)) = self.slash_command_at_cursor(cx) else {
+            cx.propagate();
+            return;
+        };
+        let output = command.run(argument.as_deref(), self.workspace.clone(), cx);
+        cx.spawn(|this, mut cx| async move {
+            let output = output.await;
+            this.update(&mut cx, |this, cx| match output {
+                Ok(output) => this.insert_slash_command_output(range, output, cx),
+                Err(error) => {
+                    let message = format!("/{} failed: {error}", command.name());
+                    this.workspace
+                        .update(cx, |workspace, cx| {
+                            workspace.show_toast(Toast::new(SLASH_COMMAND_TOAST_ID, message), cx)
+                        })
+                        .ok();
+                }
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// The slash command typed on the cursor's line, if the cursor is alone at
+    /// the end of it, with the range of the line and the command's argument.
+    fn slash_command_at_cursor(
+        &self,
+        cx: &AppContext,
+    ) -> Option<(
+        Range<language::Anchor>,
+        Arc<dyn SlashCommand>,
+        Option<String>,
+    )> {
+        let selections = self.editor.read(cx).selections.all::<Point>(cx);
+        let [selection] = selections.as_slice() else {
+            return None;
+        };
+        if !selection.is_empty() {
+            return None;
+        }
+
+        let buffer = self.conversation.read(cx).buffer.read(cx);
+        let cursor = selection.head();
+        if cursor.column != buffer.line_len(cursor.row) {
+            return None;
+        }
+        let line_start = Point::new(cursor.row, 0);
+        let line = buffer
+            .text_for_range(line_start..cursor)
+            .collect::<String>();
+        let command_line = SlashCommandLine::parse(&line)?;
+        let command = SlashCommandRegistry::command(command_line.name, cx)?;
+        if command.requires_argument() && command_line.argument.is_none() {
+            return None;
+        }
+        let range = buffer.anchor_after(line_start)..buffer.anchor_before(cursor);
+        Some((range, command, command_line.argument.map(str::to_string)))
+    }
+
+    fn insert_slash_command_output(
+        &mut self,
+        range: Range<language::Anchor>,
+        output: SlashCommandOutput,
+        cx: &mut ViewContext<Self>,
+    ) {
+        if let Some(model) = output.model {
+            let choice = ModelChoice::available(cx)
+                .into_iter()
+                .find(|choice| choice.model == model);
+            if let Some(choice) = choice {
+                self.set_model(choice, cx);
+            }
+        }
+
+        let start = self.conversation.update(cx, |conversation, cx| {
+            conversation.buffer.update(cx, |buffer, cx| {
+                let start = range.start.to_offset(buffer);
+                buffer.edit([(range, output.text)], None, cx);
+                start
+            })
         });
+        let folded_ranges = output
+            .folded_ranges
+            .into_iter()
+            .map(|range| start + range.start..start + range.end)
+            .collect::<Vec<_>>();
+        if !folded_ranges.is_empty() {
+            self.editor.update(cx, |editor, cx| {
+                editor.fold_ranges(folded_ranges, false, cx);
+            });
+        }
     }
 
     fn cursors(&self, cx: &AppContext) -> Vec<usize> {
@@ -2318,6 +4781,8 @@ impl ConversationEditor {
         match event {
             ConversationEvent::MessagesEdited => {
                 self.update_message_headers(cx);
+                self.update_code_block_toolbars(cx);
+                self.highlight_file_references(cx);
                 self.conversation.update(cx, |conversation, cx| {
                     conversation.save(Some(Duration::from_millis(500)), self.fs.clone(), cx);
                 });
@@ -2328,6 +4793,9 @@ impl ConversationEditor {
                     conversation.save(None, self.fs.clone(), cx);
                 });
             }
+            ConversationEvent::CredentialsRejected => {
+                cx.emit(ConversationEditorEvent::CredentialsRejected);
+            }
             ConversationEvent::StreamedCompletion => {
                 self.editor.update(cx, |editor, cx| {
                     if let Some(scroll_position) = self.scroll_position {
@@ -2351,6 +4819,9 @@ impl ConversationEditor {
         event: &EditorEvent,
         cx: &mut ViewContext<Self>,
     ) {
+        // While a response is generated, the editor follows it by keeping the
+        // cursor, which is in the reply below it, where it is on the screen.
+        let was_following = self.scroll_position.is_some();
         match event {
             EditorEvent::ScrollPositionChanged { autoscroll, .. } => {
                 let cursor_scroll_position = self.cursor_scroll_position(cx);
@@ -2360,11 +4831,114 @@ impl ConversationEditor {
                     self.scroll_position = None;
                 }
             }
-            EditorEvent::SelectionsChanged { .. } => {
+            EditorEvent::SelectionsChanged { local } => {
                 self.scroll_position = self.cursor_scroll_position(cx);
+                // Clicking with the command key held goes to definitions in other
+                // editors, and to the referenced file here.
+                if *local && cx.modifiers().command {
+                    self.open_file_reference_at_cursor(cx);
+                }
             }
             _ => {}
         }
+        if self.scroll_position.is_some() != was_following {
+            cx.notify();
+        }
+    }
+
+    /// Underlines the references to files in the project, such as the ones
+    /// heading quoted code, to show that they can be opened.
+    fn highlight_file_references(&mut self, cx: &mut ViewContext<Self>) {
+        let Some(workspace) = self.workspace.upgrade() else {
+            return;
+        };
+        let project = workspace.read(cx).project().clone();
+        self.editor.update(cx, |editor, cx| {
+            let buffer = editor.buffer().read(cx).snapshot(cx);
+            let text = buffer.text();
+            let ranges = file_references(&text)
+                .filter(|reference| {
+                    project_path_for_reference(project.read(cx), &reference.path, cx).is_some()
+                })
+                .map(|reference| {
+                    buffer.anchor_after(reference.range.start)
+                        ..buffer.anchor_before(reference.range.end)
+                })
+                .collect::<Vec<_>>();
+            if ranges.is_empty() {
+                editor.clear_highlights::<FileReference>(cx);
+            } else {
+                editor.highlight_text::<FileReference>(
+                    ranges,
+                    HighlightStyle {
+                        underline: Some(UnderlineStyle {
+                            thickness: px(1.),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    },
+                    cx,
+                );
+            }
+        });
+    }
+
+    /// Opens the file referenced where the cursor is, selecting the referenced
+    /// lines.
+    fn open_file_reference_at_cursor(&mut self, cx: &mut ViewContext<Self>) {
+        let selections = self.editor.read(cx).selections.all::<usize>(cx);
+        let [selection] = selections.as_slice() else {
+            return;
+        };
+        if !selection.is_empty() {
+            return;
+        }
+
+        let cursor = selection.head();
+        let buffer = self.conversation.read(cx).buffer.read(cx);
+        let row = buffer.offset_to_point(cursor).row;
+        let line_start = buffer.point_to_offset(Point::new(row, 0));
+        let line_end = buffer.point_to_offset(Point::new(row, buffer.line_len(row)));
+        let line = buffer
+            .text_for_range(line_start..line_end)
+            .collect::<String>();
+        // The cursor has to be inside the reference, so moving to either end of
+        // it with the command key held doesn't open it.
+        let Some(reference) = file_references(&line).find(|reference| {
+            line_start + reference.range.start < cursor && cursor < line_start + reference.range.end
+        }) else {
+            return;
+        };
+
+        let Some(workspace) = self.workspace.upgrade() else {
+            return;
+        };
+        workspace.update(cx, |workspace, cx| {
+            let Some(project_path) =
+                project_path_for_reference(workspace.project().read(cx), &reference.path, cx)
+            else {
+                return;
+            };
+            let open_item = workspace.open_path(project_path, None, true, cx);
+            cx.spawn(|_, mut cx| async move {
+                let editor = open_item
+                    .await?
+                    .downcast::<Editor>()
+                    .ok_or_else(|| anyhow!("{} isn't a text file", reference.path))?;
+                let Some(rows) = reference.rows else {
+                    return Ok(());
+                };
+                editor.update(&mut cx, |editor, cx| {
+                    let buffer = editor.buffer().read(cx).snapshot(cx);
+                    let start = buffer.clip_point(Point::new(rows.start, 0), Bias::Left);
+                    let end = buffer.clip_point(Point::new(rows.end, u32::MAX), Bias::Left);
+                    editor.change_selections(Some(Autoscroll::center()), cx, |selections| {
+                        selections.select_ranges([start..end])
+                    });
+                })
+            })
+            .detach_and_log_err(cx);
+        });
     }
 
     fn cursor_scroll_position(&self, cx: &mut ViewContext<Self>) -> Option<ScrollPosition> {
@@ -2398,7 +4972,8 @@ impl ConversationEditor {
                 .conversation
                 .read(cx)
                 .messages(cx)
-                .map(|message| BlockProperties {
+                .enumerate()
+                .map(|(ix, message)| BlockProperties {
                     position: buffer.anchor_in_excerpt(excerpt_id, message.anchor),
                     height: 2,
                     style: BlockStyle::Sticky,
@@ -2406,35 +4981,11 @@ impl ConversationEditor {
                         let conversation = self.conversation.clone();
                         move |_cx| {
                             let message_id = message.id;
-                            let sender = ButtonLike::new("role")
-                                .style(ButtonStyle::Filled)
-                                .child(match message.role {
-                                    Role::User => Label::new("You").color(Color::Default),
-                                    Role::Assistant => Label::new("Assistant").color(Color::Info),
-                                    Role::System => Label::new("System").color(Color::Warning),
-                                })
-                                .tooltip(|cx| {
-                                    Tooltip::with_meta(
-                                        "Toggle message role",
-                                        None,
-                                        "Available roles: You (User), Assistant, System",
-                                        cx,
-                                    )
-                                })
-                                .on_click({
-                                    let conversation = conversation.clone();
-                                    move |_, cx| {
-                                        conversation.update(cx, |conversation, cx| {
-                                            conversation.cycle_message_roles(
-                                                HashSet::from_iter(Some(message_id)),
-                                                cx,
-                                            )
-                                        })
-                                    }
-                                });
+                            let sender = render_role_menu(&message, conversation.clone());
 
                             h_flex()
                                 .id(("message_header", message_id.0))
+                                .group("message_header")
                                 .h_11()
                                 .relative()
                                 .gap_1()
@@ -2452,18 +5003,113 @@ impl ConversationEditor {
                                     .size(LabelSize::XSmall)
                                     .color(Color::Muted),
                                 )
-                                .children(
-                                    if let MessageStatus::Error(error) = message.status.clone() {
-                                        Some(
-                                            div()
-                                                .id("error")
-                                                .tooltip(move |cx| Tooltip::text(error.clone(), cx))
-                                                .child(Icon::new(IconName::XCircle)),
-                                        )
-                                    } else {
-                                        None
-                                    },
+                                .child(
+                                    Button::new("pin", "Pin")
+                                        .selected_label("Pinned")
+                                        .selected(message.pinned)
+                                        .label_size(LabelSize::XSmall)
+                                        .color(Color::Muted)
+                                        .tooltip(|cx| {
+                                            Tooltip::with_meta(
+                                                "Toggle Pin",
+                                                Some(&TogglePinnedMessage),
+                                                "Pinned messages are kept when older ones are dropped",
+                                                cx,
+                                            )
+                                        })
+                                        .on_click({
+                                            let conversation = conversation.clone();
+                                            move |_, cx| {
+                                                conversation.update(cx, |conversation, cx| {
+                                                    conversation.toggle_message_pins(
+                                                        HashSet::from_iter(Some(message_id)),
+                                                        cx,
+                                                    )
+                                                })
+                                            }
+                                        }),
                                 )
+                                .children(message.answered_by.clone().map(|provider| {
+                                    Label::new(format!("via {provider}"))
+                                        .size(LabelSize::XSmall)
+                                        .color(Color::Muted)
+                                }))
+                                .children((!message.citations.is_empty()).then(|| {
+                                    let citations = message.citations.clone();
+                                    popover_menu(("citations", message_id.0))
+                                        .menu(move |cx| {
+                                            let citations = citations.clone();
+                                            Some(ContextMenu::build(cx, move |mut menu, _| {
+                                                for url in &citations {
+                                                    let url = url.clone();
+                                                    menu = menu.entry(
+                                                        url.clone(),
+                                                        None,
+                                                        move |cx| cx.open_url(&url),
+                                                    );
+                                                }
+                                                menu
+                                            }))
+                                        })
+                                        .trigger(
+                                            Button::new(
+                                                "citations",
+                                                format!("{} sources", message.citations.len()),
+                                            )
+                                            .label_size(LabelSize::XSmall)
+                                            .color(Color::Muted)
+                                            .tooltip(|cx| {
+                                                Tooltip::text("Open a source of this answer", cx)
+                                            }),
+                                        )
+                                }))
+                                .children(render_message_actions(
+                                    &message,
+                                    ix == 0,
+                                    &conversation,
+                                ))
+                                .children(match message.status.clone() {
+                                    MessageStatus::Error(error) => Some(
+                                        div()
+                                            .id("error")
+                                            .tooltip(move |cx| Tooltip::text(error.clone(), cx))
+                                            .child(Icon::new(IconName::XCircle))
+                                            .into_any_element(),
+                                    ),
+                                    MessageStatus::Queued(position) => Some(
+                                        Label::new(format!("Queued ({position})"))
+                                            .size(LabelSize::XSmall)
+                                            .color(Color::Muted)
+                                            .into_any_element(),
+                                    ),
+                                    status
+                                    @ (MessageStatus::Truncated | MessageStatus::Stopped) => Some(
+                                        Button::new("continue", "Continue")
+                                            .label_size(LabelSize::Small)
+                                            .tooltip(move |cx| {
+                                                Tooltip::for_action(
+                                                    if matches!(status, MessageStatus::Stopped) {
+                                                        "The response was stopped"
+                                                    } else {
+                                                        "The response reached the maximum length"
+                                                    },
+                                                    &ContinueGeneration,
+                                                    cx,
+                                                )
+                                            })
+                                            .on_click({
+                                                let conversation = conversation.clone();
+                                                move |_, cx| {
+                                                    conversation.update(cx, |conversation, cx| {
+                                                        conversation
+                                                            .continue_message(message_id, cx);
+                                                    });
+                                                }
+                                            })
+                                            .into_any_element(),
+                                    ),
+                                    _ => None,
+                                })
                                 .into_any_element()
                         }
                     }),
@@ -2493,7 +5139,7 @@ impl ConversationEditor {
         };
 
         let editor = editor.read(cx);
-        let range = editor.selections.newest::<usize>(cx).range();
+        let range = editor.selections.newest::<Point>(cx).range();
         let buffer = editor.buffer().read(cx).snapshot(cx);
         let start_language = buffer.language_at(range.start);
         let end_language = buffer.language_at(range.end);
@@ -2504,9 +5150,31 @@ impl ConversationEditor {
         };
         let language_name = language_name.as_deref().unwrap_or("").to_lowercase();
 
+        // Refer to the rows of the file the selection is in, which differ from the
+        // editor's when it shows excerpts of several files.
+        let reference =
+            buffer
+                .point_to_buffer_offset(range.start)
+                .and_then(|(start_buffer, start)| {
+                    let path = start_buffer.file()?.path().to_string_lossy().into_owned();
+                    let end_row = if range.end.column == 0 && range.end.row > range.start.row {
+                        range.end.row - 1
+                    } else {
+                        range.end.row
+                    };
+                    let start_row = start_buffer.offset_to_point(start).row;
+                    let rows = start_row..start_row + (end_row - range.start.row);
+                    Some(format_file_reference(&path, rows))
+                });
+
         let selected_text = buffer.text_for_range(range).collect::<String>();
         let text = if selected_text.is_empty() {
             None
+        } else if let Some(reference) = reference {
+            Some(
+                SlashCommandOutput::code_block(&reference, Some(&language_name), &selected_text)
+                    .text,
+            )
         } else {
             Some(if language_name == "markdown" {
                 selected_text
@@ -2526,17 +5194,119 @@ impl ConversationEditor {
 
         if let Some(text) = text {
             panel.update(cx, |panel, cx| {
-                let conversation = panel
-                    .active_editor()
-                    .cloned()
-                    .unwrap_or_else(|| panel.new_conversation(cx));
-                conversation.update(cx, |conversation, cx| {
-                    conversation
-                        .editor
-                        .update(cx, |editor, cx| editor.insert(&text, cx))
-                });
+                panel.insert_into_active_conversation(&text, cx)
+            });
+        }
+    }
+
+    /// Quotes the selection, along with where it comes from, into the active
+    /// conversation and asks the model to explain it.
+    fn explain_code(workspace: &mut Workspace, _: &ExplainCode, cx: &mut ViewContext<Workspace>) {
+        let Some(panel) = workspace.panel::<AssistantPanel>(cx) else {
+            return;
+        };
+        let Some(editor) = workspace
+            .active_item(cx)
+            .and_then(|item| item.act_as::<Editor>(cx))
+        else {
+            return;
+        };
+
+        let editor = editor.read(cx);
+        let range = editor.selections.newest::<Point>(cx).range();
+        if range.is_empty() {
+            return;
+        }
+        let buffer = editor.buffer().read(cx).snapshot(cx);
+        let language_name = buffer
+            .language_at(range.start)
+            .map(|language| language.name().to_lowercase())
+            .unwrap_or_default();
+        let path = buffer
+            .file_at(range.start)
+            .map(|file| file.path().to_string_lossy().to_string());
+        let selected_text = buffer.text_for_range(range.clone()).collect::<String>();
+
+        let mut text = String::new();
+        let end_row = if range.end.column == 0 && range.end.row > range.start.row {
+            range.end.row - 1
+        } else {
+            range.end.row
+        };
+        let lines = if range.start.row == end_row {
+            format!("line {}", range.start.row + 1)
+        } else {
+            format!("lines {}-{}", range.start.row + 1, end_row + 1)
+        };
+        match path {
+            Some(path) => writeln!(text, "From `{path}` ({lines}):").unwrap(),
+            None => writeln!(text, "From {lines}:").unwrap(),
+        }
+        writeln!(
+            text,
+            "```{language_name}\n{}\n```\n",
+            selected_text.trim_end_matches('\n')
+        )
+        .unwrap();
+        text.push_str("Explain what this code does.");
+
+        if !panel.focus_handle(cx).contains_focused(cx) {
+            workspace.toggle_panel_focus::<AssistantPanel>(cx);
+        }
+
+        panel.update(cx, |panel, cx| {
+            let conversation = panel
+                .active_editor()
+                .cloned()
+                .unwrap_or_else(|| panel.new_conversation(cx));
+            conversation.update(cx, |conversation, cx| {
+                conversation
+                    .editor
+                    .update(cx, |editor, cx| editor.insert(&text, cx));
+                conversation.assist(&Assist, cx);
             });
+        });
+    }
+
+    /// Quotes the end of the focused terminal into the active conversation and
+    /// asks the model to explain the last command and its output.
+    fn explain_terminal_output(
+        workspace: &mut Workspace,
+        _: &ExplainTerminalOutput,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let Some(panel) = workspace.panel::<AssistantPanel>(cx) else {
+            return;
+        };
+        let Some(terminal_view) = focused_terminal(workspace, cx) else {
+            return;
+        };
+        let output = terminal_view
+            .read(cx)
+            .terminal()
+            .read(cx)
+            .last_lines(EXPLAINED_TERMINAL_LINES);
+        if output.trim().is_empty() {
+            return;
+        }
+        let text = explain_terminal_output_prompt(&output, &shell_name());
+
+        if !panel.focus_handle(cx).contains_focused(cx) {
+            workspace.toggle_panel_focus::<AssistantPanel>(cx);
         }
+
+        panel.update(cx, |panel, cx| {
+            let conversation = panel
+                .active_editor()
+                .cloned()
+                .unwrap_or_else(|| panel.new_conversation(cx));
+            conversation.update(cx, |conversation, cx| {
+                conversation
+                    .editor
+                    .update(cx, |editor, cx| editor.insert(&text, cx));
+                conversation.assist(&Assist, cx);
+            });
+        });
     }
 
     fn copy(&mut self, _: &editor::actions::Copy, cx: &mut ViewContext<Self>) {
@@ -2591,11 +5361,50 @@ impl ConversationEditor {
         });
     }
 
-    fn cycle_model(&mut self, cx: &mut ViewContext<Self>) {
-        self.conversation.update(cx, |conversation, cx| {
-            let new_model = conversation.model.cycle();
-            conversation.set_model(new_model, cx);
-        });
+    fn export_conversation(&mut self, _: &ExportConversation, cx: &mut ViewContext<Self>) {
+        let exported = self.export(cx);
+        let directory = self
+            .workspace
+            .upgrade()
+            .and_then(|workspace| {
+                let project = workspace.read(cx).project().read(cx);
+                let worktree = project.visible_worktrees(cx).next()?;
+                Some(worktree.read(cx).as_local()?.abs_path().to_path_buf())
+            })
+            .unwrap_or_else(|| HOME.clone());
+        let path = cx.prompt_for_new_path(&directory);
+        let fs = self.fs.clone();
+        cx.spawn(|_, _| async move {
+            let Some(path) = path.await.ok().flatten() else {
+                return Ok(());
+            };
+            let text = exported.to_format(ExportFormat::for_path(&path));
+            fs.atomic_write(path, text).await
+        })
+        .detach_and_log_err(cx);
+    }
+
+    fn copy_conversation_as_markdown(
+        &mut self,
+        _: &CopyConversationAsMarkdown,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let markdown = self.export(cx).to_format(ExportFormat::Markdown);
+        cx.write_to_clipboard(ClipboardItem::new(markdown));
+    }
+
+    fn copy_conversation_as_json(
+        &mut self,
+        _: &CopyConversationAsJson,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let json = self.export(cx).to_format(ExportFormat::Json);
+        cx.write_to_clipboard(ClipboardItem::new(json));
+    }
+
+    /// The conversation as it's exported, titled like its tab.
+    fn export(&self, cx: &AppContext) -> ExportedConversation {
+        self.conversation.read(cx).export(self.title(cx), cx)
     }
 
     fn title(&self, cx: &AppContext) -> String {
@@ -2607,18 +5416,26 @@ impl ConversationEditor {
             .unwrap_or_else(|| "New Conversation".into())
     }
 
-    fn render_current_model(&self, cx: &mut ViewContext<Self>) -> impl IntoElement {
-        Button::new(
-            "current_model",
-            self.conversation.read(cx).model.short_name(),
+    fn render_stop_button(&self, cx: &mut ViewContext<Self>) -> Option<impl IntoElement> {
+        if self.conversation.read(cx).pending_completions.is_empty() {
+            return None;
+        }
+
+        Some(
+            Button::new("stop_generation", "Stop")
+                .style(ButtonStyle::Filled)
+                .tooltip(|cx| Tooltip::for_action("Stop Generating", &StopGeneration, cx))
+                .on_click(cx.listener(|this, _, cx| this.stop_generation(&StopGeneration, cx))),
         )
-        .style(ButtonStyle::Filled)
-        .tooltip(move |cx| Tooltip::text("Change Model", cx))
-        .on_click(cx.listener(|this, _, cx| this.cycle_model(cx)))
     }
 
+    /// The tokens the conversation would be sent as and how many are left in the
+    /// model's context, along with what sending it would cost for paid models.
     fn render_remaining_tokens(&self, cx: &mut ViewContext<Self>) -> Option<impl IntoElement> {
-        let remaining_tokens = self.conversation.read(cx).remaining_tokens()?;
+        let conversation = self.conversation.read(cx);
+        let token_count = conversation.token_count?;
+        let max_token_count = conversation.max_token_count;
+        let remaining_tokens = conversation.remaining_tokens()?;
         let remaining_tokens_color = if remaining_tokens <= 0 {
             Color::Error
         } else if remaining_tokens <= 500 {
@@ -2626,7 +5443,84 @@ impl ConversationEditor {
         } else {
             Color::Default
         };
-        Some(Label::new(remaining_tokens.to_string()).color(remaining_tokens_color))
+        let prompt_cost = conversation
+            .pricing()
+            .map(|pricing| pricing.cost(token_count, 0));
+
+        Some(
+            h_flex()
+                .id("remaining_tokens")
+                .gap_1()
+                .child(Label::new(remaining_tokens.to_string()).color(remaining_tokens_color))
+                .children(
+                    prompt_cost.map(|cost| {
+                        Label::new(format!("~{}", format_cost(cost))).color(Color::Muted)
+                    }),
+                )
+                .tooltip(move |cx| {
+                    let mut text =
+                        format!("{token_count} of {max_token_count} tokens in the model's context");
+                    if let Some(cost) = prompt_cost {
+                        text.push_str(&format!(", about {} to send", format_cost(cost)));
+                    }
+                    Tooltip::text(text, cx)
+                }),
+        )
+    }
+
+    /// A button to go back to following the response being generated, shown
+    /// once it's been scrolled away from.
+    fn render_jump_to_latest_button(&self, cx: &mut ViewContext<Self>) -> Option<impl IntoElement> {
+        if self.scroll_position.is_some()
+            || self.conversation.read(cx).pending_completions.is_empty()
+        {
+            return None;
+        }
+
+        Some(
+            div().absolute().bottom_3().left_5().child(
+                Button::new("jump_to_latest", "Jump to Latest")
+                    .style(ButtonStyle::Filled)
+                    .icon(IconName::ArrowDown)
+                    .icon_size(IconSize::Small)
+                    .label_size(LabelSize::Small)
+                    .tooltip(|cx| {
+                        Tooltip::for_action(
+                            "Follow the response as it's generated",
+                            &JumpToLatest,
+                            cx,
+                        )
+                    })
+                    .on_click(cx.listener(|this, _, cx| this.jump_to_latest(&JumpToLatest, cx))),
+            ),
+        )
+    }
+
+    fn render_token_usage(&self, cx: &mut ViewContext<Self>) -> Option<impl IntoElement> {
+        let conversation = self.conversation.read(cx);
+        let usage = conversation.usage;
+        if usage.total_tokens == 0 {
+            return None;
+        }
+        let cost = conversation
+            .pricing()
+            .map(|pricing| pricing.cost(usage.prompt_tokens, usage.completion_tokens));
+
+        Some(
+            div()
+                .id("token_usage")
+                .child(Label::new(format!("{} used", usage.total_tokens)).color(Color::Muted))
+                .tooltip(move |cx| {
+                    let mut text = format!(
+                        "{} prompt and {} completion tokens",
+                        usage.prompt_tokens, usage.completion_tokens
+                    );
+                    if let Some(cost) = cost {
+                        text.push_str(&format!(", about {}", format_cost(cost)));
+                    }
+                    Tooltip::text(text, cx)
+                }),
+        )
     }
 }
 
@@ -2637,11 +5531,27 @@ impl Render for ConversationEditor {
         div()
             .key_context("ConversationEditor")
             .capture_action(cx.listener(ConversationEditor::cancel_last_assist))
+            .capture_action(cx.listener(ConversationEditor::run_slash_command))
             .capture_action(cx.listener(ConversationEditor::save))
             .capture_action(cx.listener(ConversationEditor::copy))
             .capture_action(cx.listener(ConversationEditor::cycle_message_role))
+            .on_action(cx.listener(ConversationEditor::toggle_pinned_message))
+            .on_action(cx.listener(ConversationEditor::summarize_older_messages))
+            .on_action(cx.listener(ConversationEditor::next_message))
+            .on_action(cx.listener(ConversationEditor::previous_message))
+            .on_action(cx.listener(ConversationEditor::select_message))
+            .on_action(cx.listener(ConversationEditor::toggle_message_fold))
+            .on_action(cx.listener(ConversationEditor::jump_to_latest))
             .on_action(cx.listener(ConversationEditor::assist))
             .on_action(cx.listener(ConversationEditor::split))
+            .on_action(cx.listener(ConversationEditor::regenerate_with_same_seed))
+            .on_action(cx.listener(ConversationEditor::stop_generation))
+            .on_action(cx.listener(ConversationEditor::continue_generation))
+            .on_action(cx.listener(ConversationEditor::regenerate_from_here))
+            .on_action(cx.listener(ConversationEditor::branch_from_here))
+            .on_action(cx.listener(ConversationEditor::export_conversation))
+            .on_action(cx.listener(ConversationEditor::copy_conversation_as_markdown))
+            .on_action(cx.listener(ConversationEditor::copy_conversation_as_json))
             .size_full()
             .relative()
             .child(
@@ -2657,9 +5567,14 @@ impl Render for ConversationEditor {
                     .gap_1()
                     .top_3()
                     .right_5()
+                    .children(self.render_stop_button(cx))
+                    .children(self.render_branch_switcher(cx))
                     .child(self.render_current_model(cx))
+                    .children(self.render_token_usage(cx))
                     .children(self.render_remaining_tokens(cx)),
             )
+            .children(self.render_capacity_warning(cx))
+            .children(self.render_jump_to_latest_button(cx))
     }
 }
 
@@ -2684,6 +5599,9 @@ pub struct Message {
     role: Role,
     sent_at: DateTime<Local>,
     status: MessageStatus,
+    answered_by: Option<SharedString>,
+    pinned: bool,
+    citations: Vec<String>,
 }
 
 impl Message {
@@ -2803,6 +5721,7 @@ impl Render for InlineAssistant {
                     .ml(measurements.anchor_x - measurements.gutter_width)
                     .child(self.render_prompt_editor(cx)),
             )
+            .children(self.render_review_buttons(cx))
             .children(if self.retrieve_context {
                 self.retrieve_context_status(cx)
             } else {
@@ -2899,6 +5818,8 @@ impl InlineAssistant {
     ) {
         if let EditorEvent::Edited = event {
             self.pending_prompt = self.prompt_editor.read(cx).text(cx);
+            // A changed prompt runs the assist again instead of accepting its edit.
+            self.confirmed = false;
             cx.notify();
         }
     }
@@ -2936,13 +5857,17 @@ impl InlineAssistant {
 
     fn handle_codegen_changed(&mut self, _: Model<Codegen>, cx: &mut ViewContext<Self>) {
         let is_read_only = !self.codegen.read(cx).idle();
+        let failed = self.codegen.read(cx).error().is_some();
         self.prompt_editor.update(cx, |editor, cx| {
             let was_read_only = editor.read_only(cx);
             if was_read_only != is_read_only {
                 if is_read_only {
                     editor.set_read_only(true);
                 } else {
-                    self.confirmed = false;
+                    // After a failure, confirming again retries the prompt.
+                    if failed {
+                        self.confirmed = false;
+                    }
                     editor.set_read_only(false);
                 }
             }
@@ -2950,8 +5875,41 @@ impl InlineAssistant {
         cx.notify();
     }
 
+    /// Buttons to accept or reject a finished edit.
+    fn render_review_buttons(&self, cx: &mut ViewContext<Self>) -> Option<impl IntoElement> {
+        let codegen = self.codegen.read(cx);
+        if !self.confirmed || !codegen.idle() || codegen.error().is_some() || !codegen.has_edits() {
+            return None;
+        }
+
+        Some(
+            h_flex()
+                .gap_1()
+                .mr_2()
+                .child(
+                    IconButton::new("accept", IconName::Check)
+                        .icon_color(Color::Success)
+                        .on_click(cx.listener(|_, _, cx| cx.emit(InlineAssistantEvent::Dismissed)))
+                        .tooltip(|cx| Tooltip::for_action("Accept", &menu::Confirm, cx)),
+                )
+                .child(
+                    IconButton::new("reject", IconName::Close)
+                        .icon_color(Color::Error)
+                        .on_click(cx.listener(|_, _, cx| cx.emit(InlineAssistantEvent::Canceled)))
+                        .tooltip(|cx| Tooltip::for_action("Reject", &editor::actions::Cancel, cx)),
+                ),
+        )
+    }
+
     fn cancel(&mut self, _: &editor::actions::Cancel, cx: &mut ViewContext<Self>) {
-        cx.emit(InlineAssistantEvent::Canceled);
+        // Cancelling mid-stream stops the response so what's been applied so
+        // far can still be reviewed.
+        let codegen = self.codegen.read(cx);
+        if !codegen.idle() && codegen.has_edits() {
+            self.codegen.update(cx, |codegen, cx| codegen.stop(cx));
+        } else {
+            cx.emit(InlineAssistantEvent::Canceled);
+        }
     }
 
     fn confirm(&mut self, _: &menu::Confirm, cx: &mut ViewContext<Self>) {
@@ -3214,6 +6172,46 @@ struct PendingInlineAssist {
     codegen: Model<Codegen>,
     _subscriptions: Vec<Subscription>,
     project: WeakModel<Project>,
+    /// The assist whose prompt this one shares, when it was started for one of
+    /// several selections.
+    leader_id: Option<usize>,
+    /// The assists started for the other selections, which are confirmed and
+    /// finished along with this one.
+    follower_ids: Vec<usize>,
+}
+
+/// What an inline assist started for `selection` does, along with the selection
+/// extended to whole lines, or `None` if the selection spans several excerpts.
+fn inline_assist_kind(
+    selection: &Selection<Anchor>,
+    snapshot: &MultiBufferSnapshot,
+) -> Option<(CodegenKind, Selection<Point>)> {
+    if selection.start.excerpt_id != selection.end.excerpt_id {
+        return None;
+    }
+
+    // Extend the selection to the start and the end of the line.
+    let mut point_selection = selection.map(|selection| selection.to_point(snapshot));
+    if point_selection.end > point_selection.start {
+        point_selection.start.column = 0;
+        // If the selection ends at the start of the line, we don't want to include it.
+        if point_selection.end.column == 0 {
+            point_selection.end.row -= 1;
+        }
+        point_selection.end.column = snapshot.line_len(point_selection.end.row);
+    }
+
+    let codegen_kind = if point_selection.start == point_selection.end {
+        CodegenKind::Generate {
+            position: snapshot.anchor_after(point_selection.start),
+        }
+    } else {
+        CodegenKind::Transform {
+            range: snapshot.anchor_before(point_selection.start)
+                ..snapshot.anchor_after(point_selection.end),
+        }
+    };
+    Some((codegen_kind, point_selection))
 }
 
 fn merge_ranges(ranges: &mut Vec<Range<Anchor>>, buffer: &MultiBufferSnapshot) {
@@ -3245,6 +6243,7 @@ mod tests {
     use ai::test::FakeCompletionProvider;
     use gpui::{AppContext, TestAppContext};
     use settings::SettingsStore;
+    use util::http::FakeHttpClient;
 
     #[gpui::test]
     fn test_inserting_and_removing_messages(cx: &mut AppContext) {
@@ -3560,6 +6559,45 @@ mod tests {
         }
     }
 
+    #[gpui::test]
+    fn test_applying_templates(cx: &mut AppContext) {
+        let settings_store = SettingsStore::test(cx);
+        cx.set_global(settings_store);
+        init(cx);
+        let registry = Arc::new(LanguageRegistry::test());
+        let completion_provider = Arc::new(FakeCompletionProvider::new());
+        let conversation = cx.new_model(|cx| Conversation::new(registry, cx, completion_provider));
+        let template = ConversationTemplate::parse(
+            r#"{
+                "system_prompt": "Be brief.",
+                "messages": [{ "role": "assistant", "content": "Hi." }],
+                "temperature": 0.5
+            }"#,
+            "brief",
+        )
+        .unwrap();
+
+        conversation.update(cx, |conversation, cx| {
+            conversation.apply_template(&template, cx)
+        });
+        assert_eq!(
+            conversation.read(cx).buffer.read(cx).text(),
+            "Be brief.\nHi.\n"
+        );
+        assert_eq!(
+            messages(&conversation, cx)
+                .into_iter()
+                .map(|(_, role, range)| (role, range))
+                .collect::<Vec<_>>(),
+            vec![
+                (Role::System, 0..10),
+                (Role::Assistant, 10..14),
+                (Role::User, 14..14),
+            ]
+        );
+        assert_eq!(conversation.read(cx).temperature, Some(0.5));
+    }
+
     #[gpui::test]
     async fn test_serialization(cx: &mut TestAppContext) {
         let settings_store = cx.update(SettingsStore::test);
@@ -3606,6 +6644,7 @@ mod tests {
             conversation.read_with(cx, |conversation, cx| conversation.serialize(cx)),
             Default::default(),
             registry.clone(),
+            FakeHttpClient::with_404_response(),
             &mut cx.to_async(),
         )
         .await
@@ -3626,7 +6665,65 @@ mod tests {
         );
     }
 
-    fn messages(
+    #[gpui::test]
+    async fn test_stopping_and_continuing(cx: &mut TestAppContext) {
+        let settings_store = cx.update(SettingsStore::test);
+        cx.set_global(settings_store);
+        cx.update(init);
+        let registry = Arc::new(LanguageRegistry::test());
+        let completion_provider = Arc::new(FakeCompletionProvider::new());
+        let conversation =
+            cx.new_model(|cx| Conversation::new(registry, cx, completion_provider.clone()));
+        let buffer = conversation.read_with(cx, |conversation, _| conversation.buffer.clone());
+        let message_1 =
+            conversation.read_with(cx, |conversation, _| conversation.message_anchors[0].id);
+
+        buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "Hi")], None, cx));
+        conversation.update(cx, |conversation, cx| {
+            conversation.assist(HashSet::from_iter([message_1]), cx)
+        });
+        cx.run_until_parked();
+        completion_provider.send_completion("Hello");
+        cx.run_until_parked();
+
+        assert!(conversation.update(cx, |conversation, _| conversation.cancel_last_assist()));
+        cx.run_until_parked();
+        let message_2 = cx.read(|cx| conversation.read(cx).messages(cx).nth(1).unwrap());
+        assert!(matches!(message_2.status, MessageStatus::Stopped));
+        assert_eq!(
+            buffer.read_with(cx, |buffer, _| buffer.text()),
+            "Hi\nHello\n"
+        );
+
+        // The partial response is sent along so the model picks up where it left off.
+        assert!(conversation.update(cx, |conversation, cx| {
+            conversation.continue_message(message_2.id, cx)
+        }));
+        cx.run_until_parked();
+        let request = completion_provider.requests().pop().unwrap();
+        let last_message = request.messages.last().unwrap();
+        assert_eq!(last_message.role, Role::Assistant);
+        assert_eq!(last_message.content, "Hello");
+
+        completion_provider.send_completion(" world");
+        completion_provider.finish_completion();
+        cx.run_until_parked();
+        let message_2 = cx.read(|cx| conversation.read(cx).messages(cx).nth(1).unwrap());
+        assert!(matches!(message_2.status, MessageStatus::Done));
+        assert_eq!(
+            buffer.read_with(cx, |buffer, _| buffer.text()),
+            "Hi\nHello world\n"
+        );
+    }
+
+    #[test]
+    fn test_format_cost() {
+        assert_eq!(format_cost(0.), "$0.00");
+        assert_eq!(format_cost(0.0004), "<$0.01");
+        assert_eq!(format_cost(1.234), "$1.23");
+    }
+
+    pub(super) fn messages(
         conversation: &Model<Conversation>,
         cx: &AppContext,
     ) -> Vec<(MessageId, Role, Range<usize>)> {
@@ -3638,6 +6735,15 @@ mod tests {
     }
 }
 
+/// A cost in US dollars, rounded to the cent unless it's less than that.
+fn format_cost(dollars: f64) -> String {
+    if dollars > 0. && dollars < 0.01 {
+        "<$0.01".to_string()
+    } else {
+        format!("${dollars:.2}")
+    }
+}
+
 fn report_assistant_event(
     workspace: WeakView<Workspace>,
     conversation_id: Option<String>,
@@ -3651,9 +6757,7 @@ fn report_assistant_event(
     let client = workspace.read(cx).project().read(cx).client();
     let telemetry = client.telemetry();
 
-    let model = AssistantSettings::get_global(cx)
-        .default_open_ai_model
-        .clone();
+    let model = AssistantSettings::get_global(cx).openai.model.clone();
 
     telemetry.report_assistant_event(conversation_id, assistant_kind, model.full_name())
 }
@@ -0,0 +1,269 @@
+use super::*;
+
+impl Conversation {
+    /// Forks the conversation at an assistant message and generates the message
+    /// again, keeping the original response and everything after it as another
+    /// branch. Returns the user message queued up after the new response.
+    pub(super) fn regenerate_from(
+        &mut self,
+        message_id: MessageId,
+        cx: &mut ModelContext<Self>,
+    ) -> Vec<MessageAnchor> {
+        let mut previous_message = None;
+        for message in self.messages(cx) {
+            if message.id == message_id {
+                break;
+            }
+            previous_message = Some(message.id);
+        }
+        let Some(previous_message) = previous_message else {
+            return Vec::new();
+        };
+        let regenerable = self
+            .messages_metadata
+            .get(&message_id)
+            .map_or(false, |metadata| {
+                metadata.role == Role::Assistant
+                    && !matches!(
+                        metadata.status,
+                        MessageStatus::Pending | MessageStatus::Queued(_)
+                    )
+            });
+        if !regenerable || !self.completion_provider.has_credentials() {
+            return Vec::new();
+        }
+
+        self.branch_from(previous_message, cx);
+        self.assist(HashSet::from_iter([previous_message]), cx)
+    }
+
+    /// Forks the conversation at `message_id`, removing the messages after it so
+    /// the conversation can continue differently. The messages that are removed
+    /// are kept in the branch the conversation was on.
+    fn branch_from(&mut self, message_id: MessageId, cx: &mut ModelContext<Self>) {
+        let Some(message) = self.messages(cx).find(|message| message.id == message_id) else {
+            return;
+        };
+
+        let snapshot = self.snapshot(cx);
+        if self.branches.is_empty() {
+            self.branches.push(snapshot.clone());
+        } else {
+            self.branches[self.active_branch] = snapshot.clone();
+        }
+        self.branches.push(snapshot);
+        self.active_branch = self.branches.len() - 1;
+
+        self.buffer.update(cx, |buffer, cx| {
+            let len = buffer.len();
+            // Remove the newline that separates the message from the next one too.
+            if message.offset_range.end < len {
+                buffer.edit([(message.offset_range.end - 1..len, "")], None, cx);
+            }
+        });
+        cx.emit(ConversationEvent::MessagesEdited);
+        cx.notify();
+    }
+
+    fn switch_branch(&mut self, branch_ix: usize, cx: &mut ModelContext<Self>) {
+        if branch_ix == self.active_branch || branch_ix >= self.branches.len() {
+            return;
+        }
+
+        // Responses stream into the branch they were requested in.
+        for completion in self.pending_completions.drain(..) {
+            completion.cancellation.cancel();
+            completion.task.detach();
+        }
+        self.branches[self.active_branch] = self.snapshot(cx);
+        self.active_branch = branch_ix;
+
+        let branch = self.branches[branch_ix].clone();
+        let message_anchors = self.buffer.update(cx, |buffer, cx| {
+            let len = buffer.len();
+            buffer.edit([(0..len, branch.text)], None, cx);
+            branch
+                .messages
+                .iter()
+                .map(|message| MessageAnchor {
+                    id: message.id,
+                    start: buffer.anchor_before(message.start),
+                })
+                .collect::<Vec<_>>()
+        });
+        for message in &message_anchors {
+            self.next_message_id = cmp::max(self.next_message_id, MessageId(message.id.0 + 1));
+        }
+        self.message_anchors = message_anchors;
+        self.messages_metadata = branch.message_metadata;
+        cx.emit(ConversationEvent::MessagesEdited);
+        cx.notify();
+    }
+
+    /// The messages in the buffer, for keeping them as a branch.
+    fn snapshot(&self, cx: &AppContext) -> SavedBranch {
+        let messages = self.messages(cx).collect::<Vec<_>>();
+        SavedBranch {
+            text: self.buffer.read(cx).text(),
+            message_metadata: messages
+                .iter()
+                .filter_map(|message| {
+                    let metadata = self.messages_metadata.get(&message.id)?;
+                    Some((message.id, metadata.clone()))
+                })
+                .collect(),
+            messages: messages
+                .into_iter()
+                .map(|message| SavedMessage {
+                    id: message.id,
+                    start: message.offset_range.start,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ConversationEditor {
+    pub(super) fn regenerate_from_here(
+        &mut self,
+        _: &RegenerateFromHere,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let Some(cursor) = self.cursors(cx).pop() else {
+            return;
+        };
+        let user_messages = self.conversation.update(cx, |conversation, cx| {
+            let Some(message) = conversation.message_for_offset(cursor, cx) else {
+                return Vec::new();
+            };
+            conversation.regenerate_from(message.id, cx)
+        });
+        self.select_user_messages(user_messages, cx);
+    }
+
+    pub(super) fn branch_from_here(&mut self, _: &BranchFromHere, cx: &mut ViewContext<Self>) {
+        let Some(cursor) = self.cursors(cx).pop() else {
+            return;
+        };
+        self.conversation.update(cx, |conversation, cx| {
+            if let Some(message) = conversation.message_for_offset(cursor, cx) {
+                conversation.branch_from(message.id, cx);
+            }
+        });
+    }
+
+    pub(super) fn render_branch_switcher(
+        &self,
+        cx: &mut ViewContext<Self>,
+    ) -> Option<impl IntoElement> {
+        let conversation = self.conversation.read(cx);
+        let branch_count = conversation.branches.len();
+        if branch_count < 2 {
+            return None;
+        }
+        let active_branch = conversation.active_branch;
+
+        Some(
+            h_flex()
+                .child(
+                    IconButton::new("previous_branch", IconName::ChevronLeft)
+                        .disabled(active_branch == 0)
+                        .tooltip(|cx| Tooltip::text("Previous Branch", cx))
+                        .on_click(cx.listener(move |this, _, cx| {
+                            this.conversation.update(cx, |conversation, cx| {
+                                conversation.switch_branch(active_branch.saturating_sub(1), cx)
+                            });
+                        })),
+                )
+                .child(
+                    Label::new(format!("{}/{}", active_branch + 1, branch_count))
+                        .color(Color::Muted),
+                )
+                .child(
+                    IconButton::new("next_branch", IconName::ChevronRight)
+                        .disabled(active_branch + 1 == branch_count)
+                        .tooltip(|cx| Tooltip::text("Next Branch", cx))
+                        .on_click(cx.listener(move |this, _, cx| {
+                            this.conversation.update(cx, |conversation, cx| {
+                                conversation.switch_branch(active_branch + 1, cx)
+                            });
+                        })),
+                ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assistant_panel::tests::messages;
+    use ai::test::FakeCompletionProvider;
+
+    #[gpui::test]
+    fn test_branching(cx: &mut AppContext) {
+        let settings_store = SettingsStore::test(cx);
+        cx.set_global(settings_store);
+        init(cx);
+        let registry = Arc::new(LanguageRegistry::test());
+        let completion_provider = Arc::new(FakeCompletionProvider::new());
+        let conversation = cx.new_model(|cx| Conversation::new(registry, cx, completion_provider));
+        let buffer = conversation.read(cx).buffer.clone();
+
+        let message_1 = conversation.read(cx).message_anchors[0].clone();
+        let message_2 = conversation.update(cx, |conversation, cx| {
+            conversation
+                .insert_message_after(message_1.id, Role::Assistant, MessageStatus::Done, cx)
+                .unwrap()
+        });
+        let message_3 = conversation.update(cx, |conversation, cx| {
+            conversation
+                .insert_message_after(message_2.id, Role::User, MessageStatus::Done, cx)
+                .unwrap()
+        });
+        buffer.update(cx, |buffer, cx| {
+            buffer.edit([(0..0, "a"), (1..1, "b"), (2..2, "c")], None, cx)
+        });
+        assert_eq!(buffer.read(cx).text(), "a\nb\nc");
+
+        conversation.update(cx, |conversation, cx| {
+            conversation.branch_from(message_1.id, cx)
+        });
+        assert_eq!(buffer.read(cx).text(), "a");
+        assert_eq!(
+            messages(&conversation, cx),
+            vec![(message_1.id, Role::User, 0..1)]
+        );
+        assert_eq!(conversation.read(cx).branches.len(), 2);
+        assert_eq!(conversation.read(cx).active_branch, 1);
+
+        let message_4 = conversation.update(cx, |conversation, cx| {
+            conversation
+                .insert_message_after(message_1.id, Role::Assistant, MessageStatus::Done, cx)
+                .unwrap()
+        });
+        buffer.update(cx, |buffer, cx| buffer.edit([(2..2, "d")], None, cx));
+        assert_ne!(message_4.id, message_2.id);
+        assert_ne!(message_4.id, message_3.id);
+
+        conversation.update(cx, |conversation, cx| conversation.switch_branch(0, cx));
+        assert_eq!(buffer.read(cx).text(), "a\nb\nc");
+        assert_eq!(
+            messages(&conversation, cx),
+            vec![
+                (message_1.id, Role::User, 0..2),
+                (message_2.id, Role::Assistant, 2..4),
+                (message_3.id, Role::User, 4..5)
+            ]
+        );
+
+        conversation.update(cx, |conversation, cx| conversation.switch_branch(1, cx));
+        assert_eq!(buffer.read(cx).text(), "a\nd");
+        assert_eq!(
+            messages(&conversation, cx),
+            vec![
+                (message_1.id, Role::User, 0..2),
+                (message_4.id, Role::Assistant, 2..3)
+            ]
+        );
+    }
+}
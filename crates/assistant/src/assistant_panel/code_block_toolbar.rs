@@ -0,0 +1,293 @@
+use super::*;
+
+impl ConversationEditor {
+    /// Puts a toolbar above each code block in the assistant's responses, for
+    /// copying the code or using it in the project.
+    pub(super) fn update_code_block_toolbars(&mut self, cx: &mut ViewContext<Self>) {
+        let this = cx.view().downgrade();
+        let project = self
+            .workspace
+            .upgrade()
+            .map(|workspace| workspace.read(cx).project().clone());
+        let conversation = self.conversation.read(cx);
+        let buffer = conversation.buffer.read(cx);
+        let blocks = conversation
+            .messages(cx)
+            .filter(|message| message.role == Role::Assistant)
+            .flat_map(|message| {
+                let start = message.offset_range.start;
+                let text = buffer
+                    .text_for_range(message.offset_range)
+                    .collect::<String>();
+                code_blocks(&text)
+                    .into_iter()
+                    .map(move |block| (buffer.anchor_before(start + block.range.start), block))
+            })
+            .collect::<Vec<_>>();
+        // Blocks for files in the project can be applied to them.
+        let blocks = blocks
+            .into_iter()
+            .map(|(anchor, block)| {
+                let project_path =
+                    block
+                        .path
+                        .as_ref()
+                        .zip(project.as_ref())
+                        .and_then(|(path, project)| {
+                            project_path_for_reference(project.read(cx), path, cx)
+                        });
+                (anchor, block, project_path)
+            })
+            .collect::<Vec<_>>();
+
+        self.editor.update(cx, |editor, cx| {
+            let buffer = editor.buffer().read(cx).snapshot(cx);
+            let excerpt_id = *buffer.as_singleton().unwrap().0;
+            let new_blocks = blocks
+                .into_iter()
+                .enumerate()
+                .map(|(ix, (anchor, block, project_path))| BlockProperties {
+                    position: buffer.anchor_in_excerpt(excerpt_id, anchor),
+                    height: 1,
+                    style: BlockStyle::Sticky,
+                    render: Arc::new({
+                        let this = this.clone();
+                        move |_cx| {
+                            Self::render_code_block_toolbar(
+                                ix,
+                                &block,
+                                project_path.clone(),
+                                this.clone(),
+                            )
+                        }
+                    }),
+                    disposition: BlockDisposition::Above,
+                })
+                .collect::<Vec<_>>();
+
+            editor.remove_blocks(mem::take(&mut self.code_block_toolbars), None, cx);
+            let ids = editor.insert_blocks(new_blocks, None, cx);
+            self.code_block_toolbars = HashSet::from_iter(ids);
+        });
+    }
+
+    fn render_code_block_toolbar(
+        ix: usize,
+        block: &CodeBlock,
+        project_path: Option<ProjectPath>,
+        this: WeakView<Self>,
+    ) -> AnyElement {
+        let new_file_tooltip = match &block.path {
+            Some(path) => format!("Create {path}"),
+            None => "Create File".to_string(),
+        };
+        let apply_button = block
+            .path
+            .clone()
+            .zip(project_path)
+            .map(|(path, project_path)| {
+                let tooltip = match block.rows.clone() {
+                    Some(rows) => format!("Replace {}", format_file_reference(&path, rows)),
+                    None => format!("Replace {path}"),
+                };
+                Button::new("apply", "Apply")
+                    .label_size(LabelSize::XSmall)
+                    .color(Color::Muted)
+                    .tooltip(move |cx| Tooltip::text(tooltip.clone(), cx))
+                    .on_click({
+                        let block = block.clone();
+                        let this = this.clone();
+                        move |_, cx| {
+                            this.update(cx, |this, cx| {
+                                this.apply_code_block(block.clone(), project_path.clone(), cx)
+                            })
+                            .ok();
+                        }
+                    })
+            });
+
+        h_flex()
+            .id(("code_block_toolbar", ix))
+            .h_full()
+            .gap_1()
+            .children(block.language.clone().map(|language| {
+                Label::new(language)
+                    .size(LabelSize::XSmall)
+                    .color(Color::Muted)
+            }))
+            .child(
+                Button::new("copy", "Copy")
+                    .label_size(LabelSize::XSmall)
+                    .color(Color::Muted)
+                    .tooltip(|cx| Tooltip::text("Copy Code", cx))
+                    .on_click({
+                        let content = block.content.clone();
+                        move |_, cx| cx.write_to_clipboard(ClipboardItem::new(content.clone()))
+                    }),
+            )
+            .child(
+                Button::new("insert", "Insert")
+                    .label_size(LabelSize::XSmall)
+                    .color(Color::Muted)
+                    .tooltip(|cx| Tooltip::text("Insert at Cursor", cx))
+                    .on_click({
+                        let content = block.content.clone();
+                        let this = this.clone();
+                        move |_, cx| {
+                            this.update(cx, |this, cx| this.insert_code_block(&content, cx))
+                                .ok();
+                        }
+                    }),
+            )
+            .child(
+                Button::new("new_file", "New File")
+                    .label_size(LabelSize::XSmall)
+                    .color(Color::Muted)
+                    .tooltip(move |cx| Tooltip::text(new_file_tooltip.clone(), cx))
+                    .on_click({
+                        let block = block.clone();
+                        move |_, cx| {
+                            this.update(cx, |this, cx| {
+                                this.create_file_from_code_block(block.clone(), cx)
+                            })
+                            .ok();
+                        }
+                    }),
+            )
+            .children(apply_button)
+            .into_any_element()
+    }
+
+    /// Inserts code from a response at the cursor in the active editor.
+    fn insert_code_block(&mut self, content: &str, cx: &mut ViewContext<Self>) {
+        const INSERT_CODE_TOAST_ID: usize = 0x696e73657274;
+
+        self.workspace
+            .update(cx, |workspace, cx| {
+                let Some(editor) = workspace
+                    .active_item(cx)
+                    .and_then(|item| item.act_as::<Editor>(cx))
+                else {
+                    let message = "Open a file to insert the code into";
+                    workspace.show_toast(Toast::new(INSERT_CODE_TOAST_ID, message), cx);
+                    return;
+                };
+                editor.update(cx, |editor, cx| editor.insert(content, cx));
+                cx.focus_view(&editor);
+            })
+            .ok();
+    }
+
+    /// Writes code from a response to a new file, at the path the response
+    /// names if there isn't a file there yet, and otherwise where the user
+    /// chooses.
+    fn create_file_from_code_block(&mut self, block: CodeBlock, cx: &mut ViewContext<Self>) {
+        let worktree_path = self.workspace.upgrade().and_then(|workspace| {
+            let project = workspace.read(cx).project().read(cx);
+            let worktree = project.visible_worktrees(cx).next()?;
+            Some(worktree.read(cx).as_local()?.abs_path().to_path_buf())
+        });
+        let directory = worktree_path.clone().unwrap_or_else(|| HOME.clone());
+        // Only suggest paths inside the project.
+        let suggested_path = block
+            .path
+            .as_deref()
+            .map(Path::new)
+            .filter(|path| {
+                path.components()
+                    .all(|component| matches!(component, Component::Normal(_)))
+            })
+            .zip(worktree_path)
+            .map(|(path, worktree_path)| worktree_path.join(path));
+        let fs = self.fs.clone();
+        cx.spawn(|this, mut cx| async move {
+            let mut path = None;
+            if let Some(suggested_path) = suggested_path {
+                if !fs.is_file(&suggested_path).await {
+                    path = Some(suggested_path);
+                }
+            }
+            let path = match path {
+                Some(path) => path,
+                None => {
+                    let chosen_path =
+                        this.update(&mut cx, |_, cx| cx.prompt_for_new_path(&directory))?;
+                    let Some(path) = chosen_path.await.ok().flatten() else {
+                        return Ok(());
+                    };
+                    path
+                }
+            };
+
+            if let Some(parent) = path.parent() {
+                fs.create_dir(parent).await?;
+            }
+            fs.atomic_write(path.clone(), block.content).await?;
+            let open_file = this.update(&mut cx, |this, cx| {
+                this.workspace
+                    .update(cx, |workspace, cx| workspace.open_abs_path(path, true, cx))
+            })??;
+            open_file.await?;
+            anyhow::Ok(())
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Applies code from a response to the file it's for, replacing the lines
+    /// it refers to, or the whole file when it doesn't refer to any. Only the
+    /// parts that differ are edited, and they can be undone in the file's
+    /// editor.
+    fn apply_code_block(
+        &mut self,
+        block: CodeBlock,
+        project_path: ProjectPath,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let Some(workspace) = self.workspace.upgrade() else {
+            return;
+        };
+        let open_item = workspace.update(cx, |workspace, cx| {
+            workspace.open_path(project_path, None, true, cx)
+        });
+        cx.spawn(|_, mut cx| async move {
+            let editor = open_item
+                .await?
+                .downcast::<Editor>()
+                .ok_or_else(|| anyhow!("can't apply code to a file that isn't text"))?;
+            let buffer = editor
+                .update(&mut cx, |editor, cx| {
+                    editor.buffer().read(cx).as_singleton()
+                })?
+                .context("can't apply code to multiple files")?;
+            let rows = block.rows.clone();
+            let diff = buffer
+                .update(&mut cx, |buffer, cx| {
+                    let new_text = match rows {
+                        Some(rows) => replace_rows(&buffer.text(), rows, &block.content),
+                        None => block.content.clone(),
+                    };
+                    buffer.diff(new_text, cx)
+                })?
+                .await;
+            editor.update(&mut cx, |editor, cx| {
+                buffer.update(cx, |buffer, cx| {
+                    buffer.finalize_last_transaction();
+                    buffer.apply_diff(diff, cx);
+                });
+                if let Some(rows) = block.rows {
+                    let buffer = editor.buffer().read(cx).snapshot(cx);
+                    let line_count = block.content.lines().count().max(1) as u32;
+                    let start = Point::new(rows.start, 0);
+                    let end = buffer.clip_point(
+                        Point::new(rows.start + line_count - 1, u32::MAX),
+                        Bias::Left,
+                    );
+                    editor.change_selections(Some(Autoscroll::center()), cx, |selections| {
+                        selections.select_ranges([start..end])
+                    });
+                }
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+}
@@ -0,0 +1,243 @@
+use super::*;
+
+/// How a role is named in the menu for changing a message's role.
+fn role_label(role: Role) -> &'static str {
+    match role {
+        Role::User => "You (User)",
+        Role::Assistant => "Assistant",
+        Role::System => "System",
+    }
+}
+
+/// The menu in a message's header for changing its role.
+pub(super) fn render_role_menu(
+    message: &Message,
+    conversation: Model<Conversation>,
+) -> impl IntoElement {
+    let message_id = message.id;
+    popover_menu(("role_menu", message_id.0))
+        .menu(move |cx| {
+            let conversation = conversation.clone();
+            Some(ContextMenu::build(cx, move |mut menu, _| {
+                for role in [Role::User, Role::Assistant, Role::System] {
+                    let conversation = conversation.clone();
+                    menu = menu.entry(role_label(role), None, move |cx| {
+                        conversation.update(cx, |conversation, cx| {
+                            conversation.set_message_role(message_id, role, cx)
+                        })
+                    });
+                }
+                menu
+            }))
+        })
+        .trigger(
+            ButtonLike::new("role")
+                .style(ButtonStyle::Filled)
+                .child(match message.role {
+                    Role::User => Label::new("You").color(Color::Default),
+                    Role::Assistant => Label::new("Assistant").color(Color::Info),
+                    Role::System => Label::new("System").color(Color::Warning),
+                })
+                .tooltip(|cx| {
+                    Tooltip::with_meta(
+                        "Change message role",
+                        None,
+                        "Available roles: You (User), Assistant, System",
+                        cx,
+                    )
+                }),
+        )
+}
+
+/// The buttons in a message's header for regenerating, copying and deleting
+/// it, which are shown while the header is hovered.
+pub(super) fn render_message_actions(
+    message: &Message,
+    is_first: bool,
+    conversation: &Model<Conversation>,
+) -> Vec<AnyElement> {
+    let message_id = message.id;
+    let is_pending = matches!(
+        message.status,
+        MessageStatus::Pending | MessageStatus::Queued(_)
+    );
+    let mut actions = Vec::new();
+    if message.role == Role::Assistant && !is_pending {
+        actions.push(
+            IconButton::new("regenerate", IconName::Update)
+                .icon_size(IconSize::XSmall)
+                .icon_color(Color::Muted)
+                .visible_on_hover("message_header")
+                .tooltip(|cx| Tooltip::text("Regenerate From Here", cx))
+                .on_click({
+                    let conversation = conversation.clone();
+                    move |_, cx| {
+                        conversation.update(cx, |conversation, cx| {
+                            conversation.regenerate_from(message_id, cx);
+                        });
+                    }
+                })
+                .into_any_element(),
+        );
+    }
+    actions.push(
+        IconButton::new("copy", IconName::Copy)
+            .icon_size(IconSize::XSmall)
+            .icon_color(Color::Muted)
+            .visible_on_hover("message_header")
+            .tooltip(|cx| Tooltip::text("Copy Message", cx))
+            .on_click({
+                let conversation = conversation.clone();
+                move |_, cx| {
+                    let text = conversation.read(cx).message_text(message_id, cx);
+                    if let Some(text) = text {
+                        cx.write_to_clipboard(ClipboardItem::new(text));
+                    }
+                }
+            })
+            .into_any_element(),
+    );
+    // The first message can't be deleted, since the conversation's text starts
+    // with it.
+    if !is_first && !is_pending {
+        actions.push(
+            IconButton::new("delete", IconName::Delete)
+                .icon_size(IconSize::XSmall)
+                .icon_color(Color::Muted)
+                .visible_on_hover("message_header")
+                .tooltip(|cx| Tooltip::text("Delete Message", cx))
+                .on_click({
+                    let conversation = conversation.clone();
+                    move |_, cx| {
+                        conversation.update(cx, |conversation, cx| {
+                            conversation.delete_message(message_id, cx)
+                        });
+                    }
+                })
+                .into_any_element(),
+        );
+    }
+    actions
+}
+
+impl Conversation {
+    pub(super) fn set_message_role(
+        &mut self,
+        id: MessageId,
+        role: Role,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if let Some(metadata) = self.messages_metadata.get_mut(&id) {
+            if metadata.role != role {
+                metadata.role = role;
+                cx.emit(ConversationEvent::MessagesEdited);
+                cx.notify();
+            }
+        }
+    }
+
+    /// Removes the message with `id` and its text from the conversation. The
+    /// first message can't be removed, and neither can one that's still being
+    /// generated.
+    fn delete_message(&mut self, id: MessageId, cx: &mut ModelContext<Self>) {
+        let Some(message) = self.messages(cx).skip(1).find(|message| message.id == id) else {
+            return;
+        };
+        if matches!(
+            message.status,
+            MessageStatus::Pending | MessageStatus::Queued(_)
+        ) {
+            return;
+        }
+
+        self.buffer.update(cx, |buffer, cx| {
+            // Remove the newline before the message, which its anchor is attached
+            // to, and keep the one after it for the next message's anchor.
+            let end = if message.offset_range.end < buffer.len() {
+                message.offset_range.end - 1
+            } else {
+                message.offset_range.end
+            };
+            buffer.edit([(message.offset_range.start - 1..end, "")], None, cx);
+        });
+        cx.emit(ConversationEvent::MessagesEdited);
+        cx.notify();
+    }
+
+    fn message_text(&self, id: MessageId, cx: &AppContext) -> Option<String> {
+        let message = self.messages(cx).find(|message| message.id == id)?;
+        Some(
+            self.buffer
+                .read(cx)
+                .text_for_range(message.offset_range)
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assistant_panel::tests::messages;
+    use ai::test::FakeCompletionProvider;
+
+    #[gpui::test]
+    fn test_deleting_messages(cx: &mut AppContext) {
+        let settings_store = SettingsStore::test(cx);
+        cx.set_global(settings_store);
+        init(cx);
+        let registry = Arc::new(LanguageRegistry::test());
+        let completion_provider = Arc::new(FakeCompletionProvider::new());
+        let conversation = cx.new_model(|cx| Conversation::new(registry, cx, completion_provider));
+        let buffer = conversation.read(cx).buffer.clone();
+
+        let message_1 = conversation.read(cx).message_anchors[0].clone();
+        buffer.update(cx, |buffer, cx| {
+            buffer.edit([(0..0, "aaa\nbbb\nccc\n")], None, cx)
+        });
+        let message_2 = conversation
+            .update(cx, |conversation, cx| conversation.split_message(3..3, cx))
+            .1
+            .unwrap();
+        let message_3 = conversation
+            .update(cx, |conversation, cx| conversation.split_message(7..7, cx))
+            .1
+            .unwrap();
+        assert_eq!(
+            messages(&conversation, cx),
+            vec![
+                (message_1.id, Role::User, 0..4),
+                (message_2.id, Role::User, 4..8),
+                (message_3.id, Role::User, 8..12),
+            ]
+        );
+
+        conversation.update(cx, |conversation, cx| {
+            conversation.set_message_role(message_3.id, Role::Assistant, cx);
+            conversation.delete_message(message_2.id, cx);
+        });
+        assert_eq!(buffer.read(cx).text(), "aaa\nccc\n");
+        assert_eq!(
+            messages(&conversation, cx),
+            vec![
+                (message_1.id, Role::User, 0..4),
+                (message_3.id, Role::Assistant, 4..8),
+            ]
+        );
+
+        // The first message can't be deleted.
+        conversation.update(cx, |conversation, cx| {
+            conversation.delete_message(message_1.id, cx)
+        });
+        assert_eq!(buffer.read(cx).text(), "aaa\nccc\n");
+
+        conversation.update(cx, |conversation, cx| {
+            conversation.delete_message(message_3.id, cx)
+        });
+        assert_eq!(buffer.read(cx).text(), "aaa");
+        assert_eq!(
+            messages(&conversation, cx),
+            vec![(message_1.id, Role::User, 0..3)]
+        );
+    }
+}
@@ -0,0 +1,582 @@
+use super::*;
+
+/// How many follow-up requests to make for a single response when
+/// `auto_continue` is enabled.
+const MAX_AUTO_CONTINUATIONS: usize = 3;
+
+/// How many completions to keep in memory when `cache_completions` is enabled.
+/// Evicted completions are still read back from disk.
+const COMPLETION_CACHE_CAPACITY: usize = 256;
+
+fn completion_cache() -> Arc<CompletionCache> {
+    static CACHE: OnceLock<Arc<CompletionCache>> = OnceLock::new();
+    CACHE
+        .get_or_init(|| {
+            Arc::new(
+                CompletionCache::new(COMPLETION_CACHE_CAPACITY)
+                    .with_directory(COMPLETIONS_CACHE_DIR.clone()),
+            )
+        })
+        .clone()
+}
+
+/// Returns the scheduler shared by every request to the provider at `api_url`,
+/// updating its limit to `max_concurrent`.
+fn request_scheduler(api_url: &str, max_concurrent: usize) -> Arc<RequestScheduler> {
+    static SCHEDULERS: OnceLock<Mutex<HashMap<String, Arc<RequestScheduler>>>> = OnceLock::new();
+    let mut schedulers = SCHEDULERS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+    let scheduler = schedulers
+        .entry(api_url.to_string())
+        .or_insert_with(|| RequestScheduler::new(max_concurrent));
+    scheduler.set_max_concurrent(max_concurrent);
+    scheduler.clone()
+}
+
+/// A model a conversation can be pinned to, and the provider serving it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ModelChoice {
+    pub(crate) provider: ProviderKind,
+    pub(crate) model: ModelName,
+    pub(crate) api_url: String,
+}
+
+impl ModelChoice {
+    /// The model new conversations and inline assists start with: the one
+    /// `project` chooses, if it chooses one, served by the user's OpenAI API.
+    pub(super) fn default_for(project: Option<&Project>, cx: &AppContext) -> Self {
+        let settings = AssistantSettings::get_global(cx);
+        let model = project
+            .and_then(|project| {
+                ProjectAssistantSettings::for_project(project, cx).model_override(cx)
+            })
+            .unwrap_or(&settings.openai.model);
+        Self {
+            provider: ProviderKind::OpenAi,
+            model: model.clone(),
+            api_url: settings.openai.api_url.clone(),
+        }
+    }
+
+    /// The models offered with the current settings.
+    pub(crate) fn available(cx: &AppContext) -> Vec<Self> {
+        let settings = AssistantSettings::get_global(cx);
+        Self::all(
+            &settings.openai.model,
+            &settings.openai.api_url,
+            &settings.vllm,
+            &settings.fallback_providers,
+        )
+    }
+
+    /// The models offered in a conversation's header: the default model, the
+    /// known models of each provider, and the models of the fallback providers.
+    /// Known vLLM models are served by the configured vLLM server, or else by
+    /// the first vLLM fallback provider, if any.
+    fn all(
+        default_model: &ModelName,
+        openai_api_url: &str,
+        vllm: &VllmSettings,
+        fallback_providers: &[FallbackProviderSettings],
+    ) -> Vec<Self> {
+        let vllm_api_url = vllm
+            .api_url
+            .clone()
+            .or_else(|| {
+                fallback_providers
+                    .iter()
+                    .find(|fallback| fallback.provider == ProviderKind::Vllm)
+                    .and_then(|fallback| fallback.api_url.clone())
+            })
+            .unwrap_or_else(|| VLLM_API_URL.to_string());
+
+        let mut choices = vec![Self {
+            provider: ProviderKind::OpenAi,
+            model: default_model.clone(),
+            api_url: openai_api_url.to_string(),
+        }];
+        choices.extend(vllm.model.clone().map(|model| Self {
+            provider: ProviderKind::Vllm,
+            model,
+            api_url: vllm_api_url.clone(),
+        }));
+        let known_models = KNOWN_MODELS.iter().map(|model| {
+            let (provider, api_url) = match model.provider {
+                ModelProvider::OpenAi => (ProviderKind::OpenAi, openai_api_url.to_string()),
+                ModelProvider::Vllm => (ProviderKind::Vllm, vllm_api_url.clone()),
+                ModelProvider::Perplexity => {
+                    (ProviderKind::Perplexity, PERPLEXITY_API_URL.to_string())
+                }
+            };
+            Self {
+                provider,
+                model: model.into(),
+                api_url,
+            }
+        });
+        let fallback_models = fallback_providers.iter().map(|fallback| Self {
+            provider: fallback.provider,
+            model: ModelName::new(fallback.model.clone()),
+            api_url: fallback
+                .api_url
+                .clone()
+                .unwrap_or_else(|| fallback.provider.default_api_url().to_string()),
+        });
+        for choice in known_models.chain(fallback_models) {
+            if !choices.contains(&choice) {
+                choices.push(choice);
+            }
+        }
+        choices
+    }
+}
+
+/// The assistant settings that control how requests are made, on top of the
+/// provider itself.
+#[derive(Clone, PartialEq)]
+pub(super) struct RequestOptions {
+    openai_tls: TlsOptions,
+    openai_headers: BTreeMap<String, String>,
+    additional_api_urls: Vec<String>,
+    routing: RoutingStrategy,
+    auto_continue: bool,
+    cache_completions: bool,
+    max_concurrent_requests: usize,
+    fallback_providers: Vec<FallbackProviderSettings>,
+    vllm_extra_body: serde_json::Map<String, serde_json::Value>,
+    openai_stream_usage: Option<bool>,
+    vllm_stream_usage: bool,
+    vllm_tls: TlsOptions,
+    vllm_headers: BTreeMap<String, String>,
+}
+
+impl RequestOptions {
+    pub(super) fn new(settings: &AssistantSettings) -> Self {
+        Self {
+            openai_tls: settings.openai.tls.to_options(),
+            openai_headers: settings.openai.headers(),
+            additional_api_urls: settings.openai.additional_api_urls.clone(),
+            routing: settings.openai.load_balancing.to_strategy(),
+            auto_continue: settings.auto_continue,
+            cache_completions: settings.cache_completions,
+            max_concurrent_requests: settings.max_concurrent_requests,
+            fallback_providers: settings.fallback_providers.clone(),
+            vllm_extra_body: settings
+                .vllm
+                .extra_body
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            openai_stream_usage: settings.openai.stream_usage,
+            vllm_stream_usage: settings.vllm.stream_usage,
+            vllm_tls: settings.vllm.tls.to_options(),
+            vllm_headers: settings.vllm.extra_headers.clone(),
+        }
+    }
+
+    /// Every endpoint requests may be sent to when the primary API URL is
+    /// `api_url`, as `(kind, api_url)` pairs.
+    pub(super) fn endpoints(&self, api_url: &str) -> Vec<(EndpointKind, String)> {
+        let mut endpoints = vec![(EndpointKind::OpenAi, api_url.to_string())];
+        for url in &self.additional_api_urls {
+            if url != api_url {
+                endpoints.push((EndpointKind::OpenAi, url.clone()));
+            }
+        }
+        for fallback in &self.fallback_providers {
+            let kind = match fallback.provider {
+                ProviderKind::OpenAi => EndpointKind::OpenAi,
+                ProviderKind::Vllm => EndpointKind::Vllm,
+                // Perplexity doesn't list its models, so there's nothing to check
+                // it with.
+                ProviderKind::Perplexity => continue,
+            };
+            let url = fallback
+                .api_url
+                .clone()
+                .unwrap_or_else(|| fallback.provider.default_api_url().to_string());
+            if !endpoints.iter().any(|(_, existing)| *existing == url) {
+                endpoints.push((kind, url));
+            }
+        }
+        endpoints
+    }
+}
+
+/// A provider for a vLLM server, which starts asking the server for the model's
+/// context length and token counts so prompts are measured and truncated to fit it.
+fn vllm_completion_provider(
+    api_url: String,
+    model_name: String,
+    tls: TlsOptions,
+    extra_headers: BTreeMap<String, String>,
+    options: &RequestOptions,
+    http_client: Arc<dyn HttpClient>,
+    executor: BackgroundExecutor,
+) -> VllmCompletionProvider {
+    let provider = VllmCompletionProvider::new(api_url, model_name, http_client, executor.clone())
+        .with_tls(tls)
+        .with_extra_headers(extra_headers)
+        .with_extra_body(options.vllm_extra_body.clone())
+        .with_stream_usage(Some(options.vllm_stream_usage));
+    let available_models = provider.available_models();
+    let load_tokenizer = provider.load_tokenizer();
+    executor
+        .spawn(async move {
+            available_models.await.log_err();
+            load_tokenizer.await.log_err();
+        })
+        .detach();
+    provider
+}
+
+/// Builds the provider for requests to `model_name` at `api_url`, falling back
+/// to the providers in `options` when it fails.
+pub(super) async fn build_completion_provider(
+    kind: ProviderKind,
+    api_url: String,
+    model_name: String,
+    options: RequestOptions,
+    http_client: Arc<dyn HttpClient>,
+    executor: BackgroundExecutor,
+) -> Arc<dyn CompletionProvider> {
+    // Every endpoint gets its own queue, so a fallback isn't held up by the
+    // requests waiting for the provider that failed.
+    // Metrics are recorded per endpoint, so a request that's retried elsewhere
+    // counts against the endpoint that failed it.
+    let measured = |provider: Box<dyn CompletionProvider>| {
+        Box::new(MeasuredCompletionProvider::new(
+            provider,
+            CompletionMetrics::global(),
+        )) as Box<dyn CompletionProvider>
+    };
+    let scheduled = |provider: Box<dyn CompletionProvider>, api_url: &str| {
+        Box::new(ScheduledCompletionProvider::new(
+            provider,
+            request_scheduler(api_url, options.max_concurrent_requests),
+            executor.clone(),
+        )) as Box<dyn CompletionProvider>
+    };
+
+    let mut endpoints = Vec::new();
+    // The additional API URLs serve the same models as the OpenAI API URL, so
+    // they're only used alongside it.
+    let additional_api_urls = options
+        .additional_api_urls
+        .iter()
+        .filter(|url| kind == ProviderKind::OpenAi && **url != api_url);
+    for url in iter::once(&api_url).chain(additional_api_urls) {
+        let provider: Box<dyn CompletionProvider> = match kind {
+            ProviderKind::OpenAi => Box::new(
+                OpenAiCompletionProvider::new(
+                    url.clone(),
+                    model_name.clone(),
+                    http_client.clone(),
+                    executor.clone(),
+                )
+                .await
+                .with_tls(options.openai_tls.clone())
+                .with_extra_headers(options.openai_headers.clone())
+                .with_stream_usage(options.openai_stream_usage),
+            ),
+            ProviderKind::Vllm => Box::new(vllm_completion_provider(
+                url.clone(),
+                model_name.clone(),
+                options.vllm_tls.clone(),
+                options.vllm_headers.clone(),
+                &options,
+                http_client.clone(),
+                executor.clone(),
+            )),
+            ProviderKind::Perplexity => Box::new(PerplexityCompletionProvider::new(
+                url.clone(),
+                model_name.clone(),
+                http_client.clone(),
+                executor.clone(),
+            )),
+        };
+        endpoints.push((url.clone(), measured(provider)));
+    }
+    // The endpoints share a queue, so the concurrency limit applies to the
+    // provider as a whole.
+    let provider: Box<dyn CompletionProvider> = if endpoints.len() == 1 {
+        endpoints.remove(0).1
+    } else {
+        Box::new(EndpointPool::new(endpoints, options.routing))
+    };
+    let mut provider = scheduled(provider, &api_url);
+    if !options.fallback_providers.is_empty() {
+        let name = format!("{} ({})", kind.display_name(), provider.base_model().name());
+        let mut providers = FallbackCompletionProvider::new(name, provider);
+        for fallback in &options.fallback_providers {
+            let api_url = fallback
+                .api_url
+                .clone()
+                .unwrap_or_else(|| fallback.provider.default_api_url().to_string());
+            let provider: Box<dyn CompletionProvider> = match fallback.provider {
+                ProviderKind::OpenAi => Box::new(
+                    OpenAiCompletionProvider::new(
+                        api_url.clone(),
+                        fallback.model.clone(),
+                        http_client.clone(),
+                        executor.clone(),
+                    )
+                    .await
+                    .with_tls(fallback.tls.to_options())
+                    .with_extra_headers(fallback.extra_headers.clone())
+                    .with_stream_usage(options.openai_stream_usage),
+                ),
+                ProviderKind::Vllm => Box::new(vllm_completion_provider(
+                    api_url.clone(),
+                    fallback.model.clone(),
+                    fallback.tls.to_options(),
+                    fallback.extra_headers.clone(),
+                    &options,
+                    http_client.clone(),
+                    executor.clone(),
+                )),
+                ProviderKind::Perplexity => Box::new(
+                    PerplexityCompletionProvider::new(
+                        api_url.clone(),
+                        fallback.model.clone(),
+                        http_client.clone(),
+                        executor.clone(),
+                    )
+                    .with_tls(fallback.tls.to_options())
+                    .with_extra_headers(fallback.extra_headers.clone()),
+                ),
+            };
+            providers = providers.fallback(
+                fallback.display_name(),
+                scheduled(measured(provider), &api_url),
+            );
+        }
+        provider = Box::new(providers);
+    }
+    // Only requests that are actually sent are traced, so cache hits aren't.
+    provider = Box::new(MiddlewareCompletionProvider::new(provider).layer(TracingMiddleware));
+    // Cache hits are answered before queueing, so they don't take up a slot.
+    if options.cache_completions {
+        provider = Box::new(CachingCompletionProvider::new(
+            provider,
+            completion_cache(),
+            api_url,
+            executor,
+        ));
+    }
+    if options.auto_continue {
+        provider = Box::new(ContinuingCompletionProvider::new(
+            provider,
+            MAX_AUTO_CONTINUATIONS,
+        ));
+    }
+    provider.into()
+}
+
+impl Conversation {
+    /// What the conversation's model costs, if it's a paid model.
+    pub(super) fn pricing(&self) -> Option<ModelPricing> {
+        match self.provider {
+            ProviderKind::OpenAi | ProviderKind::Perplexity => self.model.definition()?.pricing,
+            ProviderKind::Vllm => None,
+        }
+    }
+
+    /// The size of the model's context window.
+    pub(super) fn context_size(&self) -> usize {
+        match self.provider {
+            ProviderKind::OpenAi => tiktoken_rs::model::get_context_size(self.model.full_name()),
+            // vLLM serves arbitrary models, so the provider asks the server for it,
+            // and Perplexity's models aren't known to tiktoken.
+            ProviderKind::Vllm | ProviderKind::Perplexity => self
+                .completion_provider
+                .base_model()
+                .capacity()
+                .unwrap_or_else(|_| tiktoken_rs::model::get_context_size(self.model.full_name())),
+        }
+    }
+
+    /// The provider, model and API URL the conversation is pinned to.
+    pub(crate) fn model_choice(&self) -> ModelChoice {
+        ModelChoice {
+            provider: self.provider,
+            model: self.model.clone(),
+            api_url: self
+                .api_url
+                .clone()
+                .unwrap_or_else(|| self.provider.default_api_url().to_string()),
+        }
+    }
+
+    /// Pins the conversation to `choice`, which the following messages are sent
+    /// to. Messages that are already streaming finish with the previous model.
+    pub(super) fn set_model(
+        &mut self,
+        choice: ModelChoice,
+        http_client: Arc<dyn HttpClient>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let request_options = RequestOptions::new(AssistantSettings::get_global(cx));
+        let executor = cx.background_executor().clone();
+        let ModelChoice {
+            provider,
+            model,
+            api_url,
+        } = choice;
+        self.pending_completion_provider = cx.spawn({
+            let model = model.clone();
+            let api_url = api_url.clone();
+            |this, mut cx| {
+                async move {
+                    let completion_provider = build_completion_provider(
+                        provider,
+                        api_url,
+                        model.full_name().into(),
+                        request_options,
+                        http_client,
+                        executor,
+                    )
+                    .await;
+                    cx.update(|cx| completion_provider.retrieve_credentials(cx))?
+                        .await;
+                    this.update(&mut cx, |this, cx| {
+                        this.completion_provider = completion_provider;
+                        this.count_remaining_tokens(cx);
+                    })
+                }
+                .log_err()
+            }
+        });
+        self.provider = provider;
+        self.model = model;
+        self.api_url = Some(api_url);
+        self.count_remaining_tokens(cx);
+        cx.notify();
+    }
+}
+
+impl ConversationEditor {
+    pub(super) fn set_model(&mut self, choice: ModelChoice, cx: &mut ViewContext<Self>) {
+        let Some(workspace) = self.workspace.upgrade() else {
+            return;
+        };
+        let http_client =
+            crate::http_client(workspace.read(cx).app_state().client.http_client(), cx);
+        let fs = self.fs.clone();
+        self.conversation.update(cx, |conversation, cx| {
+            conversation.set_model(choice, http_client, cx);
+            conversation.save(None, fs, cx);
+        });
+    }
+
+    pub(super) fn render_current_model(&self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let conversation = self.conversation.read(cx);
+        let label = format!(
+            "{} · {}",
+            conversation.provider.display_name(),
+            conversation.model.short_name()
+        );
+        let this = cx.view().downgrade();
+        popover_menu("current_model")
+            .menu(move |cx| {
+                let choices = ModelChoice::available(cx);
+                let this = this.clone();
+                let menu = ContextMenu::build(cx, move |mut menu, _| {
+                    for provider in [
+                        ProviderKind::OpenAi,
+                        ProviderKind::Vllm,
+                        ProviderKind::Perplexity,
+                    ] {
+                        let mut choices = choices
+                            .iter()
+                            .filter(|choice| choice.provider == provider)
+                            .peekable();
+                        if choices.peek().is_none() {
+                            continue;
+                        }
+                        menu = menu.header(provider.display_name());
+                        for choice in choices {
+                            let this = this.clone();
+                            let label = choice.model.short_name().to_string();
+                            let choice = choice.clone();
+                            menu = menu.entry(label, None, move |cx| {
+                                this.update(cx, |this, cx| this.set_model(choice.clone(), cx))
+                                    .ok();
+                            });
+                        }
+                    }
+                    menu
+                });
+                Some(menu)
+            })
+            .anchor(AnchorCorner::TopRight)
+            .trigger(
+                Button::new("current_model_trigger", label)
+                    .style(ButtonStyle::Filled)
+                    .tooltip(move |cx| Tooltip::text("Change Model", cx)),
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ai::providers::open_ai::OPEN_AI_API_URL;
+
+    #[test]
+    fn test_model_choices() {
+        let fallback_providers = [
+            FallbackProviderSettings {
+                provider: ProviderKind::Vllm,
+                model: "codellama/CodeLlama-13b-Instruct-hf".into(),
+                api_url: Some("http://gpu-box:8000/v1".into()),
+                ..Default::default()
+            },
+            FallbackProviderSettings {
+                provider: ProviderKind::Vllm,
+                model: "mistralai/Mistral-7B-Instruct-v0.2".into(),
+                ..Default::default()
+            },
+        ];
+        let choices = ModelChoice::all(
+            &ModelName::new("gpt-4-0613"),
+            OPEN_AI_API_URL,
+            &VllmSettings::default(),
+            &fallback_providers,
+        );
+        let choices = choices
+            .iter()
+            .map(|choice| (choice.provider, choice.model.full_name(), &*choice.api_url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            choices,
+            [
+                (ProviderKind::OpenAi, "gpt-4-0613", OPEN_AI_API_URL),
+                (ProviderKind::OpenAi, "gpt-3.5-turbo-0613", OPEN_AI_API_URL),
+                (ProviderKind::OpenAi, "gpt-4-1106-preview", OPEN_AI_API_URL),
+                (
+                    ProviderKind::Vllm,
+                    "meta-llama/Meta-Llama-3-8B-Instruct",
+                    "http://gpu-box:8000/v1"
+                ),
+                (
+                    ProviderKind::Vllm,
+                    "codellama/CodeLlama-13b-Instruct-hf",
+                    "http://gpu-box:8000/v1"
+                ),
+                (
+                    ProviderKind::Perplexity,
+                    "sonar-medium-online",
+                    PERPLEXITY_API_URL
+                ),
+                (
+                    ProviderKind::Vllm,
+                    "mistralai/Mistral-7B-Instruct-v0.2",
+                    VLLM_API_URL
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use super::*;
+
+/// How much of the model's context a conversation can fill before it's
+/// considered to be running out of room.
+const CAPACITY_WARNING_THRESHOLD: f32 = 0.8;
+
+/// How many of the latest messages are left as they are when the older ones
+/// are summarized.
+const MESSAGES_KEPT_WHEN_SUMMARIZING: usize = 4;
+
+impl Conversation {
+    /// Whether the conversation fills most of the model's context.
+    pub(super) fn is_near_capacity(&self) -> bool {
+        self.token_count.map_or(false, |token_count| {
+            token_count as f32 >= self.max_token_count as f32 * CAPACITY_WARNING_THRESHOLD
+        })
+    }
+
+    /// The older messages that summarizing the conversation replaces: all but
+    /// the latest few, leaving out the system prompt and pinned messages.
+    fn summarizable_messages(&self, cx: &AppContext) -> Vec<Message> {
+        let messages = self.messages(cx).collect::<Vec<_>>();
+        let kept_ix = messages
+            .len()
+            .saturating_sub(MESSAGES_KEPT_WHEN_SUMMARIZING);
+        messages
+            .into_iter()
+            .take(kept_ix)
+            .skip_while(|message| message.role == Role::System)
+            .filter(|message| !message.pinned && message.status.is_complete())
+            .collect()
+    }
+
+    /// Asks the model to summarize the older messages, and replaces them with
+    /// the summary to make room in its context.
+    pub(super) fn summarize_older_messages(&mut self, cx: &mut ModelContext<Self>) -> bool {
+        if self.pending_compaction.is_some() || !self.completion_provider.has_credentials() {
+            return false;
+        }
+        let summarized_messages = self.summarizable_messages(cx);
+        if summarized_messages.len() < 2 {
+            return false;
+        }
+
+        let buffer = self.buffer.read(cx);
+        let messages = summarized_messages
+            .iter()
+            .map(|message| message.to_open_ai_message(buffer))
+            .chain(Some(RequestMessage {
+                role: Role::User,
+                content: summarize_conversation_prompt(),
+            }))
+            .collect();
+        let request = ChatRequest {
+            model: self.model.full_name().to_string(),
+            messages,
+            stream: true,
+            temperature: self.sampling_temperature(cx),
+            sampling: AssistantSettings::get_global(cx)
+                .sampling_for(self.provider)
+                .to_params(),
+            ..Default::default()
+        };
+        let summarized_ids = summarized_messages
+            .iter()
+            .map(|message| message.id)
+            .collect::<Vec<_>>();
+
+        let stream = self.completion_provider.complete(request);
+        self.pending_compaction = Some(cx.spawn(|this, mut cx| async move {
+            let summary = async {
+                let mut chunks = text_only(stream.await?);
+                let mut summary = String::new();
+                while let Some(chunk) = chunks.next().await {
+                    summary.push_str(&chunk?);
+                }
+                anyhow::Ok(summary)
+            }
+            .await;
+
+            this.update(&mut cx, |this, cx| {
+                this.pending_compaction = None;
+                match summary {
+                    Ok(summary) if !summary.trim().is_empty() => {
+                        this.replace_with_summary(&summarized_ids, &summary, cx)
+                    }
+                    Ok(_) => log::error!("the model didn't summarize the conversation"),
+                    Err(error) => log::error!("failed to summarize the conversation: {error:?}"),
+                }
+                cx.notify();
+            })
+            .ok();
+        }));
+        cx.notify();
+        true
+    }
+
+    /// Replaces the messages with `ids` with a system message holding `summary`,
+    /// in the place of the first of them.
+    fn replace_with_summary(
+        &mut self,
+        ids: &[MessageId],
+        summary: &str,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let messages = self
+            .messages(cx)
+            .filter(|message| ids.contains(&message.id))
+            .collect::<Vec<_>>();
+        let Some((first_message, replaced_messages)) = messages.split_first() else {
+            return;
+        };
+
+        self.buffer.update(cx, |buffer, cx| {
+            let len = buffer.len();
+            // Keep the newline at the end of each message, which the next
+            // message's anchor is attached to.
+            let content_end = |message: &Message| {
+                if message.offset_range.end < len {
+                    message.offset_range.end - 1
+                } else {
+                    message.offset_range.end
+                }
+            };
+            let mut edits = vec![(
+                first_message.offset_range.start..content_end(first_message),
+                conversation_summary_message(summary),
+            )];
+            for message in replaced_messages {
+                edits.push((
+                    message.offset_range.start - 1..content_end(message),
+                    String::new(),
+                ));
+            }
+            buffer.edit(edits, None, cx);
+        });
+        if let Some(metadata) = self.messages_metadata.get_mut(&first_message.id) {
+            metadata.role = Role::System;
+            metadata.status = MessageStatus::Done;
+        }
+        cx.emit(ConversationEvent::MessagesEdited);
+        cx.notify();
+    }
+}
+
+impl ConversationEditor {
+    pub(super) fn summarize_older_messages(
+        &mut self,
+        _: &SummarizeOlderMessages,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.conversation.update(cx, |conversation, cx| {
+            conversation.summarize_older_messages(cx);
+        });
+    }
+
+    pub(super) fn render_capacity_warning(
+        &self,
+        cx: &mut ViewContext<Self>,
+    ) -> Option<impl IntoElement> {
+        let conversation = self.conversation.read(cx);
+        if !conversation.is_near_capacity() {
+            return None;
+        }
+        let summarizing = conversation.pending_compaction.is_some();
+        let can_summarize = conversation.summarizable_messages(cx).len() >= 2;
+
+        Some(
+            h_flex()
+                .absolute()
+                .bottom_3()
+                .right_5()
+                .gap_2()
+                .px_2()
+                .py_1()
+                .rounded_md()
+                .bg(cx.theme().colors().surface_background)
+                .border_1()
+                .border_color(cx.theme().colors().border)
+                .child(Icon::new(IconName::ExclamationTriangle).color(Color::Warning))
+                .child(
+                    Label::new("This conversation is nearing the model's context limit")
+                        .size(LabelSize::Small),
+                )
+                .child(
+                    Button::new(
+                        "summarize_older_messages",
+                        if summarizing {
+                            "Summarizing…"
+                        } else {
+                            "Summarize Older Messages"
+                        },
+                    )
+                    .label_size(LabelSize::Small)
+                    .disabled(summarizing || !can_summarize)
+                    .tooltip(|cx| {
+                        Tooltip::for_action(
+                            "Replace older messages with a summary",
+                            &SummarizeOlderMessages,
+                            cx,
+                        )
+                    })
+                    .on_click(cx.listener(|this, _, cx| {
+                        this.summarize_older_messages(&SummarizeOlderMessages, cx)
+                    })),
+                ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assistant_panel::tests::messages;
+    use ai::test::FakeCompletionProvider;
+    use gpui::TestAppContext;
+
+    #[gpui::test]
+    async fn test_summarizing_older_messages(cx: &mut TestAppContext) {
+        let settings_store = cx.update(SettingsStore::test);
+        cx.set_global(settings_store);
+        cx.update(init);
+        let registry = Arc::new(LanguageRegistry::test());
+        let completion_provider = Arc::new(FakeCompletionProvider::new());
+        let conversation =
+            cx.new_model(|cx| Conversation::new(registry, cx, completion_provider.clone()));
+        let buffer = conversation.read_with(cx, |conversation, _| conversation.buffer.clone());
+
+        let mut message_ids =
+            vec![conversation.read_with(cx, |conversation, _| conversation.message_anchors[0].id)];
+        for role in [
+            Role::Assistant,
+            Role::User,
+            Role::Assistant,
+            Role::User,
+            Role::Assistant,
+        ] {
+            let message = conversation.update(cx, |conversation, cx| {
+                conversation
+                    .insert_message_after(
+                        *message_ids.last().unwrap(),
+                        role,
+                        MessageStatus::Done,
+                        cx,
+                    )
+                    .unwrap()
+            });
+            message_ids.push(message.id);
+        }
+        buffer.update(cx, |buffer, cx| {
+            buffer.edit(
+                [
+                    (0..0, "a"),
+                    (1..1, "b"),
+                    (2..2, "c"),
+                    (3..3, "d"),
+                    (4..4, "e"),
+                    (5..5, "f"),
+                ],
+                None,
+                cx,
+            )
+        });
+        assert_eq!(
+            buffer.read_with(cx, |buffer, _| buffer.text()),
+            "a\nb\nc\nd\ne\nf"
+        );
+
+        // All but the latest messages are summarized.
+        assert!(conversation.update(cx, |conversation, cx| {
+            conversation.summarize_older_messages(cx)
+        }));
+        cx.run_until_parked();
+        let request = completion_provider.requests().pop().unwrap();
+        assert_eq!(request.messages.len(), 3);
+        assert_eq!(request.messages[0].content, "a");
+        assert_eq!(request.messages[1].content, "b");
+
+        completion_provider.send_completion("ab");
+        completion_provider.finish_completion();
+        cx.run_until_parked();
+        assert_eq!(
+            buffer.read_with(cx, |buffer, _| buffer.text()),
+            format!("{}\nc\nd\ne\nf", conversation_summary_message("ab"))
+        );
+        let messages = cx.read(|cx| messages(&conversation, cx));
+        assert_eq!(
+            messages
+                .iter()
+                .map(|(id, role, _)| (*id, *role))
+                .collect::<Vec<_>>(),
+            vec![
+                (message_ids[0], Role::System),
+                (message_ids[2], Role::User),
+                (message_ids[3], Role::Assistant),
+                (message_ids[4], Role::User),
+                (message_ids[5], Role::Assistant),
+            ]
+        );
+
+        // The summary isn't summarized again.
+        assert!(!conversation.update(cx, |conversation, cx| {
+            conversation.summarize_older_messages(cx)
+        }));
+    }
+}
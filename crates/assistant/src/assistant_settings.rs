@@ -1,61 +1,480 @@
+use ai::{
+    completion::{SamplingParams, TlsOptions},
+    endpoint_pool::RoutingStrategy,
+    models::ModelName,
+    providers::{open_ai::OPEN_AI_API_URL, perplexity::PERPLEXITY_API_URL, vllm::VLLM_API_URL},
+};
 use anyhow;
-use gpui::Pixels;
+use collections::HashMap;
+use gpui::{AppContext, Pixels};
+use project::Project;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use settings::Settings;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+use util::{
+    paths::{PathMatcher, HOME, PROMPTS_DIR},
+    ResultExt,
+};
 
+/// The system prompt, written out in the settings or read from a file.
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
-pub enum OpenAiModel {
-    #[serde(rename = "gpt-3.5-turbo-0613")]
-    ThreePointFiveTurbo,
-    #[serde(rename = "gpt-4-0613")]
-    Four,
-    #[serde(rename = "gpt-4-1106-preview")]
-    FourTurbo,
+#[serde(untagged)]
+pub enum SystemPrompt {
+    Text(String),
+    /// A Markdown or text file, such as one in the prompt library. A relative
+    /// path is relative to the prompts directory.
+    File {
+        path: PathBuf,
+    },
 }
 
-impl OpenAiModel {
-    pub fn full_name(&self) -> &'static str {
+impl SystemPrompt {
+    /// Where the prompt is read from, if it's in a file.
+    pub fn file_path(&self) -> Option<PathBuf> {
+        let Self::File { path } = self else {
+            return None;
+        };
+        Some(if let Ok(path) = path.strip_prefix("~") {
+            HOME.join(path)
+        } else {
+            PROMPTS_DIR.join(path)
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AssistantDockPosition {
+    Left,
+    Right,
+    Bottom,
+}
+
+/// TLS options for an OpenAI-compatible endpoint served behind a private
+/// certificate authority.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct TlsSettings {
+    /// A PEM file of the certificate authorities to verify the server with, used
+    /// instead of the system's certificates.
+    pub ca_bundle: Option<PathBuf>,
+    /// Whether to accept certificates that weren't issued for the server's hostname.
+    /// Only enable this for servers you control.
+    #[serde(default)]
+    pub accept_invalid_hostnames: bool,
+}
+
+impl TlsSettings {
+    pub fn to_options(&self) -> TlsOptions {
+        TlsOptions {
+            ca_bundle: self.ca_bundle.clone(),
+            accept_invalid_hostnames: self.accept_invalid_hostnames,
+        }
+    }
+}
+
+/// Options for tracing completion and embedding requests, for debugging prompts.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct DebugTraceSettings {
+    /// Whether to write the bodies of completion and embedding requests and their
+    /// responses to a log file.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Regular expressions for text to scrub from the trace, on top of API keys,
+    /// which are always scrubbed.
+    #[serde(default)]
+    pub redaction_patterns: Vec<String>,
+}
+
+/// How long saved conversations are kept before they're deleted.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(default)]
+pub struct ConversationRetentionSettings {
+    /// Delete conversations that haven't been changed in this many days.
+    pub max_age_days: Option<u32>,
+    /// Keep at most this many conversations, deleting the oldest.
+    pub max_conversations: Option<usize>,
+}
+
+/// Code completion from a local model, shown as ghost text as you type when
+/// Copilot isn't signed in.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(default)]
+pub struct InlineCompletionSettings {
+    /// Whether to suggest completions from a local model as you type.
+    pub enabled: bool,
+    /// The code model to complete with. It must support fill-in-the-middle, such
+    /// as CodeLlama, DeepSeek Coder, StarCoder or Qwen2.5-Coder.
+    pub model: String,
+    /// The URL of the vLLM or llama.cpp server serving the model.
+    pub api_url: String,
+    /// The maximum number of tokens in a suggestion.
+    pub max_tokens: u32,
+    /// Languages to never suggest completions in, such as `Markdown`.
+    pub disabled_languages: Vec<String>,
+}
+
+impl Default for InlineCompletionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: "codellama/CodeLlama-7b-hf".into(),
+            api_url: VLLM_API_URL.into(),
+            max_tokens: 64,
+            disabled_languages: Vec::new(),
+        }
+    }
+}
+
+/// How requests are spread across several OpenAI API endpoints.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalancing {
+    /// Take turns between the endpoints.
+    #[default]
+    RoundRobin,
+    /// Send each request to the endpoint with the fewest requests in flight.
+    LeastLoaded,
+}
+
+impl LoadBalancing {
+    pub fn to_strategy(self) -> RoutingStrategy {
         match self {
-            OpenAiModel::ThreePointFiveTurbo => "gpt-3.5-turbo-0613",
-            OpenAiModel::Four => "gpt-4-0613",
-            OpenAiModel::FourTurbo => "gpt-4-1106-preview",
+            LoadBalancing::RoundRobin => RoutingStrategy::RoundRobin,
+            LoadBalancing::LeastLoaded => RoutingStrategy::LeastLoaded,
+        }
+    }
+}
+
+/// Settings for OpenAI's API, or any server compatible with it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct OpenAiSettings {
+    pub api_url: String,
+    pub model: ModelName,
+    pub organization: Option<String>,
+    pub tls: TlsSettings,
+    pub extra_headers: BTreeMap<String, String>,
+    pub additional_api_urls: Vec<String>,
+    pub load_balancing: LoadBalancing,
+    #[serde(default)]
+    pub stream_usage: Option<bool>,
+    #[serde(default)]
+    pub sampling: SamplingSettings,
+}
+
+impl OpenAiSettings {
+    /// The headers to add to every request: the extra headers, and the
+    /// organization to bill, if one is set.
+    pub fn headers(&self) -> BTreeMap<String, String> {
+        let mut headers = self.extra_headers.clone();
+        if let Some(organization) = &self.organization {
+            headers.insert("OpenAI-Organization".into(), organization.clone());
         }
+        headers
     }
+}
 
-    pub fn short_name(&self) -> &'static str {
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct OpenAiSettingsContent {
+    /// The API endpoint to use when starting new conversations.
+    ///
+    /// Default: https://api.openai.com/v1
+    pub api_url: Option<String>,
+    /// The default model to use when starting new conversations. Any model served
+    /// by `api_url` can be used; a warning is shown if it isn't listed there.
+    ///
+    /// Default: gpt-4-1106-preview
+    pub model: Option<ModelName>,
+    /// The organization requests are billed to, sent as the `OpenAI-Organization`
+    /// header.
+    ///
+    /// Default: null
+    pub organization: Option<String>,
+    /// TLS options for OpenAI-compatible servers behind a private certificate
+    /// authority.
+    pub tls: Option<TlsSettings>,
+    /// Headers to add to every completion request to `api_url` and
+    /// `additional_api_urls`, for gateways that need their own authentication
+    /// or routing headers.
+    ///
+    /// Default: {}
+    pub extra_headers: Option<BTreeMap<String, String>>,
+    /// More endpoints serving the same models as `api_url`, such as other
+    /// machines running the same local server. Requests are spread across all of
+    /// them, and endpoints that stop responding are skipped for a while.
+    ///
+    /// Default: []
+    pub additional_api_urls: Option<Vec<String>>,
+    /// How requests are spread across the endpoints when `additional_api_urls`
+    /// is set.
+    ///
+    /// Default: round_robin
+    pub load_balancing: Option<LoadBalancing>,
+    /// Whether streamed responses end with their token usage. Servers that
+    /// don't support it may reject the request, so when null, it's only asked
+    /// of OpenAI's API.
+    ///
+    /// Default: null
+    pub stream_usage: Option<bool>,
+    /// Sampling parameters for requests to this provider, replacing the ones
+    /// in the assistant's `sampling` settings.
+    pub sampling: Option<SamplingSettings>,
+}
+
+/// Settings for a vLLM server.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct VllmSettings {
+    /// The server's API endpoint. Defaults to the first vLLM fallback provider's,
+    /// or else `http://localhost:8000/v1`.
+    pub api_url: Option<String>,
+    /// A model the server serves, offered alongside the known vLLM models.
+    pub model: Option<ModelName>,
+    /// Sampling parameters for requests to this provider, replacing the ones
+    /// in the assistant's `sampling` settings.
+    #[serde(default)]
+    pub sampling: SamplingSettings,
+    /// Parameters added to the body of every request to the server, such as
+    /// `repetition_penalty` or `min_p`. Parameters Zed sets itself take
+    /// precedence.
+    #[serde(default)]
+    pub extra_body: BTreeMap<String, serde_json::Value>,
+    /// Whether streamed responses end with their token usage. Older servers,
+    /// and some other OpenAI-compatible ones, reject requests that ask for it.
+    #[serde(default)]
+    pub stream_usage: bool,
+    /// TLS options for a server behind a private certificate authority.
+    #[serde(default)]
+    pub tls: TlsSettings,
+    /// Headers to add to every request to the server, for gateways that need
+    /// their own authentication or routing headers.
+    #[serde(default)]
+    pub extra_headers: BTreeMap<String, String>,
+}
+
+/// The kinds of provider assistant requests can be sent to.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    /// OpenAI, using the API key entered in the assistant panel.
+    #[default]
+    OpenAi,
+    /// A vLLM server, or any OpenAI-compatible server that doesn't need an API key.
+    Vllm,
+    /// Perplexity, using the API key in `PERPLEXITY_API_KEY`. Requests with stop
+    /// sequences fail, so it can't serve inline assists.
+    Perplexity,
+}
+
+impl ProviderKind {
+    /// The URL the provider is usually served at.
+    pub fn default_api_url(self) -> &'static str {
         match self {
-            OpenAiModel::ThreePointFiveTurbo => "gpt-3.5-turbo",
-            OpenAiModel::Four => "gpt-4",
-            OpenAiModel::FourTurbo => "gpt-4-turbo",
+            ProviderKind::OpenAi => OPEN_AI_API_URL,
+            ProviderKind::Vllm => VLLM_API_URL,
+            ProviderKind::Perplexity => PERPLEXITY_API_URL,
         }
     }
 
-    pub fn cycle(&self) -> Self {
+    /// How the provider is labeled in the assistant panel.
+    pub fn display_name(self) -> &'static str {
         match self {
-            OpenAiModel::ThreePointFiveTurbo => OpenAiModel::Four,
-            OpenAiModel::Four => OpenAiModel::FourTurbo,
-            OpenAiModel::FourTurbo => OpenAiModel::ThreePointFiveTurbo,
+            ProviderKind::OpenAi => "OpenAI",
+            ProviderKind::Vllm => "vLLM",
+            ProviderKind::Perplexity => "Perplexity",
         }
     }
+
+    /// Whether the provider accepts the seed assistant messages are sampled
+    /// with. Perplexity rejects requests that set one.
+    pub fn supports_seed(self) -> bool {
+        self != ProviderKind::Perplexity
+    }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
-#[serde(rename_all = "snake_case")]
-pub enum AssistantDockPosition {
-    Left,
-    Right,
-    Bottom,
+/// A provider to send assistant requests to when the ones before it fail.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct FallbackProviderSettings {
+    pub provider: ProviderKind,
+    /// The model to request from this provider.
+    pub model: String,
+    /// The provider's API URL. Defaults to the provider's usual URL.
+    pub api_url: Option<String>,
+    /// TLS options for a server behind a private certificate authority. The
+    /// ones in the `openai` and `vllm` settings don't apply to fallbacks.
+    #[serde(default)]
+    pub tls: TlsSettings,
+    /// Headers to add to every request to the provider. Like the TLS options,
+    /// the ones in the `openai` and `vllm` settings don't apply to fallbacks.
+    #[serde(default)]
+    pub extra_headers: BTreeMap<String, String>,
+}
+
+impl FallbackProviderSettings {
+    /// How the provider is labeled in the assistant panel.
+    pub fn display_name(&self) -> String {
+        format!("{} ({})", self.provider.display_name(), self.model)
+    }
+}
+
+/// A rule that sends inline assists and inline completions in some files to a
+/// particular model, such as a cheap local model for Markdown.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct ModelRoute {
+    /// The language the rule applies to, such as `Rust`.
+    pub language: Option<String>,
+    /// A glob for the paths the rule applies to, relative to the project, such
+    /// as `docs/**`.
+    pub path: Option<String>,
+    #[serde(default)]
+    pub provider: ProviderKind,
+    pub model: ModelName,
+    /// The provider's API URL. Defaults to the one in the provider's settings.
+    pub api_url: Option<String>,
+}
+
+impl ModelRoute {
+    /// Whether the rule applies to a file in `language` at `path`. A rule with
+    /// neither a language nor a path applies to nothing.
+    pub fn matches(&self, language: Option<&str>, path: Option<&Path>) -> bool {
+        if self.language.is_none() && self.path.is_none() {
+            return false;
+        }
+        let language_matches = self.language.as_ref().map_or(true, |expected| {
+            language.map_or(false, |language| language.eq_ignore_ascii_case(expected))
+        });
+        let path_matches = self.path.as_ref().map_or(true, |glob| {
+            path.zip(PathMatcher::new(glob).log_err())
+                .map_or(false, |(path, matcher)| matcher.is_match(path))
+        });
+        language_matches && path_matches
+    }
+}
+
+/// Sampling parameters for assistant completions. Unset parameters use the
+/// provider's defaults.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq)]
+pub struct SamplingSettings {
+    /// Between 0.0 and 2.0. Higher values make responses more varied. Used for
+    /// conversations and inline assists, unless a conversation's template sets
+    /// its own.
+    #[schemars(range(min = 0.0, max = 2.0))]
+    pub temperature: Option<f32>,
+    /// The maximum number of tokens to generate per response.
+    #[schemars(range(min = 1))]
+    pub max_tokens: Option<u32>,
+    /// Nucleus sampling: only sample from the most likely tokens whose
+    /// probabilities add up to this value.
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub top_p: Option<f32>,
+    /// Only sample from this many of the most likely tokens. Ignored by OpenAI.
+    #[schemars(range(min = 1))]
+    pub top_k: Option<i32>,
+    /// Between -2.0 and 2.0. Positive values encourage new topics.
+    #[schemars(range(min = -2.0, max = 2.0))]
+    pub presence_penalty: Option<f32>,
+    /// Between -2.0 and 2.0. Positive values discourage repetition.
+    #[schemars(range(min = -2.0, max = 2.0))]
+    pub frequency_penalty: Option<f32>,
+    /// Text that ends a response when the model generates it.
+    #[schemars(length(max = 4))]
+    pub stop_sequences: Option<Vec<String>>,
+}
+
+impl SamplingSettings {
+    /// These settings, with the ones `overrides` sets replacing them.
+    pub fn merged(&self, overrides: &Self) -> Self {
+        Self {
+            temperature: overrides.temperature.or(self.temperature),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            top_p: overrides.top_p.or(self.top_p),
+            top_k: overrides.top_k.or(self.top_k),
+            presence_penalty: overrides.presence_penalty.or(self.presence_penalty),
+            frequency_penalty: overrides.frequency_penalty.or(self.frequency_penalty),
+            stop_sequences: overrides
+                .stop_sequences
+                .clone()
+                .or_else(|| self.stop_sequences.clone()),
+        }
+    }
+
+    pub fn to_params(&self) -> SamplingParams {
+        SamplingParams {
+            max_tokens: self.max_tokens,
+            top_p: self.top_p,
+            top_k: self.top_k,
+            presence_penalty: self.presence_penalty,
+            frequency_penalty: self.frequency_penalty,
+            ..Default::default()
+        }
+    }
 }
 
+/// The assistant settings, read from the user's settings only. The few that a
+/// project can override are in [`ProjectAssistantSettings`].
 #[derive(Deserialize, Debug)]
 pub struct AssistantSettings {
     pub button: bool,
     pub dock: AssistantDockPosition,
     pub default_width: Pixels,
     pub default_height: Pixels,
-    pub default_open_ai_model: OpenAiModel,
-    pub openai_api_url: String,
+    pub openai: OpenAiSettings,
+    pub vllm: VllmSettings,
+    pub proxy: Option<String>,
+    pub sampling: SamplingSettings,
+    pub auto_continue: bool,
+    pub auto_summarize: bool,
+    pub cache_completions: bool,
+    pub max_concurrent_requests: usize,
+    pub fallback_providers: Vec<FallbackProviderSettings>,
+    pub debug_trace: DebugTraceSettings,
+    pub prompt_templates: BTreeMap<String, String>,
+    pub inline_completions: InlineCompletionSettings,
+    pub conversation_retention: ConversationRetentionSettings,
+    pub model_routes: Vec<ModelRoute>,
+    /// What was changed when legacy settings were migrated, to tell the user.
+    #[serde(skip)]
+    pub migrations: Vec<String>,
+}
+
+impl AssistantSettings {
+    /// The sampling settings for requests to `provider`: the assistant's, with
+    /// the provider's own replacing them.
+    pub fn sampling_for(&self, provider: ProviderKind) -> SamplingSettings {
+        let overrides = match provider {
+            ProviderKind::OpenAi => &self.openai.sampling,
+            ProviderKind::Vllm => &self.vllm.sampling,
+            // Perplexity has no settings of its own.
+            ProviderKind::Perplexity => return self.sampling.clone(),
+        };
+        self.sampling.merged(overrides)
+    }
+
+    /// The first routing rule that applies to a file in `language` at `path`.
+    pub fn model_route(&self, language: Option<&str>, path: Option<&Path>) -> Option<&ModelRoute> {
+        self.model_routes
+            .iter()
+            .find(|route| route.matches(language, path))
+    }
+
+    /// The API URL requests following `route` are sent to.
+    pub fn route_api_url(&self, route: &ModelRoute) -> String {
+        route
+            .api_url
+            .clone()
+            .unwrap_or_else(|| match route.provider {
+                ProviderKind::OpenAi => self.openai.api_url.clone(),
+                ProviderKind::Vllm => self
+                    .vllm
+                    .api_url
+                    .clone()
+                    .unwrap_or_else(|| VLLM_API_URL.to_string()),
+                ProviderKind::Perplexity => PERPLEXITY_API_URL.to_string(),
+            })
+    }
 }
 
 /// Assistant panel settings
@@ -77,14 +496,330 @@ pub struct AssistantSettingsContent {
     ///
     /// Default: 320
     pub default_height: Option<f32>,
-    /// The default OpenAI model to use when starting new conversations.
+    /// Settings for OpenAI's API, or any server compatible with it.
+    pub openai: Option<OpenAiSettingsContent>,
+    /// Settings for a vLLM server.
+    pub vllm: Option<VllmSettings>,
+    /// The proxy to send completion and embedding requests through, such as
+    /// `http://proxy.example.com:8080`. When unset, the `HTTPS_PROXY` and
+    /// `HTTP_PROXY` environment variables are used instead.
     ///
-    /// Default: gpt-4-1106-preview
-    pub default_open_ai_model: Option<OpenAiModel>,
-    /// OpenAI API base URL to use when starting new conversations.
+    /// Default: null
+    pub proxy: Option<String>,
+    /// Sampling parameters for assistant completions, such as `max_tokens` and
+    /// `top_p`.
     ///
-    /// Default: https://api.openai.com/v1
+    /// Default: {}
+    pub sampling: Option<SamplingSettings>,
+    /// Whether to automatically ask for the rest of a response that stopped
+    /// because it reached the maximum number of tokens.
+    ///
+    /// Default: false
+    pub auto_continue: Option<bool>,
+    /// Whether to replace the older messages of a conversation with a summary
+    /// of them once it nears its model's context limit, rather than only
+    /// offering to.
+    ///
+    /// Default: false
+    pub auto_summarize: Option<bool>,
+    /// Whether to reuse the response to an identical earlier request instead of
+    /// sending it again. Responses are kept in memory and on disk.
+    ///
+    /// Default: false
+    pub cache_completions: Option<bool>,
+    /// How many requests can be sent to a provider at once. Further requests
+    /// wait in a queue until an earlier one finishes.
+    ///
+    /// Default: 4
+    pub max_concurrent_requests: Option<usize>,
+    /// Providers to try, in order, when a request to the configured OpenAI API
+    /// fails. The assistant shows which provider answered each message.
+    ///
+    /// Default: []
+    pub fallback_providers: Option<Vec<FallbackProviderSettings>>,
+    /// Writes the bodies of completion and embedding requests and their
+    /// responses to `ai_trace.log` in the logs directory, with API keys scrubbed.
+    pub debug_trace: Option<DebugTraceSettings>,
+    /// Templates for the instructions sent to the model, by name, such as
+    /// `inline_assist` or `generate_tests`. These take precedence over `<name>.md` files in the
+    /// prompts directory.
+    ///
+    /// Default: {}
+    pub prompt_templates: Option<BTreeMap<String, String>>,
+    /// Code completion from a local model, shown as ghost text as you type when
+    /// Copilot isn't signed in.
+    pub inline_completions: Option<InlineCompletionSettings>,
+    /// How long saved conversations are kept. Older conversations are deleted
+    /// when the assistant panel loads.
+    ///
+    /// Default: kept forever
+    pub conversation_retention: Option<ConversationRetentionSettings>,
+    /// Rules that send inline assists and inline completions to a particular
+    /// model, by the file's language or path. The first rule that applies is
+    /// used. Inline completions only follow rules for vLLM models. Rules in a
+    /// project's settings are ignored.
+    ///
+    /// Default: []
+    pub model_routes: Option<Vec<ModelRoute>>,
+
+    // The flat keys the `openai` settings were read from before they had their
+    // own block. They're still honored, but no longer suggested.
+    #[schemars(skip)]
+    pub default_open_ai_model: Option<ModelName>,
+    #[schemars(skip)]
     pub openai_api_url: Option<String>,
+    #[schemars(skip)]
+    pub openai_tls: Option<TlsSettings>,
+    #[schemars(skip)]
+    pub openai_extra_headers: Option<BTreeMap<String, String>>,
+    #[schemars(skip)]
+    pub openai_additional_api_urls: Option<Vec<String>>,
+    #[schemars(skip)]
+    pub openai_load_balancing: Option<LoadBalancing>,
+}
+
+impl AssistantSettingsContent {
+    /// The content in the current schema, along with a description of each
+    /// change: flat `openai_*` keys are moved into the `openai` block where it
+    /// doesn't set them itself, and retired models are replaced.
+    pub fn migrate(&self) -> (Self, Vec<String>) {
+        fn move_key<T>(
+            nested: &mut Option<T>,
+            legacy: &mut Option<T>,
+            key: &str,
+            new_key: &str,
+            migrations: &mut Vec<String>,
+        ) {
+            let Some(value) = legacy.take() else {
+                return;
+            };
+            if nested.is_none() {
+                *nested = Some(value);
+                migrations.push(format!("`{key}` is now `{new_key}`"));
+            } else {
+                migrations.push(format!("`{key}` is ignored, since `{new_key}` is set"));
+            }
+        }
+
+        let mut content = self.clone();
+        let mut migrations = Vec::new();
+        let openai = content.openai.get_or_insert_with(Default::default);
+        move_key(
+            &mut openai.model,
+            &mut content.default_open_ai_model,
+            "default_open_ai_model",
+            "openai.model",
+            &mut migrations,
+        );
+        move_key(
+            &mut openai.api_url,
+            &mut content.openai_api_url,
+            "openai_api_url",
+            "openai.api_url",
+            &mut migrations,
+        );
+        move_key(
+            &mut openai.tls,
+            &mut content.openai_tls,
+            "openai_tls",
+            "openai.tls",
+            &mut migrations,
+        );
+        move_key(
+            &mut openai.extra_headers,
+            &mut content.openai_extra_headers,
+            "openai_extra_headers",
+            "openai.extra_headers",
+            &mut migrations,
+        );
+        move_key(
+            &mut openai.additional_api_urls,
+            &mut content.openai_additional_api_urls,
+            "openai_additional_api_urls",
+            "openai.additional_api_urls",
+            &mut migrations,
+        );
+        move_key(
+            &mut openai.load_balancing,
+            &mut content.openai_load_balancing,
+            "openai_load_balancing",
+            "openai.load_balancing",
+            &mut migrations,
+        );
+        if let Some(model) = &mut openai.model {
+            if let Some(successor) = model.successor() {
+                migrations.push(format!(
+                    "`{}` has been retired, so `{}` is used instead",
+                    model.full_name(),
+                    successor.full_name()
+                ));
+                *model = successor;
+            }
+        }
+        if content.openai.as_ref() == Some(&OpenAiSettingsContent::default()) {
+            content.openai = None;
+        }
+        (content, migrations)
+    }
+}
+
+/// `text` with each `${NAME}` in it replaced by the value of the environment
+/// variable `NAME`, as looked up by `var`. Variables that aren't set are left
+/// as written, and logged.
+fn expand_env_vars(text: &str, var: &impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start + 2..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        let reference = &rest[start..start + 3 + len];
+        expanded.push_str(&rest[..start]);
+        match var(name) {
+            Some(value) => expanded.push_str(&value),
+            None => {
+                log::warn!("environment variable {name} in the assistant settings isn't set");
+                expanded.push_str(reference);
+            }
+        }
+        rest = &rest[start + reference.len()..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+impl AssistantSettingsContent {
+    /// Expands environment variables, written as `${NAME}`, in the settings
+    /// that hold endpoints and credentials, so that they can be kept out of
+    /// settings files that are shared between machines.
+    fn expand_env_vars(&mut self, var: &impl Fn(&str) -> Option<String>) {
+        let expand = |value: &mut String| *value = expand_env_vars(value, var);
+        if let Some(openai) = &mut self.openai {
+            openai.api_url.iter_mut().for_each(expand);
+            openai.organization.iter_mut().for_each(expand);
+            openai
+                .extra_headers
+                .iter_mut()
+                .flatten()
+                .for_each(|(_, value)| expand(value));
+            openai
+                .additional_api_urls
+                .iter_mut()
+                .flatten()
+                .for_each(expand);
+        }
+        if let Some(vllm) = &mut self.vllm {
+            vllm.api_url.iter_mut().for_each(expand);
+            vllm.extra_headers.values_mut().for_each(expand);
+        }
+        for fallback in self.fallback_providers.iter_mut().flatten() {
+            fallback.api_url.iter_mut().for_each(expand);
+            fallback.extra_headers.values_mut().for_each(expand);
+        }
+        for route in self.model_routes.iter_mut().flatten() {
+            route.api_url.iter_mut().for_each(expand);
+        }
+        if let Some(inline_completions) = &mut self.inline_completions {
+            expand(&mut inline_completions.api_url);
+        }
+        self.proxy.iter_mut().for_each(expand);
+    }
+}
+
+/// The assistant settings a project can set for itself in its
+/// `.zed/settings.json`. The other assistant settings decide where requests are
+/// sent and with which credentials, so they're only read from the user's
+/// settings, with [`AssistantSettings::get_global`].
+#[derive(Deserialize, Debug)]
+pub struct ProjectAssistantSettings {
+    pub openai: ProjectModelSettings,
+    pub system_prompt: Option<SystemPrompt>,
+    pub context_rules: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ProjectModelSettings {
+    pub model: ModelName,
+}
+
+impl ProjectAssistantSettings {
+    /// The settings for `project`, with any overrides in its first worktree's
+    /// `.zed/settings.json` applied.
+    pub fn for_project<'a>(project: &Project, cx: &'a AppContext) -> &'a Self {
+        let worktree_id = project
+            .visible_worktrees(cx)
+            .next()
+            .map(|worktree| worktree.read(cx).id().to_usize());
+        Self::get(worktree_id.map(|id| (id, Path::new(""))), cx)
+    }
+
+    /// The model these settings choose in place of the user's default model,
+    /// if they choose another one.
+    pub fn model_override(&self, cx: &AppContext) -> Option<&ModelName> {
+        Some(&self.openai.model).filter(|model| **model != Self::get_global(cx).openai.model)
+    }
+
+    /// The system message conversations and inline assists start with: the
+    /// system prompt, followed by the context rules as a list. A prompt in a
+    /// file is looked up in `prompt_files`, by its path.
+    pub fn system_message(&self, prompt_files: &HashMap<PathBuf, String>) -> Option<String> {
+        let system_prompt = match &self.system_prompt {
+            Some(SystemPrompt::Text(text)) => Some(text.as_str()),
+            Some(prompt @ SystemPrompt::File { .. }) => prompt
+                .file_path()
+                .and_then(|path| prompt_files.get(&path))
+                .map(String::as_str),
+            None => None,
+        };
+        let mut message = system_prompt.unwrap_or_default().trim().to_string();
+        for rule in &self.context_rules {
+            if !message.is_empty() {
+                message.push('\n');
+            }
+            write!(&mut message, "- {}", rule.trim()).unwrap();
+        }
+        Some(message).filter(|message| !message.is_empty())
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct ProjectAssistantSettingsContent {
+    // The model is documented with the rest of the `openai` block.
+    #[schemars(skip)]
+    pub openai: Option<ProjectModelSettingsContent>,
+    /// A system message that new conversations start with, and that's sent
+    /// ahead of inline assist instructions in place of the built-in one. Either
+    /// the prompt itself, or `{ "path": "..." }` to read it from a file, with
+    /// relative paths in the prompts directory. It can be set for a single
+    /// project in its `.zed/settings.json`.
+    ///
+    /// Default: null
+    pub system_prompt: Option<SystemPrompt>,
+    /// Extra rules for the model to follow, such as a project's coding
+    /// conventions, listed after the system prompt.
+    ///
+    /// Default: []
+    pub context_rules: Option<Vec<String>>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct ProjectModelSettingsContent {
+    pub model: Option<ModelName>,
+}
+
+impl Settings for ProjectAssistantSettings {
+    const KEY: Option<&'static str> = Some("assistant");
+
+    type FileContent = ProjectAssistantSettingsContent;
+
+    fn load(
+        default_value: &Self::FileContent,
+        user_values: &[&Self::FileContent],
+        _: &mut gpui::AppContext,
+    ) -> anyhow::Result<Self> {
+        Self::load_via_json_merge(default_value, user_values)
+    }
 }
 
 impl Settings for AssistantSettings {
@@ -97,6 +832,221 @@ impl Settings for AssistantSettings {
         user_values: &[&Self::FileContent],
         _: &mut gpui::AppContext,
     ) -> anyhow::Result<Self> {
-        Self::load_via_json_merge(default_value, user_values)
+        let mut migrations = Vec::new();
+        let user_values = user_values
+            .iter()
+            .map(|value| {
+                let (mut value, value_migrations) = value.migrate();
+                migrations.extend(value_migrations);
+                // Only the global value is ever read, so these are the user's
+                // settings: projects can't have environment variables expanded.
+                value.expand_env_vars(&|name| std::env::var(name).ok());
+                value
+            })
+            .collect::<Vec<_>>();
+        let mut settings =
+            Self::load_via_json_merge(default_value, &user_values.iter().collect::<Vec<_>>())?;
+        migrations.dedup();
+        settings.migrations = migrations;
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use settings::SettingsStore;
+
+    #[test]
+    fn test_migrate_legacy_settings() {
+        let (content, migrations) = serde_json::from_str::<AssistantSettingsContent>(
+            r#"{
+                "default_open_ai_model": "gpt-4-0314",
+                "openai_api_url": "http://localhost:8000/v1",
+                "openai": { "api_url": "http://gpu-box:8000/v1", "organization": "org-1" }
+            }"#,
+        )
+        .unwrap()
+        .migrate();
+        let openai = content.openai.unwrap();
+        assert_eq!(openai.model, Some(ModelName::new("gpt-4-0613")));
+        assert_eq!(openai.api_url.as_deref(), Some("http://gpu-box:8000/v1"));
+        assert_eq!(openai.organization.as_deref(), Some("org-1"));
+        assert_eq!(content.default_open_ai_model, None);
+        assert_eq!(content.openai_api_url, None);
+        assert_eq!(
+            migrations,
+            [
+                "`default_open_ai_model` is now `openai.model`",
+                "`openai_api_url` is ignored, since `openai.api_url` is set",
+                "`gpt-4-0314` has been retired, so `gpt-4-0613` is used instead",
+            ]
+        );
+
+        let (content, migrations) = AssistantSettingsContent::default().migrate();
+        assert_eq!(content.openai, None);
+        assert!(migrations.is_empty());
+    }
+
+    #[test]
+    fn test_model_route_matches() {
+        let route = |language: Option<&str>, path: Option<&str>| ModelRoute {
+            language: language.map(Into::into),
+            path: path.map(Into::into),
+            provider: ProviderKind::Vllm,
+            model: ModelName::new("codellama/CodeLlama-7b-hf"),
+            api_url: None,
+        };
+        let markdown = route(Some("Markdown"), None);
+        assert!(markdown.matches(Some("markdown"), Some(Path::new("README.md"))));
+        assert!(!markdown.matches(Some("Rust"), Some(Path::new("README.md"))));
+        assert!(!markdown.matches(None, None));
+
+        let docs = route(None, Some("docs/**/*.md"));
+        assert!(docs.matches(None, Some(Path::new("docs/guide/intro.md"))));
+        assert!(!docs.matches(Some("Markdown"), Some(Path::new("README.md"))));
+        assert!(!docs.matches(Some("Markdown"), None));
+
+        let rust_tests = route(Some("Rust"), Some("tests/**"));
+        assert!(rust_tests.matches(Some("Rust"), Some(Path::new("tests/api.rs"))));
+        assert!(!rust_tests.matches(Some("Rust"), Some(Path::new("src/lib.rs"))));
+
+        assert!(!route(None, None).matches(Some("Rust"), Some(Path::new("src/lib.rs"))));
+    }
+
+    #[test]
+    fn test_expand_env_vars() {
+        let mut content = serde_json::from_str::<AssistantSettingsContent>(
+            r#"{
+                "openai": {
+                    "api_url": "http://${OLLAMA_HOST}/v1",
+                    "extra_headers": { "Authorization": "Bearer ${OPENAI_API_KEY}" }
+                },
+                "fallback_providers": [{
+                    "provider": "vllm",
+                    "model": "meta-llama/Meta-Llama-3-8B-Instruct",
+                    "extra_headers": { "X-Api-Key": "${OPENAI_API_KEY}" }
+                }],
+                "proxy": "${UNSET}"
+            }"#,
+        )
+        .unwrap();
+        content.expand_env_vars(&|name| match name {
+            "OLLAMA_HOST" => Some("gpu-box:11434".into()),
+            "OPENAI_API_KEY" => Some("sk-123".into()),
+            _ => None,
+        });
+        let openai = content.openai.unwrap();
+        assert_eq!(openai.api_url.as_deref(), Some("http://gpu-box:11434/v1"));
+        assert_eq!(
+            openai.extra_headers.unwrap()["Authorization"],
+            "Bearer sk-123"
+        );
+        assert_eq!(
+            content.fallback_providers.unwrap()[0].extra_headers["X-Api-Key"],
+            "sk-123"
+        );
+        assert_eq!(content.proxy.as_deref(), Some("${UNSET}"));
+
+        let no_var = |_: &str| None;
+        assert_eq!(expand_env_vars("${A", &no_var), "${A");
+        assert_eq!(expand_env_vars("a${}b", &no_var), "a${}b");
+    }
+
+    /// Registers the assistant settings, with `local_settings` as the
+    /// `.zed/settings.json` of worktree 1.
+    fn set_local_settings(local_settings: &str, cx: &mut AppContext) {
+        let mut store = SettingsStore::test(cx);
+        store.register_setting::<AssistantSettings>(cx);
+        store.register_setting::<ProjectAssistantSettings>(cx);
+        store
+            .set_local_settings(1, Path::new("").into(), Some(local_settings), cx)
+            .unwrap();
+        cx.set_global(store);
+    }
+
+    #[gpui::test]
+    fn test_project_settings(cx: &mut AppContext) {
+        set_local_settings(
+            r#"{
+                "assistant": {
+                    "openai": { "api_url": "https://example.com/v1", "model": "gpt-4-0613" },
+                    "proxy": "http://example.com:8080",
+                    "system_prompt": "Use tabs.",
+                    "context_rules": ["Prefer iterators."]
+                }
+            }"#,
+            cx,
+        );
+
+        let project_settings = ProjectAssistantSettings::get(Some((1, Path::new(""))), cx);
+        assert_eq!(
+            project_settings.model_override(cx),
+            Some(&ModelName::new("gpt-4-0613"))
+        );
+        assert_eq!(
+            project_settings
+                .system_message(&HashMap::default())
+                .as_deref(),
+            Some("Use tabs.\n- Prefer iterators.")
+        );
+        assert_eq!(
+            ProjectAssistantSettings::get_global(cx).model_override(cx),
+            None
+        );
+
+        // Only the project's model is read, not where requests are sent.
+        let settings = AssistantSettings::get_global(cx);
+        assert_eq!(settings.openai.api_url, OPEN_AI_API_URL);
+        assert_eq!(settings.proxy, None);
+    }
+
+    #[gpui::test]
+    fn test_project_settings_are_not_expanded(cx: &mut AppContext) {
+        set_local_settings(
+            r#"{
+                "assistant": {
+                    "openai": {
+                        "api_url": "https://example.com/v1",
+                        "extra_headers": { "X-Secret": "${HOME}" }
+                    },
+                    "system_prompt": "${HOME}"
+                }
+            }"#,
+            cx,
+        );
+
+        let settings = AssistantSettings::get_global(cx);
+        assert_eq!(settings.openai.api_url, OPEN_AI_API_URL);
+        assert!(settings.openai.extra_headers.is_empty());
+        let project_settings = ProjectAssistantSettings::get(Some((1, Path::new(""))), cx);
+        assert_eq!(
+            project_settings
+                .system_message(&HashMap::default())
+                .as_deref(),
+            Some("${HOME}")
+        );
+    }
+
+    #[gpui::test]
+    fn test_project_routes_are_ignored(cx: &mut AppContext) {
+        set_local_settings(
+            r#"{
+                "assistant": {
+                    "model_routes": [{
+                        "language": "Rust",
+                        "provider": "open_ai",
+                        "model": "gpt-4-1106-preview",
+                        "api_url": "https://example.com/v1"
+                    }]
+                }
+            }"#,
+            cx,
+        );
+
+        let settings = AssistantSettings::get_global(cx);
+        assert!(settings.model_route(Some("Rust"), None).is_none());
+        let project_settings = ProjectAssistantSettings::get(Some((1, Path::new(""))), cx);
+        assert_eq!(project_settings.model_override(cx), None);
     }
 }
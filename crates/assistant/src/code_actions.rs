@@ -0,0 +1,49 @@
+use crate::{conflicts::find_conflicts, FixDiagnostic, ResolveConflict};
+use editor::{CodeActionProvider, ProvidedCodeAction};
+use gpui::{AppContext, Model};
+use language::{Anchor, Buffer, DiagnosticSeverity, OffsetRangeExt};
+use std::ops::Range;
+
+/// Offers to fix errors and warnings, and to resolve merge conflicts, with the
+/// inline assistant.
+pub struct AssistantCodeActionProvider;
+
+impl CodeActionProvider for AssistantCodeActionProvider {
+    fn code_actions(
+        &self,
+        buffer: &Model<Buffer>,
+        range: Range<Anchor>,
+        cx: &AppContext,
+    ) -> Vec<ProvidedCodeAction> {
+        let snapshot = buffer.read(cx).snapshot();
+        let mut actions = Vec::new();
+
+        let has_problem = snapshot
+            .diagnostics_in_range::<_, usize>(range.clone(), false)
+            .any(|entry| {
+                matches!(
+                    entry.diagnostic.severity,
+                    DiagnosticSeverity::ERROR | DiagnosticSeverity::WARNING
+                )
+            });
+        if has_problem {
+            actions.push(ProvidedCodeAction {
+                title: "Fix with AI".into(),
+                action: Box::new(FixDiagnostic),
+            });
+        }
+
+        let range = range.to_offset(&snapshot);
+        let in_conflict = find_conflicts(&snapshot.text())
+            .iter()
+            .any(|conflict| conflict.range.start <= range.end && range.start <= conflict.range.end);
+        if in_conflict {
+            actions.push(ProvidedCodeAction {
+                title: "Resolve Conflict with AI".into(),
+                action: Box::new(ResolveConflict),
+            });
+        }
+
+        actions
+    }
+}
@@ -0,0 +1,148 @@
+use crate::file_reference::file_references;
+use std::ops::Range;
+
+/// A fenced code block in a message, such as code suggested in a response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeBlock {
+    /// Where the block is in the text, from its opening fence to its closing one.
+    pub range: Range<usize>,
+    pub language: Option<String>,
+    /// The file the code is for, named in the block's info string or on the
+    /// line before it.
+    pub path: Option<String>,
+    /// The rows of that file the code is for, if the reference includes them.
+    pub rows: Option<Range<u32>>,
+    /// The code between the fences.
+    pub content: String,
+}
+
+/// The code blocks in `text`. Blocks that aren't closed yet, such as one that's
+/// still being streamed, are left out.
+pub fn code_blocks(text: &str) -> Vec<CodeBlock> {
+    struct OpenBlock<'a> {
+        start: usize,
+        content_start: usize,
+        fence: &'a str,
+        info: &'a str,
+        previous_line: &'a str,
+    }
+
+    let mut blocks = Vec::new();
+    let mut open_block: Option<OpenBlock> = None;
+    let mut previous_line = "";
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+
+        let trimmed = line.trim();
+        let fence_len = trimmed
+            .chars()
+            .next()
+            .filter(|c| *c == '`' || *c == '~')
+            .map_or(0, |marker| {
+                trimmed.chars().take_while(|c| *c == marker).count()
+            });
+        if fence_len < 3 {
+            if !trimmed.is_empty() && open_block.is_none() {
+                previous_line = trimmed;
+            }
+            continue;
+        }
+
+        let fence = &trimmed[..fence_len];
+        match open_block.take() {
+            Some(block) => {
+                if fence.starts_with(block.fence) && trimmed[fence_len..].trim().is_empty() {
+                    let info_reference = file_references(block.info).next();
+                    let language = block
+                        .info
+                        .split_whitespace()
+                        .next()
+                        .filter(|word| {
+                            info_reference
+                                .as_ref()
+                                .map_or(true, |reference| !word.starts_with(&reference.path))
+                        })
+                        .map(str::to_string);
+                    let reference =
+                        info_reference.or_else(|| file_references(block.previous_line).last());
+                    blocks.push(CodeBlock {
+                        range: block.start..line_start + line.trim_end().len(),
+                        language,
+                        path: reference.as_ref().map(|reference| reference.path.clone()),
+                        rows: reference.and_then(|reference| reference.rows),
+                        content: text[block.content_start..line_start].to_string(),
+                    });
+                    previous_line = "";
+                } else {
+                    open_block = Some(block);
+                }
+            }
+            None => {
+                open_block = Some(OpenBlock {
+                    start: line_start + (line.len() - line.trim_start().len()),
+                    content_start: offset,
+                    fence,
+                    info: trimmed[fence_len..].trim(),
+                    previous_line,
+                });
+            }
+        }
+    }
+    blocks
+}
+
+/// `text` with `rows`, which are inclusive of the last one, replaced by
+/// `replacement`.
+pub fn replace_rows(text: &str, rows: Range<u32>, replacement: &str) -> String {
+    let row_offset = |row: u32| {
+        text.split_inclusive('\n')
+            .take(row as usize)
+            .map(str::len)
+            .sum::<usize>()
+    };
+    let start = row_offset(rows.start);
+    let end = row_offset(rows.end + 1);
+    let mut replaced = text[..start].to_string();
+    replaced.push_str(replacement);
+    if text[start..end].ends_with('\n') && !replacement.ends_with('\n') {
+        replaced.push('\n');
+    }
+    replaced.push_str(&text[end..]);
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_blocks() {
+        let text = "Change `src/lib.rs`:\n\n\
+                    ```rust\nfn a() {}\n```\n\n\
+                    Then:\n\n  ```` src/main.rs:2-3\n```\nnested\n```\n  ````\n\n\
+                    ```python\nunclosed\n";
+        let blocks = code_blocks(text);
+        assert_eq!(blocks.len(), 2);
+
+        assert_eq!(&text[blocks[0].range.clone()], "```rust\nfn a() {}\n```");
+        assert_eq!(blocks[0].language.as_deref(), Some("rust"));
+        assert_eq!(blocks[0].path.as_deref(), Some("src/lib.rs"));
+        assert_eq!(blocks[0].rows, None);
+        assert_eq!(blocks[0].content, "fn a() {}\n");
+
+        assert_eq!(blocks[1].language, None);
+        assert_eq!(blocks[1].path.as_deref(), Some("src/main.rs"));
+        assert_eq!(blocks[1].rows, Some(1..2));
+        assert_eq!(blocks[1].content, "```\nnested\n```\n");
+    }
+
+    #[test]
+    fn test_replace_rows() {
+        let text = "one\ntwo\nthree\nfour";
+        assert_eq!(replace_rows(text, 1..2, "2\n3\n"), "one\n2\n3\nfour");
+        assert_eq!(replace_rows(text, 0..0, "1"), "1\ntwo\nthree\nfour");
+        assert_eq!(replace_rows(text, 3..3, "4\n"), "one\ntwo\nthree\n4\n");
+    }
+}
@@ -1,5 +1,8 @@
 use crate::streaming_diff::{Hunk, StreamingDiff};
-use ai::completion::{CompletionProvider, CompletionRequest};
+use ai::{
+    chat::ChatRequest,
+    completion::{text_only, CompletionProvider},
+};
 use anyhow::Result;
 use editor::{Anchor, MultiBuffer, MultiBufferSnapshot, ToOffset, ToPoint};
 use futures::{channel::mpsc, SinkExt, Stream, StreamExt};
@@ -94,7 +97,17 @@ impl Codegen {
         self.error.as_ref()
     }
 
-    pub fn start(&mut self, prompt: Box<dyn CompletionRequest>, cx: &mut ModelContext<Self>) {
+    /// Sends the following requests to `provider` instead.
+    pub fn set_provider(&mut self, provider: Arc<dyn CompletionProvider>) {
+        self.provider = provider;
+    }
+
+    /// Whether the assist has made edits that haven't been undone.
+    pub fn has_edits(&self) -> bool {
+        self.transaction_id.is_some()
+    }
+
+    pub fn start(&mut self, request: ChatRequest, cx: &mut ModelContext<Self>) {
         let range = self.range();
         let snapshot = self.snapshot.clone();
         let selected_text = snapshot
@@ -108,7 +121,7 @@ impl Codegen {
             .next()
             .unwrap_or_else(|| snapshot.indent_size_for_line(selection_start.row));
 
-        let response = self.provider.complete(prompt);
+        let response = self.provider.complete(request);
         self.generation = cx.spawn(|this, mut cx| {
             async move {
                 let generate = async {
@@ -116,7 +129,7 @@ impl Codegen {
 
                     let (mut hunks_tx, mut hunks_rx) = mpsc::channel(1);
                     let diff = cx.background_executor().spawn(async move {
-                        let chunks = strip_invalid_spans_from_codeblock(response.await?);
+                        let chunks = strip_invalid_spans_from_codeblock(text_only(response.await?));
                         futures::pin_mut!(chunks);
                         let mut diff = StreamingDiff::new(selected_text.to_string());
 
@@ -262,6 +275,31 @@ impl Codegen {
         cx.notify();
     }
 
+    /// Stops streaming the response, keeping whatever has been applied so far.
+    pub fn stop(&mut self, cx: &mut ModelContext<Self>) {
+        if self.idle {
+            return;
+        }
+
+        self.generation = Task::ready(());
+        self.last_equal_ranges.clear();
+        self.idle = true;
+        cx.emit(Event::Finished);
+        cx.notify();
+    }
+
+    /// Reverts the edits made so far without ending the assist, so it can be
+    /// started again with a different prompt.
+    pub fn revert(&mut self, cx: &mut ModelContext<Self>) {
+        self.generation = Task::ready(());
+        if let Some(transaction_id) = self.transaction_id.take() {
+            self.buffer
+                .update(cx, |buffer, cx| buffer.undo_transaction(transaction_id, cx));
+        }
+        self.idle = true;
+        cx.notify();
+    }
+
     pub fn undo(&mut self, cx: &mut ModelContext<Self>) {
         if let Some(transaction_id) = self.transaction_id {
             self.buffer
@@ -370,20 +408,8 @@ mod tests {
         LanguageMatcher, Point,
     };
     use rand::prelude::*;
-    use serde::Serialize;
     use settings::SettingsStore;
 
-    #[derive(Serialize)]
-    pub struct DummyCompletionRequest {
-        pub name: String,
-    }
-
-    impl CompletionRequest for DummyCompletionRequest {
-        fn data(&self) -> serde_json::Result<String> {
-            serde_json::to_string(self)
-        }
-    }
-
     #[gpui::test(iterations = 10)]
     async fn test_transform_autoindent(cx: &mut TestAppContext, mut rng: StdRng) {
         cx.set_global(cx.update(SettingsStore::test));
@@ -415,10 +441,7 @@ mod tests {
             )
         });
 
-        let request = Box::new(DummyCompletionRequest {
-            name: "test".to_string(),
-        });
-        codegen.update(cx, |codegen, cx| codegen.start(request, cx));
+        codegen.update(cx, |codegen, cx| codegen.start(ChatRequest::default(), cx));
 
         let mut new_text = concat!(
             "       let mut x = 0;\n",
@@ -451,6 +474,58 @@ mod tests {
         );
     }
 
+    #[gpui::test]
+    async fn test_revert(cx: &mut TestAppContext) {
+        cx.set_global(cx.update(SettingsStore::test));
+        cx.update(language_settings::init);
+
+        let text = "let x = 0;\n";
+        let buffer = cx.new_model(|_| Buffer::new(0, BufferId::new(1).unwrap(), text));
+        let buffer = cx.new_model(|cx| MultiBuffer::singleton(buffer, cx));
+        let range = buffer.read_with(cx, |buffer, cx| {
+            let snapshot = buffer.snapshot(cx);
+            snapshot.anchor_before(Point::new(0, 0))..snapshot.anchor_after(Point::new(0, 10))
+        });
+        let provider = Arc::new(FakeCompletionProvider::new());
+        let codegen = cx.new_model(|cx| {
+            Codegen::new(
+                buffer.clone(),
+                CodegenKind::Transform { range },
+                provider.clone(),
+                cx,
+            )
+        });
+
+        codegen.update(cx, |codegen, cx| codegen.start(ChatRequest::default(), cx));
+        provider.send_completion("let y = 1;");
+        provider.finish_completion();
+        cx.background_executor.run_until_parked();
+        assert_eq!(
+            buffer.read_with(cx, |buffer, cx| buffer.snapshot(cx).text()),
+            "let y = 1;\n"
+        );
+        assert!(codegen.read_with(cx, |codegen, _| codegen.has_edits()));
+
+        // Reverting restores the original text but keeps the assist alive.
+        let undone = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        cx.update(|cx| {
+            let undone = undone.clone();
+            cx.subscribe(&codegen, move |_, event, _| {
+                if let Event::Undone = event {
+                    undone.store(true, std::sync::atomic::Ordering::SeqCst);
+                }
+            })
+            .detach();
+        });
+        codegen.update(cx, |codegen, cx| codegen.revert(cx));
+        assert_eq!(
+            buffer.read_with(cx, |buffer, cx| buffer.snapshot(cx).text()),
+            text
+        );
+        assert!(!codegen.read_with(cx, |codegen, _| codegen.has_edits()));
+        assert!(!undone.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
     #[gpui::test(iterations = 10)]
     async fn test_autoindent_when_generating_past_indentation(
         cx: &mut TestAppContext,
@@ -482,10 +557,7 @@ mod tests {
             )
         });
 
-        let request = Box::new(DummyCompletionRequest {
-            name: "test".to_string(),
-        });
-        codegen.update(cx, |codegen, cx| codegen.start(request, cx));
+        codegen.update(cx, |codegen, cx| codegen.start(ChatRequest::default(), cx));
 
         let mut new_text = concat!(
             "t mut x = 0;\n",
@@ -548,10 +620,7 @@ mod tests {
             )
         });
 
-        let request = Box::new(DummyCompletionRequest {
-            name: "test".to_string(),
-        });
-        codegen.update(cx, |codegen, cx| codegen.start(request, cx));
+        codegen.update(cx, |codegen, cx| codegen.start(ChatRequest::default(), cx));
 
         let mut new_text = concat!(
             "let mut x = 0;\n",
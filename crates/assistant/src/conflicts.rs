@@ -0,0 +1,84 @@
+use std::ops::Range;
+
+/// A region of a file where git couldn't merge two versions automatically,
+/// delimited by conflict markers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    /// The range of the whole region, including the markers.
+    pub range: Range<usize>,
+    /// The name git gave our side, usually `HEAD`.
+    pub ours_name: String,
+    /// The name git gave their side, usually the branch being merged.
+    pub theirs_name: String,
+}
+
+/// Finds the conflicts git left in `text`, in order. A region whose markers
+/// aren't all there is skipped.
+pub fn find_conflicts(text: &str) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    let mut start = None;
+    let mut separated = false;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+
+        if let Some(name) = line.strip_prefix("<<<<<<<") {
+            start = Some((line_start, name.trim().to_string()));
+            separated = false;
+        } else if line.starts_with("=======") && start.is_some() {
+            separated = true;
+        } else if let Some(name) = line.strip_prefix(">>>>>>>") {
+            if let Some((start, ours_name)) = start.take().filter(|_| separated) {
+                conflicts.push(Conflict {
+                    range: start..offset,
+                    ours_name,
+                    theirs_name: name.trim().to_string(),
+                });
+            }
+        }
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_find_conflicts() {
+        let text = indoc! {"
+            fn main() {
+            <<<<<<< HEAD
+                println!(\"hello\");
+            =======
+                println!(\"goodbye\");
+            >>>>>>> feature
+            }
+            <<<<<<< HEAD
+            // incomplete
+            >>>>>>> other
+            <<<<<<< ours
+            a
+            ||||||| base
+            b
+            =======
+            c
+            >>>>>>> theirs
+        "};
+        let conflicts = find_conflicts(text);
+        assert_eq!(conflicts.len(), 2);
+
+        assert_eq!(conflicts[0].ours_name, "HEAD");
+        assert_eq!(conflicts[0].theirs_name, "feature");
+        assert_eq!(
+            &text[conflicts[0].range.clone()],
+            "<<<<<<< HEAD\n    println!(\"hello\");\n=======\n    println!(\"goodbye\");\n>>>>>>> feature\n"
+        );
+
+        assert_eq!(conflicts[1].ours_name, "ours");
+        assert_eq!(conflicts[1].theirs_name, "theirs");
+        assert!(text[conflicts[1].range.clone()].starts_with("<<<<<<< ours\na\n||||||| base\n"));
+    }
+}
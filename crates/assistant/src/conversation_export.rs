@@ -0,0 +1,201 @@
+use crate::{
+    assistant_settings::{ProviderKind, SamplingSettings},
+    MessageStatus,
+};
+use ai::{chat::Role, completion::TokenUsage, models::ModelName};
+use chrono::{DateTime, Local};
+use gpui::SharedString;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write, path::Path};
+
+/// A conversation exported to JSON, with everything needed to recreate it: each
+/// message with its metadata, and the model and parameters it was sent with.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedConversation {
+    pub version: String,
+    pub title: String,
+    pub model: ModelName,
+    #[serde(default)]
+    pub provider: ProviderKind,
+    pub api_url: Option<String>,
+    pub parameters: ExportedParameters,
+    #[serde(default)]
+    pub usage: TokenUsage,
+    pub messages: Vec<ExportedMessage>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedParameters {
+    pub temperature: f32,
+    #[serde(default)]
+    pub sampling: SamplingSettings,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedMessage {
+    pub role: Role,
+    pub content: String,
+    pub sent_at: DateTime<Local>,
+    pub status: MessageStatus,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub answered_by: Option<SharedString>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub citations: Vec<String>,
+}
+
+/// The formats a conversation can be exported in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+}
+
+impl ExportFormat {
+    /// The format to export to `path` in, based on its extension.
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Markdown,
+        }
+    }
+}
+
+impl ExportedConversation {
+    pub const VERSION: &'static str = "0.1.0";
+
+    pub fn to_format(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Markdown => self.to_markdown(),
+            ExportFormat::Json => serde_json::to_string_pretty(self).unwrap(),
+        }
+    }
+
+    /// The conversation as a Markdown document, with a heading for the role of
+    /// each message and a list of the sources it cites. Empty messages are left
+    /// out.
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = format!("# {}\n", self.title.trim());
+        for message in &self.messages {
+            let content = message.content.trim();
+            if content.is_empty() {
+                continue;
+            }
+            write!(&mut markdown, "\n## {}\n\n{}\n", message.role, content).unwrap();
+            if let Some(fence) = unclosed_fence(content) {
+                writeln!(&mut markdown, "{fence}").unwrap();
+            }
+            if !message.citations.is_empty() {
+                markdown.push_str("\nSources:\n\n");
+                for url in &message.citations {
+                    writeln!(&mut markdown, "- <{url}>").unwrap();
+                }
+            }
+        }
+        markdown
+    }
+}
+
+/// The fence of a code block that's still open at the end of `text`, such as a
+/// response that was cut off, so it can be closed before the next heading.
+fn unclosed_fence(text: &str) -> Option<&str> {
+    let mut open_fence: Option<&str> = None;
+    for line in text.lines() {
+        let line = line.trim_start();
+        let Some(marker) = line.chars().next().filter(|c| *c == '`' || *c == '~') else {
+            continue;
+        };
+        let fence_len = line.chars().take_while(|c| *c == marker).count();
+        if fence_len < 3 {
+            continue;
+        }
+        let fence = &line[..fence_len];
+        match open_fence {
+            Some(open) => {
+                if fence.starts_with(open) && line[fence_len..].trim().is_empty() {
+                    open_fence = None;
+                }
+            }
+            None => open_fence = Some(fence),
+        }
+    }
+    open_fence
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_export() {
+        let message = |role, content: &str| ExportedMessage {
+            role,
+            content: content.into(),
+            sent_at: Local::now(),
+            status: MessageStatus::Done,
+            seed: None,
+            answered_by: None,
+            pinned: false,
+            citations: Vec::new(),
+        };
+        let mut conversation = ExportedConversation {
+            version: ExportedConversation::VERSION.into(),
+            title: "Reversing a string".into(),
+            model: ModelName::new("gpt-4"),
+            provider: ProviderKind::OpenAi,
+            api_url: None,
+            parameters: ExportedParameters {
+                temperature: 1.,
+                sampling: Default::default(),
+            },
+            usage: Default::default(),
+            messages: vec![
+                message(Role::User, "How do I reverse a string?\n"),
+                message(
+                    Role::Assistant,
+                    "Like this:\n\n```rust\nlet reversed: String = s.chars().rev().collect();\n```",
+                ),
+                message(Role::User, "And in Python?"),
+                message(Role::Assistant, "Use a slice:\n\n```python\ns[::-1]"),
+                message(Role::User, "\n"),
+            ],
+        };
+        conversation.messages[3].citations = vec!["https://docs.python.org/3/".into()];
+
+        assert_eq!(
+            conversation.to_markdown(),
+            concat!(
+                "# Reversing a string\n",
+                "\n## User\n\nHow do I reverse a string?\n",
+                "\n## Assistant\n\nLike this:\n\n```rust\nlet reversed: String = s.chars().rev().collect();\n```\n",
+                "\n## User\n\nAnd in Python?\n",
+                "\n## Assistant\n\nUse a slice:\n\n```python\ns[::-1]\n```\n",
+                "\nSources:\n\n- <https://docs.python.org/3/>\n",
+            )
+        );
+
+        let json = conversation.to_format(ExportFormat::Json);
+        let imported: ExportedConversation = serde_json::from_str(&json).unwrap();
+        assert_eq!(imported.messages.len(), 5);
+        assert_eq!(
+            imported.messages[1].content,
+            conversation.messages[1].content
+        );
+        assert_eq!(
+            imported.messages[3].citations,
+            ["https://docs.python.org/3/"]
+        );
+        assert_eq!(imported.model, conversation.model);
+    }
+
+    #[test]
+    fn test_unclosed_fence() {
+        assert_eq!(unclosed_fence("no code"), None);
+        assert_eq!(unclosed_fence("```\nfn main() {}\n```"), None);
+        assert_eq!(unclosed_fence("````md\n```rust\n```\n"), Some("````"));
+        assert_eq!(unclosed_fence("~~~\ncode"), Some("~~~"));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{
+    conversation_store::{ConversationQuery, CONVERSATION_STORE},
+    AssistantPanel, SavedConversationMetadata, ShowConversationHistory,
+};
+use chrono::{DateTime, Local};
+use gpui::{
+    AppContext, DismissEvent, EventEmitter, FocusHandle, FocusableView, Render, Task, View,
+    ViewContext, VisualContext, WeakView,
+};
+use picker::{Picker, PickerDelegate};
+use std::{path::PathBuf, sync::Arc};
+use ui::{prelude::*, HighlightedLabel, ListItem, ListItemSpacing};
+use util::ResultExt;
+use workspace::{ModalView, Workspace};
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|workspace: &mut Workspace, _| {
+        workspace.register_action(ConversationHistory::toggle);
+    })
+    .detach();
+}
+
+/// Searches the saved conversations by title and content, optionally filtered
+/// by model, provider and date, and opens the chosen one in the assistant panel.
+pub struct ConversationHistory {
+    picker: View<Picker<ConversationHistoryDelegate>>,
+}
+
+impl ConversationHistory {
+    fn toggle(
+        workspace: &mut Workspace,
+        _: &ShowConversationHistory,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let Some(panel) = workspace.panel::<AssistantPanel>(cx) else {
+            return;
+        };
+        let conversations = panel.read(cx).saved_conversations().to_vec();
+        let workspace_handle = workspace.weak_handle();
+        workspace.toggle_modal(cx, move |cx| {
+            let delegate = ConversationHistoryDelegate::new(
+                cx.view().downgrade(),
+                workspace_handle,
+                conversations,
+            );
+            let picker = cx.new_view(|cx| Picker::uniform_list(delegate, cx));
+            Self { picker }
+        });
+    }
+}
+
+impl Render for ConversationHistory {
+    fn render(&mut self, _cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex().w(rems(34.)).child(self.picker.clone())
+    }
+}
+
+impl FocusableView for ConversationHistory {
+    fn focus_handle(&self, cx: &AppContext) -> FocusHandle {
+        self.picker.focus_handle(cx)
+    }
+}
+
+impl EventEmitter<DismissEvent> for ConversationHistory {}
+impl ModalView for ConversationHistory {}
+
+struct ConversationMatch {
+    path: PathBuf,
+    title: String,
+    updated_at: DateTime<Local>,
+    model: Option<String>,
+    /// Where the search terms were found in the conversation, and the byte
+    /// positions of the matched characters.
+    snippet: Option<(String, Vec<usize>)>,
+}
+
+pub struct ConversationHistoryDelegate {
+    history: WeakView<ConversationHistory>,
+    workspace: WeakView<Workspace>,
+    conversations: Vec<SavedConversationMetadata>,
+    matches: Vec<ConversationMatch>,
+    selected_index: usize,
+}
+
+impl ConversationHistoryDelegate {
+    fn new(
+        history: WeakView<ConversationHistory>,
+        workspace: WeakView<Workspace>,
+        conversations: Vec<SavedConversationMetadata>,
+    ) -> Self {
+        Self {
+            history,
+            workspace,
+            conversations,
+            matches: Vec::new(),
+            selected_index: 0,
+        }
+    }
+}
+
+impl PickerDelegate for ConversationHistoryDelegate {
+    type ListItem = ListItem;
+
+    fn placeholder_text(&self, _cx: &mut WindowContext) -> Arc<str> {
+        "Search conversations (model:, provider:, after:, before:)...".into()
+    }
+
+    fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    fn confirm(&mut self, _: bool, cx: &mut ViewContext<Picker<Self>>) {
+        if let Some(mat) = self.matches.get(self.selected_index) {
+            let path = mat.path.clone();
+            self.workspace
+                .update(cx, |workspace, cx| {
+                    if let Some(panel) = workspace.focus_panel::<AssistantPanel>(cx) {
+                        panel.update(cx, |panel, cx| {
+                            panel.open_conversation(path, cx).detach_and_log_err(cx)
+                        });
+                    }
+                })
+                .log_err();
+        }
+        self.dismissed(cx);
+    }
+
+    fn dismissed(&mut self, cx: &mut ViewContext<Picker<Self>>) {
+        self.history
+            .update(cx, |_, cx| cx.emit(DismissEvent))
+            .log_err();
+    }
+
+    fn selected_index(&self) -> usize {
+        self.selected_index
+    }
+
+    fn set_selected_index(&mut self, ix: usize, _: &mut ViewContext<Picker<Self>>) {
+        self.selected_index = ix;
+    }
+
+    fn update_matches(&mut self, query: String, cx: &mut ViewContext<Picker<Self>>) -> Task<()> {
+        let query = ConversationQuery::parse(&query);
+        if query.is_empty() {
+            self.matches = self
+                .conversations
+                .iter()
+                .map(|conversation| ConversationMatch {
+                    path: conversation.path.clone(),
+                    title: conversation.title.clone(),
+                    updated_at: conversation.mtime,
+                    model: None,
+                    snippet: None,
+                })
+                .collect();
+            self.selected_index = 0;
+            cx.notify();
+            return Task::ready(());
+        }
+
+        let search = cx
+            .background_executor()
+            .spawn(async move { CONVERSATION_STORE.search(&query) });
+        cx.spawn(|this, mut cx| async move {
+            let matches = search
+                .await
+                .log_err()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|result| ConversationMatch {
+                    path: result.path,
+                    title: result.title,
+                    updated_at: result.updated_at,
+                    model: Some(format!("{} · {}", result.model, result.provider)),
+                    snippet: result.snippet,
+                })
+                .collect();
+
+            this.update(&mut cx, |this, cx| {
+                let delegate = &mut this.delegate;
+                delegate.matches = matches;
+                delegate.selected_index = delegate
+                    .selected_index
+                    .min(delegate.matches.len().saturating_sub(1));
+                cx.notify();
+            })
+            .log_err();
+        })
+    }
+
+    fn render_match(
+        &self,
+        ix: usize,
+        selected: bool,
+        _cx: &mut ViewContext<Picker<Self>>,
+    ) -> Option<Self::ListItem> {
+        let mat = &self.matches[ix];
+        Some(
+            ListItem::new(ix)
+                .inset(true)
+                .spacing(ListItemSpacing::Sparse)
+                .selected(selected)
+                .child(
+                    v_flex()
+                        .w_full()
+                        .child(
+                            h_flex()
+                                .w_full()
+                                .gap_2()
+                                .justify_between()
+                                .child(Label::new(mat.title.clone()))
+                                .child(
+                                    h_flex()
+                                        .gap_2()
+                                        .children(mat.model.clone().map(|model| {
+                                            Label::new(model)
+                                                .color(Color::Muted)
+                                                .size(LabelSize::Small)
+                                        }))
+                                        .child(
+                                            Label::new(
+                                                mat.updated_at.format("%F %I:%M%p").to_string(),
+                                            )
+                                            .color(Color::Muted)
+                                            .size(LabelSize::Small),
+                                        ),
+                                ),
+                        )
+                        .children(mat.snippet.clone().map(|(snippet, positions)| {
+                            HighlightedLabel::new(snippet, positions)
+                                .color(Color::Muted)
+                                .size(LabelSize::Small)
+                        })),
+                ),
+        )
+    }
+}
@@ -0,0 +1,449 @@
+use crate::{
+    assistant_settings::ProviderKind,
+    conversation_export::{ExportedConversation, ExportedMessage},
+    conversation_store::{IndexedConversation, CONVERSATION_STORE},
+    new_conversation_path, MessageId, MessageMetadata, MessageStatus, SavedConversation,
+    SavedMessage,
+};
+use ai::{chat::Role, completion::TokenUsage, models::ModelName};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use collections::HashMap;
+use fs::Fs;
+use serde::Deserialize;
+use serde_json::Value;
+use util::{paths::CONVERSATIONS_DIR, ResultExt};
+
+/// A conversation read from an export, converted to the format conversations
+/// are saved in.
+pub struct ImportedConversation {
+    pub title: String,
+    pub updated_at: DateTime<Local>,
+    pub conversation: SavedConversation,
+}
+
+/// Reads the conversations in `json`, which is either a conversation exported
+/// as JSON from the assistant, or ChatGPT's `conversations.json` export.
+///
+/// ChatGPT conversations are set up to continue with `model` at `api_url`, and
+/// each of their responses records the ChatGPT model that wrote it.
+pub fn import_conversations(
+    json: &str,
+    model: &ModelName,
+    api_url: &str,
+) -> Result<Vec<ImportedConversation>> {
+    let value = serde_json::from_str::<Value>(json).context("invalid JSON")?;
+    match value {
+        Value::Array(conversations) => {
+            let conversations = conversations
+                .into_iter()
+                .filter_map(|conversation| {
+                    serde_json::from_value::<ChatGptConversation>(conversation).log_err()
+                })
+                .map(|conversation| conversation.import(model, api_url))
+                .collect::<Vec<_>>();
+            if conversations.is_empty() {
+                Err(anyhow!("no conversations found"))
+            } else {
+                Ok(conversations)
+            }
+        }
+        Value::Object(ref object) if object.contains_key("mapping") => {
+            let conversation = serde_json::from_value::<ChatGptConversation>(value)?;
+            Ok(vec![conversation.import(model, api_url)])
+        }
+        value => {
+            let conversation = serde_json::from_value::<ExportedConversation>(value)
+                .context("not a conversation exported from Zed or ChatGPT")?;
+            Ok(vec![conversation.import()])
+        }
+    }
+}
+
+/// Saves an imported conversation alongside the others, and adds it to the
+/// search index.
+pub async fn save_imported_conversation(imported: ImportedConversation, fs: &dyn Fs) -> Result<()> {
+    fs.create_dir(&CONVERSATIONS_DIR).await?;
+    let path = new_conversation_path(&imported.title, fs).await;
+    fs.atomic_write(path.clone(), serde_json::to_string(&imported.conversation)?)
+        .await?;
+    CONVERSATION_STORE
+        .save_conversation(IndexedConversation::new(
+            path,
+            &imported.conversation,
+            imported.updated_at,
+        ))
+        .await
+        .log_err();
+    Ok(())
+}
+
+/// Lays out `messages` in a conversation's buffer, one after the other, ending
+/// with an empty user message to continue the conversation from.
+fn saved_conversation(
+    summary: String,
+    provider: ProviderKind,
+    model: ModelName,
+    api_url: Option<String>,
+    usage: TokenUsage,
+    temperature: Option<f32>,
+    mut messages: Vec<ExportedMessage>,
+) -> SavedConversation {
+    match messages.last() {
+        Some(message) if message.role == Role::User => {}
+        last_message => {
+            let sent_at = last_message.map_or_else(Local::now, |message| message.sent_at);
+            messages.push(ExportedMessage {
+                role: Role::User,
+                content: String::new(),
+                sent_at,
+                status: MessageStatus::Done,
+                seed: None,
+                answered_by: None,
+                pinned: false,
+                citations: Vec::new(),
+            });
+        }
+    }
+
+    let mut text = String::new();
+    let mut saved_messages = Vec::new();
+    let mut message_metadata = HashMap::default();
+    for (ix, message) in messages.into_iter().enumerate() {
+        if ix > 0 {
+            text.push('\n');
+        }
+        let id = MessageId(ix);
+        saved_messages.push(SavedMessage {
+            id,
+            start: text.len(),
+        });
+        text.push_str(&message.content);
+        message_metadata.insert(
+            id,
+            MessageMetadata {
+                role: message.role,
+                sent_at: message.sent_at,
+                status: message.status,
+                seed: message.seed,
+                answered_by: message.answered_by,
+                pinned: message.pinned,
+                citations: message.citations,
+            },
+        );
+    }
+
+    SavedConversation {
+        id: None,
+        zed: "conversation".into(),
+        version: SavedConversation::VERSION.into(),
+        text,
+        messages: saved_messages,
+        message_metadata,
+        summary,
+        provider,
+        api_url,
+        model,
+        usage,
+        temperature,
+        branches: Vec::new(),
+        active_branch: 0,
+    }
+}
+
+impl ExportedConversation {
+    fn import(self) -> ImportedConversation {
+        let updated_at = self
+            .messages
+            .iter()
+            .map(|message| message.sent_at)
+            .max()
+            .unwrap_or_else(Local::now);
+        ImportedConversation {
+            title: self.title.clone(),
+            updated_at,
+            conversation: saved_conversation(
+                self.title,
+                self.provider,
+                self.model,
+                self.api_url,
+                self.usage,
+                Some(self.parameters.temperature),
+                self.messages,
+            ),
+        }
+    }
+}
+
+/// A conversation in ChatGPT's export, which stores every version of each
+/// message as a tree.
+#[derive(Deserialize)]
+struct ChatGptConversation {
+    title: Option<String>,
+    create_time: Option<f64>,
+    update_time: Option<f64>,
+    mapping: HashMap<String, ChatGptNode>,
+    /// The last message of the version of the conversation that was shown.
+    current_node: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptNode {
+    message: Option<ChatGptMessage>,
+    parent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptMessage {
+    author: ChatGptAuthor,
+    content: ChatGptContent,
+    create_time: Option<f64>,
+    #[serde(default)]
+    metadata: Option<ChatGptMetadata>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptAuthor {
+    role: String,
+}
+
+#[derive(Deserialize)]
+struct ChatGptContent {
+    /// Text, or other content such as images that can't be imported.
+    #[serde(default)]
+    parts: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptMetadata {
+    model_slug: Option<String>,
+}
+
+fn chat_gpt_time(timestamp: Option<f64>) -> Option<DateTime<Local>> {
+    let timestamp = timestamp?;
+    let time = Utc
+        .timestamp_opt(timestamp.trunc() as i64, (timestamp.fract() * 1e9) as u32)
+        .single()?;
+    Some(time.with_timezone(&Local))
+}
+
+impl ChatGptConversation {
+    /// The messages of the conversation as it was last shown, oldest first.
+    fn current_messages(&self) -> Vec<&ChatGptMessage> {
+        let mut messages = Vec::new();
+        let mut node_id = self.current_node.as_ref();
+        // The path to the root can't be longer than the tree, which stops the walk
+        // if a malformed export's parents form a cycle.
+        for _ in 0..self.mapping.len() {
+            let Some(node) = node_id.and_then(|id| self.mapping.get(id)) else {
+                break;
+            };
+            messages.extend(&node.message);
+            node_id = node.parent.as_ref();
+        }
+        messages.reverse();
+        messages
+    }
+
+    fn import(self, model: &ModelName, api_url: &str) -> ImportedConversation {
+        let created_at = chat_gpt_time(self.create_time).unwrap_or_else(Local::now);
+        let messages = self
+            .current_messages()
+            .into_iter()
+            .filter_map(|message| {
+                let role = match message.author.role.as_str() {
+                    "user" => Role::User,
+                    "assistant" => Role::Assistant,
+                    "system" => Role::System,
+                    _ => return None,
+                };
+                let content = message
+                    .content
+                    .parts
+                    .iter()
+                    .filter_map(|part| part.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if content.trim().is_empty() {
+                    return None;
+                }
+                let answered_by = message
+                    .metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata.model_slug.as_ref())
+                    .filter(|_| role == Role::Assistant)
+                    .map(|model_slug| format!("ChatGPT ({model_slug})").into());
+                Some(ExportedMessage {
+                    role,
+                    content: content.trim_end().to_string(),
+                    sent_at: chat_gpt_time(message.create_time).unwrap_or(created_at),
+                    status: MessageStatus::Done,
+                    seed: None,
+                    answered_by,
+                    pinned: false,
+                    citations: Vec::new(),
+                })
+            })
+            .collect();
+
+        let title = self
+            .title
+            .clone()
+            .filter(|title| !title.trim().is_empty())
+            .unwrap_or_else(|| "Imported Conversation".into());
+        ImportedConversation {
+            title: title.clone(),
+            updated_at: chat_gpt_time(self.update_time).unwrap_or(created_at),
+            conversation: saved_conversation(
+                title,
+                ProviderKind::OpenAi,
+                model.clone(),
+                Some(api_url.to_string()),
+                TokenUsage::default(),
+                None,
+                messages,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn messages(conversation: &SavedConversation) -> Vec<(Role, &str)> {
+        let mut messages = Vec::new();
+        for (ix, message) in conversation.messages.iter().enumerate() {
+            let end = conversation
+                .messages
+                .get(ix + 1)
+                .map_or(conversation.text.len(), |next| next.start - 1);
+            let role = conversation.message_metadata[&message.id].role;
+            messages.push((role, &conversation.text[message.start..end]));
+        }
+        messages
+    }
+
+    #[test]
+    fn test_import_chat_gpt_conversations() {
+        let export = json!([{
+            "title": "Rust/Python comparison",
+            "create_time": 1700000000.5,
+            "update_time": 1700000100.0,
+            "current_node": "c",
+            "mapping": {
+                "root": {"id": "root", "message": null, "parent": null, "children": ["s"]},
+                "s": {
+                    "id": "s",
+                    "message": {
+                        "author": {"role": "system"},
+                        "content": {"content_type": "text", "parts": [""]},
+                        "create_time": null
+                    },
+                    "parent": "root",
+                    "children": ["a"]
+                },
+                "a": {
+                    "id": "a",
+                    "message": {
+                        "author": {"role": "user"},
+                        "content": {"content_type": "text", "parts": ["Is Rust faster than Python?"]},
+                        "create_time": 1700000001.0
+                    },
+                    "parent": "s",
+                    "children": ["b", "c"]
+                },
+                "b": {
+                    "id": "b",
+                    "message": {
+                        "author": {"role": "assistant"},
+                        "content": {"content_type": "text", "parts": ["An abandoned answer."]},
+                        "create_time": 1700000002.0,
+                        "metadata": {"model_slug": "gpt-4"}
+                    },
+                    "parent": "a",
+                    "children": []
+                },
+                "c": {
+                    "id": "c",
+                    "message": {
+                        "author": {"role": "assistant"},
+                        "content": {"content_type": "text", "parts": ["Usually, yes.\n"]},
+                        "create_time": 1700000003.0,
+                        "metadata": {"model_slug": "gpt-4"}
+                    },
+                    "parent": "a",
+                    "children": []
+                }
+            }
+        }]);
+
+        let model = ModelName::new("codellama/CodeLlama-7b-Instruct-hf");
+        let imported =
+            import_conversations(&export.to_string(), &model, "http://localhost:8000/v1").unwrap();
+        assert_eq!(imported.len(), 1);
+        let imported = &imported[0];
+        assert_eq!(imported.title, "Rust/Python comparison");
+        assert_eq!(imported.updated_at.timestamp(), 1700000100);
+
+        let conversation = &imported.conversation;
+        assert_eq!(conversation.model, model);
+        assert_eq!(
+            conversation.api_url.as_deref(),
+            Some("http://localhost:8000/v1")
+        );
+        assert_eq!(
+            messages(conversation),
+            [
+                (Role::User, "Is Rust faster than Python?"),
+                (Role::Assistant, "Usually, yes."),
+                (Role::User, ""),
+            ]
+        );
+        let answer = &conversation.message_metadata[&conversation.messages[1].id];
+        assert_eq!(answer.answered_by, Some("ChatGPT (gpt-4)".into()));
+    }
+
+    #[test]
+    fn test_import_exported_conversation() {
+        let export = json!({
+            "version": "0.1.0",
+            "title": "Sorting",
+            "model": "gpt-4",
+            "api_url": null,
+            "parameters": {"temperature": 1.0},
+            "usage": {"prompt_tokens": 10, "completion_tokens": 20, "total_tokens": 30},
+            "messages": [
+                {"role": "user", "content": "How do I sort?", "sent_at": "2024-03-01T10:00:00+00:00", "status": "Done"},
+                {"role": "assistant", "content": "Call `sort`.", "sent_at": "2024-03-01T10:00:05+00:00", "status": "Done", "seed": 42},
+                {"role": "user", "content": "", "sent_at": "2024-03-01T10:00:05+00:00", "status": "Done"}
+            ]
+        });
+
+        let imported = import_conversations(
+            &export.to_string(),
+            &ModelName::new("codellama"),
+            "http://localhost:8000/v1",
+        )
+        .unwrap();
+        let conversation = &imported[0].conversation;
+        assert_eq!(conversation.model, ModelName::new("gpt-4"));
+        assert_eq!(conversation.provider, ProviderKind::OpenAi);
+        assert_eq!(conversation.api_url, None);
+        assert_eq!(conversation.usage.total_tokens, 30);
+        assert_eq!(conversation.text, "How do I sort?\nCall `sort`.\n");
+        assert_eq!(
+            messages(conversation),
+            [
+                (Role::User, "How do I sort?"),
+                (Role::Assistant, "Call `sort`."),
+                (Role::User, ""),
+            ]
+        );
+        let answer = &conversation.message_metadata[&conversation.messages[1].id];
+        assert_eq!(answer.seed, Some(42));
+
+        assert!(import_conversations("{}", &ModelName::new("gpt-4"), "").is_err());
+    }
+}
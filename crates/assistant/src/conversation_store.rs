@@ -0,0 +1,437 @@
+use crate::{conversation_title, SavedConversation};
+use ai::providers::open_ai::OPEN_AI_API_URL;
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+use db::{define_connection, query, sqlez_macros::sql};
+use fs::Fs;
+use std::path::{Path, PathBuf};
+use util::{http::Uri, ResultExt};
+
+/// Wrapped around the matched terms in a result's snippet, to highlight them.
+const SNIPPET_MATCH_START: char = '«';
+const SNIPPET_MATCH_END: char = '»';
+
+define_connection!(pub static ref CONVERSATION_STORE: ConversationStore<()> =
+    &[sql!(
+        CREATE TABLE conversations(
+            path BLOB PRIMARY KEY,
+            title TEXT NOT NULL,
+            model TEXT NOT NULL,
+            provider TEXT NOT NULL,
+            updated_at INTEGER NOT NULL
+        ) STRICT;
+
+        CREATE VIRTUAL TABLE conversations_fts USING fts5(
+            path UNINDEXED,
+            title,
+            content
+        );
+    )];
+);
+
+/// A saved conversation as it's indexed for search.
+pub struct IndexedConversation {
+    pub path: PathBuf,
+    pub title: String,
+    pub model: String,
+    /// The host of the API the conversation was sent to.
+    pub provider: String,
+    pub updated_at: DateTime<Local>,
+    pub content: String,
+}
+
+impl IndexedConversation {
+    pub fn new(
+        path: PathBuf,
+        conversation: &SavedConversation,
+        updated_at: DateTime<Local>,
+    ) -> Self {
+        let title = conversation_title(&path).unwrap_or_else(|| conversation.summary.clone());
+        Self {
+            path,
+            title,
+            model: conversation.model.full_name().to_string(),
+            provider: provider_name(conversation.api_url.as_deref()),
+            updated_at,
+            content: conversation.text.clone(),
+        }
+    }
+}
+
+/// The name conversations sent to `api_url` are filtered by: its host, such as
+/// `api.openai.com` or `localhost:8000`.
+fn provider_name(api_url: Option<&str>) -> String {
+    let api_url = api_url.unwrap_or(OPEN_AI_API_URL);
+    api_url
+        .parse::<Uri>()
+        .ok()
+        .and_then(|uri| uri.authority().map(|authority| authority.to_string()))
+        .unwrap_or_else(|| api_url.to_string())
+}
+
+/// A search of the saved conversations, parsed from what's typed in the
+/// conversation history. Words are matched against the titles and contents of
+/// conversations, and `model:`, `provider:`, `after:` and `before:` filter them.
+#[derive(Debug, Default, PartialEq)]
+pub struct ConversationQuery {
+    pub terms: Vec<String>,
+    pub model: Option<String>,
+    pub provider: Option<String>,
+    /// Only conversations updated on or after this day.
+    pub after: Option<NaiveDate>,
+    /// Only conversations updated before this day.
+    pub before: Option<NaiveDate>,
+}
+
+impl ConversationQuery {
+    pub fn parse(query: &str) -> Self {
+        let mut this = Self::default();
+        for word in query.split_whitespace() {
+            let filter = word.split_once(':').filter(|(_, value)| !value.is_empty());
+            match filter {
+                Some(("model", model)) => this.model = Some(model.to_string()),
+                Some(("provider", provider)) => this.provider = Some(provider.to_string()),
+                Some(("after", date)) => match NaiveDate::parse_from_str(date, "%F") {
+                    Ok(date) => this.after = Some(date),
+                    Err(_) => this.terms.push(word.to_string()),
+                },
+                Some(("before", date)) => match NaiveDate::parse_from_str(date, "%F") {
+                    Ok(date) => this.before = Some(date),
+                    Err(_) => this.terms.push(word.to_string()),
+                },
+                _ => this.terms.push(word.to_string()),
+            }
+        }
+        this
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// The terms as an FTS5 query that matches conversations containing all of
+    /// them, treating the last one as a prefix since it may still be being typed.
+    fn match_expression(&self) -> Option<String> {
+        let (last, rest) = self.terms.split_last()?;
+        let quote = |term: &str| format!("\"{}\"", term.replace('"', "\"\""));
+        let mut expression = rest
+            .iter()
+            .map(|term| quote(term) + " ")
+            .collect::<String>();
+        expression.push_str(&quote(last));
+        expression.push('*');
+        Some(expression)
+    }
+}
+
+/// A saved conversation that matched a search.
+pub struct ConversationSearchResult {
+    pub path: PathBuf,
+    pub title: String,
+    pub model: String,
+    pub provider: String,
+    pub updated_at: DateTime<Local>,
+    /// The part of the conversation the terms were found in, if it was searched
+    /// by content, along with the byte positions of the matched characters.
+    pub snippet: Option<(String, Vec<usize>)>,
+}
+
+/// Removes the markers around the matched terms in a snippet from FTS5, and
+/// returns the positions of the characters that were between them.
+fn highlight_snippet(snippet: &str) -> (String, Vec<usize>) {
+    let mut text = String::with_capacity(snippet.len());
+    let mut positions = Vec::new();
+    let mut in_match = false;
+    for character in snippet.chars() {
+        match character {
+            SNIPPET_MATCH_START => in_match = true,
+            SNIPPET_MATCH_END => in_match = false,
+            _ => {
+                if in_match {
+                    positions.push(text.len());
+                }
+                text.push(if character == '\n' { ' ' } else { character });
+            }
+        }
+    }
+    (text, positions)
+}
+
+fn start_of_day(date: NaiveDate) -> Option<i64> {
+    Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()
+        .map(|date| date.timestamp())
+}
+
+fn local_time(timestamp: i64) -> DateTime<Local> {
+    Utc.timestamp_opt(timestamp, 0)
+        .single()
+        .unwrap_or_default()
+        .with_timezone(&Local)
+}
+
+impl ConversationStore {
+    pub async fn save_conversation(&self, conversation: IndexedConversation) -> Result<()> {
+        self.write(move |conn| {
+            conn.with_savepoint("save_conversation", || {
+                conn.exec_bound(sql!(
+                    DELETE FROM conversations_fts WHERE path = ?
+                ))?(conversation.path.as_path())?;
+                conn.exec_bound(sql!(
+                    INSERT OR REPLACE INTO conversations(path, title, model, provider, updated_at)
+                    VALUES (?, ?, ?, ?, ?)
+                ))?((
+                    conversation.path.as_path(),
+                    conversation.title.as_str(),
+                    conversation.model.as_str(),
+                    conversation.provider.as_str(),
+                    conversation.updated_at.timestamp(),
+                ))?;
+                conn.exec_bound(sql!(
+                    INSERT INTO conversations_fts(path, title, content) VALUES (?, ?, ?)
+                ))?((
+                    conversation.path.as_path(),
+                    conversation.title.as_str(),
+                    conversation.content.as_str(),
+                ))
+            })
+        })
+        .await
+    }
+
+    query! {
+        pub async fn delete_conversation(path: PathBuf) -> Result<()> {
+            DELETE FROM conversations WHERE path = ?1;
+            DELETE FROM conversations_fts WHERE path = ?1;
+        }
+    }
+
+    query! {
+        fn indexed_conversations() -> Result<Vec<PathBuf>> {
+            SELECT path FROM conversations
+        }
+    }
+
+    /// The saved conversations matching `query`, best matches first, or most
+    /// recently updated first if it only has filters.
+    pub fn search(&self, query: &ConversationQuery) -> Result<Vec<ConversationSearchResult>> {
+        let model = query.model.clone();
+        let provider = query.provider.clone();
+        let after = query.after.and_then(start_of_day);
+        let before = query.before.and_then(start_of_day);
+
+        let results = if let Some(expression) = query.match_expression() {
+            self.select_bound::<_, (PathBuf, String, String, String, i64, String)>(sql!(
+                SELECT
+                    conversations.path,
+                    conversations.title,
+                    conversations.model,
+                    conversations.provider,
+                    conversations.updated_at,
+                    snippet(conversations_fts, -1, '«', '»', '…', 24)
+                FROM conversations_fts
+                JOIN conversations ON conversations.path = conversations_fts.path
+                WHERE conversations_fts MATCH ?1
+                    AND (?2 IS NULL OR conversations.model LIKE '%' || ?2 || '%')
+                    AND (?3 IS NULL OR conversations.provider LIKE '%' || ?3 || '%')
+                    AND (?4 IS NULL OR conversations.updated_at >= ?4)
+                    AND (?5 IS NULL OR conversations.updated_at < ?5)
+                ORDER BY rank
+                LIMIT 50
+            ))?((expression, model, provider, after, before))?
+            .into_iter()
+            .map(
+                |(path, title, model, provider, updated_at, snippet)| ConversationSearchResult {
+                    path,
+                    title,
+                    model,
+                    provider,
+                    updated_at: local_time(updated_at),
+                    snippet: Some(highlight_snippet(&snippet)),
+                },
+            )
+            .collect()
+        } else {
+            self.select_bound::<_, (PathBuf, String, String, String, i64)>(sql!(
+                SELECT path, title, model, provider, updated_at
+                FROM conversations
+                WHERE (?1 IS NULL OR model LIKE '%' || ?1 || '%')
+                    AND (?2 IS NULL OR provider LIKE '%' || ?2 || '%')
+                    AND (?3 IS NULL OR updated_at >= ?3)
+                    AND (?4 IS NULL OR updated_at < ?4)
+                ORDER BY updated_at DESC
+                LIMIT 50
+            ))?((model, provider, after, before))?
+            .into_iter()
+            .map(
+                |(path, title, model, provider, updated_at)| ConversationSearchResult {
+                    path,
+                    title,
+                    model,
+                    provider,
+                    updated_at: local_time(updated_at),
+                    snippet: None,
+                },
+            )
+            .collect()
+        };
+        Ok(results)
+    }
+
+    /// Indexes the saved conversations that aren't yet, such as ones saved before
+    /// the index existed, and drops ones that were deleted outside of Zed. The
+    /// rest are reindexed whenever they're saved, and keep their update time
+    /// rather than their file's, which is newer for imported conversations.
+    pub async fn reindex(&self, saved_conversations: Vec<(PathBuf, DateTime<Local>)>, fs: &dyn Fs) {
+        let Some(indexed) = self.indexed_conversations().log_err() else {
+            return;
+        };
+
+        for path in &indexed {
+            if !saved_conversations
+                .iter()
+                .any(|(saved_path, _)| saved_path == path)
+            {
+                self.delete_conversation(path.clone()).await.log_err();
+            }
+        }
+
+        for (path, mtime) in saved_conversations {
+            if indexed.contains(&path) {
+                continue;
+            }
+            let Some(conversation) = load_conversation(&path, fs).await.log_err() else {
+                continue;
+            };
+            self.save_conversation(IndexedConversation::new(path, &conversation, mtime))
+                .await
+                .log_err();
+        }
+    }
+}
+
+async fn load_conversation(path: &Path, fs: &dyn Fs) -> Result<SavedConversation> {
+    let text = fs.load(path).await?;
+    Ok(serde_json::from_str(&text)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ai::models::ModelName;
+
+    #[test]
+    fn test_parse_conversation_query() {
+        assert_eq!(
+            ConversationQuery::parse("borrow checker model:gpt-4 after:2024-03-01"),
+            ConversationQuery {
+                terms: vec!["borrow".into(), "checker".into()],
+                model: Some("gpt-4".into()),
+                after: NaiveDate::from_ymd_opt(2024, 3, 1),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            ConversationQuery::parse("provider:localhost before:yesterday http://"),
+            ConversationQuery {
+                terms: vec!["before:yesterday".into(), "http://".into()],
+                provider: Some("localhost".into()),
+                ..Default::default()
+            }
+        );
+        assert_eq!(ConversationQuery::parse("  model: ").terms, ["model:"]);
+        assert!(ConversationQuery::parse(" ").is_empty());
+
+        assert_eq!(
+            ConversationQuery::parse(r#"say "hi"#).match_expression(),
+            Some(r#""say" """hi"*"#.to_string())
+        );
+    }
+
+    #[test]
+    fn test_highlight_snippet() {
+        assert_eq!(
+            highlight_snippet("…the «borrow» checker\nsays…"),
+            (
+                "…the borrow checker says…".to_string(),
+                vec![7, 8, 9, 10, 11, 12]
+            )
+        );
+    }
+
+    #[gpui::test]
+    async fn test_search_conversations() {
+        let store = ConversationStore(db::open_test_db("test_search_conversations").await);
+        let conversation =
+            |title: &str, model: &str, api_url: Option<&str>, text: &str| SavedConversation {
+                id: None,
+                zed: "conversation".into(),
+                version: SavedConversation::VERSION.into(),
+                text: text.into(),
+                messages: Vec::new(),
+                message_metadata: Default::default(),
+                summary: title.into(),
+                provider: Default::default(),
+                api_url: api_url.map(Into::into),
+                model: ModelName::new(model),
+                usage: Default::default(),
+                temperature: None,
+                branches: Vec::new(),
+                active_branch: 0,
+            };
+        let march = Local.with_ymd_and_hms(2024, 3, 10, 12, 0, 0).unwrap();
+        let april = Local.with_ymd_and_hms(2024, 4, 10, 12, 0, 0).unwrap();
+        for (title, model, api_url, text, updated_at) in [
+            (
+                "Lifetimes",
+                "gpt-4",
+                None,
+                "Why does the borrow checker reject this?",
+                march,
+            ),
+            (
+                "Sorting",
+                "codellama",
+                Some("http://localhost:8000/v1"),
+                "How do I sort a vector of structs?",
+                april,
+            ),
+        ] {
+            let path = PathBuf::from(format!("/conversations/{title} - 1.zed.json"));
+            let conversation = conversation(title, model, api_url, text);
+            store
+                .save_conversation(IndexedConversation::new(path, &conversation, updated_at))
+                .await
+                .unwrap();
+        }
+
+        let titles = |query: &str| {
+            store
+                .search(&ConversationQuery::parse(query))
+                .unwrap()
+                .into_iter()
+                .map(|result| result.title)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles("borrow chec"), ["Lifetimes"]);
+        assert_eq!(titles("sort"), ["Sorting"]);
+        assert_eq!(titles("model:gpt"), ["Lifetimes"]);
+        assert_eq!(titles("provider:localhost"), ["Sorting"]);
+        assert_eq!(titles("after:2024-04-01"), ["Sorting"]);
+        assert_eq!(titles("before:2024-04-01"), ["Lifetimes"]);
+        assert_eq!(titles("provider:openai"), ["Lifetimes"]);
+        assert!(titles("vector model:gpt").is_empty());
+
+        let results = store.search(&ConversationQuery::parse("borrow")).unwrap();
+        let (snippet, positions) = results[0].snippet.clone().unwrap();
+        assert_eq!(snippet, "Why does the borrow checker reject this?");
+        assert_eq!(positions.len(), "borrow".len());
+
+        store
+            .delete_conversation(PathBuf::from("/conversations/Lifetimes - 1.zed.json"))
+            .await
+            .unwrap();
+        assert!(titles("borrow").is_empty());
+    }
+}
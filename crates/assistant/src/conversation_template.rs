@@ -0,0 +1,122 @@
+use ai::{chat::Role, models::ModelName};
+use anyhow::{Context, Result};
+use fs::Fs;
+use futures::StreamExt;
+use serde::Deserialize;
+use std::{ffi::OsStr, sync::Arc};
+use util::{paths::CONVERSATION_TEMPLATES_DIR, ResultExt};
+
+/// A starting point for new conversations, stored as a JSON file in the
+/// templates directory of the prompt library.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ConversationTemplate {
+    /// The name the template is listed under, which defaults to its file's name.
+    #[serde(default)]
+    pub name: String,
+    /// The text of a system message the conversation starts with.
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// The messages that follow the system prompt.
+    #[serde(default)]
+    pub messages: Vec<TemplateMessage>,
+    /// The model to use instead of the default one.
+    #[serde(default)]
+    pub model: Option<ModelName>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TemplateMessage {
+    pub role: Role,
+    #[serde(default)]
+    pub content: String,
+}
+
+impl ConversationTemplate {
+    pub fn parse(json: &str, default_name: &str) -> Result<Self> {
+        let mut template = serde_json::from_str::<Self>(json)?;
+        if template.name.trim().is_empty() {
+            template.name = default_name.to_string();
+        }
+        Ok(template)
+    }
+
+    /// The messages a conversation created from the template starts with, the
+    /// system prompt first.
+    pub fn initial_messages(&self) -> Vec<TemplateMessage> {
+        self.system_prompt
+            .iter()
+            .map(|system_prompt| TemplateMessage {
+                role: Role::System,
+                content: system_prompt.clone(),
+            })
+            .chain(self.messages.iter().cloned())
+            .collect()
+    }
+}
+
+/// The templates in the templates directory, ordered by name. Files that can't
+/// be parsed are logged and skipped.
+pub async fn load_conversation_templates(fs: Arc<dyn Fs>) -> Result<Vec<ConversationTemplate>> {
+    fs.create_dir(&CONVERSATION_TEMPLATES_DIR).await?;
+
+    let mut templates = Vec::new();
+    let mut paths = fs.read_dir(&CONVERSATION_TEMPLATES_DIR).await?;
+    while let Some(path) = paths.next().await {
+        let path = path?;
+        if path.extension() != Some(OsStr::new("json")) {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(OsStr::to_str) else {
+            continue;
+        };
+        let template = async {
+            let json = fs.load(&path).await?;
+            ConversationTemplate::parse(&json, name)
+                .with_context(|| format!("invalid conversation template {path:?}"))
+        };
+        templates.extend(template.await.log_err());
+    }
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(templates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_conversation_template() {
+        let template = ConversationTemplate::parse(
+            r#"{
+                "system_prompt": "You review Rust code.",
+                "messages": [{ "role": "user", "content": "Review this:\n" }],
+                "model": "gpt-4",
+                "temperature": 0.2
+            }"#,
+            "code-review",
+        )
+        .unwrap();
+        assert_eq!(template.name, "code-review");
+        assert_eq!(template.model, Some(ModelName::new("gpt-4")));
+        assert_eq!(template.temperature, Some(0.2));
+
+        let messages = template.initial_messages();
+        assert_eq!(
+            messages
+                .iter()
+                .map(|message| (message.role, message.content.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (Role::System, "You review Rust code."),
+                (Role::User, "Review this:\n"),
+            ]
+        );
+
+        let template = ConversationTemplate::parse(r#"{ "name": "Empty" }"#, "empty").unwrap();
+        assert_eq!(template.name, "Empty");
+        assert!(template.initial_messages().is_empty());
+    }
+}
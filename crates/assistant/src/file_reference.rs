@@ -0,0 +1,106 @@
+use gpui::AppContext;
+use project::{Project, ProjectPath};
+use regex::Regex;
+use std::{
+    ops::Range,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+/// A reference to a file, or to lines of it when written as `path:line` or
+/// `path:first-last`, such as the ones heading quoted code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileReference {
+    /// Where the reference is in the text it was found in.
+    pub range: Range<usize>,
+    pub path: String,
+    /// The referenced rows, zero-based and inclusive of the last one.
+    pub rows: Option<Range<u32>>,
+}
+
+/// The reference to `rows` of `path`, in the format [`file_references`] finds.
+pub fn format_file_reference(path: &str, rows: Range<u32>) -> String {
+    if rows.start == rows.end {
+        format!("{path}:{}", rows.start + 1)
+    } else {
+        format!("{path}:{}-{}", rows.start + 1, rows.end + 1)
+    }
+}
+
+/// The file references in `text`. Paths need an extension so that things like
+/// times and ports aren't mistaken for them, but can still match text that
+/// isn't a path, so check that they exist before using them.
+pub fn file_references(text: &str) -> impl Iterator<Item = FileReference> + '_ {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = REGEX.get_or_init(|| {
+        Regex::new(
+            r"(?m)(?:^|[\s`'(\[])((?:[\w.\-]+/)*[\w\-]+(?:\.[\w\-]+)+)(?::(\d+)(?:-(\d+))?)?\b",
+        )
+        .unwrap()
+    });
+    regex.captures_iter(text).filter_map(|captures| {
+        let path = captures.get(1)?;
+        let line = |ix: usize| {
+            let line = captures.get(ix)?.as_str().parse::<u32>().ok()?;
+            line.checked_sub(1)
+        };
+        let rows = line(2).map(|first_row| first_row..line(3).unwrap_or(0).max(first_row));
+        Some(FileReference {
+            range: path.start()..captures.get(0)?.end(),
+            path: path.as_str().to_string(),
+            rows,
+        })
+    })
+}
+
+/// The file in one of the project's worktrees at `path`, which is relative to
+/// the worktree and starts with its name when there's more than one.
+pub fn project_path_for_reference(
+    project: &Project,
+    path: &str,
+    cx: &AppContext,
+) -> Option<ProjectPath> {
+    let path = Path::new(path);
+    project.visible_worktrees(cx).find_map(|worktree| {
+        let worktree = worktree.read(cx);
+        let relative_path = path.strip_prefix(worktree.root_name()).unwrap_or(path);
+        let project_path = ProjectPath {
+            worktree_id: worktree.id(),
+            path: Arc::from(relative_path),
+        };
+        project
+            .entry_for_path(&project_path, cx)
+            .filter(|entry| entry.is_file())
+            .map(|_| project_path)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_references() {
+        let text = "```rust src/main.rs:3-5\nfn main() {}\n```\n\
+                    See `crates/ai/src/ai.rs:10` and README.md, not 12:30 or localhost:8080.";
+        let references = file_references(text).collect::<Vec<_>>();
+        assert_eq!(
+            references
+                .iter()
+                .map(|reference| (&text[reference.range.clone()], reference.rows.clone()))
+                .collect::<Vec<_>>(),
+            [
+                ("src/main.rs:3-5", Some(2..4)),
+                ("crates/ai/src/ai.rs:10", Some(9..9)),
+                ("README.md", None),
+            ]
+        );
+        assert_eq!(references[1].path, "crates/ai/src/ai.rs");
+
+        assert_eq!(
+            format_file_reference("src/main.rs", 2..4),
+            "src/main.rs:3-5"
+        );
+        assert_eq!(format_file_reference("src/main.rs", 9..9), "src/main.rs:10");
+    }
+}
@@ -0,0 +1,312 @@
+use crate::assistant_settings::{InlineCompletionSettings, ModelRoute, ProviderKind};
+use ai::{
+    completion::{text_only, CachedCompletion, CompletionCache, SamplingParams},
+    providers::vllm::VllmCompletionProvider,
+};
+use anyhow::Result;
+use copilot::Completion;
+use editor::InlineCompletionProvider;
+use futures::TryStreamExt;
+use gpui::{AppContext, BackgroundExecutor, Model, Task};
+use language::{Anchor, Buffer, Point, ToPoint};
+use parking_lot::Mutex;
+use serde_json::json;
+use std::{cmp, sync::Arc};
+use util::http::HttpClient;
+use uuid::Uuid;
+
+/// How many lines before the cursor are sent as the prefix of a completion.
+const PREFIX_LINES: u32 = 64;
+/// How many lines after the cursor are sent as the suffix of a completion.
+const SUFFIX_LINES: u32 = 16;
+/// How many suggestions are remembered, keyed on the text around the cursor.
+const CACHE_CAPACITY: usize = 256;
+
+/// The most recent suggestion the model made, so that typing it out is served
+/// from what's left of it rather than asking the model again.
+struct LastSuggestion {
+    prefix: String,
+    suffix: String,
+    text: String,
+}
+
+/// A code model that suggestions can be requested from.
+struct CodeModel {
+    provider: VllmCompletionProvider,
+    api_url: String,
+    model: String,
+}
+
+impl CodeModel {
+    fn new(
+        api_url: String,
+        model: String,
+        http_client: Arc<dyn HttpClient>,
+        executor: BackgroundExecutor,
+    ) -> Self {
+        let provider =
+            VllmCompletionProvider::new(api_url.clone(), model.clone(), http_client, executor);
+        Self {
+            provider,
+            api_url,
+            model,
+        }
+    }
+}
+
+/// Suggests code completions as ghost text using a fill-in-the-middle model
+/// served by vLLM or llama.cpp, for when Copilot isn't signed in.
+pub struct LocalInlineCompletionProvider {
+    default_model: CodeModel,
+    /// The routing rules for vLLM models, and the models they route to.
+    routes: Vec<(ModelRoute, CodeModel)>,
+    settings: InlineCompletionSettings,
+    cache: Arc<CompletionCache>,
+    last_suggestion: Arc<Mutex<Option<LastSuggestion>>>,
+}
+
+impl LocalInlineCompletionProvider {
+    /// Creates a provider suggesting completions from the model in `settings`,
+    /// or from the model a rule in `routes` picks for the file being edited.
+    pub fn new(
+        settings: InlineCompletionSettings,
+        routes: &[ModelRoute],
+        http_client: Arc<dyn HttpClient>,
+        executor: BackgroundExecutor,
+    ) -> Self {
+        let default_model = CodeModel::new(
+            settings.api_url.clone(),
+            settings.model.clone(),
+            http_client.clone(),
+            executor.clone(),
+        );
+        let routes = routes
+            .iter()
+            .filter(|route| route.provider == ProviderKind::Vllm)
+            .map(|route| {
+                let model = CodeModel::new(
+                    route
+                        .api_url
+                        .clone()
+                        .unwrap_or_else(|| settings.api_url.clone()),
+                    route.model.full_name().to_string(),
+                    http_client.clone(),
+                    executor.clone(),
+                );
+                (route.clone(), model)
+            })
+            .collect();
+        Self {
+            default_model,
+            routes,
+            settings,
+            cache: Arc::new(CompletionCache::new(CACHE_CAPACITY)),
+            last_suggestion: Default::default(),
+        }
+    }
+
+    /// The model to suggest completions from at `position` in `buffer`.
+    fn code_model(&self, buffer: &Buffer, position: Anchor) -> &CodeModel {
+        let language = buffer.language_at(position);
+        let language = language.as_ref().map(|language| language.name());
+        let path = buffer.file().map(|file| &**file.path());
+        self.routes
+            .iter()
+            .find(|(route, _)| route.matches(language.as_deref(), path))
+            .map_or(&self.default_model, |(_, model)| model)
+    }
+
+    fn cache_key(&self, model: &CodeModel, prefix: &str, suffix: &str) -> String {
+        CompletionCache::key(
+            &model.api_url,
+            &model.model,
+            &json!({ "prefix": prefix, "suffix": suffix }),
+        )
+    }
+
+    /// A suggestion for the cursor that doesn't need a request: either one made
+    /// earlier for the same surrounding text, or the rest of the last suggestion
+    /// if the user has been typing it out. An empty suggestion means the model
+    /// had nothing to add here.
+    fn cached_suggestion(&self, model: &CodeModel, prefix: &str, suffix: &str) -> Option<String> {
+        if let Some(completion) = self.cache.get(&self.cache_key(model, prefix, suffix)) {
+            return Some(completion.text);
+        }
+
+        let last_suggestion = self.last_suggestion.lock();
+        let last_suggestion = last_suggestion.as_ref()?;
+        if last_suggestion.suffix != suffix {
+            return None;
+        }
+        let typed = prefix.strip_prefix(last_suggestion.prefix.as_str())?;
+        let remaining = last_suggestion.text.strip_prefix(typed)?;
+        (!remaining.is_empty()).then(|| remaining.to_string())
+    }
+}
+
+fn suggestion(position: Anchor, text: String) -> Vec<Completion> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    vec![Completion {
+        uuid: Uuid::new_v4().to_string(),
+        range: position..position,
+        text,
+    }]
+}
+
+impl InlineCompletionProvider for LocalInlineCompletionProvider {
+    fn is_enabled(&self, buffer: &Model<Buffer>, position: Anchor, cx: &AppContext) -> bool {
+        let buffer = buffer.read(cx);
+        if !self.code_model(buffer, position).provider.supports_fim() {
+            return false;
+        }
+
+        let Some(language) = buffer.language_at(position) else {
+            return true;
+        };
+        !self
+            .settings
+            .disabled_languages
+            .iter()
+            .any(|name| name.as_str() == language.name().as_ref())
+    }
+
+    fn completions(
+        &self,
+        buffer: &Model<Buffer>,
+        position: Anchor,
+        cx: &mut AppContext,
+    ) -> Task<Result<Vec<Completion>>> {
+        let model = self.code_model(buffer.read(cx), position);
+        let snapshot = buffer.read(cx).snapshot();
+        let cursor = position.to_point(&snapshot);
+        let prefix_start = Point::new(cursor.row.saturating_sub(PREFIX_LINES), 0);
+        let suffix_end = cmp::min(
+            Point::new(cursor.row + SUFFIX_LINES, 0),
+            snapshot.max_point(),
+        );
+        let prefix = snapshot
+            .text_for_range(prefix_start..cursor)
+            .collect::<String>();
+        let suffix = snapshot
+            .text_for_range(cursor..suffix_end)
+            .collect::<String>();
+
+        // In the middle of a line, only suggest the rest of that line.
+        let line_end = Point::new(cursor.row, snapshot.line_len(cursor.row));
+        let single_line = snapshot
+            .text_for_range(cursor..line_end)
+            .any(|chunk| !chunk.trim().is_empty());
+
+        if let Some(text) = self.cached_suggestion(model, &prefix, &suffix) {
+            return Task::ready(Ok(suggestion(position, text)));
+        }
+
+        let sampling = SamplingParams {
+            max_tokens: Some(self.settings.max_tokens),
+            ..Default::default()
+        };
+        let events = model.provider.complete_fim(&prefix, &suffix, &sampling);
+        let key = self.cache_key(model, &prefix, &suffix);
+        let cache = self.cache.clone();
+        let last_suggestion = self.last_suggestion.clone();
+        cx.background_executor().spawn(async move {
+            let mut text = text_only(events.await?).try_collect::<String>().await?;
+            if single_line {
+                if let Some(newline) = text.find('\n') {
+                    text.truncate(newline);
+                }
+            }
+            text.truncate(text.trim_end().len());
+
+            cache.insert(
+                key,
+                CachedCompletion {
+                    text: text.clone(),
+                    finish_reason: None,
+                },
+            );
+            if !text.is_empty() {
+                *last_suggestion.lock() = Some(LastSuggestion {
+                    prefix,
+                    suffix,
+                    text: text.clone(),
+                });
+            }
+            Ok(suggestion(position, text))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::{Context, TestAppContext};
+    use language::BufferId;
+    use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+    use util::http::{AsyncBody, FakeHttpClient, Response};
+
+    #[gpui::test]
+    async fn test_local_inline_completions(cx: &mut TestAppContext) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let client = FakeHttpClient::create({
+            let requests = requests.clone();
+            move |_| {
+                requests.fetch_add(1, SeqCst);
+                async move {
+                    let body = [
+                        r#"data: {"id": "cmpl-1", "object": "text_completion", "created": 1710000000, "model": "codellama", "choices": [{"index": 0, "text": "a + b", "finish_reason": null}]}"#,
+                        r#"data: {"id": "cmpl-1", "object": "text_completion", "created": 1710000000, "model": "codellama", "choices": [{"index": 0, "text": "\n", "finish_reason": "stop"}]}"#,
+                        "data: [DONE]",
+                    ]
+                    .join("\n\n");
+                    Ok(Response::builder()
+                        .status(200)
+                        .body(AsyncBody::from(body))
+                        .unwrap())
+                }
+            }
+        });
+        let settings = InlineCompletionSettings {
+            enabled: true,
+            model: "codellama/CodeLlama-7b-hf".into(),
+            ..Default::default()
+        };
+        let provider = LocalInlineCompletionProvider::new(settings, &[], client, cx.executor());
+
+        let text = "fn add(a: i32, b: i32) -> i32 {\n    \n}\n";
+        let buffer = cx.new_model(|_| Buffer::new(0, BufferId::new(1).unwrap(), text));
+        let position = buffer.read_with(cx, |buffer, _| buffer.anchor_before(Point::new(1, 4)));
+
+        assert!(cx.read(|cx| provider.is_enabled(&buffer, position, cx)));
+        let completions = cx
+            .update(|cx| provider.completions(&buffer, position, cx))
+            .await
+            .unwrap();
+        assert_eq!(completions.len(), 1);
+        assert_eq!(completions[0].text, "a + b");
+        assert_eq!(completions[0].range, position..position);
+        assert_eq!(requests.load(SeqCst), 1);
+
+        // Asking again at the same place is served from the cache.
+        let completions = cx
+            .update(|cx| provider.completions(&buffer, position, cx))
+            .await
+            .unwrap();
+        assert_eq!(completions[0].text, "a + b");
+        assert_eq!(requests.load(SeqCst), 1);
+
+        // Typing out the start of the suggestion leaves the rest of it.
+        let position = buffer.update(cx, |buffer, cx| {
+            buffer.edit([(Point::new(1, 4)..Point::new(1, 4), "a +")], None, cx);
+            buffer.anchor_before(Point::new(1, 7))
+        });
+        let completions = cx
+            .update(|cx| provider.completions(&buffer, position, cx))
+            .await
+            .unwrap();
+        assert_eq!(completions[0].text, " b");
+        assert_eq!(requests.load(SeqCst), 1);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::ShowCompletionMetrics;
+use ai::metrics::{CompletionMetrics, ModelMetrics};
+use gpui::{
+    div, AppContext, DismissEvent, EventEmitter, FocusHandle, FocusableView, FontWeight, Render,
+    Task, ViewContext,
+};
+use std::{sync::Arc, time::Duration};
+use ui::prelude::*;
+use workspace::{ModalView, Workspace};
+
+/// How often the figures are refreshed while the view is open.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|workspace: &mut Workspace, _| {
+        workspace.register_action(|workspace, _: &ShowCompletionMetrics, cx| {
+            workspace.toggle_modal(cx, |cx| MetricsView::new(CompletionMetrics::global(), cx));
+        });
+    })
+    .detach();
+}
+
+/// Shows the latency, throughput and error rate of the completions made with each
+/// model, to compare providers against each other.
+pub struct MetricsView {
+    metrics: Arc<CompletionMetrics>,
+    focus_handle: FocusHandle,
+    _refresh: Task<()>,
+}
+
+impl MetricsView {
+    fn new(metrics: Arc<CompletionMetrics>, cx: &mut ViewContext<Self>) -> Self {
+        let _refresh = cx.spawn(|this, mut cx| async move {
+            loop {
+                cx.background_executor().timer(REFRESH_INTERVAL).await;
+                if this.update(&mut cx, |_, cx| cx.notify()).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            metrics,
+            focus_handle: cx.focus_handle(),
+            _refresh,
+        }
+    }
+
+    fn dismiss(&mut self, _: &menu::Cancel, cx: &mut ViewContext<Self>) {
+        cx.emit(DismissEvent);
+    }
+
+    fn render_row(model: &ModelMetrics) -> impl IntoElement {
+        let time_to_first_token = model
+            .time_to_first_token
+            .map_or("–".to_string(), |duration| {
+                format!("{} ms", duration.as_millis())
+            });
+        let tokens_per_second = model
+            .tokens_per_second
+            .map_or("–".to_string(), |rate| format!("{rate:.1}"));
+        let error_color = if model.errors > 0 {
+            Color::Error
+        } else {
+            Color::Default
+        };
+        h_flex()
+            .gap_4()
+            .child(div().w_48().child(Label::new(model.model.clone())))
+            .child(div().w_16().child(Label::new(model.requests.to_string())))
+            .child(
+                div().w_24().child(
+                    Label::new(format!("{:.0}%", model.error_rate() * 100.)).color(error_color),
+                ),
+            )
+            .child(div().w_24().child(Label::new(time_to_first_token)))
+            .child(div().w_24().child(Label::new(tokens_per_second)))
+    }
+}
+
+impl ModalView for MetricsView {}
+
+impl EventEmitter<DismissEvent> for MetricsView {}
+
+impl FocusableView for MetricsView {
+    fn focus_handle(&self, _: &AppContext) -> FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl Render for MetricsView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let summary = self.metrics.summary();
+        let header = |label: &'static str| Label::new(label).color(Color::Muted);
+        v_flex()
+            .key_context("CompletionMetrics")
+            .track_focus(&self.focus_handle)
+            .on_action(cx.listener(Self::dismiss))
+            .elevation_3(cx)
+            .p_4()
+            .gap_2()
+            .child(
+                div()
+                    .font_weight(FontWeight::SEMIBOLD)
+                    .child(Label::new("Completion Metrics")),
+            )
+            .child(
+                h_flex()
+                    .gap_4()
+                    .child(div().w_48().child(header("Model")))
+                    .child(div().w_16().child(header("Requests")))
+                    .child(div().w_24().child(header("Errors")))
+                    .child(div().w_24().child(header("First token")))
+                    .child(div().w_24().child(header("Tokens/s"))),
+            )
+            .children(summary.iter().map(Self::render_row))
+            .when(summary.is_empty(), |this| {
+                this.child(Label::new("No completions yet.").color(Color::Muted))
+            })
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{
+    assistant_panel::{Conversation, ModelChoice},
+    AssistantPanel, SwitchModel,
+};
+use gpui::{EntityId, Model, MouseButton, Render, Subscription, Task, View, ViewContext};
+use std::time::Duration;
+use ui::{prelude::*, Tooltip};
+use workspace::{item::ItemHandle, StatusItemView};
+use zed_actions::OpenSettings;
+
+const SPINNER_FRAMES: [&str; 4] = ["◐", "◓", "◑", "◒"];
+const SPINNER_INTERVAL: Duration = Duration::from_millis(150);
+
+/// Shows the provider and model of the assistant panel's active conversation
+/// in the status bar, with a spinner while a response is streaming. Clicking
+/// it opens the model switcher, and right-clicking it opens the settings.
+pub struct ModelIndicator {
+    model: Option<ModelChoice>,
+    generating: bool,
+    spinner_frame: usize,
+    animate_spinner: Option<Task<()>>,
+    conversation_subscription: Option<(Subscription, EntityId)>,
+    _observe_panel: Subscription,
+}
+
+impl ModelIndicator {
+    pub fn new(panel: &View<AssistantPanel>, cx: &mut ViewContext<Self>) -> Self {
+        let mut this = Self {
+            model: None,
+            generating: false,
+            spinner_frame: 0,
+            animate_spinner: None,
+            conversation_subscription: None,
+            _observe_panel: cx.observe(panel, Self::update_active_conversation),
+        };
+        this.update_active_conversation(panel.clone(), cx);
+        this
+    }
+
+    fn update_active_conversation(
+        &mut self,
+        panel: View<AssistantPanel>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let Some(conversation) = panel.read(cx).active_conversation(cx) else {
+            self.conversation_subscription = None;
+            self.model = None;
+            self.set_generating(false, cx);
+            cx.notify();
+            return;
+        };
+
+        let conversation_id = conversation.entity_id();
+        if self
+            .conversation_subscription
+            .as_ref()
+            .map_or(true, |(_, id)| *id != conversation_id)
+        {
+            self.conversation_subscription = Some((
+                cx.observe(&conversation, Self::update_conversation),
+                conversation_id,
+            ));
+            self.update_conversation(conversation, cx);
+        }
+    }
+
+    fn update_conversation(
+        &mut self,
+        conversation: Model<Conversation>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let conversation = conversation.read(cx);
+        self.model = Some(conversation.model_choice());
+        let generating = conversation.is_generating();
+        self.set_generating(generating, cx);
+        cx.notify();
+    }
+
+    /// Starts or stops advancing the spinner.
+    fn set_generating(&mut self, generating: bool, cx: &mut ViewContext<Self>) {
+        self.generating = generating;
+        if !generating {
+            self.animate_spinner = None;
+            self.spinner_frame = 0;
+        } else if self.animate_spinner.is_none() {
+            self.animate_spinner = Some(cx.spawn(|this, mut cx| async move {
+                loop {
+                    cx.background_executor().timer(SPINNER_INTERVAL).await;
+                    let advanced = this.update(&mut cx, |this, cx| {
+                        this.spinner_frame = (this.spinner_frame + 1) % SPINNER_FRAMES.len();
+                        cx.notify();
+                    });
+                    if advanced.is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+    }
+}
+
+impl Render for ModelIndicator {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        h_flex()
+            .gap_1()
+            .when(self.generating, |el| {
+                el.child(
+                    Label::new(SPINNER_FRAMES[self.spinner_frame])
+                        .size(LabelSize::Small)
+                        .color(Color::Accent),
+                )
+            })
+            .when_some(self.model.as_ref(), |el, model| {
+                let label = format!(
+                    "{} · {}",
+                    model.provider.display_name(),
+                    model.model.short_name()
+                );
+                el.on_mouse_down(
+                    MouseButton::Right,
+                    cx.listener(|_, _, cx| cx.dispatch_action(Box::new(OpenSettings))),
+                )
+                .child(
+                    Button::new("assistant-model", label)
+                        .label_size(LabelSize::Small)
+                        .on_click(|_, cx| cx.dispatch_action(Box::new(SwitchModel)))
+                        .tooltip(|cx| Tooltip::for_action("Switch Model", &SwitchModel, cx)),
+                )
+            })
+    }
+}
+
+impl StatusItemView for ModelIndicator {
+    fn set_active_pane_item(
+        &mut self,
+        _item: Option<&dyn ItemHandle>,
+        _cx: &mut ViewContext<Self>,
+    ) {
+    }
+}
@@ -0,0 +1,494 @@
+use crate::{
+    assistant_panel::ModelChoice,
+    assistant_settings::{AssistantSettings, ProviderKind},
+    AssistantPanel, SwitchModel,
+};
+use ai::{
+    auth::ProviderCredential,
+    completion::ConnectionOptions,
+    models::{LanguageModel, ModelName},
+    providers::{perplexity::PerplexityLanguageModel, vllm},
+};
+use anyhow::Result;
+use db::kvp::KEY_VALUE_STORE;
+use fuzzy::{match_strings, StringMatch, StringMatchCandidate};
+use gpui::{
+    AppContext, DismissEvent, EventEmitter, FocusHandle, FocusableView, Render, Task, View,
+    ViewContext, VisualContext, WeakView,
+};
+use picker::{Picker, PickerDelegate};
+use settings::Settings;
+use std::sync::Arc;
+use ui::{prelude::*, HighlightedLabel, ListItem, ListItemSpacing};
+use util::{http::HttpClient, ResultExt};
+use workspace::{ModalView, Workspace};
+
+const RECENT_MODELS_KEY: &str = "AssistantRecentModels";
+const MAX_RECENT_MODELS: usize = 8;
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|workspace: &mut Workspace, _| {
+        workspace.register_action(ModelSwitcher::toggle);
+    })
+    .detach();
+}
+
+/// Searches the models of every configured provider, the recently used ones
+/// first, and switches the active conversation to the chosen one.
+pub struct ModelSwitcher {
+    picker: View<Picker<ModelSwitcherDelegate>>,
+}
+
+impl ModelSwitcher {
+    fn toggle(workspace: &mut Workspace, _: &SwitchModel, cx: &mut ViewContext<Workspace>) {
+        let Some(panel) = workspace.panel::<AssistantPanel>(cx) else {
+            return;
+        };
+        let panel = panel.read(cx);
+        let active = panel.active_model(cx);
+        let http_client = panel.http_client();
+        let workspace_handle = workspace.weak_handle();
+        workspace.toggle_modal(cx, move |cx| {
+            let entries = ModelChoice::available(cx)
+                .into_iter()
+                .map(ModelEntry::new)
+                .collect();
+            let delegate = ModelSwitcherDelegate::new(
+                cx.view().downgrade(),
+                workspace_handle,
+                entries,
+                active,
+            );
+            let picker = cx.new_view(|cx| Picker::uniform_list(delegate, cx));
+
+            let recent_models = cx
+                .background_executor()
+                .spawn(async { read_recent_models() });
+            let served_models = served_vllm_models(http_client, cx);
+            cx.spawn(|this, mut cx| async move {
+                let recent_models = recent_models.await.log_err().unwrap_or_default();
+                let served_models = served_models.await;
+                this.update(&mut cx, |this, cx| {
+                    this.picker.update(cx, |picker, cx| {
+                        picker.delegate.add_entries(served_models);
+                        picker.delegate.set_recent_models(&recent_models);
+                        picker.refresh(cx);
+                    })
+                })
+            })
+            .detach_and_log_err(cx);
+
+            Self { picker }
+        });
+    }
+}
+
+impl Render for ModelSwitcher {
+    fn render(&mut self, _cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex().w(rems(34.)).child(self.picker.clone())
+    }
+}
+
+impl FocusableView for ModelSwitcher {
+    fn focus_handle(&self, cx: &AppContext) -> FocusHandle {
+        self.picker.focus_handle(cx)
+    }
+}
+
+impl EventEmitter<DismissEvent> for ModelSwitcher {}
+impl ModalView for ModelSwitcher {}
+
+/// A model offered in the switcher, with what's known of its context window
+/// when the provider reports it.
+#[derive(Clone, Debug, PartialEq)]
+struct ModelEntry {
+    choice: ModelChoice,
+    context_size: Option<usize>,
+}
+
+impl ModelEntry {
+    fn new(choice: ModelChoice) -> Self {
+        let context_size = match choice.provider {
+            ProviderKind::OpenAi => Some(tiktoken_rs::model::get_context_size(
+                choice.model.full_name(),
+            )),
+            ProviderKind::Vllm => None,
+            ProviderKind::Perplexity => PerplexityLanguageModel::load(choice.model.full_name())
+                .capacity()
+                .ok(),
+        };
+        Self {
+            choice,
+            context_size,
+        }
+    }
+
+    /// The model's context window and pricing, such as
+    /// `128k context · $10/$30 per 1M tokens`.
+    fn hint(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(context_size) = self.context_size {
+            parts.push(format!("{}k context", context_size / 1000));
+        }
+        let pricing = match self.choice.provider {
+            ProviderKind::OpenAi | ProviderKind::Perplexity => {
+                self.choice.model.definition().and_then(|d| d.pricing)
+            }
+            ProviderKind::Vllm => None,
+        };
+        if let Some(pricing) = pricing {
+            parts.push(format!(
+                "${}/${} per 1M tokens",
+                pricing.prompt, pricing.completion
+            ));
+        }
+        (!parts.is_empty()).then(|| parts.join(" · "))
+    }
+}
+
+/// Moves the recently used models to the front, most recent first, keeping
+/// the order of the rest.
+fn sort_by_recent(entries: &mut [ModelEntry], recent_models: &[ModelChoice]) {
+    entries.sort_by_key(|entry| {
+        recent_models
+            .iter()
+            .position(|choice| *choice == entry.choice)
+            .unwrap_or(recent_models.len())
+    });
+}
+
+fn read_recent_models() -> Result<Vec<ModelChoice>> {
+    Ok(KEY_VALUE_STORE
+        .read_kvp(RECENT_MODELS_KEY)?
+        .map(|recent| serde_json::from_str(&recent))
+        .transpose()?
+        .unwrap_or_default())
+}
+
+/// Records `choice` as the most recently used model.
+fn record_recent_model(choice: ModelChoice, cx: &AppContext) -> Task<Result<()>> {
+    cx.background_executor().spawn(async move {
+        let mut recent_models = read_recent_models()?;
+        recent_models.retain(|recent| *recent != choice);
+        recent_models.insert(0, choice);
+        recent_models.truncate(MAX_RECENT_MODELS);
+        KEY_VALUE_STORE
+            .write_kvp(
+                RECENT_MODELS_KEY.into(),
+                serde_json::to_string(&recent_models)?,
+            )
+            .await
+    })
+}
+
+/// The models served by the vLLM servers in the settings, as the servers list
+/// them. Servers that can't be reached are logged and skipped.
+fn served_vllm_models(http_client: Arc<dyn HttpClient>, cx: &AppContext) -> Task<Vec<ModelEntry>> {
+    let settings = AssistantSettings::get_global(cx);
+    let vllm_server = settings
+        .vllm
+        .api_url
+        .as_ref()
+        .map(|api_url| (api_url, &settings.vllm.tls, &settings.vllm.extra_headers));
+    let fallback_servers = settings
+        .fallback_providers
+        .iter()
+        .filter(|fallback| fallback.provider == ProviderKind::Vllm)
+        .filter_map(|fallback| {
+            Some((
+                fallback.api_url.as_ref()?,
+                &fallback.tls,
+                &fallback.extra_headers,
+            ))
+        });
+    let mut servers = Vec::<(String, ConnectionOptions)>::new();
+    for (api_url, tls, extra_headers) in vllm_server.into_iter().chain(fallback_servers) {
+        if !servers.iter().any(|(existing, _)| existing == api_url) {
+            let options = ConnectionOptions {
+                tls: tls.to_options(),
+                extra_headers: extra_headers.clone(),
+                ..Default::default()
+            };
+            servers.push((api_url.clone(), options));
+        }
+    }
+
+    cx.background_executor().spawn(async move {
+        let mut entries = Vec::new();
+        for (api_url, options) in servers {
+            let models = vllm::list_models(
+                http_client.as_ref(),
+                &api_url,
+                &ProviderCredential::NoCredentials,
+                &options,
+            )
+            .await
+            .log_err()
+            .unwrap_or_default();
+            entries.extend(models.into_iter().map(|model| ModelEntry {
+                choice: ModelChoice {
+                    provider: ProviderKind::Vllm,
+                    model: ModelName::new(model.id),
+                    api_url: api_url.clone(),
+                },
+                context_size: model.max_model_len,
+            }));
+        }
+        entries
+    })
+}
+
+pub struct ModelSwitcherDelegate {
+    switcher: WeakView<ModelSwitcher>,
+    workspace: WeakView<Workspace>,
+    entries: Vec<ModelEntry>,
+    candidates: Vec<StringMatchCandidate>,
+    /// The model of the active conversation.
+    active: Option<ModelChoice>,
+    matches: Vec<StringMatch>,
+    selected_index: usize,
+}
+
+impl ModelSwitcherDelegate {
+    fn new(
+        switcher: WeakView<ModelSwitcher>,
+        workspace: WeakView<Workspace>,
+        entries: Vec<ModelEntry>,
+        active: Option<ModelChoice>,
+    ) -> Self {
+        let mut this = Self {
+            switcher,
+            workspace,
+            entries,
+            candidates: Vec::new(),
+            active,
+            matches: Vec::new(),
+            selected_index: 0,
+        };
+        this.update_candidates();
+        this
+    }
+
+    /// Adds models discovered from the providers, or fills in the context
+    /// size of ones that are already listed.
+    fn add_entries(&mut self, entries: Vec<ModelEntry>) {
+        for entry in entries {
+            match self
+                .entries
+                .iter_mut()
+                .find(|existing| existing.choice == entry.choice)
+            {
+                Some(existing) => existing.context_size = entry.context_size,
+                None => self.entries.push(entry),
+            }
+        }
+        self.update_candidates();
+    }
+
+    fn set_recent_models(&mut self, recent_models: &[ModelChoice]) {
+        sort_by_recent(&mut self.entries, recent_models);
+        self.update_candidates();
+    }
+
+    fn update_candidates(&mut self) {
+        self.candidates = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(id, entry)| {
+                StringMatchCandidate::new(
+                    id,
+                    format!(
+                        "{} {}",
+                        entry.choice.model.short_name(),
+                        entry.choice.provider.display_name()
+                    ),
+                )
+            })
+            .collect();
+    }
+}
+
+impl PickerDelegate for ModelSwitcherDelegate {
+    type ListItem = ListItem;
+
+    fn placeholder_text(&self, _cx: &mut WindowContext) -> Arc<str> {
+        "Switch model...".into()
+    }
+
+    fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    fn confirm(&mut self, _: bool, cx: &mut ViewContext<Picker<Self>>) {
+        if let Some(mat) = self.matches.get(self.selected_index) {
+            let choice = self.entries[mat.candidate_id].choice.clone();
+            record_recent_model(choice.clone(), cx).detach_and_log_err(cx);
+            self.workspace
+                .update(cx, |workspace, cx| {
+                    if let Some(panel) = workspace.focus_panel::<AssistantPanel>(cx) {
+                        panel.update(cx, |panel, cx| panel.set_active_model(choice, cx));
+                    }
+                })
+                .log_err();
+        }
+        self.dismissed(cx);
+    }
+
+    fn dismissed(&mut self, cx: &mut ViewContext<Picker<Self>>) {
+        self.switcher
+            .update(cx, |_, cx| cx.emit(DismissEvent))
+            .log_err();
+    }
+
+    fn selected_index(&self) -> usize {
+        self.selected_index
+    }
+
+    fn set_selected_index(&mut self, ix: usize, _: &mut ViewContext<Picker<Self>>) {
+        self.selected_index = ix;
+    }
+
+    fn update_matches(&mut self, query: String, cx: &mut ViewContext<Picker<Self>>) -> Task<()> {
+        let background = cx.background_executor().clone();
+        let candidates = self.candidates.clone();
+        cx.spawn(|this, mut cx| async move {
+            let matches = if query.is_empty() {
+                candidates
+                    .into_iter()
+                    .map(|candidate| StringMatch {
+                        candidate_id: candidate.id,
+                        string: candidate.string,
+                        positions: Vec::new(),
+                        score: 0.0,
+                    })
+                    .collect()
+            } else {
+                match_strings(
+                    &candidates,
+                    &query,
+                    false,
+                    100,
+                    &Default::default(),
+                    background,
+                )
+                .await
+            };
+
+            this.update(&mut cx, |this, cx| {
+                let delegate = &mut this.delegate;
+                delegate.matches = matches;
+                delegate.selected_index = delegate
+                    .selected_index
+                    .min(delegate.matches.len().saturating_sub(1));
+                cx.notify();
+            })
+            .log_err();
+        })
+    }
+
+    fn render_match(
+        &self,
+        ix: usize,
+        selected: bool,
+        _cx: &mut ViewContext<Picker<Self>>,
+    ) -> Option<Self::ListItem> {
+        let mat = &self.matches[ix];
+        let entry = &self.entries[mat.candidate_id];
+        let model = entry.choice.model.short_name().to_string();
+        // The provider's name is only matched so it can be searched by.
+        let positions = mat
+            .positions
+            .iter()
+            .copied()
+            .filter(|position| *position < model.len())
+            .collect();
+        let is_active = self.active.as_ref() == Some(&entry.choice);
+        Some(
+            ListItem::new(ix)
+                .inset(true)
+                .spacing(ListItemSpacing::Sparse)
+                .selected(selected)
+                .start_slot(
+                    Icon::new(IconName::Check)
+                        .size(IconSize::Small)
+                        .color(if is_active {
+                            Color::Accent
+                        } else {
+                            Color::Hidden
+                        }),
+                )
+                .child(
+                    h_flex()
+                        .w_full()
+                        .gap_2()
+                        .justify_between()
+                        .child(HighlightedLabel::new(model, positions))
+                        .child(
+                            h_flex()
+                                .gap_2()
+                                .children(entry.hint().map(|hint| {
+                                    Label::new(hint).color(Color::Muted).size(LabelSize::Small)
+                                }))
+                                .child(
+                                    Label::new(entry.choice.provider.display_name())
+                                        .color(Color::Muted)
+                                        .size(LabelSize::Small),
+                                ),
+                        ),
+                ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(provider: ProviderKind, model: &str, context_size: Option<usize>) -> ModelEntry {
+        ModelEntry {
+            choice: ModelChoice {
+                provider,
+                model: ModelName::new(model),
+                api_url: provider.default_api_url().to_string(),
+            },
+            context_size,
+        }
+    }
+
+    #[test]
+    fn test_model_entry_hint() {
+        assert_eq!(
+            entry(ProviderKind::OpenAi, "gpt-4-1106-preview", Some(128000)).hint(),
+            Some("128k context · $10/$30 per 1M tokens".to_string())
+        );
+        assert_eq!(
+            entry(ProviderKind::Vllm, "mistral-7b", Some(32768)).hint(),
+            Some("32k context".to_string())
+        );
+        assert_eq!(entry(ProviderKind::Vllm, "mistral-7b", None).hint(), None);
+    }
+
+    #[test]
+    fn test_sort_by_recent() {
+        let mut entries = vec![
+            entry(ProviderKind::OpenAi, "gpt-4", None),
+            entry(ProviderKind::OpenAi, "gpt-3.5-turbo", None),
+            entry(ProviderKind::Vllm, "mistral-7b", None),
+            entry(ProviderKind::Vllm, "llama-3-8b", None),
+        ];
+        let recent_models = [
+            entries[3].choice.clone(),
+            entries[1].choice.clone(),
+            entry(ProviderKind::Vllm, "unavailable", None).choice,
+        ];
+        sort_by_recent(&mut entries, &recent_models);
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| entry.choice.model.full_name())
+                .collect::<Vec<_>>(),
+            ["llama-3-8b", "gpt-3.5-turbo", "gpt-4", "mistral-7b"]
+        );
+    }
+}
@@ -0,0 +1,479 @@
+use crate::{AssistantPanel, NewPrompt, ShowPromptLibrary};
+use anyhow::Result;
+use fs::{Fs, RemoveOptions};
+use futures::StreamExt;
+use gpui::{
+    AppContext, DismissEvent, EventEmitter, FocusHandle, FocusableView, PromptLevel, Render, Task,
+    View, ViewContext, VisualContext, WeakView,
+};
+use picker::{Picker, PickerDelegate};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use ui::{prelude::*, ListItem, ListItemSpacing, Tooltip};
+use util::{paths::PROMPTS_DIR, ResultExt};
+use workspace::{ModalView, Workspace};
+
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|workspace: &mut Workspace, _| {
+        workspace
+            .register_action(PromptLibrary::toggle)
+            .register_action(|workspace, _: &NewPrompt, cx| {
+                new_prompt(workspace, None, cx).detach_and_log_err(cx)
+            });
+    })
+    .detach();
+}
+
+/// A prompt in the prompt library, saved as a Markdown file in the prompts
+/// directory. Its tags are listed in a front matter block at the top:
+///
+/// ```markdown
+/// ---
+/// tags: rust, review
+/// ---
+/// Review this code for...
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct SavedPrompt {
+    /// The file's name, which is what `/prompt` is given to insert it.
+    pub name: String,
+    pub path: PathBuf,
+    pub tags: Vec<String>,
+    /// The prompt itself, without the front matter.
+    pub body: String,
+}
+
+impl SavedPrompt {
+    pub fn parse(path: &Path, text: &str) -> Option<Self> {
+        let name = path.file_stem()?.to_str()?.to_string();
+        let (tags, body) = split_front_matter(text);
+        Some(Self {
+            name,
+            path: path.to_path_buf(),
+            tags,
+            body: body.to_string(),
+        })
+    }
+
+    /// The prompts in the prompts directory, ordered by name.
+    pub async fn list(fs: &dyn Fs) -> Result<Vec<Self>> {
+        fs.create_dir(&PROMPTS_DIR).await?;
+
+        let mut paths = fs.read_dir(&PROMPTS_DIR).await?;
+        let mut prompts = Vec::new();
+        while let Some(path) = paths.next().await {
+            let path = path?;
+            if path.extension() != Some(OsStr::new("md")) {
+                continue;
+            }
+            let Some(text) = fs.load(&path).await.log_err() else {
+                continue;
+            };
+            prompts.extend(Self::parse(&path, &text));
+        }
+        prompts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(prompts)
+    }
+
+    fn matches(&self, query: &PromptQuery) -> bool {
+        let name = self.name.to_lowercase();
+        let body = self.body.to_lowercase();
+        query.tags.iter().all(|tag| {
+            self.tags
+                .iter()
+                .any(|prompt_tag| prompt_tag.eq_ignore_ascii_case(tag))
+        }) && query
+            .terms
+            .iter()
+            .all(|term| name.contains(term.as_str()) || body.contains(term.as_str()))
+    }
+}
+
+/// The tags in the front matter at the start of a prompt file, and the text
+/// after it. Text without front matter has no tags.
+pub fn split_front_matter(text: &str) -> (Vec<String>, &str) {
+    let Some(rest) = text.strip_prefix("---\n") else {
+        return (Vec::new(), text);
+    };
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            let tags = rest[..offset]
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .filter(|(key, _)| key.trim() == "tags")
+                .flat_map(|(_, value)| {
+                    value
+                        .trim()
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                        .split(',')
+                })
+                .map(|tag| tag.trim().trim_matches(['"', '\'']).to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect();
+            return (tags, &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (Vec::new(), text)
+}
+
+/// What's typed into the prompt library's search: `tag:` filters, and words
+/// the prompt's name or text must contain.
+#[derive(Debug, Default, PartialEq)]
+struct PromptQuery {
+    tags: Vec<String>,
+    terms: Vec<String>,
+}
+
+impl PromptQuery {
+    fn parse(query: &str) -> Self {
+        let mut parsed = Self::default();
+        for word in query.split_whitespace() {
+            match word.strip_prefix("tag:") {
+                Some(tag) if !tag.is_empty() => parsed.tags.push(tag.to_string()),
+                Some(_) => {}
+                None => parsed.terms.push(word.to_lowercase()),
+            }
+        }
+        parsed
+    }
+}
+
+/// Creates a prompt named `name`, or "untitled", in the prompts directory and
+/// opens it for editing.
+fn new_prompt(
+    workspace: &mut Workspace,
+    name: Option<&str>,
+    cx: &mut ViewContext<Workspace>,
+) -> Task<Result<()>> {
+    let fs = workspace.app_state().fs.clone();
+    let name = name
+        .map(|name| name.trim().replace(['/', '\\'], "-"))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "untitled".into());
+    cx.spawn(|workspace, mut cx| async move {
+        fs.create_dir(&PROMPTS_DIR).await?;
+        let mut path = PROMPTS_DIR.join(&name).with_extension("md");
+        let mut discriminant = 1;
+        while fs.is_file(&path).await {
+            discriminant += 1;
+            path = PROMPTS_DIR
+                .join(format!("{name}-{discriminant}"))
+                .with_extension("md");
+        }
+        fs.atomic_write(path.clone(), "---\ntags:\n---\n".into())
+            .await?;
+        workspace
+            .update(&mut cx, |workspace, cx| {
+                workspace.open_abs_path(path, true, cx)
+            })?
+            .await?;
+        Ok(())
+    })
+}
+
+/// Searches the saved prompts by name, text and tag, and inserts the chosen one
+/// into the active conversation. Prompts can also be created, opened for
+/// editing and deleted from here.
+pub struct PromptLibrary {
+    picker: View<Picker<PromptLibraryDelegate>>,
+}
+
+impl PromptLibrary {
+    fn toggle(workspace: &mut Workspace, _: &ShowPromptLibrary, cx: &mut ViewContext<Workspace>) {
+        let fs = workspace.app_state().fs.clone();
+        cx.spawn(|workspace, mut cx| async move {
+            let prompts = SavedPrompt::list(fs.as_ref()).await?;
+            workspace.update(&mut cx, |workspace, cx| {
+                let workspace_handle = workspace.weak_handle();
+                workspace.toggle_modal(cx, move |cx| {
+                    let delegate = PromptLibraryDelegate {
+                        library: cx.view().downgrade(),
+                        workspace: workspace_handle,
+                        fs,
+                        prompts,
+                        matches: Vec::new(),
+                        selected_index: 0,
+                        query: String::new(),
+                    };
+                    let picker = cx.new_view(|cx| Picker::uniform_list(delegate, cx));
+                    Self { picker }
+                });
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+}
+
+impl Render for PromptLibrary {
+    fn render(&mut self, _cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex().w(rems(34.)).child(self.picker.clone())
+    }
+}
+
+impl FocusableView for PromptLibrary {
+    fn focus_handle(&self, cx: &AppContext) -> FocusHandle {
+        self.picker.focus_handle(cx)
+    }
+}
+
+impl EventEmitter<DismissEvent> for PromptLibrary {}
+impl ModalView for PromptLibrary {}
+
+pub struct PromptLibraryDelegate {
+    library: WeakView<PromptLibrary>,
+    workspace: WeakView<Workspace>,
+    fs: Arc<dyn Fs>,
+    prompts: Vec<SavedPrompt>,
+    /// Indices into `prompts` of the ones matching the query.
+    matches: Vec<usize>,
+    selected_index: usize,
+    query: String,
+}
+
+impl PromptLibraryDelegate {
+    fn open_prompt(&self, path: PathBuf, cx: &mut ViewContext<Picker<Self>>) {
+        self.workspace
+            .update(cx, |workspace, cx| {
+                workspace
+                    .open_abs_path(path, true, cx)
+                    .detach_and_log_err(cx)
+            })
+            .log_err();
+    }
+
+    /// Deletes the prompt at `ix` in `prompts`, once the user confirms it.
+    fn delete_prompt(&mut self, ix: usize, cx: &mut ViewContext<Picker<Self>>) {
+        let Some(prompt) = self.prompts.get(ix).cloned() else {
+            return;
+        };
+        let answer = cx.prompt(
+            PromptLevel::Warning,
+            &format!("Delete the prompt \"{}\"?", prompt.name),
+            None,
+            &["Delete", "Cancel"],
+        );
+        let fs = self.fs.clone();
+        cx.spawn(|this, mut cx| async move {
+            if answer.await? != 0 {
+                return Ok(());
+            }
+            fs.remove_file(
+                &prompt.path,
+                RemoveOptions {
+                    ignore_if_not_exists: true,
+                    ..Default::default()
+                },
+            )
+            .await?;
+            this.update(&mut cx, |this, cx| {
+                this.delegate
+                    .prompts
+                    .retain(|other| other.path != prompt.path);
+                let query = this.delegate.query.clone();
+                this.delegate.update_matches(query, cx)
+            })?
+            .await;
+            anyhow::Ok(())
+        })
+        .detach_and_log_err(cx);
+    }
+}
+
+impl PickerDelegate for PromptLibraryDelegate {
+    type ListItem = ListItem;
+
+    fn placeholder_text(&self, _cx: &mut WindowContext) -> Arc<str> {
+        "Search prompts (tag:)...".into()
+    }
+
+    fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    /// Inserts the prompt into the active conversation, or opens it for
+    /// editing on secondary confirm.
+    fn confirm(&mut self, secondary: bool, cx: &mut ViewContext<Picker<Self>>) {
+        let Some(prompt) = self
+            .matches
+            .get(self.selected_index)
+            .and_then(|ix| self.prompts.get(*ix))
+        else {
+            return;
+        };
+
+        if secondary {
+            self.open_prompt(prompt.path.clone(), cx);
+        } else {
+            let body = prompt.body.clone();
+            self.workspace
+                .update(cx, |workspace, cx| {
+                    if let Some(panel) = workspace.focus_panel::<AssistantPanel>(cx) {
+                        panel.update(cx, |panel, cx| {
+                            panel.insert_into_active_conversation(&body, cx)
+                        });
+                    }
+                })
+                .log_err();
+        }
+        self.dismissed(cx);
+    }
+
+    fn dismissed(&mut self, cx: &mut ViewContext<Picker<Self>>) {
+        self.library
+            .update(cx, |_, cx| cx.emit(DismissEvent))
+            .log_err();
+    }
+
+    fn selected_index(&self) -> usize {
+        self.selected_index
+    }
+
+    fn set_selected_index(&mut self, ix: usize, _: &mut ViewContext<Picker<Self>>) {
+        self.selected_index = ix;
+    }
+
+    fn update_matches(&mut self, query: String, cx: &mut ViewContext<Picker<Self>>) -> Task<()> {
+        let parsed_query = PromptQuery::parse(&query);
+        self.query = query;
+        self.matches = self
+            .prompts
+            .iter()
+            .enumerate()
+            .filter(|(_, prompt)| prompt.matches(&parsed_query))
+            .map(|(ix, _)| ix)
+            .collect();
+        self.selected_index = self
+            .selected_index
+            .min(self.matches.len().saturating_sub(1));
+        cx.notify();
+        Task::ready(())
+    }
+
+    fn render_match(
+        &self,
+        ix: usize,
+        selected: bool,
+        cx: &mut ViewContext<Picker<Self>>,
+    ) -> Option<Self::ListItem> {
+        let prompt_ix = *self.matches.get(ix)?;
+        let prompt = &self.prompts[prompt_ix];
+        let first_line = prompt
+            .body
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or_default()
+            .to_string();
+        Some(
+            ListItem::new(ix)
+                .inset(true)
+                .spacing(ListItemSpacing::Sparse)
+                .selected(selected)
+                .child(
+                    v_flex()
+                        .w_full()
+                        .child(
+                            h_flex()
+                                .w_full()
+                                .gap_2()
+                                .child(Label::new(prompt.name.clone()))
+                                .children(prompt.tags.iter().map(|tag| {
+                                    Label::new(format!("#{tag}"))
+                                        .color(Color::Muted)
+                                        .size(LabelSize::Small)
+                                })),
+                        )
+                        .child(
+                            Label::new(first_line)
+                                .color(Color::Muted)
+                                .size(LabelSize::Small),
+                        ),
+                )
+                .end_hover_slot(
+                    IconButton::new(("delete_prompt", ix), IconName::Delete)
+                        .icon_size(IconSize::Small)
+                        .tooltip(|cx| Tooltip::text("Delete Prompt", cx))
+                        .on_click(cx.listener(move |this, _, cx| {
+                            this.delegate.delete_prompt(prompt_ix, cx)
+                        })),
+                ),
+        )
+    }
+
+    fn render_footer(&self, cx: &mut ViewContext<Picker<Self>>) -> Option<AnyElement> {
+        let label = if self.query.trim().is_empty() {
+            "New Prompt".to_string()
+        } else {
+            format!("New Prompt \"{}\"", self.query.trim())
+        };
+        Some(
+            h_flex()
+                .w_full()
+                .p_2()
+                .justify_end()
+                .child(
+                    Button::new("new_prompt", label).on_click(cx.listener(|this, _, cx| {
+                        let name = this.delegate.query.clone();
+                        this.delegate
+                            .workspace
+                            .update(cx, |workspace, cx| {
+                                new_prompt(workspace, Some(&name), cx).detach_and_log_err(cx)
+                            })
+                            .log_err();
+                        this.delegate.dismissed(cx);
+                    })),
+                )
+                .into_any_element(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_front_matter() {
+        assert_eq!(
+            split_front_matter("---\ntags: rust, review\n---\nReview this.\n"),
+            (
+                vec!["rust".to_string(), "review".to_string()],
+                "Review this.\n"
+            )
+        );
+        assert_eq!(
+            split_front_matter("---\ntags: [\"sql\"]\n---\n"),
+            (vec!["sql".to_string()], "")
+        );
+        assert_eq!(
+            split_front_matter("No front matter.\n---\n"),
+            (Vec::new(), "No front matter.\n---\n")
+        );
+        assert_eq!(
+            split_front_matter("---\nnot closed\n"),
+            (Vec::new(), "---\nnot closed\n")
+        );
+    }
+
+    #[test]
+    fn test_prompt_matches_query() {
+        let prompt = SavedPrompt::parse(
+            Path::new("/prompts/review.md"),
+            "---\ntags: Rust\n---\nCheck lifetimes.",
+        )
+        .unwrap();
+        assert_eq!(prompt.name, "review");
+        assert!(prompt.matches(&PromptQuery::parse("")));
+        assert!(prompt.matches(&PromptQuery::parse("tag:rust LIFETIMES")));
+        assert!(prompt.matches(&PromptQuery::parse("rev")));
+        assert!(!prompt.matches(&PromptQuery::parse("tag:python")));
+        assert!(!prompt.matches(&PromptQuery::parse("borrow")));
+    }
+}
@@ -1,14 +1,439 @@
-use ai::models::LanguageModel;
+use crate::conflicts::Conflict;
+use crate::prompt_library::split_front_matter;
+use ai::models::{LanguageModel, TruncationDirection};
 use ai::prompts::base::{PromptArguments, PromptChain, PromptPriority, PromptTemplate};
 use ai::prompts::file_context::FileContext;
 use ai::prompts::generate::GenerateInlineContent;
 use ai::prompts::preamble::EngineerPreamble;
 use ai::prompts::repository_context::{PromptCodeSnippet, RepositoryContext};
+use ai::prompts::template::{render_template, TemplateVariables, TemplatedInstructions};
 use ai::providers::open_ai::OpenAiLanguageModel;
-use language::{BufferSnapshot, OffsetRangeExt, ToOffset};
+use fs::Fs;
+use language::{BufferSnapshot, Diagnostic, DiagnosticSeverity, OffsetRangeExt, Point, ToOffset};
 use std::cmp::{self, Reverse};
+use std::collections::BTreeMap;
+use std::fmt::Write;
 use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use util::paths::PROMPTS_DIR;
+
+/// The name of the template used for inline assist instructions.
+pub const INLINE_ASSIST_TEMPLATE: &str = "inline_assist";
+/// The name of the template used to generate tests. A language can have its
+/// own, named after it, such as `generate_tests_rust`.
+pub const GENERATE_TESTS_TEMPLATE: &str = "generate_tests";
+
+const DEFAULT_GENERATE_TESTS_TEMPLATE: &str = "\
+Write unit tests for the following {{language}} code from `{{file_path}}`.
+{{test_conventions}}
+
+The imports of the file it comes from:
+```
+{{imports}}
+```
+
+The code to test:
+```
+{{selection}}
+```
+
+Respond with only the test code, without any explanation.";
+
+/// The name of the template used to write commit messages.
+pub const COMMIT_MESSAGE_TEMPLATE: &str = "commit_message";
+
+const DEFAULT_COMMIT_MESSAGE_TEMPLATE: &str = "\
+Write a commit message for the following staged changes in the Conventional \
+Commits format: a `type(scope): summary` subject line of at most 72 characters, \
+a blank line, then a short body explaining what changed and why, wrapped at 72 \
+columns.
+
+```diff
+{{diff}}
+```
+
+Respond with only the commit message.";
+
+/// The name of the template used to describe a branch for a pull request.
+pub const PULL_REQUEST_TEMPLATE: &str = "pull_request";
+
+const DEFAULT_PULL_REQUEST_TEMPLATE: &str = "\
+Write a pull request for the branch `{{branch}}`, which makes the following \
+changes.
+
+{{changes}}
+
+Respond with only a title of at most 72 characters on the first line, a blank \
+line, then the description. {{description_format}}";
+
+/// Tokens kept free of the diff for the rest of the prompt and the response.
+const DIFF_RESERVED_TOKENS: usize = 1024;
+
+/// The most lines of imports included in a prompt.
+const MAX_IMPORT_LINES: usize = 50;
+
+/// Returns the user's template with the given name, preferring the
+/// `prompt_templates` setting over a `<name>.md` file in the prompts directory,
+/// whose front matter is left out.
+pub async fn load_prompt_template(
+    fs: &dyn Fs,
+    name: &str,
+    settings_templates: &BTreeMap<String, String>,
+) -> Option<String> {
+    if let Some(template) = settings_templates.get(name) {
+        return Some(template.clone());
+    }
+
+    let path = PROMPTS_DIR.join(name).with_extension("md");
+    if fs.is_file(&path).await {
+        match fs.load(&path).await {
+            Ok(template) => return Some(split_front_matter(&template).1.to_string()),
+            Err(error) => log::error!("failed to load prompt template {path:?}: {error}"),
+        }
+    }
+    None
+}
+
+/// Returns the template for generating tests in `language`: the user's template
+/// for the language, then their general one, then the built-in one.
+pub async fn load_generate_tests_template(
+    fs: &dyn Fs,
+    language: &str,
+    settings_templates: &BTreeMap<String, String>,
+) -> String {
+    let language_template = format!(
+        "{GENERATE_TESTS_TEMPLATE}_{}",
+        language.to_lowercase().replace(' ', "_")
+    );
+    if let Some(template) = load_prompt_template(fs, &language_template, settings_templates).await {
+        return template;
+    }
+    load_prompt_template(fs, GENERATE_TESTS_TEMPLATE, settings_templates)
+        .await
+        .unwrap_or_else(|| DEFAULT_GENERATE_TESTS_TEMPLATE.to_string())
+}
+
+/// How tests are usually written in `language`, for the test generation prompt.
+fn test_conventions(language: &str) -> &'static str {
+    match language {
+        "Rust" => "Put them in a `#[cfg(test)] mod tests` module that starts with `use super::*;`, as `#[test]` functions.",
+        "Python" => "Write them as pytest functions named `test_*` that import the code under test from its module.",
+        "JavaScript" | "TypeScript" | "TSX" => "Write them with `describe` and `it` blocks that import the code under test from its module.",
+        "Go" => "Write them in the same package as `func TestXxx(t *testing.T)` functions using the standard `testing` package.",
+        _ => "Follow the most common testing conventions for the language.",
+    }
+}
+
+/// Where tests for the file at `path` usually live. For Rust that's the file
+/// itself, since unit tests go in a module at its end.
+pub fn conventional_test_path(path: &Path, language: &str) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?;
+    match language {
+        "Rust" => Some(path.to_path_buf()),
+        "Python" => Some(path.with_file_name(format!("test_{stem}.py"))),
+        "Go" => Some(path.with_file_name(format!("{stem}_test.go"))),
+        "JavaScript" | "TypeScript" | "TSX" => {
+            let extension = path.extension()?.to_str()?;
+            Some(path.with_file_name(format!("{stem}.test.{extension}")))
+        }
+        _ => None,
+    }
+}
+
+/// The lines of `buffer` that import other code, so the model knows where the
+/// names used by the code under test come from.
+pub fn source_imports(buffer: &BufferSnapshot) -> String {
+    const IMPORT_PREFIXES: &[&str] = &[
+        "use ", "pub use ", "import ", "from ", "#include", "require ", "using ",
+    ];
+    let mut imports = String::new();
+    for line in buffer
+        .text()
+        .lines()
+        .filter(|line| {
+            IMPORT_PREFIXES
+                .iter()
+                .any(|prefix| line.starts_with(prefix))
+        })
+        .take(MAX_IMPORT_LINES)
+    {
+        imports.push_str(line);
+        imports.push('\n');
+    }
+    imports
+}
+
+/// The prompt asking the model to write tests for `selection`.
+pub fn generate_tests_prompt(
+    template: &str,
+    language: &str,
+    file_path: &str,
+    imports: &str,
+    selection: &str,
+) -> anyhow::Result<String> {
+    let mut variables = TemplateVariables::new();
+    variables
+        .insert("language", language)
+        .insert("file_path", file_path)
+        .insert("imports", imports.trim_end())
+        .insert("selection", selection.trim_end())
+        .insert("test_conventions", test_conventions(language));
+    render_template(template, &variables)
+}
+
+/// Cuts `diff` down to what fits in `model`'s context alongside the rest of a
+/// prompt, noting when it had to be truncated.
+pub fn truncate_diff(diff: &str, model: &dyn LanguageModel) -> anyhow::Result<String> {
+    let budget = model.capacity()?.saturating_sub(DIFF_RESERVED_TOKENS);
+    if model.count_tokens(diff)? <= budget {
+        return Ok(diff.to_string());
+    }
+
+    let mut truncated = model.truncate(diff, budget, TruncationDirection::End)?;
+    truncated.push_str("\n[the rest of the diff was truncated]");
+    Ok(truncated)
+}
+
+/// The prompt asking the model for a commit message describing `diff`, which
+/// should already fit in the model's context (see [`truncate_diff`]).
+pub async fn commit_message_prompt(
+    fs: &dyn Fs,
+    diff: &str,
+    settings_templates: &BTreeMap<String, String>,
+) -> anyhow::Result<String> {
+    let template = load_prompt_template(fs, COMMIT_MESSAGE_TEMPLATE, settings_templates)
+        .await
+        .unwrap_or_else(|| DEFAULT_COMMIT_MESSAGE_TEMPLATE.to_string());
+    let mut variables = TemplateVariables::new();
+    variables.insert("diff", diff.trim_end());
+    render_template(&template, &variables)
+}
+
+/// Splits `diff` into pieces that each fit in `model`'s context alongside the
+/// rest of a prompt, keeping each file's changes together where they fit. The
+/// whole diff is returned as is if it fits.
+pub fn split_diff(diff: &str, model: &dyn LanguageModel) -> anyhow::Result<Vec<String>> {
+    let budget = model.capacity()?.saturating_sub(DIFF_RESERVED_TOKENS);
+    if model.count_tokens(diff)? <= budget {
+        return Ok(vec![diff.to_string()]);
+    }
+
+    let mut files = Vec::new();
+    let mut file_start = 0;
+    for (offset, _) in diff.match_indices("diff --git ") {
+        if offset > 0 && diff[..offset].ends_with('\n') {
+            files.push(&diff[file_start..offset]);
+            file_start = offset;
+        }
+    }
+    files.push(&diff[file_start..]);
+
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chunk_tokens = 0;
+    for file in files {
+        let mut file = file.to_string();
+        let mut file_tokens = model.count_tokens(&file)?;
+        if file_tokens > budget {
+            file = model.truncate(&file, budget, TruncationDirection::End)?;
+            file_tokens = budget;
+        }
+        if chunk_tokens + file_tokens > budget && !chunk.is_empty() {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_tokens = 0;
+        }
+        chunk.push_str(&file);
+        chunk_tokens += file_tokens;
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    Ok(chunks)
+}
+
+/// The prompt asking the model to summarize one piece of a diff too large to
+/// send at once.
+pub fn diff_summary_prompt(chunk: &str) -> String {
+    format!(
+        "Summarize the following changes as a short list of what changed and why, \
+         for someone writing a description of them.\n\n```diff\n{}\n```",
+        chunk.trim_end()
+    )
+}
+
+/// The prompt asking the model to summarize a file that's too large to include
+/// in a conversation as it is.
+pub fn file_summary_prompt(path: &str, language: Option<&str>, content: &str) -> String {
+    format!(
+        "Summarize the purpose of `{path}` and its most important definitions, \
+         with their signatures, for someone asking questions about it.\n\n```{}\n{}\n```",
+        language.unwrap_or_default().to_lowercase(),
+        content.trim_end()
+    )
+}
+
+/// The message asking the model to summarize the older messages of a
+/// conversation that's running out of room in the model's context.
+pub fn summarize_conversation_prompt() -> String {
+    "Summarize the conversation so far so that it can continue from the summary alone. \
+     Keep every decision, requirement, code snippet and open question that later \
+     messages could refer to, and leave out pleasantries."
+        .into()
+}
+
+/// The message that takes the place of the messages summarized by `summary`.
+pub fn conversation_summary_message(summary: &str) -> String {
+    format!("Summary of the earlier conversation:\n\n{}", summary.trim())
+}
+
+/// The prompt asking the model for a pull request title and description of the
+/// changes on `branch`, which are either the diff itself or summaries of its
+/// pieces. The description follows `repository_template` if the repository has
+/// one.
+pub async fn pull_request_prompt(
+    fs: &dyn Fs,
+    branch: &str,
+    changes: &str,
+    repository_template: Option<&str>,
+    settings_templates: &BTreeMap<String, String>,
+) -> anyhow::Result<String> {
+    let template = load_prompt_template(fs, PULL_REQUEST_TEMPLATE, settings_templates)
+        .await
+        .unwrap_or_else(|| DEFAULT_PULL_REQUEST_TEMPLATE.to_string());
+    let description_format = match repository_template {
+        Some(repository_template) => format!(
+            "Fill in the repository's pull request template for the description:\n\n```markdown\n{}\n```",
+            repository_template.trim_end()
+        ),
+        None => "Write the description in Markdown, explaining what changed and why.".into(),
+    };
+    let mut variables = TemplateVariables::new();
+    variables
+        .insert("branch", branch)
+        .insert("changes", changes.trim_end())
+        .insert("description_format", description_format);
+    render_template(&template, &variables)
+}
+
+pub fn severity_name(severity: DiagnosticSeverity) -> &'static str {
+    match severity {
+        DiagnosticSeverity::ERROR => "error",
+        DiagnosticSeverity::WARNING => "warning",
+        DiagnosticSeverity::INFORMATION => "info",
+        _ => "hint",
+    }
+}
+
+/// The instruction sent to the inline assistant to fix `diagnostic`. The code
+/// around it and the language are added by [`generate_content_prompt`].
+pub fn fix_diagnostic_prompt(diagnostic: &Diagnostic) -> String {
+    let mut prompt = format!(
+        "Fix the following {} reported",
+        severity_name(diagnostic.severity)
+    );
+    if let Some(source) = &diagnostic.source {
+        write!(prompt, " by {source}").unwrap();
+    }
+    write!(
+        prompt,
+        ": {}\nOnly change what's needed to fix it.",
+        diagnostic.message
+    )
+    .unwrap();
+    prompt
+}
+
+/// The instruction sent to the inline assistant to resolve `conflict`, which is
+/// selected along with its markers so the model sees both sides.
+pub fn resolve_conflict_prompt(conflict: &Conflict) -> String {
+    let side_name = |name: &str, fallback: &'static str| {
+        if name.is_empty() {
+            fallback.to_string()
+        } else {
+            format!("`{name}`")
+        }
+    };
+    format!(
+        "Resolve this merge conflict between {} and {}, keeping the intent of both sides.\n\
+         Respond with only the resolved code, without any conflict markers.",
+        side_name(&conflict.ours_name, "our side"),
+        side_name(&conflict.theirs_name, "their side"),
+    )
+}
+
+/// The instruction asking the model for a `shell` command on `os` that does
+/// what `request` describes.
+pub fn terminal_command_prompt(
+    request: &str,
+    shell: &str,
+    os: &str,
+    working_directory: Option<&Path>,
+) -> String {
+    let mut prompt =
+        format!("Write a {shell} command for {os} that does the following: {request}\n");
+    if let Some(working_directory) = working_directory {
+        writeln!(
+            prompt,
+            "It will be run from `{}`.",
+            working_directory.display()
+        )
+        .unwrap();
+    }
+    prompt.push_str("Respond with only the command on a single line, without any explanation.");
+    prompt
+}
+
+/// The message asking the model to explain the last command run in a terminal,
+/// given the `output` at the end of its scrollback.
+pub fn explain_terminal_output_prompt(output: &str, shell: &str) -> String {
+    format!(
+        "Here is the end of my {shell} terminal:\n```\n{}\n```\n\n\
+         Explain what the last command did and what its output means. If it \
+         failed, explain why and how to fix it.",
+        output.trim_end()
+    )
+}
+
+/// The variables available to prompt templates for a selection in a buffer.
+pub fn template_variables(
+    user_prompt: &str,
+    language_name: Option<&str>,
+    buffer: &BufferSnapshot,
+    range: Range<usize>,
+) -> TemplateVariables {
+    let file_path = buffer
+        .file()
+        .map(|file| file.path().to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut diagnostics = String::new();
+    for entry in buffer.diagnostics_in_range::<_, Point>(range.clone(), false) {
+        if !entry.diagnostic.is_primary {
+            continue;
+        }
+        writeln!(
+            diagnostics,
+            "line {}: {}: {}",
+            entry.range.start.row + 1,
+            severity_name(entry.diagnostic.severity),
+            entry.diagnostic.message
+        )
+        .unwrap();
+    }
+
+    let mut variables = TemplateVariables::new();
+    variables
+        .insert("user_prompt", user_prompt)
+        .insert(
+            "selection",
+            buffer.text_for_range(range).collect::<String>(),
+        )
+        .insert("file_path", file_path)
+        .insert("language", language_name.unwrap_or_default())
+        .insert("diagnostics", diagnostics);
+    variables
+}
 
 #[allow(dead_code)]
 fn summarize(buffer: &BufferSnapshot, selected_range: Range<impl ToOffset>) -> String {
@@ -129,6 +554,8 @@ pub fn generate_content_prompt(
     search_results: Vec<PromptCodeSnippet>,
     model: &str,
     project_name: Option<String>,
+    template: Option<String>,
+    include_preamble: bool,
 ) -> anyhow::Result<String> {
     // Using new Prompt Templates
     let openai_model: Arc<dyn LanguageModel> = Arc::new(OpenAiLanguageModel::load(model));
@@ -138,6 +565,15 @@ pub fn generate_content_prompt(
         None
     };
 
+    let instructions: Box<dyn PromptTemplate> = if let Some(template) = template {
+        Box::new(TemplatedInstructions {
+            variables: template_variables(&user_prompt, language_name, &buffer, range.clone()),
+            template,
+        })
+    } else {
+        Box::new(GenerateInlineContent {})
+    };
+
     let args = PromptArguments {
         model: openai_model,
         language_name: lang_name.clone(),
@@ -149,8 +585,7 @@ pub fn generate_content_prompt(
         user_prompt: Some(user_prompt.clone()),
     };
 
-    let templates: Vec<(PromptPriority, Box<dyn PromptTemplate>)> = vec![
-        (PromptPriority::Mandatory, Box::new(EngineerPreamble {})),
+    let mut templates: Vec<(PromptPriority, Box<dyn PromptTemplate>)> = vec![
         (
             PromptPriority::Ordered { order: 1 },
             Box::new(RepositoryContext {}),
@@ -159,11 +594,15 @@ pub fn generate_content_prompt(
             PromptPriority::Ordered { order: 0 },
             Box::new(FileContext {}),
         ),
-        (
-            PromptPriority::Mandatory,
-            Box::new(GenerateInlineContent {}),
-        ),
+        (PromptPriority::Mandatory, instructions),
     ];
+    // A system prompt from the settings takes the place of the built-in preamble.
+    if include_preamble {
+        templates.insert(
+            0,
+            (PromptPriority::Mandatory, Box::new(EngineerPreamble {})),
+        );
+    }
     let chain = PromptChain::new(args, templates);
     let (prompt, _) = chain.generate(true)?;
 
@@ -173,6 +612,7 @@ pub fn generate_content_prompt(
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
+    use ai::test::FakeLanguageModel;
     use gpui::{AppContext, Context};
     use indoc::indoc;
     use language::{
@@ -391,4 +831,134 @@ pub(crate) mod tests {
             "}
         );
     }
+
+    #[gpui::test]
+    fn test_template_variables(cx: &mut AppContext) {
+        let settings_store = SettingsStore::test(cx);
+        cx.set_global(settings_store);
+        language_settings::init(cx);
+        let text = "fn main() {\n    let x = 1;\n}\n";
+        let buffer = cx.new_model(|cx| {
+            Buffer::new(0, BufferId::new(1).unwrap(), text).with_language(Arc::new(rust_lang()), cx)
+        });
+        let snapshot = buffer.read(cx).snapshot();
+        let range = text.find("let").unwrap()..text.find(";").unwrap() + 1;
+
+        let variables = template_variables("inline x", Some("Rust"), &snapshot, range);
+        assert_eq!(variables.get("user_prompt"), Some("inline x"));
+        assert_eq!(variables.get("selection"), Some("let x = 1;"));
+        assert_eq!(variables.get("language"), Some("Rust"));
+        assert_eq!(variables.get("file_path"), Some(""));
+        assert_eq!(variables.get("diagnostics"), Some(""));
+    }
+
+    #[test]
+    fn test_fix_diagnostic_prompt() {
+        let diagnostic = Diagnostic {
+            source: Some("rustc".into()),
+            severity: DiagnosticSeverity::ERROR,
+            message: "cannot find value `y` in this scope".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            fix_diagnostic_prompt(&diagnostic),
+            "Fix the following error reported by rustc: cannot find value `y` in this scope\nOnly change what's needed to fix it."
+        );
+    }
+
+    #[test]
+    fn test_split_diff() {
+        let first_file = "diff --git a/a.rs b/a.rs\n+fn a() {}\n";
+        let second_file = "diff --git a/b.rs b/b.rs\n+fn b() {}\n";
+        let diff = format!("{first_file}{second_file}");
+
+        let model = FakeLanguageModel {
+            capacity: DIFF_RESERVED_TOKENS + diff.len(),
+        };
+        assert_eq!(split_diff(&diff, &model).unwrap(), vec![diff.clone()]);
+
+        let model = FakeLanguageModel {
+            capacity: DIFF_RESERVED_TOKENS + first_file.len(),
+        };
+        assert_eq!(
+            split_diff(&diff, &model).unwrap(),
+            vec![first_file.to_string(), second_file.to_string()]
+        );
+
+        let model = FakeLanguageModel {
+            capacity: DIFF_RESERVED_TOKENS + 10,
+        };
+        let chunks = split_diff(&diff, &model).unwrap();
+        assert_eq!(chunks, vec!["diff --git", "diff --git"]);
+    }
+
+    #[test]
+    fn test_resolve_conflict_prompt() {
+        let conflict = Conflict {
+            range: 0..0,
+            ours_name: "HEAD".into(),
+            theirs_name: "feature".into(),
+        };
+        assert_eq!(
+            resolve_conflict_prompt(&conflict),
+            "Resolve this merge conflict between `HEAD` and `feature`, keeping the intent of both sides.\nRespond with only the resolved code, without any conflict markers."
+        );
+    }
+
+    #[test]
+    fn test_terminal_command_prompt() {
+        assert_eq!(
+            terminal_command_prompt(
+                "list files changed today",
+                "zsh",
+                "macos",
+                Some(Path::new("/tmp/project"))
+            ),
+            "Write a zsh command for macos that does the following: list files changed today\n\
+             It will be run from `/tmp/project`.\n\
+             Respond with only the command on a single line, without any explanation."
+        );
+    }
+
+    #[test]
+    fn test_conventional_test_path() {
+        assert_eq!(
+            conventional_test_path(Path::new("src/lib.rs"), "Rust"),
+            Some(PathBuf::from("src/lib.rs"))
+        );
+        assert_eq!(
+            conventional_test_path(Path::new("app/models.py"), "Python"),
+            Some(PathBuf::from("app/test_models.py"))
+        );
+        assert_eq!(
+            conventional_test_path(Path::new("server/handler.go"), "Go"),
+            Some(PathBuf::from("server/handler_test.go"))
+        );
+        assert_eq!(
+            conventional_test_path(Path::new("src/parse.ts"), "TypeScript"),
+            Some(PathBuf::from("src/parse.test.ts"))
+        );
+        assert_eq!(conventional_test_path(Path::new("main.c"), "C"), None);
+    }
+
+    #[gpui::test]
+    fn test_generate_tests_prompt(cx: &mut AppContext) {
+        let text = "use std::fmt;\nuse crate::parser::Parser;\n\nfn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n";
+        let buffer = cx.new_model(|_| Buffer::new(0, BufferId::new(1).unwrap(), text));
+        let imports = source_imports(&buffer.read(cx).snapshot());
+        assert_eq!(imports, "use std::fmt;\nuse crate::parser::Parser;\n");
+
+        let prompt = generate_tests_prompt(
+            "Test this {{language}} from {{file_path}}:\n{{imports}}\n{{selection}}",
+            "Rust",
+            "src/math.rs",
+            &imports,
+            "fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            prompt,
+            "Test this Rust from src/math.rs:\nuse std::fmt;\nuse crate::parser::Parser;\nfn add(a: i32, b: i32) -> i32 {\n    a + b\n}"
+        );
+    }
 }
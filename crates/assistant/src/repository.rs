@@ -0,0 +1,54 @@
+use fs::{repository::GitRepository, Fs};
+use parking_lot::Mutex;
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Where GitHub looks for the template a pull request's description starts from,
+/// relative to the root of the repository.
+const PULL_REQUEST_TEMPLATE_PATHS: &[&str] = &[
+    ".github/pull_request_template.md",
+    ".github/PULL_REQUEST_TEMPLATE.md",
+    "pull_request_template.md",
+    "PULL_REQUEST_TEMPLATE.md",
+    "docs/pull_request_template.md",
+    "docs/PULL_REQUEST_TEMPLATE.md",
+];
+
+/// Finds the `.git` directory of the repository containing `abs_path`. This
+/// also finds it for files inside the `.git` directory, such as the
+/// `COMMIT_EDITMSG` that git opens the editor on.
+pub async fn dot_git_for_path(fs: &dyn Fs, abs_path: &Path) -> Option<PathBuf> {
+    for ancestor in abs_path.ancestors() {
+        if ancestor.file_name() == Some(OsStr::new(".git")) {
+            return Some(ancestor.to_path_buf());
+        }
+
+        let dot_git = ancestor.join(".git");
+        if fs.metadata(&dot_git).await.ok().flatten().is_some() {
+            return Some(dot_git);
+        }
+    }
+    None
+}
+
+/// Opens the git repository containing `abs_path`.
+pub async fn repository_for_path(
+    fs: &dyn Fs,
+    abs_path: &Path,
+) -> Option<Arc<Mutex<dyn GitRepository>>> {
+    let dot_git = dot_git_for_path(fs, abs_path).await?;
+    fs.open_repo(&dot_git)
+}
+
+/// The repository's pull request template, if it has one.
+pub async fn pull_request_template(fs: &dyn Fs, work_directory: &Path) -> Option<String> {
+    for path in PULL_REQUEST_TEMPLATE_PATHS {
+        if let Ok(template) = fs.load(&work_directory.join(path)).await {
+            return Some(template);
+        }
+    }
+    None
+}
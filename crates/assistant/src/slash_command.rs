@@ -0,0 +1,360 @@
+mod diagnostics_command;
+mod file_command;
+mod model_command;
+mod prompt_command;
+mod search_command;
+mod symbol_command;
+mod tab_command;
+mod tabs_command;
+mod terminal_command;
+mod web_command;
+
+use ai::models::ModelName;
+use anyhow::Result;
+use collections::BTreeMap;
+use editor::{CompletionProvider, Editor};
+use gpui::{AppContext, Global, Model, Task, ViewContext, WeakView, WindowContext};
+use language::{
+    Anchor, Buffer, CodeLabel, Completion, Documentation, LanguageServerId, Point, ToPoint,
+};
+use parking_lot::RwLock;
+use std::{ops::Range, sync::Arc};
+use workspace::Workspace;
+
+pub fn init(cx: &mut AppContext) {
+    SlashCommandRegistry::register(diagnostics_command::DiagnosticsSlashCommand, cx);
+    SlashCommandRegistry::register(file_command::FileSlashCommand, cx);
+    SlashCommandRegistry::register(model_command::ModelSlashCommand, cx);
+    SlashCommandRegistry::register(prompt_command::PromptSlashCommand, cx);
+    SlashCommandRegistry::register(search_command::SearchSlashCommand, cx);
+    SlashCommandRegistry::register(symbol_command::SymbolSlashCommand, cx);
+    SlashCommandRegistry::register(tab_command::TabSlashCommand, cx);
+    SlashCommandRegistry::register(tabs_command::TabsSlashCommand, cx);
+    SlashCommandRegistry::register(terminal_command::TerminalSlashCommand, cx);
+    SlashCommandRegistry::register(web_command::WebSlashCommand, cx);
+}
+
+/// A command typed on its own line in a conversation, such as `/tab main.rs`,
+/// that's replaced with its output when Enter is pressed at the end of the line.
+pub trait SlashCommand: 'static {
+    /// The name the command is typed as, without the slash.
+    fn name(&self) -> String;
+
+    /// What the command does, shown when completing command names.
+    fn description(&self) -> String;
+
+    /// What the command's argument is, such as `path`, or `None` if it doesn't
+    /// take one.
+    fn argument_placeholder(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether the command can't be run without an argument.
+    fn requires_argument(&self) -> bool {
+        false
+    }
+
+    /// Suggestions for the argument, given what's been typed of it so far.
+    fn complete_argument(
+        &self,
+        _query: String,
+        _workspace: WeakView<Workspace>,
+        _cx: &mut WindowContext,
+    ) -> Task<Result<Vec<String>>> {
+        Task::ready(Ok(Vec::new()))
+    }
+
+    fn run(
+        &self,
+        argument: Option<&str>,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<SlashCommandOutput>>;
+}
+
+/// What running a slash command produces.
+#[derive(Debug, Default)]
+pub struct SlashCommandOutput {
+    /// The text that takes the place of the command in the conversation.
+    pub text: String,
+    /// Ranges of `text` to fold once it's inserted, such as a file's contents
+    /// below its path, so that long context doesn't crowd the conversation.
+    pub folded_ranges: Vec<Range<usize>>,
+    /// A model to switch the conversation to.
+    pub model: Option<ModelName>,
+}
+
+impl SlashCommandOutput {
+    /// `content` in a code block whose info string has the language and the
+    /// `path` it came from. The contents are folded below that line.
+    pub fn code_block(path: &str, language: Option<&str>, content: &str) -> Self {
+        // Use a longer fence than any in the content so it can't close the block.
+        let mut fence = "```".to_string();
+        while content.contains(&fence) {
+            fence.push('`');
+        }
+        let language = language.map(str::to_lowercase).unwrap_or_default();
+        let header = format!("{fence}{language} {path}");
+        let mut text = format!("{header}\n{content}");
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&fence);
+        Self {
+            folded_ranges: vec![header.len()..text.len()],
+            text,
+            model: None,
+        }
+    }
+
+    /// A line of `header` followed by `content`, which is folded below it.
+    pub fn section(header: &str, content: &str) -> Self {
+        let text = format!("{header}\n{}", content.trim());
+        Self {
+            folded_ranges: vec![header.len()..text.len()],
+            text,
+            model: None,
+        }
+    }
+
+    /// The outputs one after another, separated by blank lines.
+    pub fn join(outputs: impl IntoIterator<Item = Self>) -> Self {
+        let mut joined = Self::default();
+        for output in outputs {
+            if !joined.text.is_empty() {
+                joined.text.push_str("\n\n");
+            }
+            let offset = joined.text.len();
+            joined.text.push_str(&output.text);
+            joined.folded_ranges.extend(
+                output
+                    .folded_ranges
+                    .into_iter()
+                    .map(|range| offset + range.start..offset + range.end),
+            );
+            joined.model = output.model.or(joined.model);
+        }
+        joined
+    }
+}
+
+/// The slash commands available in conversations. Other crates can add theirs
+/// with [`SlashCommandRegistry::register`].
+#[derive(Default)]
+pub struct SlashCommandRegistry {
+    commands: BTreeMap<String, Arc<dyn SlashCommand>>,
+}
+
+impl Global for SlashCommandRegistry {}
+
+impl SlashCommandRegistry {
+    /// Adds `command`, replacing any command with the same name.
+    pub fn register(command: impl SlashCommand, cx: &mut AppContext) {
+        let command = Arc::new(command);
+        cx.default_global::<Self>()
+            .commands
+            .insert(command.name(), command);
+    }
+
+    pub fn command(name: &str, cx: &AppContext) -> Option<Arc<dyn SlashCommand>> {
+        cx.try_global::<Self>()?.commands.get(name).cloned()
+    }
+
+    /// Every registered command, ordered by name.
+    pub fn commands(cx: &AppContext) -> Vec<Arc<dyn SlashCommand>> {
+        cx.try_global::<Self>()
+            .map(|registry| registry.commands.values().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// A line that invokes a slash command: the command's name and the rest of the
+/// line as its argument.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct SlashCommandLine<'a> {
+    pub name: &'a str,
+    pub argument: Option<&'a str>,
+}
+
+impl<'a> SlashCommandLine<'a> {
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let line = line.trim_end().strip_prefix('/')?;
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, Some(argument.trim()).filter(|a| !a.is_empty())),
+            None => (line, None),
+        };
+        let is_name_char = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+        if name.is_empty() || !name.chars().all(is_name_char) {
+            return None;
+        }
+        Some(Self { name, argument })
+    }
+}
+
+/// Completes slash command names, and then their arguments, in the
+/// conversation editor.
+pub(crate) struct SlashCommandCompletionProvider {
+    workspace: WeakView<Workspace>,
+}
+
+impl SlashCommandCompletionProvider {
+    pub fn new(workspace: WeakView<Workspace>) -> Self {
+        Self { workspace }
+    }
+
+    fn command_completions(&self, name_range: Range<Anchor>, cx: &AppContext) -> Vec<Completion> {
+        SlashCommandRegistry::commands(cx)
+            .into_iter()
+            .map(|command| {
+                let name = command.name();
+                let mut label = format!("/{name}");
+                let new_text = match command.argument_placeholder() {
+                    Some(placeholder) => {
+                        label.push_str(&format!(" <{placeholder}>"));
+                        format!("{name} ")
+                    }
+                    None => name.clone(),
+                };
+                Completion {
+                    old_range: name_range.clone(),
+                    new_text,
+                    label: CodeLabel {
+                        filter_range: 1..name.len() + 1,
+                        text: label,
+                        runs: Vec::new(),
+                    },
+                    documentation: Some(Documentation::SingleLine(command.description())),
+                    server_id: LanguageServerId(0),
+                    lsp_completion: Default::default(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl CompletionProvider for SlashCommandCompletionProvider {
+    fn completions(
+        &self,
+        buffer: &Model<Buffer>,
+        buffer_position: Anchor,
+        cx: &mut ViewContext<Editor>,
+    ) -> Task<Result<Vec<Completion>>> {
+        let buffer = buffer.read(cx);
+        let position = buffer_position.to_point(buffer);
+        let line_start = Point::new(position.row, 0);
+        let line = buffer
+            .text_for_range(line_start..position)
+            .collect::<String>();
+        let Some(command_line) = SlashCommandLine::parse(&line) else {
+            return Task::ready(Ok(Vec::new()));
+        };
+
+        // Until there's a space after the name, the name itself is completed.
+        let name_end = 1 + command_line.name.len();
+        if line.len() == name_end {
+            let name_start = buffer.anchor_after(Point::new(position.row, 1));
+            return Task::ready(Ok(self.command_completions(name_start..buffer_position, cx)));
+        }
+
+        let Some(command) = SlashCommandRegistry::command(command_line.name, cx) else {
+            return Task::ready(Ok(Vec::new()));
+        };
+        let argument_offset = line[name_end..]
+            .find(|c: char| !c.is_whitespace())
+            .map_or(line.len(), |offset| name_end + offset);
+        let argument_start = buffer.anchor_after(Point::new(position.row, argument_offset as u32));
+        let query = line[argument_offset..].to_string();
+        let arguments = command.complete_argument(query, self.workspace.clone(), cx);
+        cx.background_executor().spawn(async move {
+            Ok(arguments
+                .await?
+                .into_iter()
+                .map(|argument| Completion {
+                    old_range: argument_start..buffer_position,
+                    label: CodeLabel {
+                        filter_range: 0..argument.len(),
+                        text: argument.clone(),
+                        runs: Vec::new(),
+                    },
+                    new_text: argument,
+                    documentation: None,
+                    server_id: LanguageServerId(0),
+                    lsp_completion: Default::default(),
+                })
+                .collect())
+        })
+    }
+
+    fn resolve_completions(
+        &self,
+        _completion_indices: Vec<usize>,
+        _completions: Arc<RwLock<Box<[Completion]>>>,
+        _cx: &mut ViewContext<Editor>,
+    ) -> Task<Result<bool>> {
+        Task::ready(Ok(false))
+    }
+
+    fn apply_additional_edits_for_completion(
+        &self,
+        _buffer: Model<Buffer>,
+        _completion: Completion,
+        _push_to_history: bool,
+        _cx: &mut ViewContext<Editor>,
+    ) -> Task<Result<Option<language::Transaction>>> {
+        Task::ready(Ok(None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_slash_command_line() {
+        assert_eq!(
+            SlashCommandLine::parse("/tab src/main.rs\n"),
+            Some(SlashCommandLine {
+                name: "tab",
+                argument: Some("src/main.rs"),
+            })
+        );
+        assert_eq!(
+            SlashCommandLine::parse("/model  "),
+            Some(SlashCommandLine {
+                name: "model",
+                argument: None,
+            })
+        );
+        assert_eq!(SlashCommandLine::parse("/"), None);
+        assert_eq!(SlashCommandLine::parse("/usr/bin/env"), None);
+        assert_eq!(SlashCommandLine::parse("see /tab"), None);
+    }
+
+    #[test]
+    fn test_code_block_output() {
+        let output = SlashCommandOutput::code_block("src/main.rs", Some("Rust"), "fn main() {}");
+        assert_eq!(output.text, "```rust src/main.rs\nfn main() {}\n```");
+        assert_eq!(
+            &output.text[output.folded_ranges[0].clone()],
+            "\nfn main() {}\n```"
+        );
+
+        let output = SlashCommandOutput::code_block("README.md", None, "```sh\nls\n```\n");
+        assert_eq!(output.text, "```` README.md\n```sh\nls\n```\n````");
+    }
+
+    #[test]
+    fn test_join_outputs() {
+        let output = SlashCommandOutput::join([
+            SlashCommandOutput::code_block("a.txt", None, "a"),
+            SlashCommandOutput::code_block("b.txt", None, "b"),
+        ]);
+        assert_eq!(output.text, "``` a.txt\na\n```\n\n``` b.txt\nb\n```");
+        let folded = output
+            .folded_ranges
+            .iter()
+            .map(|range| &output.text[range.clone()])
+            .collect::<Vec<_>>();
+        assert_eq!(folded, ["\na\n```", "\nb\n```"]);
+    }
+}
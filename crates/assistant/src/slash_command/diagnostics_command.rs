@@ -0,0 +1,179 @@
+use super::{SlashCommand, SlashCommandOutput};
+use crate::{file_reference::format_file_reference, prompts::severity_name};
+use anyhow::{anyhow, Result};
+use collections::HashSet;
+use editor::Editor;
+use futures::future;
+use gpui::{AppContext, Model, Task, WeakView, WindowContext};
+use language::{Buffer, DiagnosticEntry, DiagnosticSeverity, Point};
+use std::fmt::Write;
+use workspace::Workspace;
+
+/// Lines of code included above and below each diagnostic.
+const DIAGNOSTIC_CONTEXT_LINES: u32 = 2;
+
+/// The most diagnostics inserted at once, so a broken build doesn't flood the
+/// conversation.
+const MAX_DIAGNOSTICS: usize = 50;
+
+/// Inserts the errors and warnings in the active editor, or in the whole
+/// project with `/diagnostics project`, each with the code around it.
+pub(crate) struct DiagnosticsSlashCommand;
+
+impl SlashCommand for DiagnosticsSlashCommand {
+    fn name(&self) -> String {
+        "diagnostics".into()
+    }
+
+    fn description(&self) -> String {
+        "insert errors and warnings with their code".into()
+    }
+
+    fn argument_placeholder(&self) -> Option<String> {
+        Some("project".into())
+    }
+
+    fn complete_argument(
+        &self,
+        _query: String,
+        _workspace: WeakView<Workspace>,
+        _cx: &mut WindowContext,
+    ) -> Task<Result<Vec<String>>> {
+        Task::ready(Ok(vec!["project".into()]))
+    }
+
+    fn run(
+        &self,
+        argument: Option<&str>,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<SlashCommandOutput>> {
+        let Some(workspace) = workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+
+        let buffers = match argument {
+            None => {
+                let buffer = workspace
+                    .read(cx)
+                    .active_item_as::<Editor>(cx)
+                    .and_then(|editor| editor.read(cx).buffer().read(cx).as_singleton());
+                match buffer {
+                    Some(buffer) => vec![Task::ready(Ok(buffer))],
+                    None => return Task::ready(Err(anyhow!("no active tab"))),
+                }
+            }
+            Some("project") => {
+                let project = workspace.read(cx).project().clone();
+                let mut seen = HashSet::default();
+                let paths = project
+                    .read(cx)
+                    .diagnostic_summaries(false, cx)
+                    .filter(|(_, _, summary)| summary.error_count + summary.warning_count > 0)
+                    .map(|(path, _, _)| path)
+                    .filter(|path| seen.insert(path.clone()))
+                    .collect::<Vec<_>>();
+                project.update(cx, |project, cx| {
+                    paths
+                        .into_iter()
+                        .map(|path| project.open_buffer(path, cx))
+                        .collect()
+                })
+            }
+            Some(argument) => {
+                return Task::ready(Err(anyhow!("unknown argument {argument:?}")));
+            }
+        };
+
+        cx.spawn(|mut cx| async move {
+            let buffers = future::try_join_all(buffers).await?;
+            cx.update(|cx| diagnostics_output(&buffers, cx))?
+        })
+    }
+}
+
+fn diagnostics_output(buffers: &[Model<Buffer>], cx: &AppContext) -> Result<SlashCommandOutput> {
+    let mut outputs = Vec::new();
+    let mut omitted = 0;
+    for buffer in buffers {
+        let buffer = buffer.read(cx);
+        let snapshot = buffer.snapshot();
+        let path = buffer.file().map_or_else(
+            || "untitled".to_string(),
+            |file| file.path().to_string_lossy().into_owned(),
+        );
+        let language = snapshot.language().map(|language| language.name());
+        let entries = snapshot
+            .diagnostics_in_range::<_, Point>(0..snapshot.len(), false)
+            .filter(|entry| {
+                entry.diagnostic.is_primary
+                    && entry.diagnostic.severity <= DiagnosticSeverity::WARNING
+            });
+        for entry in entries {
+            if outputs.len() == MAX_DIAGNOSTICS {
+                omitted += 1;
+                continue;
+            }
+            outputs.push(diagnostic_output(
+                &path,
+                language.as_deref(),
+                &snapshot,
+                &entry,
+            ));
+        }
+    }
+
+    if outputs.is_empty() {
+        return Err(anyhow!("there are no errors or warnings"));
+    }
+    let mut output = SlashCommandOutput::join(outputs);
+    if omitted > 0 {
+        write!(output.text, "\n\n...and {omitted} more").unwrap();
+    }
+    Ok(output)
+}
+
+/// The diagnostic's message on one line, followed by the lines around it.
+fn diagnostic_output(
+    path: &str,
+    language: Option<&str>,
+    snapshot: &language::BufferSnapshot,
+    entry: &DiagnosticEntry<Point>,
+) -> SlashCommandOutput {
+    let start = entry.range.start;
+    let diagnostic = &entry.diagnostic;
+    let mut header = format!(
+        "{} at {path}:{}:{}",
+        severity_name(diagnostic.severity),
+        start.row + 1,
+        start.column + 1
+    );
+    if let Some(source) = &diagnostic.source {
+        write!(header, " ({source})").unwrap();
+    }
+    write!(
+        header,
+        ": {}",
+        diagnostic.message.lines().next().unwrap_or_default()
+    )
+    .unwrap();
+
+    let first_row = start.row.saturating_sub(DIAGNOSTIC_CONTEXT_LINES);
+    let last_row = (entry.range.end.row + DIAGNOSTIC_CONTEXT_LINES).min(snapshot.max_point().row);
+    let code = snapshot
+        .text_for_range(Point::new(first_row, 0)..Point::new(last_row, snapshot.line_len(last_row)))
+        .collect::<String>();
+    let code_path = format_file_reference(path, first_row..last_row);
+    let code_block = SlashCommandOutput::code_block(&code_path, language, &code);
+
+    let offset = header.len() + 1;
+    SlashCommandOutput {
+        text: format!("{header}\n{}", code_block.text),
+        folded_ranges: code_block
+            .folded_ranges
+            .into_iter()
+            .map(|range| offset + range.start..offset + range.end)
+            .collect(),
+        model: None,
+    }
+}
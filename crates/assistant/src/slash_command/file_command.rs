@@ -0,0 +1,101 @@
+use super::{SlashCommand, SlashCommandOutput};
+use crate::file_reference::project_path_for_reference;
+use anyhow::{anyhow, Result};
+use gpui::{Task, WeakView, WindowContext};
+use project::PathMatchCandidateSet;
+use std::sync::atomic::AtomicBool;
+use workspace::Workspace;
+
+/// How many paths to suggest while completing the argument.
+const MAX_PATH_COMPLETIONS: usize = 50;
+
+/// Inserts the contents of a file in the project, whether or not it's open.
+pub(crate) struct FileSlashCommand;
+
+impl SlashCommand for FileSlashCommand {
+    fn name(&self) -> String {
+        "file".into()
+    }
+
+    fn description(&self) -> String {
+        "insert a project file's contents".into()
+    }
+
+    fn argument_placeholder(&self) -> Option<String> {
+        Some("path".into())
+    }
+
+    fn requires_argument(&self) -> bool {
+        true
+    }
+
+    fn complete_argument(
+        &self,
+        query: String,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<Vec<String>>> {
+        let Some(workspace) = workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+        let project = workspace.read(cx).project().read(cx);
+        let include_root_name = project.visible_worktrees(cx).count() > 1;
+        let candidate_sets = project
+            .visible_worktrees(cx)
+            .map(|worktree| {
+                let worktree = worktree.read(cx);
+                PathMatchCandidateSet {
+                    snapshot: worktree.snapshot(),
+                    include_ignored: worktree
+                        .root_entry()
+                        .map_or(false, |entry| entry.is_ignored),
+                    include_root_name,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let executor = cx.background_executor().clone();
+        cx.background_executor().spawn(async move {
+            let matches = fuzzy::match_path_sets(
+                candidate_sets.as_slice(),
+                &query,
+                None,
+                false,
+                MAX_PATH_COMPLETIONS,
+                &AtomicBool::default(),
+                executor,
+            )
+            .await;
+            Ok(matches
+                .into_iter()
+                .map(|mat| format!("{}{}", mat.path_prefix, mat.path.to_string_lossy()))
+                .collect())
+        })
+    }
+
+    fn run(
+        &self,
+        argument: Option<&str>,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<SlashCommandOutput>> {
+        let Some(path) = argument.map(str::to_string) else {
+            return Task::ready(Err(anyhow!("no path specified")));
+        };
+        let Some(workspace) = workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+        let project = workspace.read(cx).project().clone();
+        let Some(project_path) = project_path_for_reference(project.read(cx), &path, cx) else {
+            return Task::ready(Err(anyhow!("no file at {path:?}")));
+        };
+        let buffer = project.update(cx, |project, cx| project.open_buffer(project_path, cx));
+        cx.spawn(|cx| async move {
+            let buffer = buffer.await?;
+            buffer.read_with(&cx, |buffer, _| {
+                let language = buffer.language().map(|language| language.name());
+                SlashCommandOutput::code_block(&path, language.as_deref(), &buffer.text())
+            })
+        })
+    }
+}
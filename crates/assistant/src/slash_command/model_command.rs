@@ -0,0 +1,63 @@
+use super::{SlashCommand, SlashCommandOutput};
+use crate::assistant_panel::ModelChoice;
+use anyhow::{anyhow, Result};
+use gpui::{Task, WeakView, WindowContext};
+use workspace::Workspace;
+
+/// Switches the conversation to another of the available models.
+pub(crate) struct ModelSlashCommand;
+
+impl SlashCommand for ModelSlashCommand {
+    fn name(&self) -> String {
+        "model".into()
+    }
+
+    fn description(&self) -> String {
+        "switch the conversation's model".into()
+    }
+
+    fn argument_placeholder(&self) -> Option<String> {
+        Some("model".into())
+    }
+
+    fn requires_argument(&self) -> bool {
+        true
+    }
+
+    fn complete_argument(
+        &self,
+        _query: String,
+        _workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<Vec<String>>> {
+        let mut names = Vec::new();
+        for choice in ModelChoice::available(cx) {
+            let name = choice.model.short_name().to_string();
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        Task::ready(Ok(names))
+    }
+
+    fn run(
+        &self,
+        argument: Option<&str>,
+        _workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<SlashCommandOutput>> {
+        let Some(name) = argument else {
+            return Task::ready(Err(anyhow!("no model specified")));
+        };
+        let choice = ModelChoice::available(cx)
+            .into_iter()
+            .find(|choice| choice.model.short_name() == name || choice.model.full_name() == name);
+        Task::ready(match choice {
+            Some(choice) => Ok(SlashCommandOutput {
+                model: Some(choice.model),
+                ..Default::default()
+            }),
+            None => Err(anyhow!("unknown model {name:?}")),
+        })
+    }
+}
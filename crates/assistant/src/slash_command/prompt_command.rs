@@ -0,0 +1,82 @@
+use super::{SlashCommand, SlashCommandOutput};
+use crate::{
+    assistant_settings::AssistantSettings, prompt_library::SavedPrompt,
+    prompts::load_prompt_template,
+};
+use anyhow::{anyhow, Result};
+use gpui::{Task, WeakView, WindowContext};
+use settings::Settings;
+use workspace::Workspace;
+
+/// Inserts one of the user's prompt templates, from the `prompt_templates`
+/// setting or the prompt library.
+pub(crate) struct PromptSlashCommand;
+
+impl SlashCommand for PromptSlashCommand {
+    fn name(&self) -> String {
+        "prompt".into()
+    }
+
+    fn description(&self) -> String {
+        "insert a prompt template".into()
+    }
+
+    fn argument_placeholder(&self) -> Option<String> {
+        Some("name".into())
+    }
+
+    fn requires_argument(&self) -> bool {
+        true
+    }
+
+    fn complete_argument(
+        &self,
+        _query: String,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<Vec<String>>> {
+        let Some(workspace) = workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+        let fs = workspace.read(cx).app_state().fs.clone();
+        let mut names = AssistantSettings::get_global(cx)
+            .prompt_templates
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        cx.background_executor().spawn(async move {
+            for prompt in SavedPrompt::list(fs.as_ref()).await.unwrap_or_default() {
+                if !names.contains(&prompt.name) {
+                    names.push(prompt.name);
+                }
+            }
+            names.sort();
+            Ok(names)
+        })
+    }
+
+    fn run(
+        &self,
+        argument: Option<&str>,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<SlashCommandOutput>> {
+        let Some(name) = argument.map(str::to_string) else {
+            return Task::ready(Err(anyhow!("no prompt specified")));
+        };
+        let Some(workspace) = workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+        let fs = workspace.read(cx).app_state().fs.clone();
+        let templates = AssistantSettings::get_global(cx).prompt_templates.clone();
+        cx.background_executor().spawn(async move {
+            let text = load_prompt_template(fs.as_ref(), &name, &templates)
+                .await
+                .ok_or_else(|| anyhow!("no prompt named {name:?}"))?;
+            Ok(SlashCommandOutput {
+                text,
+                ..Default::default()
+            })
+        })
+    }
+}
@@ -0,0 +1,137 @@
+use super::{SlashCommand, SlashCommandOutput};
+use crate::file_reference::format_file_reference;
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use gpui::{Task, WeakView, WindowContext};
+use language::{BufferSnapshot, OffsetRangeExt, Point};
+use project::search::SearchQuery;
+use std::ops::Range;
+use workspace::Workspace;
+
+/// Lines of code included above and below each match.
+const SEARCH_CONTEXT_LINES: u32 = 2;
+
+/// The most matches inserted at once.
+const MAX_SEARCH_MATCHES: usize = 50;
+
+/// Searches the project for a regex, or for the text as it is if it isn't a
+/// valid regex, and inserts the code around each match.
+pub(crate) struct SearchSlashCommand;
+
+impl SlashCommand for SearchSlashCommand {
+    fn name(&self) -> String {
+        "search".into()
+    }
+
+    fn description(&self) -> String {
+        "insert the project's matches for a search".into()
+    }
+
+    fn argument_placeholder(&self) -> Option<String> {
+        Some("query".into())
+    }
+
+    fn requires_argument(&self) -> bool {
+        true
+    }
+
+    fn run(
+        &self,
+        argument: Option<&str>,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<SlashCommandOutput>> {
+        let Some(query) = argument else {
+            return Task::ready(Err(anyhow!("no query specified")));
+        };
+        let Some(workspace) = workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+        // Like smart case in the search bar, only match case when the query
+        // has uppercase letters.
+        let case_sensitive = query.chars().any(char::is_uppercase);
+        let search_query =
+            match SearchQuery::regex(query, false, case_sensitive, false, Vec::new(), Vec::new())
+                .or_else(|_| {
+                    SearchQuery::text(query, false, case_sensitive, false, Vec::new(), Vec::new())
+                }) {
+                Ok(search_query) => search_query,
+                Err(error) => return Task::ready(Err(error)),
+            };
+
+        let query = query.to_string();
+        let project = workspace.read(cx).project().clone();
+        let mut results = project.update(cx, |project, cx| project.search(search_query, cx));
+        cx.spawn(|cx| async move {
+            let mut outputs = Vec::new();
+            let mut match_count = 0;
+            while let Some((buffer, ranges)) = results.next().await {
+                let snapshot = buffer.read_with(&cx, |buffer, _| buffer.snapshot())?;
+                let ranges = &ranges[..ranges.len().min(MAX_SEARCH_MATCHES - match_count)];
+                match_count += ranges.len();
+                let match_rows = ranges.iter().map(|range| {
+                    let range = range.to_point(&snapshot);
+                    range.start.row..range.end.row
+                });
+                for rows in excerpt_rows(match_rows, snapshot.max_point().row) {
+                    outputs.push(excerpt_output(&snapshot, rows));
+                }
+                if match_count == MAX_SEARCH_MATCHES {
+                    break;
+                }
+            }
+            if outputs.is_empty() {
+                return Err(anyhow!("no matches for {query:?}"));
+            }
+            Ok(SlashCommandOutput::join(outputs))
+        })
+    }
+}
+
+/// The rows to show around matches on `match_rows`, which are sorted, merging
+/// excerpts that touch. Ranges are inclusive of their last row.
+fn excerpt_rows(match_rows: impl IntoIterator<Item = Range<u32>>, max_row: u32) -> Vec<Range<u32>> {
+    let mut excerpts: Vec<Range<u32>> = Vec::new();
+    for rows in match_rows {
+        let start = rows.start.saturating_sub(SEARCH_CONTEXT_LINES);
+        let end = (rows.end + SEARCH_CONTEXT_LINES).min(max_row);
+        match excerpts.last_mut() {
+            Some(last) if start <= last.end + 1 => last.end = last.end.max(end),
+            _ => excerpts.push(start..end),
+        }
+    }
+    excerpts
+}
+
+fn excerpt_output(snapshot: &BufferSnapshot, rows: Range<u32>) -> SlashCommandOutput {
+    let path = snapshot.file().map_or_else(
+        || "untitled".to_string(),
+        |file| file.path().to_string_lossy().into_owned(),
+    );
+    let text = snapshot
+        .text_for_range(
+            Point::new(rows.start, 0)..Point::new(rows.end, snapshot.line_len(rows.end)),
+        )
+        .collect::<String>();
+    let language = snapshot.language().map(|language| language.name());
+    SlashCommandOutput::code_block(
+        &format_file_reference(&path, rows),
+        language.as_deref(),
+        &text,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_excerpt_rows() {
+        assert_eq!(
+            excerpt_rows([0..0, 3..3, 10..11, 40..40], 41),
+            [0..5, 8..13, 38..41]
+        );
+        assert_eq!(excerpt_rows([5..5, 5..5], 100), [3..7]);
+        assert!(excerpt_rows([], 100).is_empty());
+    }
+}
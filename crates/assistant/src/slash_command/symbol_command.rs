@@ -0,0 +1,161 @@
+use super::{SlashCommand, SlashCommandOutput};
+use crate::file_reference::format_file_reference;
+use anyhow::{anyhow, Result};
+use collections::HashSet;
+use editor::Editor;
+use gpui::{AppContext, Model, Task, WeakView, WindowContext};
+use language::{Anchor, Buffer, BufferSnapshot, OffsetRangeExt, OutlineItem, Point};
+use std::ops::Range;
+use workspace::Workspace;
+
+/// Inserts the definition of a function, type or other item, found in the
+/// outlines of the open editors or else through the language servers' symbols,
+/// rather than the whole file it's in.
+pub(crate) struct SymbolSlashCommand;
+
+impl SymbolSlashCommand {
+    fn open_buffers(workspace: &Workspace, cx: &AppContext) -> Vec<Model<Buffer>> {
+        let mut buffer_ids = HashSet::default();
+        workspace
+            .items_of_type::<Editor>(cx)
+            .filter_map(|editor| editor.read(cx).buffer().read(cx).as_singleton())
+            .filter(|buffer| buffer_ids.insert(buffer.entity_id()))
+            .collect()
+    }
+}
+
+impl SlashCommand for SymbolSlashCommand {
+    fn name(&self) -> String {
+        "symbol".into()
+    }
+
+    fn description(&self) -> String {
+        "insert a symbol's definition".into()
+    }
+
+    fn argument_placeholder(&self) -> Option<String> {
+        Some("name".into())
+    }
+
+    fn requires_argument(&self) -> bool {
+        true
+    }
+
+    fn complete_argument(
+        &self,
+        _query: String,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<Vec<String>>> {
+        let Some(workspace) = workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+        let mut names = Vec::new();
+        for buffer in Self::open_buffers(workspace.read(cx), cx) {
+            let Some(outline) = buffer.read(cx).snapshot().outline(None) else {
+                continue;
+            };
+            for item in &outline.items {
+                let name = item_name(item);
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        Task::ready(Ok(names))
+    }
+
+    fn run(
+        &self,
+        argument: Option<&str>,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<SlashCommandOutput>> {
+        let Some(name) = argument.map(str::to_string) else {
+            return Task::ready(Err(anyhow!("no symbol specified")));
+        };
+        let Some(workspace) = workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+
+        for buffer in Self::open_buffers(workspace.read(cx), cx) {
+            let snapshot = buffer.read(cx).snapshot();
+            let Some(outline) = snapshot.outline(None) else {
+                continue;
+            };
+            if let Some(item) = outline.items.iter().find(|item| item_name(item) == name) {
+                let rows = item.range.to_point(&snapshot);
+                return Task::ready(Ok(definition_output(
+                    &snapshot,
+                    rows.start.row..rows.end.row,
+                )));
+            }
+        }
+
+        let project = workspace.read(cx).project().clone();
+        let symbols = project.update(cx, |project, cx| project.symbols(&name, cx));
+        cx.spawn(|mut cx| async move {
+            let symbol = symbols
+                .await?
+                .into_iter()
+                .find(|symbol| symbol.name == name)
+                .ok_or_else(|| anyhow!("no symbol named {name:?}"))?;
+            let buffer = project
+                .update(&mut cx, |project, cx| {
+                    project.open_buffer_for_symbol(&symbol, cx)
+                })?
+                .await?;
+            buffer.read_with(&cx, |buffer, _| {
+                let snapshot = buffer.snapshot();
+                let start = snapshot.unclipped_point_utf16_to_point(symbol.range.start);
+                let end = snapshot.unclipped_point_utf16_to_point(symbol.range.end);
+                // Language servers often report only the symbol's name, so use
+                // the innermost outline item around it to get the whole thing.
+                let rows = snapshot
+                    .outline(None)
+                    .and_then(|outline| {
+                        outline
+                            .items
+                            .iter()
+                            .map(|item| (item.depth, item.range.to_point(&snapshot)))
+                            .filter(|(_, range)| range.start <= start && end <= range.end)
+                            .max_by_key(|(depth, _)| *depth)
+                            .map(|(_, range)| range)
+                    })
+                    .unwrap_or(start..end);
+                definition_output(&snapshot, rows.start.row..rows.end.row)
+            })
+        })
+    }
+}
+
+/// The name of an outline item, without keywords such as `fn` or `struct`.
+fn item_name(item: &OutlineItem<Anchor>) -> String {
+    if item.name_ranges.is_empty() {
+        return item.text.clone();
+    }
+    item.name_ranges
+        .iter()
+        .map(|range| &item.text[range.clone()])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The lines in `rows`, inclusive, labeled with the file and line numbers.
+fn definition_output(snapshot: &BufferSnapshot, rows: Range<u32>) -> SlashCommandOutput {
+    let path = snapshot.file().map_or_else(
+        || "untitled".to_string(),
+        |file| file.path().to_string_lossy().into_owned(),
+    );
+    let text = snapshot
+        .text_for_range(
+            Point::new(rows.start, 0)..Point::new(rows.end, snapshot.line_len(rows.end)),
+        )
+        .collect::<String>();
+    let language = snapshot.language().map(|language| language.name());
+    SlashCommandOutput::code_block(
+        &format_file_reference(&path, rows),
+        language.as_deref(),
+        &text,
+    )
+}
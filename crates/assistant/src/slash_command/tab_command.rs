@@ -0,0 +1,86 @@
+use super::{SlashCommand, SlashCommandOutput};
+use anyhow::{anyhow, Result};
+use editor::Editor;
+use gpui::{AppContext, Task, View, WeakView, WindowContext};
+use workspace::Workspace;
+
+/// Inserts the contents of an open editor, defaulting to the active one.
+pub(crate) struct TabSlashCommand;
+
+impl TabSlashCommand {
+    fn path(editor: &View<Editor>, cx: &AppContext) -> Option<String> {
+        let buffer = editor.read(cx).buffer().read(cx).as_singleton()?;
+        let file = buffer.read(cx).file()?;
+        Some(file.path().to_string_lossy().into_owned())
+    }
+}
+
+impl SlashCommand for TabSlashCommand {
+    fn name(&self) -> String {
+        "tab".into()
+    }
+
+    fn description(&self) -> String {
+        "insert an open tab's contents".into()
+    }
+
+    fn argument_placeholder(&self) -> Option<String> {
+        Some("path".into())
+    }
+
+    fn complete_argument(
+        &self,
+        _query: String,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<Vec<String>>> {
+        let Some(workspace) = workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+        let mut paths = Vec::new();
+        for editor in workspace.read(cx).items_of_type::<Editor>(cx) {
+            if let Some(path) = Self::path(&editor, cx) {
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        Task::ready(Ok(paths))
+    }
+
+    fn run(
+        &self,
+        argument: Option<&str>,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<SlashCommandOutput>> {
+        let Some(workspace) = workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+        let workspace = workspace.read(cx);
+        let editor = match argument {
+            Some(argument) => workspace
+                .items_of_type::<Editor>(cx)
+                .find(|editor| Self::path(editor, cx).as_deref() == Some(argument)),
+            None => workspace.active_item_as::<Editor>(cx),
+        };
+        let Some(editor) = editor else {
+            return Task::ready(Err(match argument {
+                Some(argument) => anyhow!("no open tab for {argument:?}"),
+                None => anyhow!("no active tab"),
+            }));
+        };
+        let Some(buffer) = editor.read(cx).buffer().read(cx).as_singleton() else {
+            return Task::ready(Err(anyhow!("tab has more than one buffer")));
+        };
+        let buffer = buffer.read(cx);
+        let path = Self::path(&editor, cx).unwrap_or_else(|| "untitled".into());
+        let language = buffer.language().map(|language| language.name());
+        let text = buffer.text();
+        Task::ready(Ok(SlashCommandOutput::code_block(
+            &path,
+            language.as_deref(),
+            &text,
+        )))
+    }
+}
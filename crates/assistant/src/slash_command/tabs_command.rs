@@ -0,0 +1,125 @@
+use super::{SlashCommand, SlashCommandOutput};
+use crate::{prompts::file_summary_prompt, AssistantPanel};
+use ai::{
+    chat::{ChatRequest, RequestMessage, Role},
+    completion::text_only,
+};
+use anyhow::{anyhow, Result};
+use collections::HashSet;
+use editor::Editor;
+use futures::{future, TryStreamExt};
+use gpui::{Task, WeakView, WindowContext};
+use std::sync::Arc;
+use workspace::Workspace;
+
+/// The fraction of the default model's context the open files can take up
+/// before the largest ones are summarized.
+const TABS_TOKEN_BUDGET: f32 = 0.5;
+
+/// Inserts the contents of every open editor. When they'd take up too much of
+/// the model's context, the files over their share of it are summarized by
+/// the model instead.
+pub(crate) struct TabsSlashCommand;
+
+struct OpenFile {
+    path: String,
+    language: Option<Arc<str>>,
+    text: String,
+}
+
+impl SlashCommand for TabsSlashCommand {
+    fn name(&self) -> String {
+        "tabs".into()
+    }
+
+    fn description(&self) -> String {
+        "insert the contents of all open tabs".into()
+    }
+
+    fn run(
+        &self,
+        _argument: Option<&str>,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<SlashCommandOutput>> {
+        let Some(workspace) = workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+        let workspace = workspace.read(cx);
+        let mut buffers = HashSet::default();
+        let mut files = Vec::new();
+        for editor in workspace.items_of_type::<Editor>(cx) {
+            let Some(buffer) = editor.read(cx).buffer().read(cx).as_singleton() else {
+                continue;
+            };
+            if !buffers.insert(buffer.entity_id()) {
+                continue;
+            }
+            let buffer = buffer.read(cx);
+            files.push(OpenFile {
+                path: buffer.file().map_or_else(
+                    || "untitled".to_string(),
+                    |file| file.path().to_string_lossy().into_owned(),
+                ),
+                language: buffer.language().map(|language| language.name()),
+                text: buffer.text(),
+            });
+        }
+        if files.is_empty() {
+            return Task::ready(Err(anyhow!("no open tabs")));
+        }
+
+        let Some(panel) = workspace.panel::<AssistantPanel>(cx) else {
+            return Task::ready(Err(anyhow!("the assistant panel isn't available")));
+        };
+        let provider = panel.read(cx).completion_provider();
+        let request_template = panel.read(cx).request_for(String::new(), cx);
+        cx.background_executor().spawn(async move {
+            let (budget, token_counts) = {
+                let language_model = provider.base_model();
+                let budget = (language_model.capacity()? as f32 * TABS_TOKEN_BUDGET) as usize;
+                let token_counts = files
+                    .iter()
+                    .map(|file| language_model.count_tokens(&file.text))
+                    .collect::<Result<Vec<_>>>()?;
+                (budget, token_counts)
+            };
+            let share = budget / files.len();
+            let over_budget = token_counts.iter().sum::<usize>() > budget;
+
+            let outputs =
+                future::try_join_all(files.iter().zip(token_counts).map(|(file, token_count)| {
+                    let provider = provider.clone();
+                    let request_template = &request_template;
+                    async move {
+                        if !over_budget || token_count <= share {
+                            return Ok(SlashCommandOutput::code_block(
+                                &file.path,
+                                file.language.as_deref(),
+                                &file.text,
+                            ));
+                        }
+                        let request = ChatRequest {
+                            messages: vec![RequestMessage {
+                                role: Role::User,
+                                content: file_summary_prompt(
+                                    &file.path,
+                                    file.language.as_deref(),
+                                    &file.text,
+                                ),
+                            }],
+                            ..request_template.clone()
+                        };
+                        let events = provider.complete(request).await?;
+                        let summary = text_only(events).try_collect::<String>().await?;
+                        anyhow::Ok(SlashCommandOutput::section(
+                            &format!("Summary of `{}`:", file.path),
+                            &summary,
+                        ))
+                    }
+                }))
+                .await?;
+            Ok(SlashCommandOutput::join(outputs))
+        })
+    }
+}
@@ -0,0 +1,83 @@
+use super::{SlashCommand, SlashCommandOutput};
+use crate::terminal_assistant::{shell_name, EXPLAINED_TERMINAL_LINES};
+use anyhow::{anyhow, Context as _, Result};
+use gpui::{Task, View, WeakView, WindowContext};
+use terminal_view::{terminal_panel::TerminalPanel, TerminalView};
+use workspace::Workspace;
+
+/// Inserts the end of the active terminal's output, including its scrollback.
+/// The argument is how many lines to insert.
+pub(crate) struct TerminalSlashCommand;
+
+impl TerminalSlashCommand {
+    /// The active item if it's a terminal, or otherwise the active terminal in
+    /// the terminal panel, which is usually the one the user was last running
+    /// commands in.
+    fn active_terminal(workspace: &Workspace, cx: &WindowContext) -> Option<View<TerminalView>> {
+        workspace.active_item_as::<TerminalView>(cx).or_else(|| {
+            workspace
+                .panel::<TerminalPanel>(cx)?
+                .read(cx)
+                .pane()
+                .read(cx)
+                .active_item()?
+                .act_as::<TerminalView>(cx)
+        })
+    }
+}
+
+impl SlashCommand for TerminalSlashCommand {
+    fn name(&self) -> String {
+        "terminal".into()
+    }
+
+    fn description(&self) -> String {
+        "insert the terminal's recent output".into()
+    }
+
+    fn argument_placeholder(&self) -> Option<String> {
+        Some("lines".into())
+    }
+
+    fn run(
+        &self,
+        argument: Option<&str>,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<SlashCommandOutput>> {
+        let line_count = match argument {
+            Some(argument) => match argument.parse::<usize>() {
+                Ok(line_count) => line_count,
+                Err(error) => {
+                    return Task::ready(
+                        Err(error).with_context(|| format!("invalid line count {argument:?}")),
+                    )
+                }
+            },
+            None => EXPLAINED_TERMINAL_LINES,
+        };
+        let Some(workspace) = workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+        let Some(terminal_view) = Self::active_terminal(workspace.read(cx), cx) else {
+            return Task::ready(Err(anyhow!("no open terminal")));
+        };
+
+        // The terminal's grid holds the text as displayed, with escape
+        // sequences already interpreted, so there's nothing to strip.
+        let output = terminal_view
+            .read(cx)
+            .terminal()
+            .read(cx)
+            .last_lines(line_count);
+        if output.trim().is_empty() {
+            return Task::ready(Err(anyhow!("the terminal is empty")));
+        }
+        let label = format!("{} terminal", shell_name());
+        Task::ready(Ok(SlashCommandOutput::code_block(
+            &label,
+            Some("console"),
+            &output,
+        )))
+    }
+}
@@ -0,0 +1,240 @@
+use super::{SlashCommand, SlashCommandOutput};
+use crate::AssistantPanel;
+use ai::models::TruncationDirection;
+use anyhow::{anyhow, Context as _, Result};
+use futures::AsyncReadExt;
+use gpui::{Task, WeakView, WindowContext};
+use util::http::{AsyncBody, Url};
+use workspace::Workspace;
+
+/// The fraction of the default model's context a page can take up before the
+/// rest of it is cut off.
+const WEB_PAGE_TOKEN_BUDGET: f32 = 0.25;
+
+/// Elements whose contents aren't part of a page's readable text.
+const SKIPPED_ELEMENTS: &[&str] = &["head", "noscript", "script", "style", "svg", "template"];
+
+/// Elements that start on a new line.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "article",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dt",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "tr",
+    "ul",
+];
+
+/// Fetches a URL and inserts its readable text, so documentation can be
+/// referred to by its link.
+pub(crate) struct WebSlashCommand;
+
+impl SlashCommand for WebSlashCommand {
+    fn name(&self) -> String {
+        "web".into()
+    }
+
+    fn description(&self) -> String {
+        "insert the text of a web page".into()
+    }
+
+    fn argument_placeholder(&self) -> Option<String> {
+        Some("url".into())
+    }
+
+    fn requires_argument(&self) -> bool {
+        true
+    }
+
+    fn run(
+        &self,
+        argument: Option<&str>,
+        workspace: WeakView<Workspace>,
+        cx: &mut WindowContext,
+    ) -> Task<Result<SlashCommandOutput>> {
+        let Some(argument) = argument else {
+            return Task::ready(Err(anyhow!("no URL specified")));
+        };
+        let url = if argument.starts_with("http://") || argument.starts_with("https://") {
+            argument.to_string()
+        } else {
+            format!("https://{argument}")
+        };
+        if let Err(error) = Url::parse(&url) {
+            return Task::ready(Err(error).with_context(|| format!("invalid URL {argument:?}")));
+        }
+        let Some(workspace) = workspace.upgrade() else {
+            return Task::ready(Err(anyhow!("workspace was dropped")));
+        };
+        let Some(panel) = workspace.read(cx).panel::<AssistantPanel>(cx) else {
+            return Task::ready(Err(anyhow!("the assistant panel isn't available")));
+        };
+        let provider = panel.read(cx).completion_provider();
+        let http_client =
+            crate::http_client(workspace.read(cx).app_state().client.http_client(), cx);
+
+        cx.background_executor().spawn(async move {
+            let mut response = http_client.get(&url, AsyncBody::default(), true).await?;
+            let mut body = String::new();
+            response.body_mut().read_to_string(&mut body).await?;
+            if !response.status().is_success() {
+                return Err(anyhow!("{url} responded with {}", response.status()));
+            }
+            let is_html = response
+                .headers()
+                .get("content-type")
+                .and_then(|content_type| content_type.to_str().ok())
+                .map_or(true, |content_type| content_type.contains("html"));
+            let text = if is_html { html_to_text(&body) } else { body };
+
+            let language_model = provider.base_model();
+            let budget = (language_model.capacity()? as f32 * WEB_PAGE_TOKEN_BUDGET) as usize;
+            let mut truncated = language_model.truncate(&text, budget, TruncationDirection::End)?;
+            if truncated.len() < text.len() {
+                truncated.push_str("\n[truncated]");
+            }
+            Ok(SlashCommandOutput::section(
+                &format!("Contents of {url}:"),
+                &truncated,
+            ))
+        })
+    }
+}
+
+/// The readable text of an HTML document: its tags and scripts removed,
+/// entities decoded, and whitespace collapsed, with block elements on their
+/// own lines and no blank lines.
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(tag_start) = rest.find('<') {
+        push_text(&mut text, &rest[..tag_start]);
+        rest = &rest[tag_start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(tag_end) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..tag_end];
+        rest = &rest[tag_end + 1..];
+
+        let is_closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if !is_closing && SKIPPED_ELEMENTS.contains(&name.as_str()) {
+            // ASCII lowercasing keeps byte offsets the same.
+            let closing_tag = format!("</{name}");
+            rest = match rest.to_ascii_lowercase().find(&closing_tag) {
+                Some(ix) => rest[ix..].find('>').map_or("", |end| &rest[ix + end + 1..]),
+                None => "",
+            };
+            continue;
+        }
+        if BLOCK_ELEMENTS.contains(&name.as_str()) {
+            text.push('\n');
+        }
+    }
+    push_text(&mut text, rest);
+
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Appends text from between tags, with its entities decoded. Line breaks in
+/// the source are just whitespace, so they become spaces.
+fn push_text(text: &mut String, mut source: &str) {
+    while let Some(ampersand) = source.find('&') {
+        push_whitespace_as_spaces(text, &source[..ampersand]);
+        source = &source[ampersand..];
+        let entity = source
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| Some((decode_entity(&source[1..end])?, end)));
+        match entity {
+            Some((decoded, end)) => {
+                text.push(decoded);
+                source = &source[end + 1..];
+            }
+            None => {
+                text.push('&');
+                source = &source[1..];
+            }
+        }
+    }
+    push_whitespace_as_spaces(text, source);
+}
+
+fn push_whitespace_as_spaces(text: &mut String, source: &str) {
+    text.extend(
+        source
+            .chars()
+            .map(|c| if c.is_whitespace() { ' ' } else { c }),
+    );
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = name.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<!DOCTYPE html>\n<html><head><title>Docs</title>\n\
+            <style>p { color: red; }</style></head>\n<body>\n\
+            <h1>Getting   started</h1><!-- nav -->\n\
+            <p>Install with <code>cargo&nbsp;add</code> &amp; run.</p>\n\
+            <SCRIPT>alert(\"<p>hi</p>\")</SCRIPT>\n\
+            <ul><li>One &lt;1&gt;</li><li>Two &#8212; &#x2713;</li></ul>\n\
+            </body></html>";
+        assert_eq!(
+            html_to_text(html),
+            "Getting started\nInstall with cargo add & run.\nOne <1>\nTwo \u{2014} \u{2713}"
+        );
+    }
+}
@@ -64,16 +64,61 @@ impl Debug for Matrix {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub enum Hunk {
     Insert { text: String },
     Remove { len: usize },
     Keep { len: usize },
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharKind {
+    Word,
+    Whitespace,
+    Punctuation,
+}
+
+impl CharKind {
+    fn of(ch: char) -> Self {
+        if ch.is_alphanumeric() || ch == '_' {
+            CharKind::Word
+        } else if ch.is_whitespace() && ch != '\n' {
+            CharKind::Whitespace
+        } else {
+            CharKind::Punctuation
+        }
+    }
+}
+
+/// Splits `text` into words, runs of whitespace, and single punctuation
+/// characters. Newlines are always a token of their own.
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut token_start = 0;
+    let mut prev_kind = None;
+    for (ix, ch) in text.char_indices() {
+        let kind = CharKind::of(ch);
+        if prev_kind.map_or(false, |prev_kind| {
+            prev_kind != kind || kind == CharKind::Punctuation
+        }) {
+            tokens.push(&text[token_start..ix]);
+            token_start = ix;
+        }
+        prev_kind = Some(kind);
+    }
+    if token_start < text.len() {
+        tokens.push(&text[token_start..]);
+    }
+    tokens
+}
+
+/// Diffs the new text against the old one word by word as it streams in, so
+/// each batch of hunks can be applied right away without splitting a word.
 pub struct StreamingDiff {
-    old: Vec<char>,
-    new: Vec<char>,
+    old: Vec<String>,
+    new: Vec<String>,
+    /// The end of the new text, which may be the start of a longer word.
+    pending_new: String,
     scores: Matrix,
     old_text_ix: usize,
     new_text_ix: usize,
@@ -87,7 +132,10 @@ impl StreamingDiff {
     const MAX_EQUALITY_EXPONENT: i32 = 16;
 
     pub fn new(old: String) -> Self {
-        let old = old.chars().collect::<Vec<_>>();
+        let old = tokenize(&old)
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
         let mut scores = Matrix::new();
         scores.resize(old.len() + 1, 1);
         for i in 0..=old.len() {
@@ -96,6 +144,7 @@ impl StreamingDiff {
         Self {
             old,
             new: Vec::new(),
+            pending_new: String::new(),
             scores,
             old_text_ix: 0,
             new_text_ix: 0,
@@ -104,7 +153,24 @@ impl StreamingDiff {
     }
 
     pub fn push_new(&mut self, text: &str) -> Vec<Hunk> {
-        self.new.extend(text.chars());
+        self.pending_new.push_str(text);
+        let pending_new = std::mem::take(&mut self.pending_new);
+        let mut tokens = tokenize(&pending_new);
+        // Hold back the last token until we know it's complete.
+        if let Some(last_token) = tokens.last() {
+            if last_token
+                .chars()
+                .next()
+                .map_or(false, |ch| CharKind::of(ch) != CharKind::Punctuation)
+            {
+                self.pending_new = tokens.pop().unwrap().to_string();
+            }
+        }
+        self.new.extend(tokens.into_iter().map(str::to_string));
+        self.diff_new_tokens()
+    }
+
+    fn diff_new_tokens(&mut self) -> Vec<Hunk> {
         self.scores.resize(self.old.len() + 1, self.new.len() + 1);
 
         for j in self.new_text_ix + 1..=self.new.len() {
@@ -186,22 +252,22 @@ impl StreamingDiff {
             } else {
                 if let Some(range) = pending_insert.take() {
                     hunks.push(Hunk::Insert {
-                        text: self.new[range].iter().collect(),
+                        text: self.new[range].concat(),
                     });
                 }
 
-                let char_len = self.old[i - 1].len_utf8();
+                let token_len = self.old[i - 1].len();
                 if prev_i == i - 1 && prev_j == j {
                     if let Some(Hunk::Remove { len }) = hunks.last_mut() {
-                        *len += char_len;
+                        *len += token_len;
                     } else {
-                        hunks.push(Hunk::Remove { len: char_len })
+                        hunks.push(Hunk::Remove { len: token_len })
                     }
                 } else {
                     if let Some(Hunk::Keep { len }) = hunks.last_mut() {
-                        *len += char_len;
+                        *len += token_len;
                     } else {
-                        hunks.push(Hunk::Keep { len: char_len })
+                        hunks.push(Hunk::Keep { len: token_len })
                     }
                 }
             }
@@ -212,7 +278,7 @@ impl StreamingDiff {
 
         if let Some(range) = pending_insert.take() {
             hunks.push(Hunk::Insert {
-                text: self.new[range].iter().collect(),
+                text: self.new[range].concat(),
             });
         }
 
@@ -220,8 +286,13 @@ impl StreamingDiff {
         hunks
     }
 
-    pub fn finish(self) -> Vec<Hunk> {
-        self.backtrack(self.old.len(), self.new.len())
+    pub fn finish(mut self) -> Vec<Hunk> {
+        let pending_new = std::mem::take(&mut self.pending_new);
+        self.new
+            .extend(tokenize(&pending_new).into_iter().map(str::to_string));
+        let mut hunks = self.diff_new_tokens();
+        hunks.extend(self.backtrack(self.old.len(), self.new.len()));
+        hunks
     }
 }
 
@@ -232,6 +303,36 @@ mod tests {
     use super::*;
     use rand::prelude::*;
 
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("let foo_1 = bar(x);\n    }"),
+            ["let", " ", "foo_1", " ", "=", " ", "bar", "(", "x", ")", ";", "\n", "    ", "}"]
+        );
+    }
+
+    #[test]
+    fn test_word_level_hunks() {
+        let mut diff = StreamingDiff::new("let foo = bar(1);".into());
+        let mut hunks = Vec::new();
+        // The partial word "fo" isn't diffed until the rest of it arrives.
+        hunks.extend(diff.push_new("let fo"));
+        assert_eq!(hunks, [Hunk::Keep { len: 4 }]);
+        hunks.extend(diff.push_new("o = baz(1);"));
+        hunks.extend(diff.finish());
+        assert_eq!(
+            hunks,
+            [
+                Hunk::Keep { len: 4 },
+                Hunk::Keep { len: 6 },
+                Hunk::Insert {
+                    text: "baz(1);".into()
+                },
+                Hunk::Remove { len: 7 },
+            ]
+        );
+    }
+
     #[gpui::test(iterations = 100)]
     fn test_random_diffs(mut rng: StdRng) {
         let old_text_len = env::var("OLD_TEXT_LEN")
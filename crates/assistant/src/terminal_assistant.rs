@@ -0,0 +1,318 @@
+use crate::{assistant_settings::SamplingSettings, prompts::terminal_command_prompt};
+use ai::{
+    chat::{ChatRequest, RequestMessage, Role},
+    completion::{text_only, CompletionProvider},
+    models::ModelName,
+};
+use editor::{Editor, EditorElement, EditorStyle};
+use futures::TryStreamExt;
+use gpui::{
+    AppContext, DismissEvent, EventEmitter, FocusHandle, FocusableView, FontStyle, FontWeight,
+    Model, Render, Task, TextStyle, View, ViewContext, WhiteSpace, WindowContext,
+};
+use settings::Settings;
+use std::{path::Path, sync::Arc};
+use terminal::Terminal;
+use terminal_view::{terminal_panel::TerminalPanel, TerminalView};
+use theme::ThemeSettings;
+use ui::{prelude::*, Tooltip};
+use workspace::{ModalView, Workspace};
+
+/// How many lines at the end of a terminal are sent to have its output explained.
+pub const EXPLAINED_TERMINAL_LINES: usize = 100;
+
+/// The terminal that actions should apply to: the focused one in the terminal
+/// panel, or otherwise the active item if it's a terminal.
+pub fn focused_terminal(workspace: &Workspace, cx: &WindowContext) -> Option<View<TerminalView>> {
+    if let Some(panel) = workspace.panel::<TerminalPanel>(cx) {
+        if panel.focus_handle(cx).contains_focused(cx) {
+            return panel
+                .read(cx)
+                .pane()
+                .read(cx)
+                .active_item()?
+                .act_as::<TerminalView>(cx);
+        }
+    }
+    workspace
+        .active_item(cx)
+        .and_then(|item| item.act_as::<TerminalView>(cx))
+}
+
+/// The name of the shell the terminal runs, for telling the model which syntax
+/// to use.
+pub fn shell_name() -> String {
+    std::env::var("SHELL")
+        .ok()
+        .and_then(|shell| {
+            Path::new(&shell)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| "sh".to_string())
+}
+
+/// The command in a response from the model, without any code fence it was
+/// wrapped in despite being asked not to.
+fn command_from_response(response: &str) -> String {
+    let response = response.trim();
+    let Some(fenced) = response.strip_prefix("```") else {
+        return response.to_string();
+    };
+    let body = fenced.split_once('\n').map_or("", |(_, body)| body);
+    body.trim_end().trim_end_matches("```").trim().to_string()
+}
+
+enum State {
+    Prompting,
+    Generating,
+    Suggested,
+    Failed(SharedString),
+}
+
+/// Asks the model for a shell command that does what the user describes, and
+/// lets them review and edit it before it's run in the terminal.
+pub struct TerminalAssistant {
+    terminal: Model<Terminal>,
+    prompt_editor: View<Editor>,
+    command_editor: View<Editor>,
+    provider: Arc<dyn CompletionProvider>,
+    model: ModelName,
+    sampling: SamplingSettings,
+    state: State,
+    _pending_command: Task<()>,
+}
+
+impl TerminalAssistant {
+    pub fn new(
+        terminal: Model<Terminal>,
+        provider: Arc<dyn CompletionProvider>,
+        model: ModelName,
+        sampling: SamplingSettings,
+        cx: &mut ViewContext<Self>,
+    ) -> Self {
+        let prompt_editor = cx.new_view(|cx| {
+            let mut editor = Editor::single_line(cx);
+            editor.set_placeholder_text("Describe the command to run…", cx);
+            editor
+        });
+        let command_editor = cx.new_view(|cx| Editor::single_line(cx));
+        Self {
+            terminal,
+            prompt_editor,
+            command_editor,
+            provider,
+            model,
+            sampling,
+            state: State::Prompting,
+            _pending_command: Task::ready(()),
+        }
+    }
+
+    fn cancel(&mut self, _: &menu::Cancel, cx: &mut ViewContext<Self>) {
+        cx.emit(DismissEvent);
+    }
+
+    /// Asks for a command from the prompt, or runs the suggested command once
+    /// it's been reviewed.
+    fn confirm(&mut self, _: &menu::Confirm, cx: &mut ViewContext<Self>) {
+        if matches!(self.state, State::Suggested)
+            && self.command_editor.focus_handle(cx).is_focused(cx)
+        {
+            self.run_command(true, cx);
+        } else {
+            self.generate(cx);
+        }
+    }
+
+    /// Puts the suggested command at the prompt without running it.
+    fn secondary_confirm(&mut self, _: &menu::SecondaryConfirm, cx: &mut ViewContext<Self>) {
+        if matches!(self.state, State::Suggested) {
+            self.run_command(false, cx);
+        }
+    }
+
+    fn generate(&mut self, cx: &mut ViewContext<Self>) {
+        let user_prompt = self.prompt_editor.read(cx).text(cx);
+        if user_prompt.trim().is_empty() || matches!(self.state, State::Generating) {
+            return;
+        }
+
+        let working_directory = self
+            .terminal
+            .read(cx)
+            .foreground_process_info
+            .as_ref()
+            .map(|info| info.cwd.clone());
+        let prompt = terminal_command_prompt(
+            user_prompt.trim(),
+            &shell_name(),
+            std::env::consts::OS,
+            working_directory.as_deref(),
+        );
+        let request = ChatRequest {
+            model: self.model.full_name().into(),
+            messages: vec![RequestMessage {
+                role: Role::User,
+                content: prompt,
+            }],
+            stream: true,
+            stop: self.sampling.stop_sequences.clone().unwrap_or_default(),
+            // Commands are sampled greedily unless the settings say otherwise.
+            temperature: self.sampling.temperature.unwrap_or(0.),
+            sampling: self.sampling.to_params(),
+            ..Default::default()
+        };
+
+        let events = self.provider.complete(request);
+        self.state = State::Generating;
+        self._pending_command = cx.spawn(|this, mut cx| async move {
+            let response = async move { text_only(events.await?).try_collect::<String>().await };
+            let response = response.await;
+            this.update(&mut cx, |this, cx| {
+                match response {
+                    Ok(response) => {
+                        let command = command_from_response(&response);
+                        this.command_editor
+                            .update(cx, |editor, cx| editor.set_text(command, cx));
+                        this.state = State::Suggested;
+                        cx.focus_view(&this.command_editor);
+                    }
+                    Err(error) => this.state = State::Failed(error.to_string().into()),
+                }
+                cx.notify();
+            })
+            .ok();
+        });
+        cx.notify();
+    }
+
+    fn run_command(&mut self, execute: bool, cx: &mut ViewContext<Self>) {
+        let command = self.command_editor.read(cx).text(cx);
+        if command.trim().is_empty() {
+            return;
+        }
+        self.terminal.update(cx, |terminal, _| {
+            terminal.paste(command.trim());
+            if execute {
+                terminal.input("\r".into());
+            }
+        });
+        cx.emit(DismissEvent);
+    }
+
+    fn render_editor(&self, editor: &View<Editor>, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let settings = ThemeSettings::get_global(cx);
+        let text_style = TextStyle {
+            color: if editor.read(cx).read_only(cx) {
+                cx.theme().colors().text_disabled
+            } else {
+                cx.theme().colors().text
+            },
+            font_family: settings.buffer_font.family.clone(),
+            font_features: settings.buffer_font.features,
+            font_size: rems(0.875).into(),
+            font_weight: FontWeight::NORMAL,
+            font_style: FontStyle::Normal,
+            line_height: relative(1.3).into(),
+            background_color: None,
+            underline: None,
+            strikethrough: None,
+            white_space: WhiteSpace::Normal,
+        };
+        EditorElement::new(
+            editor,
+            EditorStyle {
+                background: cx.theme().colors().editor_background,
+                local_player: cx.theme().players().local(),
+                text: text_style,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn render_suggestion(&self, cx: &mut ViewContext<Self>) -> Option<impl IntoElement> {
+        let status = match &self.state {
+            State::Prompting => return None,
+            State::Generating => Label::new("Generating…")
+                .color(Color::Muted)
+                .into_any_element(),
+            State::Failed(error) => Label::new(error.clone())
+                .color(Color::Error)
+                .into_any_element(),
+            State::Suggested => h_flex()
+                .gap_1()
+                .child(
+                    div()
+                        .flex_1()
+                        .child(self.render_editor(&self.command_editor, cx)),
+                )
+                .child(
+                    IconButton::new("run_command", IconName::Play)
+                        .icon_color(Color::Success)
+                        .on_click(cx.listener(|this, _, cx| this.run_command(true, cx)))
+                        .tooltip(|cx| Tooltip::for_action("Run", &menu::Confirm, cx)),
+                )
+                .child(
+                    IconButton::new("insert_command", IconName::Return)
+                        .on_click(cx.listener(|this, _, cx| this.run_command(false, cx)))
+                        .tooltip(|cx| {
+                            Tooltip::for_action(
+                                "Insert Without Running",
+                                &menu::SecondaryConfirm,
+                                cx,
+                            )
+                        }),
+                )
+                .into_any_element(),
+        };
+        Some(div().px_2().py_1().child(status))
+    }
+}
+
+impl ModalView for TerminalAssistant {}
+
+impl EventEmitter<DismissEvent> for TerminalAssistant {}
+
+impl FocusableView for TerminalAssistant {
+    fn focus_handle(&self, cx: &AppContext) -> FocusHandle {
+        self.prompt_editor.focus_handle(cx)
+    }
+}
+
+impl Render for TerminalAssistant {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex()
+            .elevation_2(cx)
+            .key_context("TerminalAssistant")
+            .on_action(cx.listener(Self::cancel))
+            .on_action(cx.listener(Self::confirm))
+            .on_action(cx.listener(Self::secondary_confirm))
+            .w(rems(34.))
+            .child(
+                div()
+                    .px_2()
+                    .py_1()
+                    .child(self.render_editor(&self.prompt_editor, cx)),
+            )
+            .children(self.render_suggestion(cx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_from_response() {
+        assert_eq!(command_from_response("ls -la\n"), "ls -la");
+        assert_eq!(
+            command_from_response("```bash\nfind . -name '*.rs'\n```"),
+            "find . -name '*.rs'"
+        );
+        assert_eq!(
+            command_from_response("```\ngit status\n```\n"),
+            "git status"
+        );
+    }
+}
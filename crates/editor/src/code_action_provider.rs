@@ -0,0 +1,56 @@
+use gpui::{Action, AppContext, Global, Model, SharedString};
+use language::{Anchor, Buffer};
+use std::{ops::Range, sync::Arc};
+
+/// An entry in the code actions menu that doesn't come from a language server.
+/// Choosing it dispatches `action` from the editor.
+pub struct ProvidedCodeAction {
+    pub title: SharedString,
+    pub action: Box<dyn Action>,
+}
+
+impl Clone for ProvidedCodeAction {
+    fn clone(&self) -> Self {
+        Self {
+            title: self.title.clone(),
+            action: self.action.boxed_clone(),
+        }
+    }
+}
+
+/// A source of code actions offered next to the language server's. The
+/// assistant registers one that offers to fix diagnostics.
+pub trait CodeActionProvider: 'static {
+    /// The actions to offer for `range` of `buffer`.
+    fn code_actions(
+        &self,
+        buffer: &Model<Buffer>,
+        range: Range<Anchor>,
+        cx: &AppContext,
+    ) -> Vec<ProvidedCodeAction>;
+}
+
+struct GlobalCodeActionProvider(Arc<dyn CodeActionProvider>);
+
+impl Global for GlobalCodeActionProvider {}
+
+/// Sets the provider whose actions editors add to the code actions menu, or
+/// removes it when `provider` is `None`.
+pub fn set_code_action_provider(
+    provider: Option<Arc<dyn CodeActionProvider>>,
+    cx: &mut AppContext,
+) {
+    match provider {
+        Some(provider) => cx.set_global(GlobalCodeActionProvider(provider)),
+        None => {
+            if cx.has_global::<GlobalCodeActionProvider>() {
+                cx.remove_global::<GlobalCodeActionProvider>();
+            }
+        }
+    }
+}
+
+pub(crate) fn code_action_provider(cx: &AppContext) -> Option<Arc<dyn CodeActionProvider>> {
+    cx.try_global::<GlobalCodeActionProvider>()
+        .map(|provider| provider.0.clone())
+}
@@ -14,10 +14,12 @@
 //! If you're looking to improve Vim mode, you should check out Vim crate that wraps Editor and overrides it's behaviour.
 pub mod actions;
 mod blink_manager;
+mod code_action_provider;
 pub mod display_map;
 mod editor_settings;
 mod element;
 mod inlay_hint_cache;
+mod inline_completion_provider;
 
 mod debounced_delay;
 mod git;
@@ -43,6 +45,7 @@ use anyhow::{anyhow, Context as _, Result};
 use blink_manager::BlinkManager;
 use client::{Collaborator, ParticipantIndex};
 use clock::ReplicaId;
+pub use code_action_provider::{set_code_action_provider, CodeActionProvider, ProvidedCodeAction};
 use collections::{BTreeMap, Bound, HashMap, HashSet, VecDeque};
 use convert_case::{Case, Casing};
 use copilot::Copilot;
@@ -67,6 +70,7 @@ use gpui::{
 use highlight_matching_bracket::refresh_matching_bracket_highlights;
 use hover_popover::{hide_hover, HoverState};
 use inlay_hint_cache::{InlayHintCache, InlaySplice, InvalidationStrategy};
+pub use inline_completion_provider::{set_inline_completion_provider, InlineCompletionProvider};
 pub use items::MAX_TAB_TITLE_LEN;
 use itertools::Itertools;
 use language::{char_kind, CharKind};
@@ -396,7 +400,7 @@ pub struct Editor {
     completion_tasks: Vec<(CompletionId, Task<Option<()>>)>,
     next_completion_id: CompletionId,
     completion_documentation_pre_resolve_debounce: DebouncedDelay,
-    available_code_actions: Option<(Model<Buffer>, Arc<[CodeAction]>)>,
+    available_code_actions: Option<(Model<Buffer>, Arc<[CodeActionsItem]>)>,
     code_actions_task: Option<Task<()>>,
     document_highlights_task: Option<Task<()>>,
     pending_rename: Option<RenameState>,
@@ -1091,8 +1095,22 @@ impl CompletionsMenu {
 }
 
 #[derive(Clone)]
+enum CodeActionsItem {
+    Lsp(CodeAction),
+    Provided(ProvidedCodeAction),
+}
+
+impl CodeActionsItem {
+    fn title(&self) -> &str {
+        match self {
+            CodeActionsItem::Lsp(action) => &action.lsp_action.title,
+            CodeActionsItem::Provided(action) => &action.title,
+        }
+    }
+}
+
 struct CodeActionsMenu {
-    actions: Arc<[CodeAction]>,
+    actions: Arc<[CodeActionsItem]>,
     buffer: Model<Buffer>,
     selected_item: usize,
     scroll_handle: UniformListScrollHandle,
@@ -1186,7 +1204,7 @@ impl CodeActionsMenu {
                                 }),
                             )
                             // TASK: It would be good to make lsp_action.title a SharedString to avoid allocating here.
-                            .child(SharedString::from(action.lsp_action.title.clone()))
+                            .child(SharedString::from(action.title().to_string()))
                     })
                     .collect()
             },
@@ -1200,7 +1218,7 @@ impl CodeActionsMenu {
             self.actions
                 .iter()
                 .enumerate()
-                .max_by_key(|(_, action)| action.lsp_action.title.chars().count())
+                .max_by_key(|(_, action)| action.title().chars().count())
                 .map(|(ix, _)| ix),
         )
         .into_any_element();
@@ -1221,6 +1239,8 @@ pub(crate) struct CopilotState {
     completions: Vec<copilot::Completion>,
     active_completion_index: usize,
     suggestion: Option<Inlay>,
+    /// The provider the completions came from, or `None` if they came from Copilot.
+    provider: Option<Arc<dyn InlineCompletionProvider>>,
 }
 
 impl Default for CopilotState {
@@ -1233,10 +1253,17 @@ impl Default for CopilotState {
             active_completion_index: 0,
             cycled: false,
             suggestion: None,
+            provider: None,
         }
     }
 }
 
+/// Where the suggestions shown as ghost text come from.
+enum InlineCompletionSource {
+    Copilot(Model<Copilot>),
+    Provider(Arc<dyn InlineCompletionProvider>),
+}
+
 impl CopilotState {
     fn active_completion(&self) -> Option<&copilot::Completion> {
         self.completions.get(self.active_completion_index)
@@ -3546,7 +3573,13 @@ impl Editor {
             return None;
         };
         let action_ix = action.item_ix.unwrap_or(actions_menu.selected_item);
-        let action = actions_menu.actions.get(action_ix)?.clone();
+        let action = match actions_menu.actions.get(action_ix)?.clone() {
+            CodeActionsItem::Lsp(action) => action,
+            CodeActionsItem::Provided(action) => {
+                cx.dispatch_action(action.action);
+                return Some(Task::ready(Ok(())));
+            }
+        };
         let title = action.lsp_action.title.clone();
         let buffer = actions_menu.buffer;
         let workspace = self.workspace()?;
@@ -3676,13 +3709,24 @@ impl Editor {
             };
 
             this.update(&mut cx, |this, cx| {
-                this.available_code_actions = actions.and_then(|actions| {
-                    if actions.is_empty() {
-                        None
-                    } else {
-                        Some((start_buffer, actions.into()))
-                    }
-                });
+                let mut actions = actions
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(CodeActionsItem::Lsp)
+                    .collect::<Vec<_>>();
+                if let Some(provider) = code_action_provider::code_action_provider(cx) {
+                    actions.extend(
+                        provider
+                            .code_actions(&start_buffer, start..end, cx)
+                            .into_iter()
+                            .map(CodeActionsItem::Provided),
+                    );
+                }
+                this.available_code_actions = if actions.is_empty() {
+                    None
+                } else {
+                    Some((start_buffer, actions.into()))
+                };
                 cx.notify();
             })
             .log_err();
@@ -3796,11 +3840,10 @@ impl Editor {
         debounce: bool,
         cx: &mut ViewContext<Self>,
     ) -> Option<()> {
-        let copilot = Copilot::global(cx)?;
-        if !self.show_copilot_suggestions || !copilot.read(cx).status().is_authorized() {
+        let Some(source) = self.inline_completion_source(cx) else {
             self.clear_copilot_suggestions(cx);
             return None;
-        }
+        };
         self.update_visible_copilot_suggestion(cx);
 
         let snapshot = self.buffer.read(cx).snapshot(cx);
@@ -3812,6 +3855,13 @@ impl Editor {
 
         let (buffer, buffer_position) =
             self.buffer.read(cx).text_anchor_for_position(cursor, cx)?;
+        if let InlineCompletionSource::Provider(provider) = &source {
+            if !provider.is_enabled(&buffer, buffer_position, cx) {
+                self.clear_copilot_suggestions(cx);
+                return None;
+            }
+        }
+
         self.copilot_state.pending_refresh = cx.spawn(|this, mut cx| async move {
             if debounce {
                 cx.background_executor()
@@ -3819,17 +3869,22 @@ impl Editor {
                     .await;
             }
 
-            let completions = copilot
-                .update(&mut cx, |copilot, cx| {
-                    copilot.completions(&buffer, buffer_position, cx)
-                })
-                .log_err()
-                .unwrap_or(Task::ready(Ok(Vec::new())))
-                .await
-                .log_err()
-                .into_iter()
-                .flatten()
-                .collect_vec();
+            let completions = match &source {
+                InlineCompletionSource::Copilot(copilot) => copilot
+                    .update(&mut cx, |copilot, cx| {
+                        copilot.completions(&buffer, buffer_position, cx)
+                    })
+                    .log_err(),
+                InlineCompletionSource::Provider(provider) => cx
+                    .update(|cx| provider.completions(&buffer, buffer_position, cx))
+                    .log_err(),
+            }
+            .unwrap_or(Task::ready(Ok(Vec::new())))
+            .await
+            .log_err()
+            .into_iter()
+            .flatten()
+            .collect_vec();
 
             this.update(&mut cx, |this, cx| {
                 if !completions.is_empty() {
@@ -3838,6 +3893,10 @@ impl Editor {
                     this.copilot_state.completions.clear();
                     this.copilot_state.active_completion_index = 0;
                     this.copilot_state.excerpt_id = Some(cursor.excerpt_id);
+                    this.copilot_state.provider = match source {
+                        InlineCompletionSource::Copilot(_) => None,
+                        InlineCompletionSource::Provider(provider) => Some(provider),
+                    };
                     for completion in completions {
                         this.copilot_state.push_completion(completion);
                     }
@@ -3856,10 +3915,16 @@ impl Editor {
         direction: Direction,
         cx: &mut ViewContext<Self>,
     ) -> Option<()> {
-        let copilot = Copilot::global(cx)?;
-        if !self.show_copilot_suggestions || !copilot.read(cx).status().is_authorized() {
-            return None;
-        }
+        let copilot = match self.inline_completion_source(cx)? {
+            InlineCompletionSource::Copilot(copilot) => copilot,
+            InlineCompletionSource::Provider(_) => {
+                // Providers return all of their candidates up front, so there's
+                // nothing more to fetch.
+                self.copilot_state.cycle_completions(direction);
+                self.update_visible_copilot_suggestion(cx);
+                return Some(());
+            }
+        };
 
         if self.copilot_state.cycled {
             self.copilot_state.cycle_completions(direction);
@@ -3948,7 +4013,11 @@ impl Editor {
 
     fn accept_copilot_suggestion(&mut self, cx: &mut ViewContext<Self>) -> bool {
         if let Some(suggestion) = self.take_active_copilot_suggestion(cx) {
-            if let Some((copilot, completion)) =
+            if let Some(provider) = self.copilot_state.provider.clone() {
+                if let Some(completion) = self.copilot_state.active_completion() {
+                    provider.accept(completion, cx);
+                }
+            } else if let Some((copilot, completion)) =
                 Copilot::global(cx).zip(self.copilot_state.active_completion())
             {
                 copilot
@@ -3971,7 +4040,9 @@ impl Editor {
 
     fn discard_copilot_suggestion(&mut self, cx: &mut ViewContext<Self>) -> bool {
         if let Some(suggestion) = self.take_active_copilot_suggestion(cx) {
-            if let Some(copilot) = Copilot::global(cx) {
+            if let Some(provider) = self.copilot_state.provider.clone() {
+                provider.discard(&self.copilot_state.completions, cx);
+            } else if let Some(copilot) = Copilot::global(cx) {
                 copilot
                     .update(cx, |copilot, cx| {
                         copilot.discard_completions(&self.copilot_state.completions, cx)
@@ -3991,6 +4062,21 @@ impl Editor {
         }
     }
 
+    /// Copilot when it's signed in, and otherwise the registered
+    /// [`InlineCompletionProvider`], if any.
+    fn inline_completion_source(&self, cx: &AppContext) -> Option<InlineCompletionSource> {
+        if !self.show_copilot_suggestions {
+            return None;
+        }
+        if let Some(copilot) = Copilot::global(cx) {
+            if copilot.read(cx).status().is_authorized() {
+                return Some(InlineCompletionSource::Copilot(copilot));
+            }
+        }
+        inline_completion_provider::inline_completion_provider(cx)
+            .map(InlineCompletionSource::Provider)
+    }
+
     fn is_copilot_enabled_at(
         &self,
         location: Anchor,
@@ -7706,6 +7706,56 @@ async fn test_copilot_completion_invalidation(
     });
 }
 
+#[gpui::test]
+async fn test_inline_completion_provider(
+    executor: BackgroundExecutor,
+    cx: &mut gpui::TestAppContext,
+) {
+    struct FakeInlineCompletionProvider;
+
+    impl InlineCompletionProvider for FakeInlineCompletionProvider {
+        fn is_enabled(&self, _: &Model<Buffer>, _: language::Anchor, _: &AppContext) -> bool {
+            true
+        }
+
+        fn completions(
+            &self,
+            _: &Model<Buffer>,
+            position: language::Anchor,
+            _: &mut AppContext,
+        ) -> Task<Result<Vec<copilot::Completion>>> {
+            Task::ready(Ok(vec![copilot::Completion {
+                uuid: "fake".into(),
+                range: position..position,
+                text: ".foo()".into(),
+            }]))
+        }
+    }
+
+    init_test(cx, |_| {});
+    cx.update(|cx| {
+        set_inline_completion_provider(Some(Arc::new(FakeInlineCompletionProvider)), cx)
+    });
+    let mut cx = EditorTestContext::new(cx).await;
+
+    // Without Copilot, suggestions come from the registered provider.
+    cx.set_state(indoc! {"
+        oneˇ
+        two
+    "});
+    cx.update_editor(|editor, cx| editor.next_copilot_suggestion(&Default::default(), cx));
+    executor.run_until_parked();
+    cx.update_editor(|editor, cx| {
+        assert!(editor.has_active_copilot_suggestion(cx));
+        assert_eq!(editor.display_text(cx), "one.foo()\ntwo\n");
+        assert_eq!(editor.text(cx), "one\ntwo\n");
+
+        assert!(editor.accept_copilot_suggestion(cx));
+        assert!(!editor.has_active_copilot_suggestion(cx));
+        assert_eq!(editor.text(cx), "one.foo()\ntwo\n");
+    });
+}
+
 #[gpui::test]
 async fn test_copilot_multibuffer(executor: BackgroundExecutor, cx: &mut gpui::TestAppContext) {
     init_test(cx, |_| {});
@@ -0,0 +1,57 @@
+use anyhow::Result;
+use copilot::Completion;
+use gpui::{AppContext, Global, Model, Task};
+use language::{Anchor, Buffer};
+use std::sync::Arc;
+
+/// A source of inline completions, shown as ghost text at the cursor, that's used
+/// when Copilot isn't signed in. The assistant registers one that completes code
+/// with a local model.
+pub trait InlineCompletionProvider: 'static {
+    /// Whether to suggest completions at `position`, on top of the editor's own
+    /// `show_copilot_suggestions` setting.
+    fn is_enabled(&self, buffer: &Model<Buffer>, position: Anchor, cx: &AppContext) -> bool;
+
+    /// Suggests completions for the text at `position`. The editor drops the task
+    /// when the cursor moves or the buffer is edited, which should cancel any
+    /// request that's still in flight.
+    fn completions(
+        &self,
+        buffer: &Model<Buffer>,
+        position: Anchor,
+        cx: &mut AppContext,
+    ) -> Task<Result<Vec<Completion>>>;
+
+    /// Called when the user accepts one of the suggested completions.
+    fn accept(&self, _completion: &Completion, _cx: &mut AppContext) {}
+
+    /// Called when the suggested completions are dismissed without accepting any.
+    fn discard(&self, _completions: &[Completion], _cx: &mut AppContext) {}
+}
+
+struct GlobalInlineCompletionProvider(Arc<dyn InlineCompletionProvider>);
+
+impl Global for GlobalInlineCompletionProvider {}
+
+/// Sets the provider editors fall back to when Copilot isn't signed in, or
+/// removes it when `provider` is `None`.
+pub fn set_inline_completion_provider(
+    provider: Option<Arc<dyn InlineCompletionProvider>>,
+    cx: &mut AppContext,
+) {
+    match provider {
+        Some(provider) => cx.set_global(GlobalInlineCompletionProvider(provider)),
+        None => {
+            if cx.has_global::<GlobalInlineCompletionProvider>() {
+                cx.remove_global::<GlobalInlineCompletionProvider>();
+            }
+        }
+    }
+}
+
+pub(crate) fn inline_completion_provider(
+    cx: &AppContext,
+) -> Option<Arc<dyn InlineCompletionProvider>> {
+    cx.try_global::<GlobalInlineCompletionProvider>()
+        .map(|provider| provider.0.clone())
+}
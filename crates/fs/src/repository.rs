@@ -53,6 +53,13 @@ pub trait GitRepository: Send {
     fn branches(&self) -> Result<Vec<Branch>>;
     fn change_branch(&self, _: &str) -> Result<()>;
     fn create_branch(&self, _: &str) -> Result<()>;
+
+    /// Returns the changes staged for the next commit as a unified diff.
+    fn staged_diff(&self) -> Result<String>;
+
+    /// Returns the changes committed on the current branch since it diverged
+    /// from its upstream as a unified diff.
+    fn upstream_diff(&self) -> Result<String>;
 }
 
 impl std::fmt::Debug for dyn GitRepository {
@@ -209,6 +216,45 @@ impl GitRepository for LibGitRepository {
 
         Ok(())
     }
+
+    fn staged_diff(&self) -> Result<String> {
+        // There's no HEAD to compare against before the first commit.
+        let head_tree = match self.head() {
+            Ok(head) => Some(head.peel_to_tree()?),
+            Err(_) => None,
+        };
+        let diff = self.diff_tree_to_index(head_tree.as_ref(), None, None)?;
+        diff_to_patch(&diff)
+    }
+
+    fn upstream_diff(&self) -> Result<String> {
+        let head = self.head()?;
+        let branch_name = head
+            .shorthand()
+            .ok_or_else(|| anyhow::anyhow!("HEAD isn't on a branch"))?;
+        let upstream = self
+            .find_branch(branch_name, BranchType::Local)?
+            .upstream()
+            .map_err(|_| anyhow::anyhow!("{branch_name} has no upstream branch"))?;
+        let head_commit = head.peel_to_commit()?;
+        let upstream_commit = upstream.get().peel_to_commit()?;
+        let merge_base = self.merge_base(head_commit.id(), upstream_commit.id())?;
+        let base_tree = self.find_commit(merge_base)?.tree()?;
+        let diff = self.diff_tree_to_tree(Some(&base_tree), Some(&head_commit.tree()?), None)?;
+        diff_to_patch(&diff)
+    }
+}
+
+fn diff_to_patch(diff: &git2::Diff) -> Result<String> {
+    let mut patch = Vec::new();
+    diff.print(git2::DiffFormat::Patch, |_, _, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            patch.push(line.origin() as u8);
+        }
+        patch.extend_from_slice(line.content());
+        true
+    })?;
+    Ok(String::from_utf8_lossy(&patch).into_owned())
 }
 
 fn matches_index(repo: &LibGitRepository, path: &RepoPath, mtime: SystemTime) -> bool {
@@ -253,6 +299,8 @@ pub struct FakeGitRepositoryState {
     pub index_contents: HashMap<PathBuf, String>,
     pub worktree_statuses: HashMap<RepoPath, GitFileStatus>,
     pub branch_name: Option<String>,
+    pub staged_diff: String,
+    pub upstream_diff: String,
 }
 
 impl FakeGitRepository {
@@ -317,6 +365,16 @@ impl GitRepository for FakeGitRepository {
         state.branch_name = Some(name.to_owned());
         Ok(())
     }
+
+    fn staged_diff(&self) -> Result<String> {
+        let state = self.state.lock();
+        Ok(state.staged_diff.clone())
+    }
+
+    fn upstream_diff(&self) -> Result<String> {
+        let state = self.state.lock();
+        Ok(state.upstream_diff.clone())
+    }
 }
 
 fn check_path_to_repo_path_errors(relative_file_path: &Path) -> Result<()> {
@@ -980,6 +980,19 @@ impl Terminal {
         self.set_selection(Some((make_selection(&(start..=end)), end)));
     }
 
+    /// The text of the last `max_lines` lines of the terminal, including its
+    /// scrollback, without trailing blank lines.
+    pub fn last_lines(&self, max_lines: usize) -> String {
+        let term = self.term.lock();
+        let end = AlacPoint::new(term.bottommost_line(), term.last_column());
+        let start_line = cmp::max(
+            term.topmost_line(),
+            Line(end.line.0 - max_lines.saturating_sub(1) as i32),
+        );
+        let text = term.bounds_to_string(AlacPoint::new(start_line, Column(0)), end);
+        text.trim_end().to_string()
+    }
+
     fn set_selection(&mut self, selection: Option<(Selection, AlacPoint)>) {
         self.events
             .push_back(InternalEvent::SetSelection(selection));
@@ -281,6 +281,10 @@ impl TerminalPanel {
         }
     }
 
+    pub fn pane(&self) -> &View<Pane> {
+        &self.pane
+    }
+
     pub fn open_terminal(
         workspace: &mut Workspace,
         action: &workspace::OpenTerminal,
@@ -112,14 +112,21 @@ pub trait HttpClient: Send + Sync {
 }
 
 pub fn client() -> Arc<dyn HttpClient> {
-    Arc::new(
-        isahc::HttpClient::builder()
-            .connect_timeout(Duration::from_secs(5))
-            .low_speed_timeout(100, Duration::from_secs(5))
-            .proxy(http_proxy_from_env())
-            .build()
-            .unwrap(),
-    )
+    client_with_proxy(http_proxy_from_env(), Vec::new())
+}
+
+/// Returns a client that sends requests through the given proxy, except for the
+/// hosts in `no_proxy`.
+pub fn client_with_proxy(proxy: Option<Uri>, no_proxy: Vec<String>) -> Arc<dyn HttpClient> {
+    let mut builder = isahc::HttpClient::builder()
+        .connect_timeout(Duration::from_secs(5))
+        .low_speed_timeout(100, Duration::from_secs(5))
+        .proxy(proxy);
+    // Even an empty list replaces curl's own handling of `NO_PROXY`.
+    if !no_proxy.is_empty() {
+        builder = builder.proxy_blacklist(no_proxy);
+    }
+    Arc::new(builder.build().unwrap())
 }
 
 impl HttpClient for isahc::HttpClient {
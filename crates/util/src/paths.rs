@@ -10,6 +10,8 @@ lazy_static::lazy_static! {
     pub static ref HOME: PathBuf = dirs::home_dir().expect("failed to determine home directory");
     pub static ref CONFIG_DIR: PathBuf = HOME.join(".config").join("zed");
     pub static ref CONVERSATIONS_DIR: PathBuf = CONFIG_DIR.join("conversations");
+    pub static ref PROMPTS_DIR: PathBuf = CONFIG_DIR.join("prompts");
+    pub static ref CONVERSATION_TEMPLATES_DIR: PathBuf = PROMPTS_DIR.join("templates");
     pub static ref EMBEDDINGS_DIR: PathBuf = CONFIG_DIR.join("embeddings");
     pub static ref THEMES_DIR: PathBuf = CONFIG_DIR.join("themes");
     pub static ref LOGS_DIR: PathBuf = if cfg!(target_os = "macos") {
@@ -43,8 +45,10 @@ lazy_static::lazy_static! {
     pub static ref LAST_USERNAME: PathBuf = CONFIG_DIR.join("last-username.txt");
     pub static ref LOG: PathBuf = LOGS_DIR.join("Zed.log");
     pub static ref OLD_LOG: PathBuf = LOGS_DIR.join("Zed.log.old");
+    pub static ref AI_TRACE_LOG: PathBuf = LOGS_DIR.join("ai_trace.log");
     pub static ref LOCAL_SETTINGS_RELATIVE_PATH: &'static Path = Path::new(".zed/settings.json");
     pub static ref TEMP_DIR: PathBuf = HOME.join(".cache").join("zed");
+    pub static ref COMPLETIONS_CACHE_DIR: PathBuf = TEMP_DIR.join("completions");
 }
 
 pub trait PathExt {
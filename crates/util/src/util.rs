@@ -67,6 +67,21 @@ pub fn http_proxy_from_env() -> Option<isahc::http::Uri> {
     None
 }
 
+/// Returns the hosts listed in `NO_PROXY`, which should be reached without going
+/// through the proxy.
+pub fn no_proxy_from_env() -> Vec<String> {
+    std::env::var("NO_PROXY")
+        .or_else(|_| std::env::var("no_proxy"))
+        .map(|hosts| {
+            hosts
+                .split(',')
+                .map(|host| host.trim().to_string())
+                .filter(|host| !host.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 /// Removes characters from the end of the string if its length is greater than `max_chars` and
 /// appends "..." to the string. Returns string unchanged if its length is smaller than max_chars.
 pub fn truncate_and_trailoff(s: &str, max_chars: usize) -> String {
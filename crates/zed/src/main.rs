@@ -173,7 +173,7 @@ fn main() {
             node_runtime.clone(),
             cx,
         );
-        assistant::init(cx);
+        assistant::init(http.clone(), cx);
 
         extension::init(
             fs.clone(),
@@ -248,7 +248,12 @@ fn main() {
         tasks_ui::init(cx);
         channel::init(&client, user_store.clone(), cx);
         search::init(cx);
-        semantic_index::init(fs.clone(), http.clone(), languages.clone(), cx);
+        semantic_index::init(
+            fs.clone(),
+            assistant::http_client(http.clone(), cx),
+            languages.clone(),
+            cx,
+        );
         vim::init(cx);
         terminal_view::init(cx);
 
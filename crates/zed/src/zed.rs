@@ -199,6 +199,11 @@ pub fn initialize_workspace(app_state: Arc<AppState>, cx: &mut AppContext) {
             )?;
 
             workspace_handle.update(&mut cx, |workspace, cx| {
+                let model_indicator =
+                    cx.new_view(|cx| assistant::ModelIndicator::new(&assistant_panel, cx));
+                workspace.status_bar().update(cx, |status_bar, cx| {
+                    status_bar.add_right_item(model_indicator, cx);
+                });
                 workspace.add_panel(project_panel, cx);
                 workspace.add_panel(terminal_panel, cx);
                 workspace.add_panel(assistant_panel, cx);
@@ -2787,7 +2792,7 @@ mod tests {
             collab_ui::init(&app_state, cx);
             project_panel::init((), cx);
             terminal_view::init(cx);
-            assistant::init(cx);
+            assistant::init(app_state.client.http_client(), cx);
             initialize_workspace(app_state.clone(), cx);
             app_state
         })